@@ -0,0 +1,127 @@
+//! Compares decoding `Transition` messages through a `JsonValue`, as the
+//! client used to, with decoding them straight from their text or bytes.
+//!
+//! Run with `cargo bench --bench decode_server_message`.
+
+use std::collections::BTreeMap;
+
+use convex::Value;
+use convex_sync_types::{
+    QueryId,
+    ServerMessage,
+    StateModification,
+    StateVersion,
+};
+use criterion::{
+    criterion_group,
+    criterion_main,
+    BatchSize,
+    Criterion,
+    Throughput,
+};
+use serde_json::{
+    value::RawValue,
+    Value as JsonValue,
+};
+
+/// A page of `n` chat messages, like a typical query result.
+fn messages(n: usize) -> Value {
+    let messages = (0..n)
+        .map(|i| {
+            Value::Object(BTreeMap::from([
+                (
+                    "_id".to_string(),
+                    Value::id(format!("k57c2kd8d9cd5f8ga7ey1fa6h{i:06}")),
+                ),
+                (
+                    "_creationTime".to_string(),
+                    Value::Float64(1.7e12 + i as f64),
+                ),
+                (
+                    "author".to_string(),
+                    Value::String(format!("user {}", i % 7)),
+                ),
+                (
+                    "body".to_string(),
+                    Value::String("Hello, world! ".repeat(4)),
+                ),
+                ("likes".to_string(), Value::Int64(i as i64)),
+            ]))
+        })
+        .collect();
+    Value::Array(messages)
+}
+
+/// The text of a stream of `Transition`s, each updating a few queries.
+fn transitions(count: u32) -> Vec<String> {
+    let mut version = StateVersion::initial();
+    (0..count)
+        .map(|i| {
+            let start_version = version;
+            version = StateVersion {
+                ts: version.ts.succ().unwrap(),
+                ..version
+            };
+            let modifications = (0..4)
+                .map(|query_id| StateModification::QueryUpdated {
+                    query_id: QueryId::new(query_id),
+                    value: messages(20 + i as usize % 5),
+                    log_lines: vec![],
+                    journal: None,
+                })
+                .collect();
+            let transition = ServerMessage::Transition {
+                start_version,
+                end_version: version,
+                modifications,
+            };
+            JsonValue::from(transition).to_string()
+        })
+        .collect()
+}
+
+fn decode(c: &mut Criterion) {
+    let stream = transitions(100);
+    let bytes: usize = stream.iter().map(String::len).sum();
+    let mut group = c.benchmark_group("decode_transitions");
+    group.throughput(Throughput::Bytes(bytes as u64));
+    group.bench_function("json_value", |b| {
+        b.iter_batched(
+            || stream.clone(),
+            |stream| {
+                for text in stream {
+                    let json: JsonValue = serde_json::from_str(&text).unwrap();
+                    ServerMessage::<Value>::try_from(json).unwrap();
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("raw_value", |b| {
+        b.iter_batched(
+            || stream.clone(),
+            |stream| {
+                for text in stream {
+                    let json: &RawValue = serde_json::from_str(&text).unwrap();
+                    ServerMessage::<Value>::try_from(json).unwrap();
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("slice", |b| {
+        b.iter_batched(
+            || stream.clone(),
+            |stream| {
+                for text in stream {
+                    ServerMessage::<Value>::from_json_slice(text.as_bytes()).unwrap();
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
@@ -0,0 +1,114 @@
+//! Compares the memory and decode time of a large, repetitive query result
+//! decoded as a `Value` and as an `InternedValue`.
+//!
+//! Run with `cargo bench --bench intern_strings`. The heap usage of each
+//! representation is printed before the timings.
+
+use std::{
+    alloc::{
+        GlobalAlloc,
+        Layout,
+        System,
+    },
+    collections::BTreeMap,
+    sync::atomic::{
+        AtomicIsize,
+        Ordering,
+    },
+};
+
+use convex::{
+    InternedValue,
+    Value,
+};
+use criterion::{
+    criterion_group,
+    criterion_main,
+    Criterion,
+    Throughput,
+};
+use serde_json::Value as JsonValue;
+
+/// Tracks the bytes currently allocated on the heap.
+struct CountingAlloc;
+
+static ALLOCATED: AtomicIsize = AtomicIsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size() as isize, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size() as isize, Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// `n` orders, with the same field names and a few distinct statuses and
+/// currencies, like a typical large query result.
+fn orders(n: usize) -> Vec<u8> {
+    let orders = (0..n)
+        .map(|i| {
+            Value::Object(BTreeMap::from([
+                (
+                    "_id".to_string(),
+                    Value::id(format!("k57c2kd8d9cd5f8ga7ey1fa6h{i:06}")),
+                ),
+                (
+                    "_creationTime".to_string(),
+                    Value::Float64(1.7e12 + i as f64),
+                ),
+                (
+                    "status".to_string(),
+                    Value::from(["pending", "shipped", "delivered"][i % 3]),
+                ),
+                ("currency".to_string(), Value::from(["USD", "EUR"][i % 2])),
+                (
+                    "customer".to_string(),
+                    Value::from(format!("customer {}", i % 50)),
+                ),
+                ("total".to_string(), Value::Int64(i as i64 * 100)),
+            ]))
+        })
+        .collect();
+    JsonValue::from(Value::Array(orders))
+        .to_string()
+        .into_bytes()
+}
+
+/// The heap bytes retained by the result of `decode`.
+fn retained<T>(decode: impl FnOnce() -> T) -> isize {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let value = decode();
+    let after = ALLOCATED.load(Ordering::Relaxed);
+    drop(value);
+    after - before
+}
+
+fn decode(c: &mut Criterion) {
+    let text = orders(10_000);
+    println!(
+        "Heap retained for {} bytes of JSON: Value {} bytes, InternedValue {} bytes",
+        text.len(),
+        retained(|| Value::from_json_slice(&text).unwrap()),
+        retained(|| InternedValue::from_json_slice(&text).unwrap()),
+    );
+
+    let mut group = c.benchmark_group("decode_orders");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    group.bench_function("value", |b| {
+        b.iter(|| Value::from_json_slice(&text).unwrap())
+    });
+    group.bench_function("interned_value", |b| {
+        b.iter(|| InternedValue::from_json_slice(&text).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
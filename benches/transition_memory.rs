@@ -0,0 +1,161 @@
+//! Measures the memory a client retains under a stream of transitions that
+//! each replace a large query result, and the time to apply one.
+//!
+//! Run with `cargo bench --bench transition_memory`. The heap usage is
+//! printed before the timings, and the benchmark fails if it grows with the
+//! number of transitions applied.
+
+use std::{
+    alloc::{
+        GlobalAlloc,
+        Layout,
+        System,
+    },
+    collections::BTreeMap,
+    sync::atomic::{
+        AtomicIsize,
+        Ordering,
+    },
+};
+
+use convex::{
+    base_client::BaseConvexClient,
+    Value,
+};
+use convex_sync_types::{
+    QueryId,
+    ServerMessage,
+    StateModification,
+    StateVersion,
+};
+use criterion::{
+    criterion_group,
+    criterion_main,
+    BatchSize,
+    Criterion,
+};
+
+/// Tracks the bytes currently allocated on the heap.
+struct CountingAlloc;
+
+static ALLOCATED: AtomicIsize = AtomicIsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size() as isize, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size() as isize, Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const ORDERS: usize = 2_000;
+const UPDATES: usize = 1_000;
+
+/// A page of orders as of `revision`, so that every transition changes the
+/// result.
+fn orders(revision: i64) -> Value {
+    let orders = (0..ORDERS)
+        .map(|i| {
+            Value::Object(BTreeMap::from([
+                (
+                    "_id".to_string(),
+                    Value::id(format!("k57c2kd8d9cd5f8ga7ey1fa6h{i:06}")),
+                ),
+                ("status".to_string(), Value::from("pending")),
+                ("revision".to_string(), Value::Int64(revision)),
+            ]))
+        })
+        .collect();
+    Value::Array(orders)
+}
+
+/// A client subscribed to one query.
+fn subscribed_client() -> (BaseConvexClient, QueryId) {
+    let mut client = BaseConvexClient::new();
+    let query_id = client
+        .subscribe("listOrders".parse().unwrap(), BTreeMap::new())
+        .query_id();
+    (client, query_id)
+}
+
+/// The transition from `version` that sets the query's result to `value`,
+/// advancing `version` past it.
+fn transition(version: &mut StateVersion, query_id: QueryId, value: Value) -> ServerMessage<Value> {
+    let start_version = *version;
+    *version = StateVersion {
+        query_set: 1,
+        ts: version.ts.succ().unwrap(),
+        ..start_version
+    };
+    ServerMessage::Transition {
+        start_version,
+        end_version: *version,
+        modifications: vec![StateModification::QueryUpdated {
+            query_id,
+            value,
+            log_lines: vec![],
+            journal: None,
+        }],
+    }
+}
+
+fn steady_state_memory() {
+    let result_size = {
+        let before = ALLOCATED.load(Ordering::Relaxed);
+        let value = orders(0);
+        let size = ALLOCATED.load(Ordering::Relaxed) - before;
+        drop(value);
+        size
+    };
+
+    let (mut client, query_id) = subscribed_client();
+    let mut version = StateVersion::initial();
+    let mut apply = |client: &mut BaseConvexClient, revision| {
+        let message = transition(&mut version, query_id, orders(revision));
+        client.receive_message(message).unwrap();
+    };
+    for revision in 0..10 {
+        apply(&mut client, revision);
+    }
+    let warm = ALLOCATED.load(Ordering::Relaxed);
+    for revision in 10..10 + UPDATES as i64 {
+        apply(&mut client, revision);
+    }
+    let growth = ALLOCATED.load(Ordering::Relaxed) - warm;
+    println!(
+        "Heap retained with a {result_size} byte result: {warm} bytes after 10 transitions, \
+         {growth:+} bytes after {UPDATES} more"
+    );
+    assert!(
+        growth < result_size,
+        "Memory grew by {growth} bytes over {UPDATES} transitions"
+    );
+}
+
+fn apply_transition(c: &mut Criterion) {
+    steady_state_memory();
+
+    let (mut client, query_id) = subscribed_client();
+    let mut version = StateVersion::initial();
+    let mut revision = 0;
+    c.bench_function("apply_large_transition", |b| {
+        b.iter_batched(
+            || {
+                revision += 1;
+                transition(&mut version, query_id, orders(revision))
+            },
+            |message| client.receive_message(message).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, apply_transition);
+criterion_main!(benches);
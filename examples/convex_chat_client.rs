@@ -64,7 +64,7 @@ async fn main() -> anyhow::Result<()> {
     // Client code used in thread #2
     let mut client_ = client.clone();
 
-    println!("{}", format!("Hi! What's your name?").red().bold());
+    println!("{}", "Hi! What's your name?".red().bold());
     let mut sender = readline()?;
     if sender.is_empty() {
         sender = String::from("Anonymous Person");
@@ -88,7 +88,7 @@ async fn main() -> anyhow::Result<()> {
                     let new_val = new_val.expect("Client dropped prematurely");
                     println!(
                         "{}",
-                        format!("---------------- Message History ----------------").yellow()
+                        "---------------- Message History ----------------".yellow()
                     );
                     if let FunctionResult::Value(Value::Array(array)) = new_val {
                         for item in array {
@@ -98,10 +98,10 @@ async fn main() -> anyhow::Result<()> {
                                         Some(Value::String(name)) => name,
                                         _ => "Anonymous Author",
                                     };
-                                    let author_string = if author == &sender_clone {
-                                        format!("{}", author).yellow().bold()
+                                    let author_string = if author == sender_clone {
+                                        author.yellow().bold()
                                     } else {
-                                        format!("{}", author).red().bold()
+                                        author.red().bold()
                                     };
                                     println!("{}: {:?}", author_string, str);
                                 }
@@ -110,7 +110,7 @@ async fn main() -> anyhow::Result<()> {
                     }
                     println!(
                         "{}",
-                        format!("-------------- End Message History --------------").yellow()
+                        "-------------- End Message History --------------".yellow()
                     );
                 },
                 _ = cancel_fut => {
@@ -132,24 +132,24 @@ async fn main() -> anyhow::Result<()> {
         if line == "quit" || line == "exit" {
             println!(
                 "{}",
-                format!("------------- Exiting Convex Demo -------------").blue()
+                "------------- Exiting Convex Demo -------------".blue()
             );
             break;
         }
 
-        println!("{}", format!("Sending a message").yellow().bold());
+        println!("{}", "Sending a message".yellow().bold());
         let result = client_
             .mutation(
                 "sendMessage",
                 btreemap! {
-                    "body".to_string() => line.try_into()?,
-                    "author".to_string() => sender.clone().try_into()?
+                    "body".to_string() => line.into(),
+                    "author".to_string() => sender.clone().into()
                 },
             )
             .await?;
         match result {
             FunctionResult::Value(Value::Null) => {
-                println!("{}.", format!("Message sent").green().bold());
+                println!("{}.", "Message sent".green().bold());
             },
             FunctionResult::Value(v) => {
                 println!(
@@ -1,18 +1,14 @@
-use std::{
-    collections::BTreeMap,
-    env,
-};
+use std::collections::BTreeMap;
 
 use convex::ConvexClient;
 
 #[tokio::main]
 async fn main() {
+    // `ConvexClient::from_env` loads these itself with the `dotenv` feature.
     dotenv::from_filename(".env.local").ok();
     dotenv::dotenv().ok();
 
-    let deployment_url = env::var("CONVEX_URL").unwrap();
-
-    let mut client = ConvexClient::new(&deployment_url).await.unwrap();
+    let mut client = ConvexClient::from_env().await.unwrap();
     let result = client.query("getTasks", BTreeMap::new()).await.unwrap();
     println!("{result:#?}");
 }
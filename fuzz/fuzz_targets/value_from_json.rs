@@ -0,0 +1,15 @@
+#![no_main]
+
+use convex::Value;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // Decoding must fail gracefully on any input.
+    let Ok(value) = Value::from_json_slice(data) else {
+        return;
+    };
+    // Anything that decodes must survive a round trip through its encoding.
+    let encoded = serde_json::Value::from(value.clone()).to_string();
+    let decoded = Value::from_json_slice(encoded.as_bytes()).expect("Re-encoded value must decode");
+    assert_eq!(decoded, value);
+});
@@ -10,6 +10,9 @@ use std::{
         BTreeSet,
         VecDeque,
     },
+    mem,
+    sync::Arc,
+    time::Duration,
 };
 
 use convex_sync_types::{
@@ -27,16 +30,25 @@ use convex_sync_types::{
     UdfPath,
 };
 use serde_json::json;
+#[cfg(feature = "unstable")]
+use tokio::sync::broadcast;
 use tokio::sync::oneshot;
 
 #[cfg(doc)]
 use crate::ConvexClient;
 use crate::{
+    client::clock::{
+        Clock,
+        SystemClock,
+    },
     sync::{
         ReconnectProtocolReason,
         ServerMessage,
     },
-    value::Value,
+    value::{
+        check_arg_names,
+        Value,
+    },
 };
 
 mod request_manager;
@@ -44,14 +56,142 @@ use request_manager::{
     RequestId,
     RequestManager,
 };
+mod query_builder;
+pub use query_builder::QueryBuilder;
 mod query_result;
+pub(crate) use query_result::ResultVersion;
 pub use query_result::{
     FunctionResult,
     QueryResults,
 };
+mod request_ids;
+pub use request_ids::{
+    RequestIdAllocator,
+    SharedRequestIds,
+};
+mod value_store;
+pub use value_store::ValueStore;
 
 use self::request_manager::RequestType;
 
+/// How many raw server messages a receiver of
+/// [`BaseConvexClient::raw_messages`] can fall behind before it skips some.
+#[cfg(feature = "unstable")]
+const RAW_MESSAGES_CAPACITY: usize = 256;
+
+/// The current result of each subscribed query, along with the path of its
+/// function, as returned by [`BaseConvexClient::snapshot`].
+pub type QuerySnapshot = BTreeMap<QueryId, (UdfPath, Result<Value, String>)>;
+
+/// What the deployment reported about itself when the client last connected,
+/// as returned by [`BaseConvexClient::deployment_info`].
+///
+/// The sync protocol doesn't carry a server version or feature flags, so this
+/// is read from the headers of the websocket handshake response. Currently the
+/// server only reports whether this client version is deprecated. Other
+/// headers are kept in [`DeploymentInfo::headers`], so capabilities the server
+/// starts advertising can be read before they're modeled here.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DeploymentInfo {
+    /// The deprecation state of this client version, e.g. `Deprecated`, from
+    /// the `x-convex-deprecation-state` header.
+    pub deprecation_state: Option<String>,
+    /// Why this client version is deprecated and what to upgrade to, from the
+    /// `x-convex-deprecation-message` header.
+    pub deprecation_message: Option<String>,
+    /// Every header of the handshake response with a UTF-8 value, keyed by
+    /// lowercase name.
+    pub headers: BTreeMap<String, String>,
+}
+
+/// A summary of the latest transition applied by the client, as returned by
+/// [`BaseConvexClient::last_transition`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TransitionInfo {
+    /// The version the transition was applied on top of.
+    pub start_version: StateVersion,
+    /// The version after the transition.
+    pub end_version: StateVersion,
+    /// The queries the transition updated, failed or removed, in the order
+    /// the server sent them.
+    pub affected_queries: Vec<QueryId>,
+}
+
+/// How long it took for a mutation's write to become visible to the client's
+/// subscriptions, as passed to the callback set with
+/// [`BaseConvexClient::set_on_write_latency`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WriteLatency {
+    /// The ID of the mutation's request.
+    pub request_id: SessionRequestSeqNumber,
+    /// The timestamp the mutation committed at.
+    pub ts: Timestamp,
+    /// The wall-clock time from receiving the mutation's response to
+    /// applying the first transition at or beyond `ts`.
+    pub latency: Duration,
+}
+
+/// A mutation's result, with the timestamp its writes committed at, as
+/// returned by [`BaseConvexClient::mutation_with_ts`].
+///
+/// A mutation that ran but didn't write anything has no commit timestamp:
+/// there's nothing for the client to catch up on, so its queries already
+/// reflect it. Failed mutations have no timestamp either.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MutationOutcome {
+    /// The value the mutation returned, or its error message.
+    pub result: FunctionResult,
+    /// `Some(ts)` if the mutation's writes committed at `ts`, and `None` if it
+    /// didn't write.
+    ///
+    /// To read your own writes, wait for this timestamp with
+    /// [`ConvexClient::await_timestamp`](crate::ConvexClient::await_timestamp),
+    /// which returns immediately for `None`.
+    pub ts: Option<Timestamp>,
+}
+
+pub(crate) type WriteLatencyCallback = Box<dyn Fn(&WriteLatency) + Send>;
+
+/// Where a mutation or action goes in the outgoing message queue, e.g. to
+/// keep a latency-sensitive mutation in an interactive app from waiting
+/// behind a burst of subscriptions.
+///
+/// This only reorders messages that are waiting to be sent. It doesn't change
+/// how the server schedules requests, or how responses are handled: the
+/// client already handles server messages before new requests.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd)]
+pub enum RequestPriority {
+    /// Send after every message that's already queued.
+    #[default]
+    Normal,
+    /// Send ahead of queued query set modifications, i.e. subscribes and
+    /// unsubscribes. Queued mutations, actions and authentication changes are
+    /// still sent first, so that mutations execute in the order they were
+    /// called, under the identity they were called with.
+    ///
+    /// Requests resent after a reconnect go in their usual order.
+    High,
+}
+
+/// How soon a query is re-subscribed after a reconnect, e.g. to get the
+/// queries of the current screen back before background ones.
+///
+/// After a reconnect, the client re-adds all of its queries in a single query
+/// set modification, with higher priority queries first, and queries of the
+/// same priority in their usual order, so that the server gets to the
+/// important ones first. The priority has no effect while connected: new
+/// subscriptions are sent in the order they're made.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd)]
+pub enum QueryPriority {
+    /// Re-subscribe after every other query.
+    Low,
+    /// Re-subscribe after high priority queries.
+    #[default]
+    Normal,
+    /// Re-subscribe before every other query.
+    High,
+}
+
 #[derive(Clone, Eq, PartialEq, PartialOrd, Ord, Debug)]
 struct QueryToken(String);
 
@@ -60,23 +200,21 @@ struct LocalQuery {
     id: QueryId,
     canonicalized_udf_path: CanonicalizedUdfPath,
     args: BTreeMap<String, Value>,
+    /// The journal to resume the query from when re-adding it on reconnect:
+    /// the one it was subscribed with, until the server sends a newer one.
+    journal: Option<String>,
+    priority: QueryPriority,
     num_subscribers: usize, // TODO: remove
 }
 
-#[derive(Clone, Debug)]
-struct Query {
-    result: FunctionResult,
-    _udf_path: CanonicalizedUdfPath,
-    _args: BTreeMap<String, Value>,
-}
-
 /// An identifier for a single subscriber to a query.
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq, PartialOrd, Ord)]
 #[cfg_attr(test, derive(proptest_derive::Arbitrary))]
 pub struct SubscriberId(QueryId, usize);
 
 impl SubscriberId {
-    #[cfg(test)]
+    /// The query this subscriber is subscribed to, e.g. to look it up in
+    /// [`TransitionInfo::affected_queries`].
     pub fn query_id(&self) -> QueryId {
         self.0
     }
@@ -92,6 +230,14 @@ fn serialize_path_and_args(udf_path: UdfPath, args: BTreeMap<String, Value>) ->
     QueryToken(json.to_string())
 }
 
+/// A query to subscribe to: its function, arguments, journal and priority.
+type NewQuery = (
+    UdfPath,
+    BTreeMap<String, Value>,
+    Option<String>,
+    QueryPriority,
+);
+
 #[derive(Clone, Default)]
 struct LocalSyncState {
     next_query_id: QueryId,
@@ -109,49 +255,74 @@ impl LocalSyncState {
         udf_path: UdfPath,
         args: BTreeMap<String, Value>,
     ) -> (Option<ClientMessage>, SubscriberId) {
-        let canonicalized_udf_path = udf_path.clone().canonicalize();
-        let query_token = serialize_path_and_args(udf_path.clone(), args.clone());
+        let (message, subscriptions) =
+            self.subscribe_many(vec![(udf_path, args, None, QueryPriority::Normal)]);
+        (message, subscriptions[0])
+    }
+
+    /// Subscribe to all of `queries`, adding the new ones to the query set in
+    /// a single modification. Journals only apply to new queries, and a query
+    /// that's already subscribed keeps the highest priority it was given.
+    fn subscribe_many(
+        &mut self,
+        queries: Vec<NewQuery>,
+    ) -> (Option<ClientMessage>, Vec<SubscriberId>) {
+        let mut modifications = vec![];
+        let mut subscriptions = Vec::with_capacity(queries.len());
+        for (udf_path, args, journal, priority) in queries {
+            let canonicalized_udf_path = udf_path.clone().canonicalize();
+            let query_token = serialize_path_and_args(udf_path.clone(), args.clone());
+
+            if let Some(existing_entry) = self.query_set.get_mut(&query_token) {
+                existing_entry.num_subscribers += 1;
+                existing_entry.priority = cmp::max(existing_entry.priority, priority);
+                let query_id = existing_entry.id;
+                let subscription = SubscriberId(query_id, existing_entry.num_subscribers - 1);
+                let prev = self.latest_results.subscribers.insert(subscription);
+                assert!(prev.is_none(), "INTERNAL BUG: Subscriber ID already taken.");
+                subscriptions.push(subscription);
+                continue;
+            }
+
+            let query_id = self.next_query_id;
+            self.next_query_id = QueryId::new(self.next_query_id.get_id() + 1);
 
-        if let Some(existing_entry) = self.query_set.get_mut(&query_token) {
-            existing_entry.num_subscribers += 1;
-            let query_id = existing_entry.id;
-            let subscription = SubscriberId(query_id, existing_entry.num_subscribers - 1);
+            modifications.push(QuerySetModification::Add(convex_sync_types::Query {
+                query_id,
+                udf_path,
+                args: Value::json_args_from_values(vec![Value::Object(args.clone())]),
+                journal: journal.clone().map(Some),
+            }));
+
+            let query = LocalQuery {
+                id: query_id,
+                canonicalized_udf_path,
+                args,
+                journal,
+                priority,
+                num_subscribers: 1,
+            };
+
+            self.query_set.insert(query_token.clone(), query);
+            self.query_id_to_token.insert(query_id, query_token.clone());
+            let subscription = SubscriberId(query_id, 0);
             let prev = self.latest_results.subscribers.insert(subscription);
             assert!(prev.is_none(), "INTERNAL BUG: Subscriber ID already taken.");
-            return (None, subscription);
+            subscriptions.push(subscription);
+        }
+        if modifications.is_empty() {
+            return (None, subscriptions);
         }
 
-        let query_id = self.next_query_id;
-        self.next_query_id = QueryId::new(self.next_query_id.get_id() + 1);
         let base_version = self.query_set_version;
         self.query_set_version += 1;
         let new_version = self.query_set_version;
-
-        let add = QuerySetModification::Add(convex_sync_types::Query {
-            query_id,
-            udf_path,
-            args: vec![Value::Object(args.clone()).into()],
-            journal: None,
-        });
         let message = ClientMessage::ModifyQuerySet {
             base_version,
             new_version,
-            modifications: vec![add],
-        };
-
-        let query = LocalQuery {
-            id: query_id,
-            canonicalized_udf_path,
-            args,
-            num_subscribers: 1,
+            modifications,
         };
-
-        self.query_set.insert(query_token.clone(), query);
-        self.query_id_to_token.insert(query_id, query_token.clone());
-        let subscription = SubscriberId(query_id, 0);
-        let prev = self.latest_results.subscribers.insert(subscription);
-        assert!(prev.is_none(), "INTERNAL BUG: Subscriber ID already taken.");
-        (Some(message), subscription)
+        (Some(message), subscriptions)
     }
 
     fn remove_subscriber(&mut self, subscriber_id: SubscriberId) -> Option<ClientMessage> {
@@ -165,7 +336,7 @@ impl LocalSyncState {
             None => panic!("INTERNAL BUG: Unknown query id {query_id}"),
             Some(t) => t,
         };
-        let mut local_query = match self.query_set.get_mut(&query_token) {
+        let local_query = match self.query_set.get_mut(&query_token) {
             None => panic!("INTERNAL BUG: No query found for query token {query_token:?}",),
             Some(q) => q,
         };
@@ -194,15 +365,6 @@ impl LocalSyncState {
         self.query_id_to_token.get(&query_id).cloned()
     }
 
-    fn query_args(&self, query_id: QueryId) -> Option<BTreeMap<String, Value>> {
-        Some(
-            self.query_set
-                .get(&self.query_token(query_id)?)?
-                .args
-                .clone(),
-        )
-    }
-
     fn query_path(&self, query_id: QueryId) -> Option<CanonicalizedUdfPath> {
         Some(
             self.query_set
@@ -212,6 +374,27 @@ impl LocalSyncState {
         )
     }
 
+    /// Keep the latest journal of each updated query, for [`Self::restart`].
+    fn record_journals(&mut self, modifications: &[StateModification<Value>]) {
+        for modification in modifications {
+            let (StateModification::QueryUpdated {
+                query_id, journal, ..
+            }
+            | StateModification::QueryFailed {
+                query_id, journal, ..
+            }) = modification
+            else {
+                continue;
+            };
+            let Some(query_token) = self.query_id_to_token.get(query_id) else {
+                continue;
+            };
+            if let Some(local_query) = self.query_set.get_mut(query_token) {
+                local_query.journal = journal.clone();
+            }
+        }
+    }
+
     fn set_auth(&mut self, token: AuthenticationToken) -> ClientMessage {
         self.auth_token = token.clone();
         let base_version = self.identity_version;
@@ -224,12 +407,14 @@ impl LocalSyncState {
 
     fn restart(&mut self) -> Vec<ClientMessage> {
         let mut modifications = Vec::new();
-        for local_query in self.query_set.values() {
+        let mut local_queries: Vec<_> = self.query_set.values().collect();
+        local_queries.sort_by_key(|local_query| cmp::Reverse(local_query.priority));
+        for local_query in local_queries {
             let add = QuerySetModification::Add(convex_sync_types::Query {
                 query_id: local_query.id,
                 udf_path: local_query.canonicalized_udf_path.clone().into(),
-                args: vec![Value::Object(local_query.args.clone()).into()],
-                journal: None,
+                args: Value::json_args_from_values(vec![Value::Object(local_query.args.clone())]),
+                journal: local_query.journal.clone().map(Some),
             });
             modifications.push(add)
         }
@@ -273,7 +458,8 @@ impl RemoteQuerySet {
             start_version,
             end_version,
             modifications,
-        } = transition else {
+        } = transition
+        else {
             panic!("not transition");
         };
         if start_version != self.version {
@@ -285,6 +471,14 @@ impl RemoteQuerySet {
             );
             return Err("StartVersionMismatch".into());
         }
+        if end_version.ts < start_version.ts {
+            tracing::error!(
+                "Protocol Error: transition went back in time from {:?} to {:?}",
+                start_version.ts,
+                end_version.ts
+            );
+            return Err("TimestampRegression".into());
+        }
         for modification in modifications {
             match modification {
                 StateModification::QueryUpdated {
@@ -315,36 +509,56 @@ impl RemoteQuerySet {
     }
 }
 
-#[derive(Default, Debug)]
+/// The optimistic results of the subscribed queries, i.e. the server's results
+/// with any optimistic updates applied, kept in the [`ValueStore`].
+///
+/// The store isn't the only copy of the results: the client also keeps the
+/// server's results in the [`RemoteQuerySet`] to compare against, and the last
+/// results handed to subscribers in its [`QueryResults`].
 struct OptimisticQueryResults {
-    query_results: BTreeMap<QueryId, Query>,
+    store: Box<dyn ValueStore + Send>,
+    /// The queries with an entry in the store.
+    query_ids: BTreeSet<QueryId>,
 }
 
 impl OptimisticQueryResults {
+    fn new(store: Box<dyn ValueStore + Send>) -> Self {
+        Self {
+            store,
+            query_ids: BTreeSet::new(),
+        }
+    }
+
+    /// Make the store match the server's results, returning the ones that
+    /// changed.
+    ///
+    /// Each result is compared with the store's, so a server result replaces
+    /// an optimistic value that differs from it even if the server's result
+    /// itself didn't change.
     fn ingest_query_results_from_server(
         &mut self,
-        server_query_results: BTreeMap<QueryId, Query>,
+        server_query_results: BTreeMap<QueryId, &FunctionResult>,
         _optimistic_updates_to_drop: BTreeSet<RequestId>,
     ) -> BTreeMap<QueryId, FunctionResult> {
         // TODO: use optimistic_updates_to_drop
-        let old_query_results = self.query_results.clone();
-        self.query_results = server_query_results;
+        let mut old_query_ids = mem::take(&mut self.query_ids);
         let mut changed_queries = BTreeMap::new();
-        for (query_id, query) in self.query_results.iter() {
-            let old_query = old_query_results.get(query_id);
-            if match old_query {
-                Some(old_query) => old_query.result != query.result,
-                None => true,
-            } {
-                let result = query.result.clone();
-                changed_queries.insert(*query_id, result);
+        for (query_id, result) in server_query_results {
+            old_query_ids.remove(&query_id);
+            self.query_ids.insert(query_id);
+            if !self.store.contains(query_id, result) {
+                self.store.set(query_id, result.clone());
+                changed_queries.insert(query_id, result.clone());
             }
         }
+        for query_id in old_query_ids {
+            self.store.remove(query_id);
+        }
         changed_queries
     }
 
     fn query_result(&self, query_id: QueryId) -> Option<FunctionResult> {
-        self.query_results.get(&query_id).map(|q| q.result.clone())
+        self.store.get(query_id)
     }
 }
 
@@ -366,7 +580,7 @@ impl OptimisticQueryResults {
 /// The main methods, [`subscribe`](Self::subscribe()),
 /// [`unsubscribe`](Self::unsubscribe()), and
 /// [`mutation`](Self::mutation()) directly correspond to its
-/// equivalent for the external [ConvexClient](crate::ConvexClient).
+/// equivalent for the external [`ConvexClient`].
 ///
 /// The only different method is [`get_query`](Self::get_query()), which
 /// returns the current value for a query given its query id. This method can be
@@ -421,31 +635,98 @@ pub struct BaseConvexClient {
     remote_query_set: RemoteQuerySet,
     optimistic_query_results: OptimisticQueryResults,
     request_manager: RequestManager,
-    next_request_id: SessionRequestSeqNumber,
+    request_ids: Box<dyn RequestIdAllocator + Send>,
     outgoing_message_queue: VecDeque<ClientMessage>,
     max_observed_timestamp: Option<Timestamp>,
+    last_transition: Option<TransitionInfo>,
+    next_result_update: u64,
+    deployment_info: Option<DeploymentInfo>,
+    on_write_latency: Option<WriteLatencyCallback>,
+    pending_writes: Vec<(SessionRequestSeqNumber, Timestamp, tokio::time::Instant)>,
+    /// Measures write latencies.
+    clock: Arc<dyn Clock>,
+    #[cfg(feature = "unstable")]
+    raw_messages: broadcast::Sender<ServerMessage>,
+}
+
+impl Default for BaseConvexClient {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl BaseConvexClient {
     /// Construct a new [`BaseConvexClient`].
     pub fn new() -> Self {
+        Self::with_value_store(BTreeMap::<QueryId, FunctionResult>::new())
+    }
+
+    /// Construct a new [`BaseConvexClient`] that keeps query results in
+    /// `store`. See [`ValueStore`] for the rules the store must follow.
+    pub fn with_value_store(store: impl ValueStore + Send + 'static) -> Self {
         let request_manager = RequestManager::new();
         let state = LocalSyncState::default();
         let remote_query_set = RemoteQuerySet::new();
-        let optimistic_query_results: OptimisticQueryResults = Default::default();
-        let next_request_id: SessionRequestSeqNumber = 0;
+        let optimistic_query_results = OptimisticQueryResults::new(Box::new(store));
 
         BaseConvexClient {
             request_manager,
             state,
             remote_query_set,
             optimistic_query_results,
-            next_request_id,
+            request_ids: Box::new(SessionRequestSeqNumber::default()),
             outgoing_message_queue: VecDeque::new(),
             max_observed_timestamp: None,
+            last_transition: None,
+            next_result_update: 0,
+            deployment_info: None,
+            on_write_latency: None,
+            pending_writes: vec![],
+            clock: Arc::new(SystemClock),
+            #[cfg(feature = "unstable")]
+            raw_messages: broadcast::channel(RAW_MESSAGES_CAPACITY).0,
         }
     }
 
+    /// Draw the request IDs of new mutations and actions from `request_ids`,
+    /// e.g. a [`SharedRequestIds`] shared with the other clients of a
+    /// connection. See [`RequestIdAllocator`] for the requirements.
+    ///
+    /// Call this before sending any mutation or action: the IDs of requests
+    /// already made aren't checked against the new allocator.
+    pub fn set_request_id_allocator(
+        &mut self,
+        request_ids: impl RequestIdAllocator + Send + 'static,
+    ) {
+        self.request_ids = Box::new(request_ids);
+    }
+
+    /// Call `callback` with the write-to-read latency of every successful
+    /// mutation: the time from receiving its response, with the timestamp it
+    /// committed at, to applying the first transition at or beyond that
+    /// timestamp, i.e. until subscriptions reflect the write. Off by default.
+    ///
+    /// The attribution is best-effort, by timestamp only: the transition
+    /// doesn't have to update a query the mutation wrote to, or any query at
+    /// all. If the client has already applied a transition at or beyond the
+    /// mutation's timestamp when its response arrives, it's reported right
+    /// away, with a latency of about zero.
+    /// Mutations pending across a reconnect are reported once the new
+    /// connection catches up to their timestamp, so their latency includes
+    /// the time spent reconnecting.
+    ///
+    /// The callback runs inside [`receive_message`](Self::receive_message()),
+    /// so it should be fast.
+    pub fn set_on_write_latency(&mut self, callback: impl Fn(&WriteLatency) + Send + 'static) {
+        self.on_write_latency = Some(Box::new(callback));
+    }
+
+    /// Measure write latencies with `clock`, the client's [`Clock`], instead
+    /// of the real time.
+    pub(crate) fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
     /// Update state to be subscribed to a query and add subscription request to
     /// the outgoing message queue.
     ///
@@ -460,6 +741,58 @@ impl BaseConvexClient {
         subscription
     }
 
+    /// Update state to be subscribed to all of `queries` and add a single
+    /// subscription request for them to the outgoing message queue.
+    ///
+    /// The server reports the result of each query separately, so a query
+    /// that fails (e.g. because its arguments don't validate) gets a
+    /// [`FunctionResult::ErrorMessage`] while the others get their values.
+    ///
+    /// After calling this, it is highly recommended to loop on
+    /// [`pop_next_message`](Self::pop_next_message()) to flush websocket
+    /// messages to the server.
+    pub fn subscribe_many(
+        &mut self,
+        queries: Vec<(UdfPath, BTreeMap<String, Value>)>,
+    ) -> Vec<SubscriberId> {
+        let queries = queries
+            .into_iter()
+            .map(|(udf_path, args)| (udf_path, args, None, QueryPriority::Normal))
+            .collect();
+        let (modification, subscriptions) = self.state.subscribe_many(queries);
+        if let Some(modification) = modification {
+            self.outgoing_message_queue.push_back(modification);
+        }
+        subscriptions
+    }
+
+    /// Like [`subscribe_many`](Self::subscribe_many()), but with full control
+    /// over each query, e.g. to resume it from a journal. See
+    /// [`QueryBuilder`] for which fields are set by the caller and which are
+    /// managed by the client.
+    ///
+    /// Fails without subscribing to any of the queries if a name is not a
+    /// valid function path, or arguments have field names Convex doesn't
+    /// accept, as checked by [`Value::check_field_names`] with system fields
+    /// allowed.
+    pub fn subscribe_queries(
+        &mut self,
+        queries: Vec<QueryBuilder>,
+    ) -> anyhow::Result<Vec<SubscriberId>> {
+        let queries = queries
+            .into_iter()
+            .map(|query| {
+                check_arg_names(&query.args)?;
+                Ok((query.udf_path()?, query.args, query.journal, query.priority))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let (modification, subscriptions) = self.state.subscribe_many(queries);
+        if let Some(modification) = modification {
+            self.outgoing_message_queue.push_back(modification);
+        }
+        Ok(subscriptions)
+    }
+
     /// Update state to be unsubscribed to a query and add unsubscription
     /// request to the outgoing message queue.
     ///
@@ -489,13 +822,23 @@ impl BaseConvexClient {
         udf_path: UdfPath,
         args: BTreeMap<String, Value>,
     ) -> oneshot::Receiver<FunctionResult> {
-        let request_id = self.next_request_id;
-        self.next_request_id = request_id + 1;
+        self.mutation_with_priority(udf_path, args, RequestPriority::Normal)
+    }
+
+    /// Like [`mutation`](Self::mutation()), but queued according to
+    /// `priority`.
+    pub fn mutation_with_priority(
+        &mut self,
+        udf_path: UdfPath,
+        args: BTreeMap<String, Value>,
+        priority: RequestPriority,
+    ) -> oneshot::Receiver<FunctionResult> {
+        let request_id = self.request_ids.next_request_id();
         tracing::info!("Starting mutation {udf_path} with id {request_id}");
         let message = ClientMessage::Mutation {
             request_id,
             udf_path,
-            args: vec![Value::Object(args).into()],
+            args: Value::json_args_from_values(vec![Value::Object(args)]),
         };
 
         let result_receiver = self.request_manager.track_request(
@@ -503,10 +846,38 @@ impl BaseConvexClient {
             RequestId::new(request_id),
             RequestType::Mutation,
         );
-        self.outgoing_message_queue.push_back(message);
+        self.push_request(message, priority);
         result_receiver
     }
 
+    /// Like [`mutation_with_priority`](Self::mutation_with_priority()), but
+    /// the result comes with the timestamp the mutation committed at, if it
+    /// wrote anything.
+    ///
+    /// The result is delivered once the client's queries reflect the
+    /// mutation: after the first transition at or beyond its timestamp, or
+    /// right away if it didn't write.
+    pub fn mutation_with_ts(
+        &mut self,
+        udf_path: UdfPath,
+        args: BTreeMap<String, Value>,
+        priority: RequestPriority,
+    ) -> oneshot::Receiver<MutationOutcome> {
+        let request_id = self.request_ids.next_request_id();
+        tracing::info!("Starting mutation {udf_path} with id {request_id}");
+        let message = ClientMessage::Mutation {
+            request_id,
+            udf_path,
+            args: Value::json_args_from_values(vec![Value::Object(args)]),
+        };
+
+        let outcome_receiver = self
+            .request_manager
+            .track_mutation_outcome(&message, RequestId::new(request_id));
+        self.push_request(message, priority);
+        outcome_receiver
+    }
+
     /// Track action and add action request to the outgoing message queue.
     ///
     /// After calling this, it is highly recommended to loop on
@@ -517,13 +888,22 @@ impl BaseConvexClient {
         udf_path: UdfPath,
         args: BTreeMap<String, Value>,
     ) -> oneshot::Receiver<FunctionResult> {
-        let request_id = self.next_request_id;
-        self.next_request_id = request_id + 1;
+        self.action_with_priority(udf_path, args, RequestPriority::Normal)
+    }
+
+    /// Like [`action`](Self::action()), but queued according to `priority`.
+    pub fn action_with_priority(
+        &mut self,
+        udf_path: UdfPath,
+        args: BTreeMap<String, Value>,
+        priority: RequestPriority,
+    ) -> oneshot::Receiver<FunctionResult> {
+        let request_id = self.request_ids.next_request_id();
         tracing::info!("Starting action {udf_path:?} with id {request_id:?}");
         let message = ClientMessage::Action {
             request_id,
             udf_path,
-            args: vec![Value::Object(args).into()],
+            args: Value::json_args_from_values(vec![Value::Object(args)]),
         };
 
         let result_receiver = self.request_manager.track_request(
@@ -531,10 +911,24 @@ impl BaseConvexClient {
             RequestId::new(request_id),
             RequestType::Action,
         );
-        self.outgoing_message_queue.push_back(message);
+        self.push_request(message, priority);
         result_receiver
     }
 
+    /// Queue a mutation or action: high-priority requests go right after the
+    /// last queued message that isn't a query set modification.
+    fn push_request(&mut self, message: ClientMessage, priority: RequestPriority) {
+        let index = match priority {
+            RequestPriority::Normal => self.outgoing_message_queue.len(),
+            RequestPriority::High => self
+                .outgoing_message_queue
+                .iter()
+                .rposition(|message| !matches!(message, ClientMessage::ModifyQuerySet { .. }))
+                .map_or(0, |index| index + 1),
+        };
+        self.outgoing_message_queue.insert(index, message);
+    }
+
     /// Set auth on the sync protocol.
     pub fn set_auth(&mut self, token: AuthenticationToken) {
         let message = self.state.set_auth(token);
@@ -550,6 +944,30 @@ impl BaseConvexClient {
         self.outgoing_message_queue.pop_front()
     }
 
+    /// **Unstable:** queue `message` to be sent exactly as given, e.g. to
+    /// experiment with the protocol.
+    ///
+    /// The message bypasses the client's state: sending a message that
+    /// changes the protocol's state, like a `ModifyQuerySet`, `Mutation` or
+    /// `Authenticate`, desynchronizes the client from the server, with
+    /// undefined results for the rest of the API. The client resends none of
+    /// these messages after a reconnect.
+    #[cfg(feature = "unstable")]
+    pub fn push_raw_message(&mut self, message: ClientMessage) {
+        self.outgoing_message_queue.push_back(message);
+    }
+
+    /// **Unstable:** receive a copy of every message passed to
+    /// [`receive_message`](Self::receive_message()) from now on, before it's
+    /// applied.
+    ///
+    /// A receiver that falls more than 256 messages behind skips the oldest
+    /// ones.
+    #[cfg(feature = "unstable")]
+    pub fn raw_messages(&self) -> broadcast::Receiver<ServerMessage> {
+        self.raw_messages.subscribe()
+    }
+
     fn observe_timestamp(&mut self, ts: Timestamp) {
         if let Some(max_observed_timestamp) = self.max_observed_timestamp {
             self.max_observed_timestamp = Some(cmp::max(ts, max_observed_timestamp));
@@ -564,22 +982,105 @@ impl BaseConvexClient {
     }
 
     /// Given a message from a Server, update the base state accordingly.
+    ///
+    /// Returns the latest results if a transition changed the result of a
+    /// subscribed query, or resynced it after a reconnect. A transition
+    /// without modifications that changes no result is a heartbeat, e.g. one
+    /// the server sends only to advance the timestamp: it updates
+    /// [`last_transition`](Self::last_transition) and completes the
+    /// mutations it covers, but returns `None`, so that subscribers aren't
+    /// woken for nothing.
+    ///
+    /// Timestamps never go back within a connection: each transition starts
+    /// at the version the previous one ended at, and its `end_version.ts` is
+    /// at least its `start_version.ts`. A transition that violates this isn't
+    /// applied. It fails with the reason `"TimestampRegression"` instead,
+    /// like any protocol error, so that the client reconnects and resyncs
+    /// its queries. The client can't resync its way out of a timestamp that
+    /// goes back across a reconnect, e.g. because the deployment was
+    /// restored from a backup, so that's only logged.
     pub fn receive_message(
         &mut self,
         message: ServerMessage,
     ) -> Result<Option<QueryResults>, ReconnectProtocolReason> {
+        #[cfg(feature = "unstable")]
+        if self.raw_messages.receiver_count() > 0 {
+            let _ = self.raw_messages.send(message.clone());
+        }
         match message {
-            ServerMessage::Transition { end_version, .. } => {
-                self.observe_timestamp(end_version.ts);
+            ServerMessage::Transition {
+                start_version,
+                end_version,
+                ref modifications,
+            } => {
+                let is_empty = modifications.is_empty();
+                let affected_queries = modifications
+                    .iter()
+                    .map(|modification| match modification {
+                        StateModification::QueryUpdated { query_id, .. }
+                        | StateModification::QueryFailed { query_id, .. }
+                        | StateModification::QueryRemoved { query_id } => *query_id,
+                    })
+                    .collect();
+                self.state.record_journals(modifications);
                 self.remote_query_set.transition(message)?;
+                if let Some(last_transition) = &self.last_transition {
+                    if end_version.ts < last_transition.end_version.ts {
+                        tracing::warn!(
+                            "Timestamp went back from {:?} to {:?} across a reconnect. The \
+                             deployment may have been restored from an earlier state.",
+                            last_transition.end_version.ts,
+                            end_version.ts
+                        );
+                    }
+                }
+                self.observe_timestamp(end_version.ts);
+                self.last_transition = Some(TransitionInfo {
+                    start_version,
+                    end_version,
+                    affected_queries,
+                });
+                self.report_write_latencies(end_version.ts);
                 let completed_requests = self
                     .request_manager
                     .remove_and_notify_completed(end_version.ts);
-                let changed_query_ids = self.on_query_result_changes(completed_requests)?;
-                for (id, result) in changed_query_ids {
-                    self.state.latest_results.results.insert(id, result);
+                let mut changed_query_ids = self.on_query_result_changes(completed_requests)?;
+                // The remote query set only holds results received since the
+                // latest reconnect, so a subscribed query whose version predates
+                // it has just been resynced, even if its result is unchanged.
+                let connection = self.state.latest_results.connection;
+                let updated_query_ids: Vec<QueryId> = self
+                    .remote_query_set
+                    .remote_query_set
+                    .keys()
+                    .filter(|query_id| {
+                        changed_query_ids.contains_key(query_id)
+                            || (self.state.query_token(**query_id).is_some()
+                                && self
+                                    .state
+                                    .latest_results
+                                    .versions
+                                    .get(query_id)
+                                    .is_some_and(|version| version.connection != connection))
+                    })
+                    .copied()
+                    .collect();
+                if is_empty && updated_query_ids.is_empty() {
+                    return Ok(None);
                 }
-                return Ok(Some(self.state.latest_results.clone()));
+                let latest_results = &mut self.state.latest_results;
+                for query_id in updated_query_ids {
+                    if let Some(result) = changed_query_ids.remove(&query_id) {
+                        latest_results.results.insert(query_id, result);
+                    }
+                    let version = ResultVersion {
+                        connection,
+                        update: self.next_result_update,
+                    };
+                    latest_results.versions.insert(query_id, version);
+                    self.next_result_update += 1;
+                }
+                return Ok(Some(latest_results.clone()));
             },
             ServerMessage::QueriesFailed { failures } => {
                 // Note that we never expect to receive this as it is not sent by the server.
@@ -595,10 +1096,21 @@ impl BaseConvexClient {
                 request_id,
                 result,
                 ts,
-                log_lines: _,
+                log_lines,
             } => {
+                for line in log_lines {
+                    tracing::debug!("Mutation {request_id}: {line}");
+                }
                 if let Some(ts) = ts {
                     self.observe_timestamp(ts);
+                    if self.on_write_latency.is_some() && result.is_ok() {
+                        self.pending_writes.push((request_id, ts, self.clock.now()));
+                        if let Some(last_ts) =
+                            self.last_transition.as_ref().map(|t| t.end_version.ts)
+                        {
+                            self.report_write_latencies(last_ts);
+                        }
+                    }
                 }
                 let request_id = RequestId::new(request_id);
                 self.request_manager.update_request(
@@ -627,8 +1139,13 @@ impl BaseConvexClient {
             ServerMessage::ActionResponse {
                 request_id,
                 result,
-                log_lines: _,
+                log_lines,
             } => {
+                // The protocol has no messages for an action that's still
+                // running, so its log lines only arrive with its response.
+                for line in log_lines {
+                    tracing::debug!("Action {request_id}: {line}");
+                }
                 let request_id = RequestId::new(request_id);
                 self.request_manager.update_request(
                     &request_id,
@@ -649,13 +1166,100 @@ impl BaseConvexClient {
         &self.state.latest_results
     }
 
+    /// Get the current result of every subscribed query, along with the
+    /// canonicalized path of its function.
+    ///
+    /// Results are those of the latest applied transition. Since transitions
+    /// are applied atomically, the results are consistent with each other.
+    /// Queries that don't have a result yet are left out.
+    pub fn snapshot(&self) -> QuerySnapshot {
+        self.state
+            .query_set
+            .values()
+            .filter_map(|query| {
+                let result = self.state.latest_results.results.get(&query.id)?;
+                let udf_path = query.canonicalized_udf_path.clone().into();
+                Some((query.id, (udf_path, result.clone().into())))
+            })
+            .collect()
+    }
+
+    /// The function and arguments of every subscribed query, in the order
+    /// they were first subscribed, e.g. to persist the query set across
+    /// restarts.
+    pub fn subscriptions(&self) -> Vec<(UdfPath, BTreeMap<String, Value>)> {
+        self.state
+            .query_id_to_token
+            .values()
+            .filter_map(|token| self.state.query_set.get(token))
+            .map(|query| {
+                (
+                    query.canonicalized_udf_path.clone().into(),
+                    query.args.clone(),
+                )
+            })
+            .collect()
+    }
+
+    /// Whether every listed query has a result from the latest applied
+    /// transition, so that their results are consistent with each other.
+    ///
+    /// Fails if one of the queries isn't subscribed.
+    pub fn is_consistent(&self, query_ids: &[QueryId]) -> anyhow::Result<bool> {
+        for query_id in query_ids {
+            anyhow::ensure!(
+                self.state.query_token(*query_id).is_some(),
+                "Query {query_id:?} isn't subscribed"
+            );
+        }
+        Ok(query_ids.iter().all(|query_id| {
+            self.remote_query_set
+                .remote_query_set
+                .contains_key(query_id)
+        }))
+    }
+
+    /// Record what the deployment reported about itself when the client
+    /// (re)connected, replacing what it reported before.
+    pub fn set_deployment_info(&mut self, info: DeploymentInfo) {
+        self.deployment_info = Some(info);
+    }
+
+    /// Get what the deployment reported about itself when the client last
+    /// connected, or `None` if it never connected.
+    pub fn deployment_info(&self) -> Option<&DeploymentInfo> {
+        self.deployment_info.as_ref()
+    }
+
+    /// Get a summary of the latest transition applied by the client, or `None`
+    /// if no transition was applied yet.
+    ///
+    /// This is a debugging aid, e.g. to check whether the server sent an
+    /// update for a query that didn't change as expected.
+    pub fn last_transition(&self) -> Option<&TransitionInfo> {
+        self.last_transition.as_ref()
+    }
+
     /// Resend all subscribed queries and ongoing mutations. Should be used once
     /// the websocket closes and reconnects.
+    ///
+    /// If the client is authenticated, the current token is resent first, so
+    /// the server runs the resubscribed queries under the same identity as
+    /// before the reconnect, never unauthenticated. Messages that were queued
+    /// but not yet sent are dropped: they're relative to the old connection's
+    /// versions, and the resent state already includes them.
+    ///
+    /// Results received before the reconnect stay in
+    /// [`latest_results`](Self::latest_results) until they're resynced. Each
+    /// query's first result after the reconnect gets a new version even if
+    /// it's unchanged, so that subscriptions deliver it exactly once.
     pub fn resend_ongoing_queries_mutations(&mut self) {
         let state_restart_messages = self.state.restart();
         let mut ongoing_mutation_messages = self.request_manager.restart();
 
         self.remote_query_set = RemoteQuerySet::new();
+        self.state.latest_results.connection += 1;
+        self.outgoing_message_queue.clear();
         for state_restart_message in state_restart_messages {
             self.outgoing_message_queue.push_back(state_restart_message);
         }
@@ -663,39 +1267,214 @@ impl BaseConvexClient {
             .append(&mut ongoing_mutation_messages);
     }
 
+    /// Complete the mutations and actions that the server already executed
+    /// with their results, before dropping the client once the connection is
+    /// closed for good. The rest fail when their result senders are dropped.
+    pub(crate) fn complete_executed_requests(&mut self) {
+        self.request_manager.complete_executed();
+    }
+
     fn on_query_result_changes(
         &mut self,
         completed_requests: BTreeSet<RequestId>,
     ) -> Result<BTreeMap<QueryId, FunctionResult>, ReconnectProtocolReason> {
-        let remote_query_results = &self.remote_query_set.remote_query_set;
-        let mut query_id_to_value = BTreeMap::new();
-        for (query_id, result) in remote_query_results.iter() {
-            let Some(_udf_path) = self
-                    .state
-                    .query_path(*query_id) else {
-                // It's possible that we've already unsubscribed to this query but
-                // the server hasn't learned about that yet. If so, ignore this one.
-                continue;
-            };
-            let _args = self
-                .state
-                .query_args(*query_id)
-                .expect("INTERNAL BUG: Query args exist, but not query path.");
-            query_id_to_value.insert(
-                *query_id,
-                Query {
-                    result: result.clone(),
-                    _udf_path,
-                    _args,
-                },
-            );
-        }
+        let state = &self.state;
+        let server_query_results = self
+            .remote_query_set
+            .remote_query_set
+            .iter()
+            // It's possible that we've already unsubscribed to this query but
+            // the server hasn't learned about that yet. If so, ignore this one.
+            .filter(|(query_id, _)| state.query_path(**query_id).is_some())
+            .map(|(query_id, result)| (*query_id, result))
+            .collect();
         Ok(self
             .optimistic_query_results
-            .ingest_query_results_from_server(query_id_to_value, completed_requests))
+            .ingest_query_results_from_server(server_query_results, completed_requests))
+    }
+
+    /// Report the latency of the pending writes that are visible at `ts`.
+    fn report_write_latencies(&mut self, ts: Timestamp) {
+        let Some(on_write_latency) = &self.on_write_latency else {
+            return;
+        };
+        let now = self.clock.now();
+        self.pending_writes
+            .retain(|&(request_id, write_ts, received_at)| {
+                if write_ts > ts {
+                    return true;
+                }
+                on_write_latency(&WriteLatency {
+                    request_id,
+                    ts: write_ts,
+                    latency: now - received_at,
+                });
+                false
+            });
     }
 
     fn local_query_result(&self, query_id: QueryId) -> Option<FunctionResult> {
         self.optimistic_query_results.query_result(query_id)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            Arc,
+            Mutex,
+        },
+        time::Duration,
+    };
+
+    use convex_sync_types::{
+        StateModification,
+        StateVersion,
+        Timestamp,
+    };
+    use maplit::btreemap;
+
+    use super::{
+        BaseConvexClient,
+        WriteLatency,
+    };
+    use crate::{
+        client::clock::TestClock,
+        sync::ServerMessage,
+        Value,
+    };
+
+    fn transition(start_version: StateVersion, end_ts: Timestamp) -> (ServerMessage, StateVersion) {
+        let end_version = StateVersion {
+            ts: end_ts,
+            ..start_version
+        };
+        let message = ServerMessage::Transition {
+            start_version,
+            end_version,
+            modifications: vec![],
+        };
+        (message, end_version)
+    }
+
+    #[test]
+    fn test_timestamp_regression() -> anyhow::Result<()> {
+        let mut base_client = BaseConvexClient::new();
+        let subscriber_id = base_client.subscribe("getValue".parse()?, btreemap! {});
+        let query_id = subscriber_id.query_id();
+        let ts = Timestamp::MIN.succ()?.succ()?;
+        let version = StateVersion {
+            query_set: 1,
+            ts,
+            ..StateVersion::initial()
+        };
+        base_client
+            .receive_message(ServerMessage::Transition {
+                start_version: StateVersion::initial(),
+                end_version: version,
+                modifications: vec![StateModification::QueryUpdated {
+                    query_id,
+                    value: Value::Int64(1),
+                    journal: None,
+                    log_lines: vec![],
+                }],
+            })
+            .unwrap();
+
+        // A transition that doesn't advance the timestamp is fine, but one that
+        // goes back isn't applied.
+        let (message, _) = transition(version, ts);
+        base_client.receive_message(message).unwrap();
+        let (message, _) = transition(version, ts.pred()?);
+        assert_eq!(
+            base_client.receive_message(message).unwrap_err(),
+            "TimestampRegression"
+        );
+        assert_eq!(
+            base_client.last_transition().map(|t| t.end_version),
+            Some(version)
+        );
+        assert_eq!(base_client.max_observed_timestamp(), Some(ts));
+
+        // After reconnecting, the client starts over from the initial version,
+        // and the server may legitimately be behind.
+        base_client.resend_ongoing_queries_mutations();
+        let (message, version) = transition(StateVersion::initial(), ts.pred()?);
+        base_client.receive_message(message).unwrap();
+        assert_eq!(
+            base_client.last_transition().map(|t| t.end_version),
+            Some(version)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_latency() -> anyhow::Result<()> {
+        let reported = Arc::new(Mutex::new(vec![]));
+        let mut base_client = BaseConvexClient::new();
+        let clock = TestClock::new();
+        base_client.set_clock(Arc::new(clock.clone()));
+        let reported_ = reported.clone();
+        base_client.set_on_write_latency(move |latency: &WriteLatency| {
+            reported_.lock().unwrap().push(latency.clone())
+        });
+        let take_one = || {
+            let mut reported = reported.lock().unwrap();
+            assert_eq!(reported.len(), 1, "{reported:?}");
+            reported.pop().unwrap()
+        };
+
+        let _first = base_client.mutation("send".parse()?, btreemap! {});
+        let _second = base_client.mutation("send".parse()?, btreemap! {});
+        let _failed = base_client.mutation("send".parse()?, btreemap! {});
+        let ts1 = Timestamp::MIN.succ()?;
+        let ts2 = ts1.succ()?;
+        for (request_id, result, ts) in [
+            (0, Ok(Value::Null), ts1),
+            (1, Ok(Value::Null), ts2),
+            (2, Err("Failed".to_string()), ts1),
+        ] {
+            base_client
+                .receive_message(ServerMessage::MutationResponse {
+                    request_id,
+                    result,
+                    ts: Some(ts),
+                    log_lines: vec![],
+                })
+                .unwrap();
+        }
+        clock.advance(Duration::from_millis(50));
+        assert_eq!(*reported.lock().unwrap(), vec![]);
+
+        // Each write is reported by the first transition that reaches its
+        // timestamp. Failed mutations aren't reported.
+        let (message, version) = transition(StateVersion::initial(), ts1);
+        base_client.receive_message(message).unwrap();
+        let first = take_one();
+        assert_eq!((first.request_id, first.ts), (0, ts1));
+        assert_eq!(first.latency, Duration::from_millis(50));
+
+        clock.advance(Duration::from_millis(20));
+        let (message, _) = transition(version, ts2);
+        base_client.receive_message(message).unwrap();
+        let second = take_one();
+        assert_eq!((second.request_id, second.ts), (1, ts2));
+        assert_eq!(second.latency, Duration::from_millis(70));
+
+        // A write the client has already seen is reported right away.
+        let _third = base_client.mutation("send".parse()?, btreemap! {});
+        base_client
+            .receive_message(ServerMessage::MutationResponse {
+                request_id: 3,
+                result: Ok(Value::Null),
+                ts: Some(ts1),
+                log_lines: vec![],
+            })
+            .unwrap();
+        let third = take_one();
+        assert_eq!((third.request_id, third.ts), (3, ts1));
+        assert_eq!(third.latency, Duration::ZERO);
+        Ok(())
+    }
+}
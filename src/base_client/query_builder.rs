@@ -0,0 +1,79 @@
+use std::collections::BTreeMap;
+
+use convex_sync_types::UdfPath;
+
+use super::QueryPriority;
+use crate::value::Value;
+
+/// A query to subscribe to in a batch, with control over the fields of the
+/// protocol's `Query` that the simpler APIs leave to the client.
+///
+/// The caller sets the function name, its arguments and optionally a query
+/// journal. The client manages the rest: it assigns the `QueryId` and the
+/// query set version, and encodes the arguments for the wire.
+///
+/// A journal is opaque state the server returns with each query result, e.g.
+/// to keep the page boundaries of a paginated query stable. Passing one
+/// resumes from it instead of starting fresh. It's sent when the query is
+/// added, and again whenever it's re-added after a reconnect. If an identical
+/// query (same function and arguments) is already subscribed, the new
+/// subscription shares it and the journal is ignored.
+///
+/// A [`QueryPriority`] orders the query's re-subscription after a reconnect.
+///
+/// ```
+/// # use convex::QueryBuilder;
+/// let query = QueryBuilder::new("messages:list")
+///     .arg("channel", "general")
+///     .journal("journal from a previous result".to_string());
+/// ```
+#[derive(Clone, Debug)]
+pub struct QueryBuilder {
+    pub(super) name: String,
+    pub(super) args: BTreeMap<String, Value>,
+    pub(super) journal: Option<String>,
+    pub(super) priority: QueryPriority,
+}
+
+impl QueryBuilder {
+    /// Start building a query to the function `name`, with no arguments.
+    /// An invalid name fails the batch it's subscribed in.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            args: BTreeMap::new(),
+            journal: None,
+            priority: QueryPriority::Normal,
+        }
+    }
+
+    /// Set the argument `name` to `value`, replacing any previous value.
+    pub fn arg(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.args.insert(name.to_string(), value.into());
+        self
+    }
+
+    /// Replace all arguments with `args`.
+    pub fn args(mut self, args: BTreeMap<String, Value>) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Resume the query from `journal`, as returned by the server with an
+    /// earlier result of the same query.
+    pub fn journal(mut self, journal: String) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Re-subscribe the query with `priority` after a reconnect, instead of
+    /// [`QueryPriority::Normal`].
+    pub fn priority(mut self, priority: QueryPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub(super) fn udf_path(&self) -> anyhow::Result<UdfPath> {
+        self.name.parse()
+    }
+}
@@ -46,12 +46,28 @@ impl std::fmt::Debug for FunctionResult {
     }
 }
 
+/// When a query's result was delivered: on which connection, and how many
+/// results the client had delivered before it.
+///
+/// A new version is assigned whenever a query's result changes, and the
+/// first time the query's result is received after a reconnect, even if it's
+/// unchanged.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct ResultVersion {
+    /// The number of reconnects before the result was received.
+    pub(crate) connection: u64,
+    pub(crate) update: u64,
+}
+
 /// A mapping from [`SubscriberId`] to its current result [`FunctionResult`]
 /// for each actively subscribed query.
 #[derive(Clone, Default, Debug)]
 pub struct QueryResults {
     pub(super) results: OrdMap<QueryId, FunctionResult>,
     pub(super) subscribers: OrdSet<SubscriberId>,
+    pub(super) versions: OrdMap<QueryId, ResultVersion>,
+    /// The number of reconnects so far.
+    pub(super) connection: u64,
 }
 
 impl QueryResults {
@@ -63,6 +79,18 @@ impl QueryResults {
         self.results.get(&subscriber_id.0)
     }
 
+    /// Get the result for the given [`SubscriberId`] along with its version,
+    /// unless the result was received before the latest reconnect and hasn't
+    /// been resynced since.
+    pub(crate) fn get_current(
+        &self,
+        subscriber_id: &SubscriberId,
+    ) -> Option<(&FunctionResult, ResultVersion)> {
+        let result = self.get(subscriber_id)?;
+        let version = *self.versions.get(&subscriber_id.0)?;
+        (version.connection == self.connection).then_some((result, version))
+    }
+
     /// Get the size of the map.
     pub fn len(&self) -> usize {
         self.subscribers.len()
@@ -110,6 +138,7 @@ mod tests {
                 s(q(1), 0),
                 s(q(2), 0)
             },
+            ..Default::default()
         };
         assert_eq!(
             qr.get(&s(q(0), 0)),
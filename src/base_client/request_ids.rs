@@ -0,0 +1,100 @@
+use std::sync::{
+    atomic::{
+        AtomicU32,
+        Ordering,
+    },
+    Arc,
+};
+
+use convex_sync_types::SessionRequestSeqNumber;
+
+/// Where a [`BaseConvexClient`](super::BaseConvexClient) gets the request IDs
+/// of its mutations and actions.
+///
+/// The server matches each response to its request by ID, so the IDs of all
+/// requests sent over one websocket session must be distinct. A client with
+/// its own connection satisfies this with its default allocator, a
+/// [`SessionRequestSeqNumber`] counter starting at 0. Clients that share a
+/// connection must draw their IDs from a shared allocator, like
+/// [`SharedRequestIds`]. Set the allocator with
+/// [`BaseConvexClient::set_request_id_allocator`](super::BaseConvexClient::set_request_id_allocator()).
+///
+/// An ID is reused when a request is resent after a reconnect, so an
+/// allocator only needs to produce IDs for new requests.
+pub trait RequestIdAllocator {
+    /// The ID of the next new request.
+    fn next_request_id(&mut self) -> SessionRequestSeqNumber;
+}
+
+/// A counter, for a client with its own connection.
+impl RequestIdAllocator for SessionRequestSeqNumber {
+    fn next_request_id(&mut self) -> SessionRequestSeqNumber {
+        let request_id = *self;
+        *self += 1;
+        request_id
+    }
+}
+
+/// A counter shared by its clones, for clients that share a connection. IDs
+/// start at 0, and every clone gets different ones.
+#[derive(Clone, Debug, Default)]
+pub struct SharedRequestIds(Arc<AtomicU32>);
+
+impl SharedRequestIds {
+    /// A new counter, starting at 0.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RequestIdAllocator for SharedRequestIds {
+    fn next_request_id(&mut self) -> SessionRequestSeqNumber {
+        self.0.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use convex_sync_types::ClientMessage;
+    use maplit::btreemap;
+
+    use super::SharedRequestIds;
+    use crate::base_client::BaseConvexClient;
+
+    #[test]
+    fn test_shared_request_ids() -> anyhow::Result<()> {
+        let request_ids = SharedRequestIds::new();
+        let mut clients: Vec<_> = (0..2)
+            .map(|_| {
+                let mut client = BaseConvexClient::new();
+                client.set_request_id_allocator(request_ids.clone());
+                client
+            })
+            .collect();
+        let mut results = vec![];
+        for i in 0..10 {
+            let client = &mut clients[i % 2];
+            results.push(if i % 3 == 0 {
+                client.action("sendEmail".parse()?, btreemap! {})
+            } else {
+                client.mutation("send".parse()?, btreemap! {})
+            });
+        }
+
+        let mut sent = BTreeSet::new();
+        for client in &mut clients {
+            while let Some(message) = client.pop_next_message() {
+                let (ClientMessage::Mutation { request_id, .. }
+                | ClientMessage::Action { request_id, .. }) = message
+                else {
+                    continue;
+                };
+                assert!(sent.insert(request_id), "Duplicate request ID {request_id}");
+            }
+        }
+        assert_eq!(sent, (0..10).collect());
+        Ok(())
+    }
+}
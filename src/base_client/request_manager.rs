@@ -1,4 +1,5 @@
 use std::{
+    cmp,
     collections::{
         BTreeMap,
         BTreeSet,
@@ -14,6 +15,7 @@ use convex_sync_types::{
 use tokio::sync::oneshot;
 
 use crate::{
+    base_client::MutationOutcome,
     sync::ReconnectProtocolReason,
     FunctionResult,
 };
@@ -69,8 +71,15 @@ impl Request {
     }
 }
 
+/// Where a request's result is sent once it completes.
+pub enum ResultSender {
+    Result(oneshot::Sender<FunctionResult>),
+    /// A mutation's result, with the timestamp it committed at.
+    Outcome(oneshot::Sender<MutationOutcome>),
+}
+
 pub struct RequestManager {
-    ongoing_requests: BTreeMap<RequestId, (Request, oneshot::Sender<FunctionResult>)>,
+    ongoing_requests: BTreeMap<RequestId, (Request, ResultSender)>,
 }
 
 impl RequestManager {
@@ -98,8 +107,9 @@ impl RequestManager {
         request.update_timestamp(ts);
         request.status = RequestStatus::Completed;
 
-        // Actions and errored mutations are ok to complete immediately
-        if request_type == RequestType::Action || errored {
+        // Actions, errored mutations and mutations that didn't write have no
+        // timestamp to wait for, so they're ok to complete immediately
+        if request_type == RequestType::Action || errored || ts.is_none() {
             self._remove_and_notify_completed(request_id);
         }
         Ok(())
@@ -132,11 +142,21 @@ impl RequestManager {
             .ongoing_requests
             .remove(request_id)
             .expect("INTERNAL BUG: request_id must be present");
-        if let Err(value) = sender.send(
-            request
-                .value
-                .expect("INTERNAL BUG: Value missing on completed request"),
-        ) {
+        let value = request
+            .value
+            .expect("INTERNAL BUG: Value missing on completed request");
+        let sent = match sender {
+            ResultSender::Result(sender) => {
+                sender.send(value).map_err(|value| format!("{value:?}"))
+            },
+            ResultSender::Outcome(sender) => sender
+                .send(MutationOutcome {
+                    result: value,
+                    ts: request.ts,
+                })
+                .map_err(|outcome| format!("{outcome:?}")),
+        };
+        if let Err(value) = sent {
             tracing::info!(
                 "Request {request_id:?} completed with result {value:?}, but result receiver was \
                  dropped"
@@ -151,15 +171,42 @@ impl RequestManager {
         request_type: RequestType,
     ) -> oneshot::Receiver<FunctionResult> {
         let (tx, rx) = oneshot::channel();
-        let request = Request::new(request_id, request_type, message.clone());
-        self.ongoing_requests.insert(request_id, (request, tx));
+        self.track(message, request_id, request_type, ResultSender::Result(tx));
+        rx
+    }
+
+    /// Like [`track_request`](Self::track_request), for a mutation whose
+    /// result should come with the timestamp it committed at.
+    pub fn track_mutation_outcome(
+        &mut self,
+        message: &ClientMessage,
+        request_id: RequestId,
+    ) -> oneshot::Receiver<MutationOutcome> {
+        let (tx, rx) = oneshot::channel();
+        self.track(
+            message,
+            request_id,
+            RequestType::Mutation,
+            ResultSender::Outcome(tx),
+        );
         rx
     }
 
+    fn track(
+        &mut self,
+        message: &ClientMessage,
+        request_id: RequestId,
+        request_type: RequestType,
+        sender: ResultSender,
+    ) {
+        let request = Request::new(request_id, request_type, message.clone());
+        self.ongoing_requests.insert(request_id, (request, sender));
+    }
+
     pub fn restart(&self) -> VecDeque<ClientMessage> {
         // Sort ongoing requests by timestamp
         let mut ordered_requests = Vec::from_iter(self.ongoing_requests.values());
-        ordered_requests.sort_by(|(req_a, _), (req_b, _)| req_b.ts.cmp(&req_a.ts));
+        ordered_requests.sort_by_key(|(req, _)| cmp::Reverse(req.ts));
 
         let mut messages = VecDeque::new();
         for (request, _) in ordered_requests {
@@ -167,4 +214,18 @@ impl RequestManager {
         }
         messages
     }
+
+    /// Complete the ongoing requests that already have a result from the
+    /// server, leaving the rest ongoing.
+    pub fn complete_executed(&mut self) {
+        let request_ids: Vec<_> = self
+            .ongoing_requests
+            .iter()
+            .filter(|(_, (request, _))| request.value.is_some())
+            .map(|(request_id, _)| *request_id)
+            .collect();
+        for request_id in request_ids {
+            self._remove_and_notify_completed(&request_id);
+        }
+    }
 }
@@ -0,0 +1,247 @@
+use std::collections::BTreeMap;
+
+use convex_sync_types::QueryId;
+
+use crate::FunctionResult;
+
+/// The client's local view of the current result of each subscribed query.
+///
+/// The [`BaseConvexClient`](super::BaseConvexClient) writes every result it
+/// receives from the server through its store, and reads from it to answer
+/// [`get_query`](super::BaseConvexClient::get_query()). Optimistic updates
+/// operate on the same store, so they can be layered on top of server results
+/// without knowing how subscriptions are tracked.
+///
+/// The default store is an in-memory `BTreeMap<QueryId, FunctionResult>`.
+/// Provide your own with
+/// [`BaseConvexClient::with_value_store`](super::BaseConvexClient::with_value_store())
+/// to keep query results in your application's state instead. The client
+/// still keeps its own copies of the server's results and of the results last
+/// sent to subscribers, so a custom store doesn't bound the client's memory.
+///
+/// ## Consistency rules
+/// - The server is authoritative. On every transition, the client compares each
+///   subscribed query's entry in the store with the server's result, and
+///   overwrites it if they differ, discarding any optimistic value.
+/// - Optimistic updates must be deterministic and only derive new values from
+///   what they read out of the store, since they may be replayed against
+///   different server results.
+/// - Optimistic updates should only [`set`](Self::set()) queries that are
+///   currently subscribed. Entries for queries that are later unsubscribed are
+///   [`remove`](Self::remove())d by the client.
+pub trait ValueStore {
+    /// Get the current result of the query with id `query_id`, if one is
+    /// known.
+    fn get(&self, query_id: QueryId) -> Option<FunctionResult>;
+
+    /// Set the current result of the query with id `query_id`.
+    fn set(&mut self, query_id: QueryId, result: FunctionResult);
+
+    /// Forget the result of the query with id `query_id`.
+    fn remove(&mut self, query_id: QueryId);
+
+    /// Whether the current result of the query with id `query_id` is
+    /// `result`. The client calls this for every subscribed query on every
+    /// transition, so stores that can compare without copying the result
+    /// out should override it.
+    fn contains(&self, query_id: QueryId, result: &FunctionResult) -> bool {
+        self.get(query_id).as_ref() == Some(result)
+    }
+}
+
+impl ValueStore for BTreeMap<QueryId, FunctionResult> {
+    fn get(&self, query_id: QueryId) -> Option<FunctionResult> {
+        BTreeMap::get(self, &query_id).cloned()
+    }
+
+    fn set(&mut self, query_id: QueryId, result: FunctionResult) {
+        self.insert(query_id, result);
+    }
+
+    fn remove(&mut self, query_id: QueryId) {
+        BTreeMap::remove(self, &query_id);
+    }
+
+    fn contains(&self, query_id: QueryId, result: &FunctionResult) -> bool {
+        BTreeMap::get(self, &query_id) == Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        sync::Arc,
+    };
+
+    use convex_sync_types::{
+        QueryId,
+        StateModification,
+        StateVersion,
+    };
+    use maplit::btreemap;
+    use parking_lot::Mutex;
+
+    use super::ValueStore;
+    use crate::{
+        base_client::BaseConvexClient,
+        sync::ServerMessage,
+        FunctionResult,
+        Value,
+    };
+
+    #[derive(Clone, Default)]
+    struct SharedStore(Arc<Mutex<BTreeMap<QueryId, FunctionResult>>>);
+
+    /// Counts the results copied into and out of the store.
+    #[derive(Clone, Default)]
+    struct CountingStore {
+        results: BTreeMap<QueryId, FunctionResult>,
+        gets: Arc<Mutex<usize>>,
+        sets: Arc<Mutex<usize>>,
+    }
+
+    impl ValueStore for CountingStore {
+        fn get(&self, query_id: QueryId) -> Option<FunctionResult> {
+            *self.gets.lock() += 1;
+            ValueStore::get(&self.results, query_id)
+        }
+
+        fn set(&mut self, query_id: QueryId, result: FunctionResult) {
+            *self.sets.lock() += 1;
+            self.results.set(query_id, result);
+        }
+
+        fn remove(&mut self, query_id: QueryId) {
+            ValueStore::remove(&mut self.results, query_id);
+        }
+
+        fn contains(&self, query_id: QueryId, result: &FunctionResult) -> bool {
+            self.results.contains(query_id, result)
+        }
+    }
+
+    impl ValueStore for SharedStore {
+        fn get(&self, query_id: QueryId) -> Option<FunctionResult> {
+            self.0.lock().get(&query_id).cloned()
+        }
+
+        fn set(&mut self, query_id: QueryId, result: FunctionResult) {
+            self.0.lock().insert(query_id, result);
+        }
+
+        fn remove(&mut self, query_id: QueryId) {
+            self.0.lock().remove(&query_id);
+        }
+    }
+
+    #[test]
+    fn test_custom_value_store() -> anyhow::Result<()> {
+        let store = SharedStore::default();
+        let mut base_client = BaseConvexClient::with_value_store(store.clone());
+        let subscriber_id = base_client.subscribe("getValue".parse()?, btreemap! {});
+        let query_id = subscriber_id.query_id();
+
+        let start_version = StateVersion::initial();
+        let end_version = StateVersion {
+            ts: start_version.ts.succ()?,
+            ..start_version
+        };
+        base_client
+            .receive_message(ServerMessage::Transition {
+                start_version,
+                end_version,
+                modifications: vec![StateModification::QueryUpdated {
+                    query_id,
+                    value: Value::Int64(1),
+                    log_lines: vec![],
+                    journal: None,
+                }],
+            })
+            .map_err(anyhow::Error::msg)?;
+        assert_eq!(
+            store.get(query_id),
+            Some(FunctionResult::Value(Value::Int64(1)))
+        );
+
+        // An optimistic write is visible through the client until the server
+        // sends a new result.
+        store
+            .clone()
+            .set(query_id, FunctionResult::Value(Value::Int64(2)));
+        assert_eq!(
+            base_client.get_query(query_id),
+            Some(FunctionResult::Value(Value::Int64(2)))
+        );
+
+        // The next transition restores the server's result, even though it
+        // didn't change on the server.
+        let results = base_client
+            .receive_message(ServerMessage::Transition {
+                start_version: end_version,
+                end_version: StateVersion {
+                    ts: end_version.ts.succ()?,
+                    ..end_version
+                },
+                modifications: vec![],
+            })
+            .map_err(anyhow::Error::msg)?;
+        assert_eq!(
+            store.get(query_id),
+            Some(FunctionResult::Value(Value::Int64(1)))
+        );
+        assert_eq!(
+            results.and_then(|results| results.get(&subscriber_id).cloned()),
+            Some(FunctionResult::Value(Value::Int64(1)))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_unchanged_results_not_copied() -> anyhow::Result<()> {
+        let store = CountingStore::default();
+        let mut base_client = BaseConvexClient::with_value_store(store.clone());
+        let query_id = base_client
+            .subscribe("getValue".parse()?, btreemap! {})
+            .query_id();
+        let other_subscriber_id = base_client.subscribe("getOtherValue".parse()?, btreemap! {});
+        let other_query_id = other_subscriber_id.query_id();
+
+        let mut version = StateVersion::initial();
+        let mut transition = |modifications| {
+            let start_version = version;
+            version = StateVersion {
+                ts: version.ts.succ().unwrap(),
+                ..version
+            };
+            base_client
+                .receive_message(ServerMessage::Transition {
+                    start_version,
+                    end_version: version,
+                    modifications,
+                })
+                .unwrap()
+        };
+        let updated = |query_id, value| StateModification::QueryUpdated {
+            query_id,
+            value,
+            log_lines: vec![],
+            journal: None,
+        };
+        transition(vec![
+            updated(query_id, Value::Int64(1)),
+            updated(other_query_id, Value::Int64(2)),
+        ]);
+        assert_eq!(*store.sets.lock(), 2);
+
+        // Only the result that changed is set, and nothing is read back out.
+        let results = transition(vec![updated(query_id, Value::Int64(3))]);
+        assert_eq!(*store.sets.lock(), 3);
+        assert_eq!(*store.gets.lock(), 0);
+        assert_eq!(
+            results.and_then(|results| results.get(&other_subscriber_id).cloned()),
+            Some(FunctionResult::Value(Value::Int64(2)))
+        );
+        Ok(())
+    }
+}
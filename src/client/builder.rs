@@ -0,0 +1,1268 @@
+use std::{
+    sync::{
+        atomic::AtomicBool,
+        Arc,
+        Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::Context;
+use convex_sync_types::AuthenticationToken;
+use futures::channel::mpsc;
+use tokio::sync::{
+    broadcast,
+    watch,
+};
+use tokio_tungstenite::tungstenite::http::{
+    HeaderMap,
+    HeaderName,
+    HeaderValue,
+};
+
+use crate::{
+    base_client::{
+        BaseConvexClient,
+        WriteLatency,
+        WriteLatencyCallback,
+    },
+    client::{
+        check_tls_scheme,
+        clock::{
+            Clock,
+            SystemClock,
+        },
+        deployment_to_ws_url,
+        failed_mutations::FailedMutations,
+        outbound::{
+            OutboundCall,
+            OutboundCallback,
+            OutboundHook,
+        },
+        query_cache::QueryCache,
+        rate_limit::RateLimiter,
+        subscription::AdaptiveCoalescing,
+        worker::worker,
+        ConvexClient,
+        TLS_ENABLED,
+    },
+    sync::{
+        retry_budget::RetryBudget,
+        web_socket_manager::WebSocketManager,
+        ProtocolConfig,
+        SyncProtocol,
+    },
+};
+
+/// A builder for a [`ConvexClient`] with non-default configuration.
+///
+/// ```no_run
+/// # use convex::ConvexClientBuilder;
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// let client = ConvexClientBuilder::new("https://cool-music-123.convex.cloud")
+///     .header("CF-Access-Client-Id", "my-client-id")
+///     .header("CF-Access-Client-Secret", "my-client-secret")
+///     .build()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ConvexClientBuilder {
+    deployment_url: String,
+    headers: Vec<(String, String)>,
+    require_tls: bool,
+    connect_retries: u32,
+    connect_timeout: Duration,
+    max_reconnect_attempts: Option<u32>,
+    retry_budget: Option<(u32, Duration)>,
+    wait_for_retry_budget: bool,
+    validate_roundtrips: bool,
+    on_outbound: Option<OutboundCallback>,
+    outbound_include_args: bool,
+    on_write_latency: Option<WriteLatencyCallback>,
+    coalescing: Option<AdaptiveCoalescing>,
+    bandwidth_rate_limit: Option<u64>,
+    bandwidth_cap: Option<u64>,
+    query_cache_ttl: Option<Duration>,
+    failed_mutations_limit: Option<usize>,
+    request_rate_limit: Option<u32>,
+    max_argument_size: Option<usize>,
+    clock: Arc<dyn Clock>,
+}
+
+impl ConvexClientBuilder {
+    /// Start building a client for communicating with `deployment_url`.
+    pub fn new(deployment_url: &str) -> Self {
+        let defaults = ProtocolConfig::default();
+        Self {
+            deployment_url: deployment_url.to_string(),
+            headers: vec![],
+            require_tls: false,
+            connect_retries: defaults.connect_retries,
+            connect_timeout: defaults.connect_timeout,
+            max_reconnect_attempts: defaults.max_reconnect_attempts,
+            retry_budget: None,
+            wait_for_retry_budget: defaults.wait_for_retry_budget,
+            validate_roundtrips: defaults.validate_roundtrips,
+            on_outbound: None,
+            outbound_include_args: false,
+            on_write_latency: None,
+            coalescing: None,
+            bandwidth_rate_limit: defaults.bandwidth_rate_limit,
+            bandwidth_cap: defaults.bandwidth_cap,
+            query_cache_ttl: None,
+            failed_mutations_limit: None,
+            request_rate_limit: None,
+            max_argument_size: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Add a header to the HTTP request that opens the websocket connection,
+    /// e.g. the `CF-Access-Client-Id` and `CF-Access-Client-Secret` headers
+    /// required by Cloudflare Access or another auth proxy in front of the
+    /// deployment.
+    ///
+    /// Headers are sent on the upgrade handshake of every connection attempt,
+    /// including reconnects. They are not attached to individual messages on
+    /// the websocket, which has no notion of headers. Invalid header names or
+    /// values cause [`build`](Self::build()) to fail.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Require the connection to the deployment to use TLS. Off by default,
+    /// since local and self-hosted deployments are often served over plain
+    /// `http`.
+    ///
+    /// With this set, [`build`](Self::build()) fails fast with
+    /// [`ConvexError::TlsSchemeMismatch`](crate::ConvexError::TlsSchemeMismatch)
+    /// for an `http` or `ws` deployment URL instead of connecting in the
+    /// clear. Likewise, an `https` or `wss` URL always fails with it if the
+    /// client was built without a TLS feature.
+    pub fn require_tls(mut self, enabled: bool) -> Self {
+        self.require_tls = enabled;
+        self
+    }
+
+    /// Set how many times a transient failure to open the initial connection
+    /// is retried with backoff before [`build`](Self::build()) fails. Defaults
+    /// to 3.
+    ///
+    /// Only refused connections and timeouts are retried. DNS and TLS failures
+    /// fail immediately, see [`ConvexError`](crate::ConvexError) for the
+    /// classification. Reconnects after the initial connection are always
+    /// retried.
+    pub fn connect_retries(mut self, retries: u32) -> Self {
+        self.connect_retries = retries;
+        self
+    }
+
+    /// Set how long a single connect attempt may take, from resolving the host
+    /// to completing the websocket handshake, before it fails with
+    /// [`ConvexError::HandshakeTimeout`](crate::ConvexError::HandshakeTimeout).
+    /// Defaults to 10 seconds.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Give up after `attempts` consecutive failed attempts to reconnect, e.g.
+    /// so a command line tool exits instead of hanging while offline. By
+    /// default the client reconnects forever.
+    ///
+    /// Once the attempts are exhausted the client is permanently disconnected:
+    /// outstanding and future calls fail with
+    /// [`ConvexError::ConnectionExhausted`](crate::ConvexError::ConnectionExhausted),
+    /// see [`ConvexClient::disconnect_error`]. Attempts only count once the
+    /// initial connection has been established, which is governed by
+    /// [`connect_retries`](Self::connect_retries) instead. A successful
+    /// reconnect resets the count.
+    pub fn max_reconnect_attempts(mut self, attempts: u32) -> Self {
+        self.max_reconnect_attempts = Some(attempts);
+        self
+    }
+
+    /// Allow at most `retries` retries per `window` in total, so that an
+    /// outage doesn't make the client retry as fast as its per-call policies
+    /// allow, e.g. to avoid a fleet of clients amplifying the load on a
+    /// recovering deployment. By default retries aren't capped.
+    ///
+    /// The budget is a token bucket that starts full and refills continuously
+    /// at `retries` per `window`, or never with a zero `window`. Connect
+    /// retries and reconnect attempts each take a token, and are still limited
+    /// by [`connect_retries`](Self::connect_retries) and
+    /// [`max_reconnect_attempts`](Self::max_reconnect_attempts), whichever runs
+    /// out first. Once the budget is spent, retrying fails fast: the initial
+    /// connect fails with the error of its last attempt, and a reconnect stops
+    /// the client with
+    /// [`ConvexError::RetryBudgetExhausted`](crate::ConvexError::RetryBudgetExhausted),
+    /// unless it's set to [wait](Self::wait_for_retry_budget) for the budget
+    /// to refill instead. Mutations and actions are never retried
+    /// automatically, and calls to [`ConvexClient::retry`] don't take from the
+    /// budget.
+    pub fn retry_budget(mut self, retries: u32, window: Duration) -> Self {
+        self.retry_budget = Some((retries, window));
+        self
+    }
+
+    /// Make a reconnect that finds the [retry budget](Self::retry_budget)
+    /// spent wait for it to refill, as measured by the client's [`Clock`],
+    /// rather than stopping the client. Off by default.
+    ///
+    /// The client then keeps reconnecting at the budget's rate for as long as
+    /// the outage lasts. Only a budget that never refills, with no `retries`
+    /// or a zero `window`, still stops the client with
+    /// [`ConvexError::RetryBudgetExhausted`](crate::ConvexError::RetryBudgetExhausted).
+    pub fn wait_for_retry_budget(mut self, enabled: bool) -> Self {
+        self.wait_for_retry_budget = enabled;
+        self
+    }
+
+    /// Check that every `Value` received from the server re-encodes to the
+    /// exact JSON it was decoded from, logging a warning with the path of any
+    /// discrepancy. Off by default.
+    ///
+    /// This is a diagnostic for tracking down lossy decoding, e.g. integers
+    /// beyond 2^53 sent as plain JSON numbers, and is meant for development
+    /// and testing. It roughly doubles the CPU spent on each message received,
+    /// so leave it off in production.
+    pub fn debug_validate_roundtrips(mut self, enabled: bool) -> Self {
+        self.validate_roundtrips = enabled;
+        self
+    }
+
+    /// Call `callback` with a summary of every mutation and action the client
+    /// sends, e.g. to keep an audit trail of state-changing calls. Queries
+    /// aren't reported. Use `tracing` for a full log of the protocol instead.
+    ///
+    /// The summary has the function path, request ID, time sent and a hash of
+    /// the arguments, see [`OutboundCall`]. The argument values themselves
+    /// are only included if enabled with
+    /// [`outbound_include_args`](Self::outbound_include_args), so that
+    /// sensitive values don't end up in the audit log by accident.
+    ///
+    /// The callback runs on the client's background task, right before the
+    /// call is written to the websocket, so it should be fast and must not
+    /// block: the client handles no other requests or results while it runs.
+    /// A call that's resent after a reconnect is reported again with the same
+    /// [`request_id`](OutboundCall::request_id).
+    ///
+    /// ```no_run
+    /// # use convex::ConvexClientBuilder;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let client = ConvexClientBuilder::new("https://cool-music-123.convex.cloud")
+    ///     .on_outbound(|call| {
+    ///         println!(
+    ///             "{:?} {} #{} args={:032x}",
+    ///             call.kind, call.udf_path, call.request_id, call.args_hash
+    ///         )
+    ///     })
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn on_outbound(mut self, callback: impl Fn(&OutboundCall) + Send + Sync + 'static) -> Self {
+        self.on_outbound = Some(Arc::new(callback));
+        self
+    }
+
+    /// Include the argument values in the summaries passed to the
+    /// [`on_outbound`](Self::on_outbound) callback. Off by default.
+    pub fn outbound_include_args(mut self, enabled: bool) -> Self {
+        self.outbound_include_args = enabled;
+        self
+    }
+
+    /// Coalesce bursts of updates on every query subscription made through
+    /// the client, delivering the updates of quiet queries immediately and
+    /// batching those of busy ones. Off by default, so that every update is
+    /// delivered as soon as it's received. See [`AdaptiveCoalescing`] for the
+    /// heuristic, and
+    /// [`QuerySubscription::coalesce`](crate::QuerySubscription::coalesce)
+    /// to coalesce a single subscription instead.
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use convex::{AdaptiveCoalescing, ConvexClientBuilder};
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let client = ConvexClientBuilder::new("https://cool-music-123.convex.cloud")
+    ///     .adaptive_coalescing(AdaptiveCoalescing {
+    ///         quiet_interval: Duration::from_millis(250),
+    ///         window: Duration::from_millis(100),
+    ///     })
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn adaptive_coalescing(mut self, config: AdaptiveCoalescing) -> Self {
+        self.coalescing = Some(config);
+        self
+    }
+
+    /// Call `callback` with the write-to-read latency of every successful
+    /// mutation, i.e. how long after the mutation's response arrived the
+    /// client's subscriptions reflected its write. See
+    /// [`BaseConvexClient::set_on_write_latency`] for how writes are matched
+    /// to transitions.
+    ///
+    /// The callback runs on the client's background task, so it should be
+    /// fast and must not block, like the [`on_outbound`](Self::on_outbound)
+    /// callback.
+    ///
+    /// ```no_run
+    /// # use convex::ConvexClientBuilder;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let client = ConvexClientBuilder::new("https://cool-music-123.convex.cloud")
+    ///     .on_write_latency(|write| {
+    ///         println!("Mutation {} visible after {:?}", write.request_id, write.latency)
+    ///     })
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn on_write_latency(mut self, callback: impl Fn(&WriteLatency) + Send + 'static) -> Self {
+        self.on_write_latency = Some(Box::new(callback));
+        self
+    }
+
+    /// Throttle sending to an average of `bytes_per_second`, e.g. to leave
+    /// room for other traffic on a constrained network. By default the client
+    /// sends as fast as it can.
+    ///
+    /// This is a soft limit: messages are never split, so a burst of large
+    /// messages goes over the limit briefly, and the following messages are
+    /// delayed to bring the average back down. Only sending is throttled.
+    /// What the client receives is up to the deployment, e.g. how often its
+    /// subscribed queries change, so it can be measured with
+    /// [`ConvexClient::bandwidth_usage`] but not throttled.
+    pub fn bandwidth_rate_limit(mut self, bytes_per_second: u64) -> Self {
+        self.bandwidth_rate_limit = Some(bytes_per_second);
+        self
+    }
+
+    /// Stop the client once it has sent and received `bytes` in total, as
+    /// reported by [`ConvexClient::bandwidth_usage`], e.g. to stay within a
+    /// data plan. By default there's no cap.
+    ///
+    /// The client fails the first send that would exceed the cap and
+    /// disconnects permanently with
+    /// [`ConvexError::BandwidthLimited`](crate::ConvexError::BandwidthLimited):
+    /// outstanding and future calls fail with it, see
+    /// [`ConvexClient::disconnect_error`]. Received bytes count towards the
+    /// cap, but since the client can't stop the deployment from sending, they
+    /// can take it over the cap until the client next sends a message.
+    pub fn bandwidth_cap(mut self, bytes: u64) -> Self {
+        self.bandwidth_cap = Some(bytes);
+        self
+    }
+
+    /// Cache the results of [`ConvexClient::query`] for `ttl`, e.g. for a
+    /// read-heavy dashboard that runs the same one-shot queries repeatedly.
+    /// Off by default.
+    ///
+    /// **The cache returns stale data:** within the TTL, a query with the same
+    /// function and arguments as an earlier one returns the earlier result
+    /// without contacting the deployment, even if the data has changed since.
+    /// Only enable it if that's acceptable. The cache is shared by the client's
+    /// clones and cleared by [`ConvexClient::set_auth`]. Errors aren't cached,
+    /// and subscriptions never use the cache: use
+    /// [`ConvexClient::subscribe`] to always see the latest results.
+    pub fn query_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.query_cache_ttl = Some(ttl);
+        self
+    }
+
+    /// Keep the last `limit` mutations that failed, with their arguments, so
+    /// that they can be listed with [`ConvexClient::failed_mutations`] and
+    /// retried deliberately with [`ConvexClient::retry`], e.g. after an outage
+    /// in an app that tolerates being offline. Off by default.
+    ///
+    /// A mutation fails if it returns an error message, or if the client fails
+    /// it, e.g. because it disconnected permanently. Arguments that fail the
+    /// checks before sending aren't kept. Once `limit` failures are kept, each
+    /// new one drops the oldest. **The arguments stay in memory** until then,
+    /// or until the mutation is retried, so only enable this if that's
+    /// acceptable for their size and sensitivity. The list is shared by the
+    /// client's clones.
+    pub fn retain_failed_mutations(mut self, limit: usize) -> Self {
+        self.failed_mutations_limit = Some(limit);
+        self
+    }
+
+    /// Send at most `requests_per_second` subscriptions, mutations and actions
+    /// per second on average, e.g. to stay under the deployment's rate limits
+    /// during a bulk import. By default there's no limit.
+    ///
+    /// Requests over the limit wait before they're queued, in the order they
+    /// were made, instead of being sent and rejected. Up to a second's worth
+    /// of requests can go out in a burst after a quiet period. The limit is
+    /// shared by the client's clones. A batch from
+    /// [`ConvexClient::subscribe_many`] counts as one request, since it's
+    /// sent as one message, and unsubscribing isn't limited.
+    ///
+    /// This is cooperative: it only spreads out this client's requests, so
+    /// it can't guarantee that the deployment won't reject any, e.g. if other
+    /// clients share its limits. A limit of 0 is treated as 1 request per
+    /// second.
+    pub fn request_rate_limit(mut self, requests_per_second: u32) -> Self {
+        self.request_rate_limit = Some(requests_per_second);
+        self
+    }
+
+    /// Fail mutations whose arguments are larger than `bytes` with
+    /// [`ConvexError::ArgumentTooLarge`](crate::ConvexError::ArgumentTooLarge)
+    /// before sending them, e.g. set to the deployment's limit on function
+    /// arguments to save the round trip to a rejection and get a clearer
+    /// error. Off by default.
+    ///
+    /// The size is that of the arguments encoded as JSON, as they're sent,
+    /// which takes a copy of them for every mutation. The deployment measures
+    /// sizes its own way, so a limit just under its own may still let through
+    /// some mutations it rejects.
+    pub fn max_argument_size(mut self, bytes: usize) -> Self {
+        self.max_argument_size = Some(bytes);
+        self
+    }
+
+    /// Measure the client's timeouts and TTLs with `clock` instead of the
+    /// real time, e.g. a [`TestClock`](crate::TestClock) to test them without
+    /// waiting. Not every timer uses it: see [`Clock`] for which do.
+    pub fn clock(mut self, clock: impl Clock) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Connect to the deployment and construct the client.
+    ///
+    /// Fails with a [`ConvexError`](crate::ConvexError) if the initial
+    /// connection can't be opened.
+    pub async fn build(self) -> anyhow::Result<ConvexClient> {
+        let deployment_url = ConvexClient::validate_url(&self.deployment_url)?
+            .url()
+            .clone();
+        let ws_url = deployment_to_ws_url(deployment_url.clone())?;
+        check_tls_scheme(&ws_url, TLS_ENABLED, self.require_tls)?;
+        let mut headers = HeaderMap::new();
+        for (name, value) in self.headers {
+            let name = HeaderName::try_from(&name).with_context(|| format!("Bad header {name}"))?;
+            let value =
+                HeaderValue::try_from(&value).with_context(|| format!("Bad value for {name}"))?;
+            headers.append(name, value);
+        }
+
+        // Channels for the `listen` background thread
+        let (response_sender, response_receiver) = mpsc::channel(1);
+        let (request_sender, request_receiver) = mpsc::unbounded();
+
+        // Listener for when each transaction completes
+        let (watch_sender, watch_receiver) = broadcast::channel(1);
+
+        let mut base_client = BaseConvexClient::new();
+        base_client.set_clock(self.clock.clone());
+        if let Some(on_write_latency) = self.on_write_latency {
+            base_client.set_on_write_latency(on_write_latency);
+        }
+        let disconnect_error = Arc::new(Mutex::new(None));
+        let (connected_sender, connected) = watch::channel(true);
+        let (timestamp_sender, timestamp) = watch::channel(None);
+
+        let config = ProtocolConfig {
+            headers,
+            connect_retries: self.connect_retries,
+            connect_timeout: self.connect_timeout,
+            max_reconnect_attempts: self.max_reconnect_attempts,
+            retry_budget: self.retry_budget.map(|(retries, window)| {
+                Arc::new(RetryBudget::new(retries, window, self.clock.clone()))
+            }),
+            wait_for_retry_budget: self.wait_for_retry_budget,
+            validate_roundtrips: self.validate_roundtrips,
+            bandwidth: Arc::default(),
+            bandwidth_rate_limit: self.bandwidth_rate_limit,
+            bandwidth_cap: self.bandwidth_cap,
+        };
+        let bandwidth = config.bandwidth.clone();
+        let protocol = WebSocketManager::open(ws_url, response_sender, config).await?;
+        let on_outbound = self.on_outbound.map(|callback| OutboundHook {
+            callback,
+            include_args: self.outbound_include_args,
+        });
+
+        let listen_handle = tokio::spawn(worker(
+            response_receiver,
+            request_receiver,
+            watch_sender,
+            connected_sender,
+            timestamp_sender,
+            base_client,
+            protocol,
+            disconnect_error.clone(),
+            on_outbound,
+        ));
+        let client = ConvexClient {
+            listen_handle: Some(Arc::new(listen_handle)),
+            request_sender,
+            watch_receiver,
+            deployment_url,
+            auth_token: Arc::new(Mutex::new(AuthenticationToken::None)),
+            http_client: reqwest::Client::new(),
+            disconnect_error,
+            closing: Arc::new(AtomicBool::new(false)),
+            bandwidth,
+            query_cache: self
+                .query_cache_ttl
+                .map(|ttl| Arc::new(QueryCache::new(ttl, self.clock.clone()))),
+            failed_mutations: self
+                .failed_mutations_limit
+                .map(|limit| Arc::new(FailedMutations::new(limit))),
+            rate_limiter: self
+                .request_rate_limit
+                .map(|rate| Arc::new(RateLimiter::new(rate, self.clock.clone()))),
+            max_argument_size: self.max_argument_size,
+            connected,
+            timestamp,
+            coalescing: self.coalescing,
+            clock: self.clock,
+        };
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env::VarError,
+        sync::{
+            atomic::{
+                AtomicUsize,
+                Ordering,
+            },
+            Arc,
+            Mutex,
+        },
+        time::Duration,
+    };
+
+    use convex_sync_types::{
+        AuthenticationToken,
+        ClientMessage,
+        QueryId,
+        StateModification,
+        StateVersion,
+    };
+    use futures::{
+        SinkExt,
+        StreamExt,
+    };
+    use maplit::btreemap;
+    use tokio::net::{
+        TcpListener,
+        TcpStream,
+    };
+    use tokio_tungstenite::{
+        tungstenite::{
+            handshake::server::{
+                Request,
+                Response,
+            },
+            protocol::{
+                frame::coding::CloseCode,
+                CloseFrame,
+            },
+            Message,
+        },
+        WebSocketStream,
+    };
+
+    use super::ConvexClientBuilder;
+    use crate::{
+        client::clock::TestClock,
+        sync::ServerMessage,
+        BandwidthUsage,
+        ConvexClient,
+        ConvexError,
+        FunctionResult,
+        OutboundCall,
+        OutboundCallKind,
+        Sequenced,
+        Value,
+    };
+
+    /// Receive client messages on `ws_stream` until one matches `last`.
+    async fn receive_until(
+        ws_stream: &mut WebSocketStream<TcpStream>,
+        last: impl Fn(&ClientMessage) -> bool,
+    ) -> anyhow::Result<Vec<ClientMessage>> {
+        let mut messages = vec![];
+        while let Some(message) = ws_stream.next().await {
+            let json: serde_json::Value = serde_json::from_str(message?.to_text()?)?;
+            let message = ClientMessage::try_from(json)?;
+            let done = last(&message);
+            messages.push(message);
+            if done {
+                return Ok(messages);
+            }
+        }
+        anyhow::bail!("Connection closed after {messages:?}");
+    }
+
+    /// Send a transition from `start_version` updating `updates`, returning
+    /// its end version.
+    async fn send_transition(
+        ws_stream: &mut WebSocketStream<TcpStream>,
+        start_version: StateVersion,
+        updates: Vec<(QueryId, Value)>,
+    ) -> anyhow::Result<StateVersion> {
+        let end_version = StateVersion {
+            ts: start_version.ts.succ()?,
+            ..start_version
+        };
+        let transition = ServerMessage::Transition {
+            start_version,
+            end_version,
+            modifications: updates
+                .into_iter()
+                .map(|(query_id, value)| StateModification::QueryUpdated {
+                    query_id,
+                    value,
+                    journal: None,
+                    log_lines: vec![],
+                })
+                .collect(),
+        };
+        let json = serde_json::Value::from(transition);
+        ws_stream.send(Message::Text(json.to_string())).await?;
+        Ok(end_version)
+    }
+
+    #[tokio::test]
+    async fn test_from_env() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let deployment_url = format!("http://{}", listener.local_addr()?);
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            anyhow::Ok(tokio_tungstenite::accept_async(stream).await?)
+        });
+
+        let e = ConvexClient::from_env_with(|_| Err(VarError::NotPresent))
+            .await
+            .err()
+            .unwrap();
+        assert!(e.to_string().contains("CONVEX_URL"), "{e}");
+
+        let _client = ConvexClient::from_env_with(|name| {
+            assert_eq!(name, "CONVEX_URL");
+            Ok(deployment_url.clone())
+        })
+        .await?;
+        let _ws_stream = tokio::time::timeout(Duration::from_secs(5), server).await???;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invalid_header() {
+        let err = ConvexClientBuilder::new("https://flying-shark-123.convex.cloud")
+            .header("Bad Header", "value")
+            .build()
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("Bad header"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_require_tls() {
+        let err = ConvexClientBuilder::new("http://127.0.0.1:3210")
+            .require_tls(true)
+            .build()
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.downcast_ref::<ConvexError>(),
+            Some(&ConvexError::TlsSchemeMismatch {
+                url: "ws://127.0.0.1:3210/api/sync".into()
+            })
+        );
+        assert!(err.to_string().contains("doesn't use TLS"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_max_reconnect_attempts() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let deployment_url = format!("http://{}", listener.local_addr()?);
+
+        // A mock server that accepts a single connection, stops listening so
+        // that reconnects are refused, and closes the connection.
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            drop(listener);
+            let mut ws_stream = tokio_tungstenite::accept_async(stream).await?;
+            ws_stream
+                .close(Some(CloseFrame {
+                    code: CloseCode::Away,
+                    reason: "Restarting".into(),
+                }))
+                .await?;
+            anyhow::Ok(())
+        });
+
+        let mut client = ConvexClientBuilder::new(&deployment_url)
+            .max_reconnect_attempts(1)
+            .build()
+            .await?;
+        server.await??;
+
+        let expected = ConvexError::ConnectionExhausted { attempts: 1 };
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while client.disconnect_error().is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await?;
+        assert_eq!(client.disconnect_error(), Some(expected.clone()));
+        let e = client
+            .mutation("sendMessage", btreemap! {})
+            .await
+            .unwrap_err();
+        assert_eq!(e.downcast_ref::<ConvexError>(), Some(&expected));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_closed_again() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let deployment_url = format!("http://{}", listener.local_addr()?);
+
+        // A mock server that closes the connection once the client has
+        // subscribed, closes the next one straight away while the client is
+        // still resending its subscription, and then waits for it again.
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut ws_stream = tokio_tungstenite::accept_async(stream).await?;
+            receive_until(&mut ws_stream, |m| {
+                matches!(m, ClientMessage::ModifyQuerySet { .. })
+            })
+            .await?;
+            ws_stream.close(None).await?;
+            let (stream, _) = listener.accept().await?;
+            let mut ws_stream = tokio_tungstenite::accept_async(stream).await?;
+            ws_stream.close(None).await?;
+            let (stream, _) = listener.accept().await?;
+            let mut ws_stream = tokio_tungstenite::accept_async(stream).await?;
+            receive_until(&mut ws_stream, |m| {
+                matches!(m, ClientMessage::ModifyQuerySet { .. })
+            })
+            .await?;
+            anyhow::Ok(ws_stream)
+        });
+
+        let mut client = ConvexClientBuilder::new(&deployment_url).build().await?;
+        let _subscription = client.subscribe("listMessages", btreemap! {}).await?;
+        let _ws_stream = tokio::time::timeout(Duration::from_secs(10), server).await???;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retry_budget_reconnect() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let deployment_url = format!("http://{}", listener.local_addr()?);
+
+        // A mock server that drops the first two connections, spending the
+        // budget's only retry.
+        let server = tokio::spawn(async move {
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().await?;
+                let mut ws_stream = tokio_tungstenite::accept_async(stream).await?;
+                ws_stream
+                    .close(Some(CloseFrame {
+                        code: CloseCode::Away,
+                        reason: "Restarting".into(),
+                    }))
+                    .await?;
+            }
+            anyhow::Ok(listener)
+        });
+
+        let mut client = ConvexClientBuilder::new(&deployment_url)
+            .retry_budget(1, Duration::from_secs(3600))
+            .build()
+            .await?;
+        let _listener = tokio::time::timeout(Duration::from_secs(5), server).await???;
+
+        // The spent budget stops the client instead of reconnecting again.
+        let expected = ConvexError::RetryBudgetExhausted {
+            retries: 1,
+            window: Duration::from_secs(3600),
+        };
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while client.disconnect_error().is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await?;
+        assert_eq!(client.disconnect_error(), Some(expected.clone()));
+        let e = client
+            .mutation("sendMessage", btreemap! {})
+            .await
+            .unwrap_err();
+        assert_eq!(e.downcast_ref::<ConvexError>(), Some(&expected));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retry_budget_reconnect_wait() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let deployment_url = format!("http://{}", listener.local_addr()?);
+        let (closed_sender, closed) = tokio::sync::oneshot::channel();
+
+        // A mock server that drops the first two connections, spending the
+        // budget's only retry, and then waits for the client to reconnect.
+        let server = tokio::spawn(async move {
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().await?;
+                let mut ws_stream = tokio_tungstenite::accept_async(stream).await?;
+                ws_stream
+                    .close(Some(CloseFrame {
+                        code: CloseCode::Away,
+                        reason: "Restarting".into(),
+                    }))
+                    .await?;
+            }
+            let _ = closed_sender.send(());
+            let (stream, _) = listener.accept().await?;
+            let mut ws_stream = tokio_tungstenite::accept_async(stream).await?;
+            receive_until(&mut ws_stream, |m| {
+                matches!(m, ClientMessage::Connect { .. })
+            })
+            .await?;
+            anyhow::Ok(ws_stream)
+        });
+
+        let clock = TestClock::new();
+        let mut client = ConvexClientBuilder::new(&deployment_url)
+            .retry_budget(1, Duration::from_secs(10))
+            .wait_for_retry_budget(true)
+            .clock(clock.clone())
+            .build()
+            .await?;
+        closed.await?;
+
+        // The spent budget holds the reconnect back instead of stopping the
+        // client, until it refills.
+        assert_eq!(client.disconnect_error(), None);
+        clock.advance(Duration::from_secs(10));
+        let _ws_stream = tokio::time::timeout(Duration::from_secs(5), server).await???;
+        // The client's timeouts don't fire on a `TestClock`.
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            client.wait_connected(Duration::from_secs(5)),
+        )
+        .await??;
+        assert_eq!(client.disconnect_error(), None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_deployment_info() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let deployment_url = format!("http://{}", listener.local_addr()?);
+
+        // A mock server that describes itself in the handshake response.
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            // The error type is dictated by `tungstenite`.
+            #[allow(clippy::result_large_err)]
+            let callback = |_: &Request, mut response: Response| {
+                let headers = response.headers_mut();
+                headers.insert("x-convex-deprecation-state", "Deprecated".parse().unwrap());
+                headers.insert(
+                    "x-convex-deprecation-message",
+                    "Upgrade to 0.3".parse().unwrap(),
+                );
+                headers.insert("x-convex-feature", "pagination".parse().unwrap());
+                Ok(response)
+            };
+            anyhow::Ok(tokio_tungstenite::accept_hdr_async(stream, callback).await?)
+        });
+
+        let mut client = ConvexClientBuilder::new(&deployment_url).build().await?;
+        let _ws_stream = server.await??;
+        let info = client.deployment_info().await?;
+        assert_eq!(info.deprecation_state.as_deref(), Some("Deprecated"));
+        assert_eq!(info.deprecation_message.as_deref(), Some("Upgrade to 0.3"));
+        assert_eq!(info.headers["x-convex-feature"], "pagination");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_reauthenticates() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let deployment_url = format!("http://{}", listener.local_addr()?);
+        let is_query_set = |m: &ClientMessage| matches!(m, ClientMessage::ModifyQuerySet { .. });
+
+        // A mock server that drops the first connection once the client has
+        // subscribed, and reports what the client resends on the second.
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut ws_stream = tokio_tungstenite::accept_async(stream).await?;
+            receive_until(&mut ws_stream, is_query_set).await?;
+            ws_stream
+                .close(Some(CloseFrame {
+                    code: CloseCode::Away,
+                    reason: "Restarting".into(),
+                }))
+                .await?;
+
+            let (stream, _) = listener.accept().await?;
+            let mut ws_stream = tokio_tungstenite::accept_async(stream).await?;
+            receive_until(&mut ws_stream, is_query_set).await
+        });
+
+        let mut client = ConvexClientBuilder::new(&deployment_url).build().await?;
+        client.set_auth(Some("token".into())).await;
+        let _subscription = client.subscribe("listMessages", btreemap! {}).await?;
+
+        let resent = tokio::time::timeout(Duration::from_secs(5), server).await???;
+        // The token is resent from scratch before the queries are re-added.
+        let [ClientMessage::Connect { .. }, ClientMessage::Authenticate {
+            base_version: 0,
+            token: AuthenticationToken::User(token),
+        }, ClientMessage::ModifyQuerySet {
+            base_version: 0,
+            new_version: 1,
+            ..
+        }] = &resent[..]
+        else {
+            panic!("Unexpected messages after reconnect: {resent:?}");
+        };
+        assert_eq!(token, "token");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_calls_while_reconnecting() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let deployment_url = format!("http://{}", listener.local_addr()?);
+        let (reconnect_sender, reconnect) = tokio::sync::oneshot::channel();
+
+        // A mock server that drops the first connection, and only accepts the
+        // second one once told to.
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut ws_stream = tokio_tungstenite::accept_async(stream).await?;
+            ws_stream
+                .close(Some(CloseFrame {
+                    code: CloseCode::Away,
+                    reason: "Restarting".into(),
+                }))
+                .await?;
+
+            reconnect.await?;
+            let (stream, _) = listener.accept().await?;
+            let mut ws_stream = tokio_tungstenite::accept_async(stream).await?;
+            receive_until(&mut ws_stream, |m| {
+                matches!(m, ClientMessage::Mutation { .. })
+            })
+            .await
+        });
+
+        let mut client = ConvexClientBuilder::new(&deployment_url).build().await?;
+        client.wait_connected(Duration::ZERO).await?;
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while client.wait_connected(Duration::ZERO).await.is_ok() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await?;
+        let e = client
+            .wait_connected(Duration::from_millis(10))
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains("Not connected"), "{e}");
+
+        // The mutation is queued until the client reconnects.
+        let mut client_ = client.clone();
+        let _mutation =
+            tokio::spawn(async move { client_.mutation("sendMessage", btreemap! {}).await });
+        let _ = reconnect_sender.send(());
+        client.wait_connected(Duration::from_secs(5)).await?;
+        let sent = tokio::time::timeout(Duration::from_secs(5), server).await???;
+        let [ClientMessage::Connect { .. }, ClientMessage::ModifyQuerySet { .. }, ClientMessage::Mutation { .. }] =
+            &sent[..]
+        else {
+            panic!("Unexpected messages after reconnect: {sent:?}");
+        };
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bandwidth_usage() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let deployment_url = format!("http://{}", listener.local_addr()?);
+        // A mock server that answers the first query and counts the bytes of
+        // the messages it receives and sends.
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut ws_stream = tokio_tungstenite::accept_async(stream).await?;
+            let mut usage = BandwidthUsage::default();
+            while let Some(message) = ws_stream.next().await {
+                let text = message?.into_text()?;
+                usage.bytes_sent += text.len() as u64;
+                let json: serde_json::Value = serde_json::from_str(&text)?;
+                if let ClientMessage::ModifyQuerySet { .. } = ClientMessage::try_from(json)? {
+                    break;
+                }
+            }
+            let transition = ServerMessage::Transition {
+                start_version: StateVersion::initial(),
+                end_version: StateVersion {
+                    ts: StateVersion::initial().ts.succ()?,
+                    ..StateVersion::initial()
+                },
+                modifications: vec![StateModification::QueryUpdated {
+                    query_id: QueryId::new(0),
+                    value: "x".repeat(1000).into(),
+                    journal: None,
+                    log_lines: vec![],
+                }],
+            };
+            let text = serde_json::Value::from(transition).to_string();
+            usage.bytes_received += text.len() as u64;
+            ws_stream.send(Message::Text(text)).await?;
+            anyhow::Ok((usage, ws_stream))
+        });
+
+        let mut client = ConvexClientBuilder::new(&deployment_url).build().await?;
+        let mut subscription = client.subscribe("listMessages", btreemap! {}).await?;
+        assert_eq!(
+            subscription.next().await,
+            Some(FunctionResult::Value("x".repeat(1000).into()))
+        );
+        let (usage, _ws_stream) = tokio::time::timeout(Duration::from_secs(5), server).await???;
+        assert!(usage.bytes_received > 1000);
+        assert_eq!(client.bandwidth_usage(), usage);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bandwidth_rate_limit() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let deployment_url = format!("http://{}", listener.local_addr()?);
+        // A mock server that answers the query once it receives a mutation.
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut ws_stream = tokio_tungstenite::accept_async(stream).await?;
+            receive_until(&mut ws_stream, |m| {
+                matches!(m, ClientMessage::Mutation { .. })
+            })
+            .await?;
+            send_transition(
+                &mut ws_stream,
+                StateVersion::initial(),
+                vec![(QueryId::new(0), 1.into())],
+            )
+            .await?;
+            anyhow::Ok(ws_stream)
+        });
+
+        let mut client = ConvexClientBuilder::new(&deployment_url)
+            .bandwidth_rate_limit(1000)
+            .build()
+            .await?;
+        let mut subscription = client.subscribe("listMessages", btreemap! {}).await?;
+        // Whichever of the mutations is sent second waits about 4 seconds.
+        for _ in 0..2 {
+            let mut client = client.clone();
+            let args = btreemap! { "body".to_string() => "x".repeat(4000).into() };
+            tokio::spawn(async move { client.mutation("sendMessage", args).await });
+        }
+
+        // Results are still received while a send waits.
+        let result = tokio::time::timeout(Duration::from_secs(2), subscription.next()).await?;
+        assert_eq!(result, Some(FunctionResult::Value(1.into())));
+        assert!(client.bandwidth_usage().bytes_sent < 8000);
+        let _ws_stream = server.await??;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_bandwidth_cap() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let deployment_url = format!("http://{}", listener.local_addr()?);
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut ws_stream = tokio_tungstenite::accept_async(stream).await?;
+            receive_until(&mut ws_stream, |m| {
+                matches!(m, ClientMessage::Connect { .. })
+            })
+            .await?;
+            anyhow::Ok(ws_stream)
+        });
+
+        let mut client = ConvexClientBuilder::new(&deployment_url)
+            .bandwidth_cap(1000)
+            .build()
+            .await?;
+        let _ws_stream = tokio::time::timeout(Duration::from_secs(5), server).await???;
+        let connect_bytes = client.bandwidth_usage().bytes_sent;
+        assert!(connect_bytes > 0 && connect_bytes < 1000);
+
+        let args = btreemap! { "body".to_string() => "x".repeat(1000).into() };
+        let expected = ConvexError::BandwidthLimited { cap: 1000 };
+        let e = client.mutation("sendMessage", args).await.unwrap_err();
+        assert_eq!(e.downcast_ref::<ConvexError>(), Some(&expected));
+        assert_eq!(client.disconnect_error(), Some(expected));
+        assert_eq!(client.bandwidth_usage().bytes_sent, connect_bytes);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_on_outbound() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let deployment_url = format!("http://{}", listener.local_addr()?);
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut ws_stream = tokio_tungstenite::accept_async(stream).await?;
+            let calls = AtomicUsize::new(0);
+            receive_until(&mut ws_stream, |m| {
+                if matches!(
+                    m,
+                    ClientMessage::Mutation { .. } | ClientMessage::Action { .. }
+                ) {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                }
+                calls.load(Ordering::SeqCst) == 2
+            })
+            .await
+        });
+
+        let calls = Arc::new(Mutex::new(vec![]));
+        let client = ConvexClientBuilder::new(&deployment_url)
+            .on_outbound({
+                let calls = calls.clone();
+                move |call: &OutboundCall| calls.lock().unwrap().push(call.clone())
+            })
+            .build()
+            .await?;
+        let args = btreemap! { "body".to_string() => "secret".into() };
+        let mut client_ = client.clone();
+        let _subscription =
+            tokio::spawn(async move { client_.subscribe("listMessages", btreemap! {}).await });
+        let (mut client_, args_) = (client.clone(), args.clone());
+        let _mutation = tokio::spawn(async move { client_.mutation("sendMessage", args_).await });
+        let (mut client_, args_) = (client.clone(), args.clone());
+        let _action = tokio::spawn(async move { client_.action("translate", args_).await });
+        tokio::time::timeout(Duration::from_secs(5), server).await???;
+
+        // Only the mutation and the action are reported, in some order.
+        let mut calls = calls.lock().unwrap().clone();
+        calls.sort_by_key(|call| call.request_id);
+        let summaries: Vec<_> = calls
+            .iter()
+            .map(|call| (call.kind, call.udf_path.as_str(), call.args.clone()))
+            .collect();
+        let mut expected = vec![
+            (OutboundCallKind::Mutation, "sendMessage", None),
+            (OutboundCallKind::Action, "translate", None),
+        ];
+        if calls[0].kind == OutboundCallKind::Action {
+            expected.reverse();
+        }
+        assert_eq!(summaries, expected);
+        for call in calls {
+            assert_eq!(call.args_hash, Value::Object(args.clone()).stable_hash());
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_resyncs_subscriptions() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let deployment_url = format!("http://{}", listener.local_addr()?);
+        let is_query_set = |m: &ClientMessage| matches!(m, ClientMessage::ModifyQuerySet { .. });
+        let (messages, counts) = (QueryId::new(0), QueryId::new(1));
+        // Lets the server wait until the client has received each update, since
+        // a subscription that lags behind skips to the latest result.
+        let (received_sender, mut received) = tokio::sync::mpsc::unbounded_channel();
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut ws_stream = tokio_tungstenite::accept_async(stream).await?;
+            receive_until(&mut ws_stream, is_query_set).await?;
+            let version = StateVersion::initial();
+            let version = send_transition(
+                &mut ws_stream,
+                version,
+                vec![(messages, 1.into()), (counts, 1.into())],
+            )
+            .await?;
+            // Only the other query changes.
+            send_transition(&mut ws_stream, version, vec![(counts, 2.into())]).await?;
+            received.recv().await;
+            ws_stream
+                .close(Some(CloseFrame {
+                    code: CloseCode::Away,
+                    reason: "Restarting".into(),
+                }))
+                .await?;
+
+            // Resync the other query first, leaving the first one with a
+            // result from the old connection, then resync it unchanged.
+            let (stream, _) = listener.accept().await?;
+            let mut ws_stream = tokio_tungstenite::accept_async(stream).await?;
+            receive_until(&mut ws_stream, is_query_set).await?;
+            let version = StateVersion::initial();
+            let version =
+                send_transition(&mut ws_stream, version, vec![(counts, 3.into())]).await?;
+            let version =
+                send_transition(&mut ws_stream, version, vec![(messages, 1.into())]).await?;
+            received.recv().await;
+            let version =
+                send_transition(&mut ws_stream, version, vec![(counts, 4.into())]).await?;
+            send_transition(&mut ws_stream, version, vec![(messages, 2.into())]).await?;
+            anyhow::Ok(ws_stream)
+        });
+
+        let mut client = ConvexClientBuilder::new(&deployment_url).build().await?;
+        let mut subscriptions = client
+            .subscribe_many(vec![
+                ("listMessages", btreemap! {}),
+                ("getCount", btreemap! {}),
+            ])
+            .await?;
+        let _counts = subscriptions.pop().unwrap()?;
+        let mut updates = subscriptions.pop().unwrap()?.sequenced();
+        let mut received = vec![];
+        for _ in 0..3 {
+            let update = tokio::time::timeout(Duration::from_secs(5), updates.next()).await?;
+            received.push(update.unwrap());
+            let _ = received_sender.send(());
+        }
+        let _ws_stream = server.await??;
+
+        let sequenced = |seq, value: i64, connection, resync| Sequenced {
+            value: FunctionResult::Value(value.into()),
+            seq,
+            connection,
+            resync,
+        };
+        assert_eq!(
+            received,
+            vec![
+                sequenced(0, 1, 0, false),
+                sequenced(1, 1, 1, true),
+                sequenced(2, 2, 1, false),
+            ]
+        );
+        Ok(())
+    }
+}
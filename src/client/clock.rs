@@ -0,0 +1,131 @@
+//! The source of time for the client's timeouts and TTLs.
+
+use std::{
+    future::Future,
+    sync::Arc,
+    time::Duration,
+};
+
+use futures::future::BoxFuture;
+use tokio::{
+    sync::watch,
+    time::Instant,
+};
+
+/// Where a [`ConvexClient`](crate::ConvexClient) gets the time from, set with
+/// [`ConvexClientBuilder::clock`](crate::ConvexClientBuilder::clock).
+///
+/// The clock measures the timeouts of
+/// [`mutation_consistent`](crate::ConvexClient::mutation_consistent),
+/// [`wait_connected`](crate::ConvexClient::wait_connected) and
+/// [`collect_updates`](crate::ConvexClient::collect_updates), the TTL of the
+/// [query cache](crate::ConvexClientBuilder::query_cache_ttl), the
+/// [request rate limit](crate::ConvexClientBuilder::request_rate_limit), and
+/// the [write latencies](crate::ConvexClientBuilder::on_write_latency). Only
+/// these: the deadline of
+/// [`next_or_pending`](crate::QuerySubscription::next_or_pending), the windows
+/// of [`coalesce`](crate::QuerySubscription::coalesce) and the connection's
+/// own timers, like reconnect backoff and heartbeats, always use the real
+/// time, as measured by [`tokio::time`].
+///
+/// Clients use [`SystemClock`] by default. Tests can use a [`TestClock`] to
+/// trigger timeouts without waiting for them.
+pub trait Clock: Send + Sync + 'static {
+    /// The current time.
+    fn now(&self) -> Instant;
+
+    /// A future that completes once [`now`](Self::now) reaches `deadline`.
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()>;
+}
+
+/// The real time, as measured by [`tokio::time`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep_until(deadline))
+    }
+}
+
+/// A clock that only moves when [`advance`](Self::advance)d, for
+/// deterministic tests of timeouts and TTLs.
+///
+/// Clones share the same time, so keep a clone to advance the clock after
+/// passing it to
+/// [`ConvexClientBuilder::clock`](crate::ConvexClientBuilder::clock).
+///
+/// ```
+/// # use std::time::Duration;
+/// # use convex::{Clock, TestClock};
+/// let clock = TestClock::new();
+/// let start = clock.now();
+/// clock.advance(Duration::from_secs(5));
+/// assert_eq!(clock.now() - start, Duration::from_secs(5));
+/// ```
+#[derive(Clone, Debug)]
+pub struct TestClock {
+    start: Instant,
+    elapsed: Arc<watch::Sender<Duration>>,
+}
+
+impl TestClock {
+    /// A clock stopped at the current time.
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: Arc::new(watch::channel(Duration::ZERO).0),
+        }
+    }
+
+    /// Move the clock forward by `duration`, completing the sleeps whose
+    /// deadlines it reaches.
+    pub fn advance(&self, duration: Duration) {
+        self.elapsed.send_modify(|elapsed| *elapsed += duration);
+    }
+}
+
+impl Default for TestClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.borrow()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+        let start = self.start;
+        let mut elapsed = self.elapsed.subscribe();
+        Box::pin(async move {
+            // The sender lives as long as the clock, so this only fails once
+            // nothing can advance it anymore.
+            if elapsed
+                .wait_for(|elapsed| start + *elapsed >= deadline)
+                .await
+                .is_err()
+            {
+                futures::future::pending::<()>().await;
+            }
+        })
+    }
+}
+
+/// Run `future` until `deadline` on `clock`, returning `None` if the deadline
+/// passes first.
+pub(crate) async fn timeout_at<F: Future>(
+    clock: &dyn Clock,
+    deadline: Instant,
+    future: F,
+) -> Option<F::Output> {
+    tokio::select! {
+        output = future => Some(output),
+        () = clock.sleep_until(deadline) => None,
+    }
+}
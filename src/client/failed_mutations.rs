@@ -0,0 +1,81 @@
+//! Mutations that failed, kept for a manual retry. See
+//! [`ConvexClientBuilder::retain_failed_mutations`].
+
+use std::{
+    collections::{
+        BTreeMap,
+        VecDeque,
+    },
+    sync::Mutex,
+};
+
+use convex_sync_types::UdfPath;
+
+use crate::value::Value;
+#[cfg(doc)]
+use crate::{
+    ConvexClient,
+    ConvexClientBuilder,
+};
+
+/// A mutation that failed, as listed by [`ConvexClient::failed_mutations`].
+/// Pass it to [`ConvexClient::retry`] to run it again.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FailedMutation {
+    /// Distinguishes failures of identical mutations, so that retrying one
+    /// only removes that one from the list.
+    id: u64,
+    /// The function that was called.
+    pub udf_path: UdfPath,
+    /// The arguments it was called with.
+    pub args: BTreeMap<String, Value>,
+    /// Why it failed: the error message of the mutation, or the client error
+    /// if it never completed, e.g. because the client disconnected.
+    pub error: String,
+}
+
+/// The most recent failed mutations of a client and its clones.
+pub struct FailedMutations {
+    limit: usize,
+    state: Mutex<(u64, VecDeque<FailedMutation>)>,
+}
+
+impl FailedMutations {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            state: Mutex::new((0, VecDeque::new())),
+        }
+    }
+
+    /// Record a failure, dropping the oldest one if the list is full.
+    pub fn insert(&self, udf_path: UdfPath, args: BTreeMap<String, Value>, error: String) {
+        let mut state = self.state.lock().expect("Failed mutations lock poisoned");
+        let (next_id, failed) = &mut *state;
+        let id = *next_id;
+        *next_id += 1;
+        if failed.len() == self.limit {
+            failed.pop_front();
+        }
+        if self.limit > 0 {
+            failed.push_back(FailedMutation {
+                id,
+                udf_path,
+                args,
+                error,
+            });
+        }
+    }
+
+    /// The recorded failures, oldest first.
+    pub fn list(&self) -> Vec<FailedMutation> {
+        let state = self.state.lock().expect("Failed mutations lock poisoned");
+        state.1.iter().cloned().collect()
+    }
+
+    /// Forget `mutation`, e.g. because it's being retried.
+    pub fn remove(&self, mutation: &FailedMutation) {
+        let mut state = self.state.lock().expect("Failed mutations lock poisoned");
+        state.1.retain(|failed| failed.id != mutation.id);
+    }
+}
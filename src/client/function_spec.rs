@@ -0,0 +1,317 @@
+//! Listing the functions of a deployment, e.g. for autocomplete in tools.
+
+use anyhow::Context;
+use convex_sync_types::AuthenticationToken;
+use reqwest::header::{
+    AUTHORIZATION,
+    CONTENT_TYPE,
+};
+use serde::Deserialize;
+use serde_json::{
+    json,
+    Value as JsonValue,
+};
+
+use crate::{
+    client::http::authorization_header,
+    ConvexClient,
+};
+
+/// The system query that describes the deployment's functions, as used by
+/// `npx convex function-spec`.
+const API_SPEC_PATH: &str = "_system/cli/modules:apiSpec";
+
+/// Whether a function is a query, a mutation or an action.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FunctionType {
+    /// A query, for [`ConvexClient::query`] and [`ConvexClient::subscribe`].
+    Query,
+    /// A mutation, for [`ConvexClient::mutation`].
+    Mutation,
+    /// An action, for [`ConvexClient::action`].
+    Action,
+}
+
+/// A function of a deployment, as listed by
+/// [`ConvexClient::list_functions`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct FunctionSpec {
+    /// The canonical path of the function, e.g. `messages.js:list`.
+    pub path: String,
+    /// Whether it's a query, a mutation or an action.
+    pub function_type: FunctionType,
+    /// Whether it's an internal function, which can only be called by other
+    /// functions and not by clients.
+    pub internal: bool,
+    /// The validator of the function's arguments, in the JSON format of
+    /// Convex's validators, if it declares one.
+    pub args: Option<JsonValue>,
+    /// The validator of the function's return value, in the same format, if it
+    /// declares one.
+    pub returns: Option<JsonValue>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FunctionSpecJson {
+    function_type: String,
+    identifier: Option<String>,
+    visibility: Option<VisibilityJson>,
+    args: Option<String>,
+    returns: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct VisibilityJson {
+    kind: String,
+}
+
+impl ConvexClient {
+    /// List the queries, mutations and actions of the deployment, e.g. to
+    /// autocomplete function names in an editor.
+    ///
+    /// This runs the system query `_system/cli/modules:apiSpec` over HTTP, by
+    /// sending a `POST` request to the deployment's `/api/query` endpoint, like
+    /// `npx convex function-spec` does. It requires a deploy key set with
+    /// `ConvexClient::set_admin_auth`, and fails without one. HTTP actions
+    /// aren't listed.
+    pub async fn list_functions(&self) -> anyhow::Result<Vec<FunctionSpec>> {
+        let token = self
+            .auth_token
+            .lock()
+            .expect("Auth token lock poisoned")
+            .clone();
+        anyhow::ensure!(
+            matches!(token, AuthenticationToken::Admin(..)),
+            "Listing functions requires admin auth with a deploy key"
+        );
+        let authorization = authorization_header(&token)?.context("Missing admin auth")?;
+        let url = self
+            .deployment_url
+            .join("api/query")
+            .context("Bad deployment URL")?;
+        let body = json!({"path": API_SPEC_PATH, "args": {}, "format": "json"});
+        let response = self
+            .http_client
+            .post(url)
+            .header(AUTHORIZATION, authorization)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await?;
+        let status = response.status();
+        let body = response.bytes().await?;
+        anyhow::ensure!(
+            status.is_success(),
+            "Listing functions failed with {status}: {}",
+            String::from_utf8_lossy(&body)
+        );
+        parse_api_spec(serde_json::from_slice(&body)?)
+    }
+}
+
+/// Parse the response of the `/api/query` endpoint to the spec query.
+fn parse_api_spec(response: JsonValue) -> anyhow::Result<Vec<FunctionSpec>> {
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct QueryResponse {
+        status: String,
+        value: Option<Vec<FunctionSpecJson>>,
+        error_message: Option<String>,
+    }
+    let response: QueryResponse = serde_json::from_value(response)?;
+    if response.status != "success" {
+        anyhow::bail!(
+            "Listing functions failed: {}",
+            response.error_message.unwrap_or(response.status)
+        );
+    }
+    let mut functions = vec![];
+    for spec in response.value.context("Missing function specs")? {
+        let function_type = match &spec.function_type[..] {
+            "Query" => FunctionType::Query,
+            "Mutation" => FunctionType::Mutation,
+            "Action" => FunctionType::Action,
+            // HTTP actions, or types added after this client.
+            _ => continue,
+        };
+        let validator = |validator: Option<String>| -> anyhow::Result<Option<JsonValue>> {
+            validator
+                .map(|validator| serde_json::from_str(&validator))
+                .transpose()
+                .context("Bad validator in function spec")
+        };
+        functions.push(FunctionSpec {
+            path: spec.identifier.context("Function spec without a path")?,
+            function_type,
+            internal: spec.visibility.is_some_and(|v| v.kind == "internal"),
+            args: validator(spec.args)?,
+            returns: validator(spec.returns)?,
+        });
+    }
+    Ok(functions)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tokio::{
+        io::{
+            AsyncReadExt,
+            AsyncWriteExt,
+        },
+        net::TcpListener,
+    };
+
+    use super::{
+        parse_api_spec,
+        FunctionSpec,
+        FunctionType,
+    };
+    use crate::ConvexClient;
+
+    /// Answer a single HTTP request on `listener` with `status` and `body`,
+    /// returning the request as text.
+    async fn respond_once(
+        listener: TcpListener,
+        status: &'static str,
+        body: String,
+    ) -> anyhow::Result<String> {
+        let (mut stream, _) = listener.accept().await?;
+        let mut request = vec![];
+        let mut buf = [0; 4096];
+        loop {
+            let n = stream.read(&mut buf).await?;
+            anyhow::ensure!(n > 0, "Connection closed mid-request");
+            request.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&request);
+            let Some(header_end) = text.find("\r\n\r\n") else {
+                continue;
+            };
+            let content_length = text[..header_end]
+                .lines()
+                .find_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length")
+                        .then(|| value.trim().parse::<usize>().ok())?
+                })
+                .unwrap_or(0);
+            if request.len() >= header_end + 4 + content_length {
+                break;
+            }
+        }
+        let response = format!(
+            "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: \
+             {}\r\nconnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(response.as_bytes()).await?;
+        Ok(String::from_utf8(request)?)
+    }
+
+    #[tokio::test]
+    async fn test_list_functions() -> anyhow::Result<()> {
+        let (mut client, _test_protocol) = ConvexClient::with_test_protocol().await?;
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        client.deployment_url = format!("http://{}", listener.local_addr()?).parse()?;
+
+        // Without a deploy key, nothing is sent.
+        client.set_auth(Some("usertoken".into())).await;
+        let err = client.list_functions().await.unwrap_err();
+        assert!(err.to_string().contains("admin auth"), "{err}");
+
+        client.set_admin_auth("mydeploykey".into(), None).await;
+        let body = json!({
+            "status": "success",
+            "value": [{"functionType": "Query", "identifier": "messages.js:list"}],
+        });
+        let server = tokio::spawn(respond_once(listener, "200 OK", body.to_string()));
+        let functions = client.list_functions().await?;
+        assert_eq!(
+            functions,
+            vec![FunctionSpec {
+                path: "messages.js:list".into(),
+                function_type: FunctionType::Query,
+                internal: false,
+                args: None,
+                returns: None,
+            }]
+        );
+        let request = server.await??;
+        assert!(
+            request.starts_with("POST /api/query HTTP/1.1\r\n"),
+            "{request}"
+        );
+        assert!(
+            request
+                .to_lowercase()
+                .contains("authorization: convex mydeploykey\r\n"),
+            "{request}"
+        );
+        let (_, request_body) = request.split_once("\r\n\r\n").unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(request_body)?,
+            json!({"path": "_system/cli/modules:apiSpec", "args": {}, "format": "json"})
+        );
+
+        // A failed request surfaces the status and the body.
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        client.deployment_url = format!("http://{}", listener.local_addr()?).parse()?;
+        let server = tokio::spawn(respond_once(
+            listener,
+            "401 Unauthorized",
+            "Bad deploy key".into(),
+        ));
+        let err = client.list_functions().await.unwrap_err();
+        server.await??;
+        assert!(err.to_string().contains("401"), "{err}");
+        assert!(err.to_string().contains("Bad deploy key"), "{err}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_api_spec() -> anyhow::Result<()> {
+        let response = json!({
+            "status": "success",
+            "value": [
+                {
+                    "functionType": "Query",
+                    "identifier": "messages.js:list",
+                    "visibility": {"kind": "public"},
+                    "args": "{\"type\":\"object\",\"value\":{}}",
+                    "returns": "{\"type\":\"any\"}",
+                },
+                {
+                    "functionType": "Mutation",
+                    "identifier": "messages.js:purge",
+                    "visibility": {"kind": "internal"},
+                },
+                {"functionType": "HttpAction", "method": "POST", "path": "/postMessage"},
+            ],
+        });
+        assert_eq!(
+            parse_api_spec(response)?,
+            vec![
+                FunctionSpec {
+                    path: "messages.js:list".into(),
+                    function_type: FunctionType::Query,
+                    internal: false,
+                    args: Some(json!({"type": "object", "value": {}})),
+                    returns: Some(json!({"type": "any"})),
+                },
+                FunctionSpec {
+                    path: "messages.js:purge".into(),
+                    function_type: FunctionType::Mutation,
+                    internal: true,
+                    args: None,
+                    returns: None,
+                },
+            ]
+        );
+
+        let error = json!({"status": "error", "errorMessage": "Unauthorized"});
+        let err = parse_api_spec(error).unwrap_err();
+        assert!(err.to_string().contains("Unauthorized"), "{err}");
+        Ok(())
+    }
+}
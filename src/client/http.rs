@@ -0,0 +1,188 @@
+use anyhow::Context;
+use bytes::Bytes;
+use convex_sync_types::AuthenticationToken;
+use reqwest::{
+    header::{
+        HeaderMap,
+        HeaderValue,
+        AUTHORIZATION,
+    },
+    Method,
+    StatusCode,
+};
+use url::Url;
+
+use crate::ConvexClient;
+
+/// The response to an HTTP action, as returned by
+/// [`ConvexClient::http_action`].
+#[derive(Clone, Debug)]
+pub struct HttpResponse {
+    /// The status code of the response.
+    pub status: StatusCode,
+    /// The headers of the response.
+    pub headers: HeaderMap,
+    /// The body of the response.
+    pub body: Bytes,
+}
+
+impl ConvexClient {
+    /// Call the HTTP action routed at `path` with `method`, `headers` and
+    /// `body`.
+    ///
+    /// HTTP actions are served from the deployment's `.convex.site` domain, so
+    /// the request is sent to the deployment URL with `.convex.cloud` replaced
+    /// by `.convex.site`, e.g. `https://cool-music-123.convex.cloud` becomes
+    /// `https://cool-music-123.convex.site`. Fails for deployment URLs that
+    /// aren't on `.convex.cloud`.
+    ///
+    /// If auth was set with [`ConvexClient::set_auth`], the token is sent as
+    /// an `Authorization: Bearer <token>` header, unless `headers` already
+    /// contains an `Authorization` header. The response is returned regardless
+    /// of its status code.
+    ///
+    /// ```no_run
+    /// # use convex::ConvexClient;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let client = ConvexClient::new("https://cool-music-123.convex.cloud").await?;
+    /// let response = client
+    ///     .http_action(
+    ///         reqwest::Method::POST,
+    ///         "/postMessage",
+    ///         reqwest::header::HeaderMap::new(),
+    ///         "Let it be.".into(),
+    ///     )
+    ///     .await?;
+    /// println!("{}: {:?}", response.status, response.body);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn http_action(
+        &self,
+        method: Method,
+        path: &str,
+        mut headers: HeaderMap,
+        body: Bytes,
+    ) -> anyhow::Result<HttpResponse> {
+        let url = http_action_url(&self.deployment_url, path)?;
+        if !headers.contains_key(AUTHORIZATION) {
+            let token = self
+                .auth_token
+                .lock()
+                .expect("Auth token lock poisoned")
+                .clone();
+            if let Some(authorization) = authorization_header(&token)? {
+                headers.insert(AUTHORIZATION, authorization);
+            }
+        }
+        let response = self
+            .http_client
+            .request(method, url)
+            .headers(headers)
+            .body(body)
+            .send()
+            .await?;
+        Ok(HttpResponse {
+            status: response.status(),
+            headers: response.headers().clone(),
+            body: response.bytes().await?,
+        })
+    }
+}
+
+fn deployment_to_site_url(deployment_url: &Url) -> anyhow::Result<Url> {
+    let host = deployment_url.host_str().unwrap_or_default();
+    let Some(name) = host.strip_suffix(".convex.cloud") else {
+        anyhow::bail!("Can't derive the HTTP actions URL for {deployment_url}");
+    };
+    let mut site_url = deployment_url.clone();
+    site_url.set_host(Some(&format!("{name}.convex.site")))?;
+    if let scheme @ ("ws" | "wss") = site_url.scheme() {
+        let scheme = if scheme == "ws" { "http" } else { "https" };
+        site_url.set_scheme(scheme).expect("Scheme not supported");
+    }
+    site_url.set_path("/");
+    Ok(site_url)
+}
+
+/// Resolve `path` against the site URL, making sure it can't point the request
+/// (and the auth header sent with it) at another origin, as an absolute URL
+/// like `https://example.com` or a protocol-relative `//example.com` would.
+fn http_action_url(deployment_url: &Url, path: &str) -> anyhow::Result<Url> {
+    let site_url = deployment_to_site_url(deployment_url)?;
+    let url = site_url.join(path).context("Bad HTTP action path")?;
+    anyhow::ensure!(
+        url.origin() == site_url.origin(),
+        "HTTP action path {path:?} points outside of {site_url}"
+    );
+    Ok(url)
+}
+
+pub(super) fn authorization_header(
+    token: &AuthenticationToken,
+) -> anyhow::Result<Option<HeaderValue>> {
+    let value = match token {
+        AuthenticationToken::User(token) => format!("Bearer {token}"),
+        AuthenticationToken::Admin(deploy_key, _) => format!("Convex {deploy_key}"),
+        AuthenticationToken::None => return Ok(None),
+    };
+    Ok(Some(
+        HeaderValue::try_from(value).context("Bad auth token")?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use convex_sync_types::AuthenticationToken;
+
+    use super::{
+        authorization_header,
+        deployment_to_site_url,
+        http_action_url,
+    };
+
+    #[test]
+    fn test_deployment_to_site_url() -> anyhow::Result<()> {
+        assert_eq!(
+            deployment_to_site_url(&"https://flying-shark-123.convex.cloud".parse()?)?
+                .join("/api/messages")?
+                .to_string(),
+            "https://flying-shark-123.convex.site/api/messages",
+        );
+        assert_eq!(
+            deployment_to_site_url(&"ws://flying-shark-123.convex.cloud".parse()?)?.to_string(),
+            "http://flying-shark-123.convex.site/",
+        );
+        assert!(deployment_to_site_url(&"https://example.com".parse()?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_http_action_url() -> anyhow::Result<()> {
+        let deployment_url = "https://flying-shark-123.convex.cloud".parse()?;
+        assert_eq!(
+            http_action_url(&deployment_url, "/api/messages?limit=10")?.to_string(),
+            "https://flying-shark-123.convex.site/api/messages?limit=10",
+        );
+        assert_eq!(
+            http_action_url(&deployment_url, "api/messages")?.to_string(),
+            "https://flying-shark-123.convex.site/api/messages",
+        );
+        // The path can't send the request elsewhere.
+        assert!(http_action_url(&deployment_url, "//evil.com/x").is_err());
+        assert!(http_action_url(&deployment_url, "https://evil.com").is_err());
+        assert!(http_action_url(&deployment_url, "http://flying-shark-123.convex.site/").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_authorization_header() -> anyhow::Result<()> {
+        assert_eq!(
+            authorization_header(&AuthenticationToken::User("abc".into()))?.unwrap(),
+            "Bearer abc"
+        );
+        assert_eq!(authorization_header(&AuthenticationToken::None)?, None);
+        Ok(())
+    }
+}
@@ -1,28 +1,45 @@
 use std::{
+    cmp,
     collections::BTreeMap,
-    convert::Infallible,
-    sync::Arc,
+    fmt,
+    sync::{
+        atomic::{
+            AtomicBool,
+            Ordering,
+        },
+        Arc,
+        Mutex,
+    },
+    time::Duration,
 };
 
+use anyhow::Context;
 use convex_sync_types::{
     AuthenticationToken,
+    QueryId,
+    Timestamp,
     UdfPath,
     UserIdentityAttributes,
 };
-#[cfg(doc)]
-use futures::Stream;
 use futures::{
     channel::{
         mpsc,
         oneshot,
     },
     SinkExt,
+    Stream,
     StreamExt,
 };
+use serde::Serialize;
 use tokio::{
-    sync::broadcast,
+    sync::{
+        broadcast,
+        watch,
+    },
     task::JoinHandle,
 };
+#[cfg(feature = "unstable")]
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
 use tokio_stream::wrappers::BroadcastStream;
 use url::Url;
 
@@ -31,33 +48,75 @@ use self::worker::AuthenticateRequest;
 use crate::SubscriberId;
 use crate::{
     base_client::{
-        BaseConvexClient,
+        DeploymentInfo,
+        MutationOutcome,
+        QueryBuilder,
+        QueryPriority,
         QueryResults,
+        QuerySnapshot,
+        RequestPriority,
+        TransitionInfo,
     },
     client::{
+        builder::ConvexClientBuilder,
+        clock::{
+            timeout_at,
+            Clock,
+        },
+        failed_mutations::{
+            FailedMutation,
+            FailedMutations,
+        },
+        query_cache::QueryCache,
+        rate_limit::RateLimiter,
+        sink::UpdateSink,
         subscription::{
+            AdaptiveCoalescing,
             QuerySetSubscription,
+            QueryState,
             QuerySubscription,
         },
         worker::{
-            worker,
             ActionRequest,
             ClientRequest,
             MutationRequest,
             SubscribeRequest,
         },
     },
-    sync::{
-        web_socket_manager::WebSocketManager,
-        SyncProtocol,
+    sync::bandwidth::{
+        BandwidthCounters,
+        BandwidthUsage,
+    },
+    value::{
+        check_arg_names,
+        Value,
     },
-    value::Value,
+    ConvexError,
     FunctionResult,
 };
 
+pub mod builder;
+pub mod clock;
+pub mod failed_mutations;
+pub mod function_spec;
+pub mod http;
+pub mod outbound;
+pub mod pagination;
+mod query_cache;
+mod rate_limit;
+pub mod single_query;
+pub mod sink;
 pub mod subscription;
 mod worker;
 
+/// The environment variable that [`ConvexClient::from_env`] reads the
+/// deployment URL from.
+const DEPLOYMENT_URL_ENV_VAR: &str = "CONVEX_URL";
+
+/// How long [`ConvexClient::mutation_consistent`] waits for the next
+/// transition when a successful mutation has no commit timestamp.
+const MISSING_TS_WAIT: Duration = Duration::from_secs(1);
+
 /// An asynchronous client to interact with a specific project to perform
 /// mutations and manage query subscriptions using [`tokio`].
 ///
@@ -84,12 +143,38 @@ mod worker;
 /// **reuse** it. You can safely clone with [`ConvexClient::clone()`] to share
 /// the connection and outstanding subscriptions.
 ///
+/// ## Calls while disconnected
+/// The client is connected once it's constructed. If the connection is lost
+/// later, the client reconnects in the background, and calls made in the
+/// meantime don't fail: subscriptions are returned right away and start
+/// producing results once reconnected, and mutations and actions are queued
+/// and sent in order once reconnected, resolving when the server responds.
+/// Only a permanent disconnect (see [`ConvexClient::disconnect_error`]) fails
+/// them. Use [`ConvexClient::wait_connected`] to wait for the connection
+/// before making calls, e.g. to confirm connectivity.
+///
 /// ## Examples
 /// For example code, please refer to the examples directory.
 pub struct ConvexClient {
-    listen_handle: Option<Arc<JoinHandle<Infallible>>>,
+    listen_handle: Option<Arc<JoinHandle<ConvexError>>>,
     request_sender: mpsc::UnboundedSender<ClientRequest>,
     watch_receiver: broadcast::Receiver<QueryResults>,
+    deployment_url: Url,
+    auth_token: Arc<Mutex<AuthenticationToken>>,
+    http_client: reqwest::Client,
+    disconnect_error: Arc<Mutex<Option<ConvexError>>>,
+    /// Set once [`ConvexClient::close`] starts, so that new calls fail
+    /// without reaching the worker.
+    closing: Arc<AtomicBool>,
+    bandwidth: Arc<BandwidthCounters>,
+    query_cache: Option<Arc<QueryCache>>,
+    failed_mutations: Option<Arc<FailedMutations>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    max_argument_size: Option<usize>,
+    connected: watch::Receiver<bool>,
+    timestamp: watch::Receiver<Option<Timestamp>>,
+    coalescing: Option<AdaptiveCoalescing>,
+    clock: Arc<dyn Clock>,
 }
 
 /// Clone the [`ConvexClient`], sharing the connection and outstanding
@@ -100,6 +185,20 @@ impl Clone for ConvexClient {
             listen_handle: self.listen_handle.clone(),
             request_sender: self.request_sender.clone(),
             watch_receiver: self.watch_receiver.resubscribe(),
+            deployment_url: self.deployment_url.clone(),
+            auth_token: self.auth_token.clone(),
+            http_client: self.http_client.clone(),
+            disconnect_error: self.disconnect_error.clone(),
+            closing: self.closing.clone(),
+            bandwidth: self.bandwidth.clone(),
+            query_cache: self.query_cache.clone(),
+            failed_mutations: self.failed_mutations.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            max_argument_size: self.max_argument_size,
+            connected: self.connected.clone(),
+            timestamp: self.timestamp.clone(),
+            coalescing: self.coalescing,
+            clock: self.clock.clone(),
         }
     }
 }
@@ -121,6 +220,10 @@ impl Drop for ConvexClient {
 impl ConvexClient {
     /// Constructs a new client for communicating with `deployment_url`.
     ///
+    /// Use [`ConvexClientBuilder`] to configure the client further. Fails with
+    /// a [`ConvexError`](crate::ConvexError) if the initial connection can't be
+    /// opened, after retrying transient failures.
+    ///
     /// ```no_run
     /// # use convex::ConvexClient;
     /// # #[tokio::main]
@@ -130,32 +233,74 @@ impl ConvexClient {
     /// # }
     /// ```
     pub async fn new(deployment_url: &str) -> anyhow::Result<Self> {
-        let ws_url = deployment_to_ws_url(deployment_url.try_into()?)?;
-
-        // Channels for the `listen` background thread
-        let (response_sender, response_receiver) = mpsc::channel(1);
-        let (request_sender, request_receiver) = mpsc::unbounded();
+        ConvexClientBuilder::new(deployment_url).build().await
+    }
 
-        // Listener for when each transaction completes
-        let (watch_sender, watch_receiver) = broadcast::channel(1);
+    /// Constructs a new client for the deployment URL in the `CONVEX_URL`
+    /// environment variable, as set up by `npx convex dev`.
+    ///
+    /// With the `dotenv` feature, `.env.local` and then `.env` are loaded
+    /// first, if they exist. Variables that are already set take precedence
+    /// over both files, and `.env.local` takes precedence over `.env`.
+    ///
+    /// Fails if `CONVEX_URL` isn't set, and otherwise like
+    /// [`ConvexClient::new`].
+    ///
+    /// ```no_run
+    /// # use convex::ConvexClient;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let client = ConvexClient::from_env().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn from_env() -> anyhow::Result<Self> {
+        #[cfg(feature = "dotenv")]
+        {
+            dotenv::from_filename(".env.local").ok();
+            dotenv::dotenv().ok();
+        }
+        Self::from_env_with(|name| std::env::var(name)).await
+    }
 
-        let base_client = BaseConvexClient::new();
+    /// [`ConvexClient::from_env`], reading variables with `var` instead of
+    /// from the process environment, so that tests don't share it.
+    async fn from_env_with(
+        var: impl Fn(&str) -> Result<String, std::env::VarError>,
+    ) -> anyhow::Result<Self> {
+        let deployment_url = var(DEPLOYMENT_URL_ENV_VAR).with_context(|| {
+            format!(
+                "Missing the deployment URL in the {DEPLOYMENT_URL_ENV_VAR} environment variable"
+            )
+        })?;
+        Self::new(&deployment_url).await
+    }
 
-        let protocol = WebSocketManager::open(ws_url, response_sender).await?;
+    /// Check and normalize `deployment_url` without connecting, e.g. to fail
+    /// fast on a misconfigured URL while loading config at startup, before
+    /// entering an async context. Connect later with
+    /// [`ConvexClient::connect`].
+    ///
+    /// See [`ValidatedUrl`] for what's checked. [`ConvexClient::new`] runs the
+    /// same checks.
+    ///
+    /// ```
+    /// # use convex::ConvexClient;
+    /// let url = ConvexClient::validate_url("https://cool-music-123.convex.cloud")?;
+    /// assert!(ConvexClient::validate_url("cool-music-123.convex.cloud").is_err());
+    /// # anyhow::Ok(())
+    /// ```
+    pub fn validate_url(deployment_url: &str) -> anyhow::Result<ValidatedUrl> {
+        ValidatedUrl::parse(deployment_url)
+    }
 
-        let listen_handle = tokio::spawn(worker(
-            response_receiver,
-            request_receiver,
-            watch_sender,
-            base_client,
-            protocol,
-        ));
-        let client = ConvexClient {
-            listen_handle: Some(Arc::new(listen_handle)),
-            request_sender,
-            watch_receiver,
-        };
-        Ok(client)
+    /// Connect to a deployment whose URL was checked with
+    /// [`ConvexClient::validate_url`]. Fails like [`ConvexClient::new`] if the
+    /// initial connection can't be opened.
+    pub async fn connect(deployment_url: ValidatedUrl) -> anyhow::Result<Self> {
+        ConvexClientBuilder::new(deployment_url.as_str())
+            .build()
+            .await
     }
 
     /// Subscribe to the results of query `name` called with `args`.
@@ -166,6 +311,10 @@ impl ConvexClient {
     ///
     /// The subscription is automatically unsubscribed when it is dropped.
     ///
+    /// Fails without sending anything if `args` has a field name Convex
+    /// doesn't accept, as checked by [`Value::check_field_names`] with system
+    /// fields like `_id` allowed.
+    ///
     /// ```no_run
     /// # use convex::ConvexClient;
     /// # use futures::StreamExt;
@@ -186,18 +335,240 @@ impl ConvexClient {
         let (tx, rx) = oneshot::channel();
 
         let udf_path = name.parse()?;
+        check_arg_names(&args)?;
         let request = SubscribeRequest { udf_path, args };
 
-        self.request_sender
-            .send(ClientRequest::Subscribe(
-                request,
-                tx,
-                self.request_sender.clone(),
-            ))
+        let request_sender = self.request_sender.clone();
+        self.send_request(ClientRequest::Subscribe(request, tx, request_sender))
+            .await?;
+
+        let res = rx.await.map_err(|_| self.disconnected())?;
+        Ok(self.with_coalescing(res))
+    }
+
+    /// Like [`subscribe`](Self::subscribe), but after a reconnect the query is
+    /// re-subscribed according to `priority`, e.g. so that the queries of the
+    /// current screen get their results before background ones. See
+    /// [`QueryPriority`] for how queries are ordered. The priority doesn't
+    /// change how the subscription is sent while connected.
+    ///
+    /// ```no_run
+    /// # use convex::{ConvexClient, QueryPriority};
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut client = ConvexClient::new("https://cool-music-123.convex.cloud").await?;
+    /// let messages = client
+    ///     .subscribe_with_priority("listMessages", maplit::btreemap!{}, QueryPriority::High)
+    ///     .await?;
+    /// let stats = client
+    ///     .subscribe_with_priority("getStats", maplit::btreemap!{}, QueryPriority::Low)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn subscribe_with_priority(
+        &mut self,
+        name: &str,
+        args: BTreeMap<String, Value>,
+        priority: QueryPriority,
+    ) -> anyhow::Result<QuerySubscription> {
+        let query = QueryBuilder::new(name).args(args).priority(priority);
+        let mut subscriptions = self.subscribe_queries(vec![query]).await?;
+        Ok(subscriptions
+            .pop()
+            .expect("INTERNAL BUG: Missing subscription for a valid query."))
+    }
+
+    /// Subscribe to query `name` called with `args`, folding each result into
+    /// a state with `f`, starting from `initial`. Yields the state after each
+    /// update, or the error message of a failed result. See
+    /// [`QuerySubscription::reduce_values`], which this is a shorthand for.
+    ///
+    /// ```no_run
+    /// # use convex::{ConvexClient, Value};
+    /// # use futures::StreamExt;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut client = ConvexClient::new("https://cool-music-123.convex.cloud").await?;
+    /// // The highest count seen so far.
+    /// let mut peaks = client
+    ///     .subscribe_reduce("getCounter", maplit::btreemap!{}, 0, |peak, value| match value {
+    ///         Value::Int64(count) => peak.max(count),
+    ///         _ => peak,
+    ///     })
+    ///     .await?;
+    /// while let Some(peak) = peaks.next().await {
+    ///     println!("{peak:?}");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn subscribe_reduce<S, F>(
+        &mut self,
+        name: &str,
+        args: BTreeMap<String, Value>,
+        initial: S,
+        f: F,
+    ) -> anyhow::Result<impl Stream<Item = Result<S, String>> + Unpin>
+    where
+        S: Clone,
+        F: FnMut(S, Value) -> S,
+    {
+        Ok(self.subscribe(name, args).await?.reduce_values(initial, f))
+    }
+
+    /// Subscribe to query `name` called with `args`, yielding its
+    /// [`QueryState`] for a UI: [`Loading`](QueryState::Loading) first, then
+    /// [`Loaded`](QueryState::Loaded) or [`Error`](QueryState::Error) for
+    /// each result. See [`QueryState`] for the transitions, e.g. across
+    /// reconnects, and [`QuerySubscription::states`], which this is a
+    /// shorthand for.
+    ///
+    /// ```no_run
+    /// # use convex::{ConvexClient, QueryState};
+    /// # use futures::StreamExt;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut client = ConvexClient::new("https://cool-music-123.convex.cloud").await?;
+    /// let mut messages = client.subscribe_state("listMessages", maplit::btreemap!{}).await?;
+    /// while let Some(state) = messages.next().await {
+    ///     match state {
+    ///         QueryState::Loading => println!("Loading..."),
+    ///         QueryState::Loaded(messages) => println!("{messages:?}"),
+    ///         QueryState::Error(error) => println!("Failed: {error}"),
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn subscribe_state(
+        &mut self,
+        name: &str,
+        args: BTreeMap<String, Value>,
+    ) -> anyhow::Result<impl Stream<Item = QueryState> + Unpin> {
+        Ok(self.subscribe(name, args).await?.states())
+    }
+
+    /// Subscribe to query `name` called with `args`, and push each result
+    /// into `sink` from a spawned task, e.g. to write the results to a
+    /// database. See [`UpdateSink`] for an example and the backpressure
+    /// semantics, and [`QuerySubscription::drain_into`] to push the results
+    /// from the current task instead.
+    ///
+    /// The task runs until the client is dropped or the sink returns an
+    /// error, and then returns the sink, or the error. Aborting it
+    /// unsubscribes.
+    pub async fn subscribe_to_sink<S: UpdateSink + 'static>(
+        &mut self,
+        name: &str,
+        args: BTreeMap<String, Value>,
+        sink: S,
+    ) -> anyhow::Result<JoinHandle<anyhow::Result<S>>> {
+        let subscription = self.subscribe(name, args).await?;
+        Ok(tokio::spawn(subscription.drain_into(sink)))
+    }
+
+    /// Subscribe to several queries at once, given as `(name, args)` pairs.
+    ///
+    /// Returns one entry per query, in the same order. The valid queries are
+    /// added to the query set in a single request to the server, and each
+    /// entry reports its own fate:
+    /// - A `name` that is not a valid function path, or `args` with a field
+    ///   name Convex doesn't accept, fails its own entry, without affecting the
+    ///   other queries.
+    /// - A query that fails on the server (e.g. because its arguments don't
+    ///   validate) still gets a [`QuerySubscription`], which produces a
+    ///   [`FunctionResult::ErrorMessage`] with the server's failure message,
+    ///   while the others produce their values as usual.
+    ///
+    /// Only a failure of the client itself, e.g. because it has disconnected,
+    /// fails the whole batch.
+    ///
+    /// ```no_run
+    /// # use convex::ConvexClient;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut client = ConvexClient::new("https://cool-music-123.convex.cloud").await?;
+    /// let subscriptions = client
+    ///     .subscribe_many(vec![
+    ///         ("listMessages", maplit::btreemap!{}),
+    ///         ("listUsers", maplit::btreemap!{}),
+    ///     ])
+    ///     .await?;
+    /// for subscription in subscriptions {
+    ///     let subscription = subscription?;
+    /// }
+    /// # Ok(())
+    /// # }
+    pub async fn subscribe_many(
+        &mut self,
+        queries: Vec<(&str, BTreeMap<String, Value>)>,
+    ) -> anyhow::Result<Vec<anyhow::Result<QuerySubscription>>> {
+        let mut results = Vec::with_capacity(queries.len());
+        let mut valid = vec![];
+        for (name, args) in queries {
+            match name.parse::<UdfPath>().and_then(|_| check_arg_names(&args)) {
+                Ok(()) => {
+                    valid.push(QueryBuilder::new(name).args(args));
+                    results.push(None);
+                },
+                Err(e) => results.push(Some(Err(e))),
+            }
+        }
+        let mut subscriptions = self.subscribe_queries(valid).await?.into_iter();
+        Ok(results
+            .into_iter()
+            .map(|result| {
+                result.unwrap_or_else(|| {
+                    Ok(subscriptions
+                        .next()
+                        .expect("INTERNAL BUG: Missing subscription for a valid query."))
+                })
+            })
+            .collect())
+    }
+
+    /// Like [`ConvexClient::subscribe_many`], but with full control over each
+    /// query, e.g. to resume a paginated query from the journal of an earlier
+    /// result. See [`QueryBuilder`] for which fields are set by the caller and
+    /// which are managed by the client.
+    ///
+    /// ```no_run
+    /// # use convex::{ConvexClient, QueryBuilder};
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// # let journal = String::new();
+    /// let mut client = ConvexClient::new("https://cool-music-123.convex.cloud").await?;
+    /// let subscriptions = client
+    ///     .subscribe_queries(vec![
+    ///         QueryBuilder::new("listMessages").journal(journal),
+    ///         QueryBuilder::new("listUsers").arg("limit", 10),
+    ///     ])
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn subscribe_queries(
+        &mut self,
+        queries: Vec<QueryBuilder>,
+    ) -> anyhow::Result<Vec<QuerySubscription>> {
+        let (tx, rx) = oneshot::channel();
+        let request_sender = self.request_sender.clone();
+        self.send_request(ClientRequest::SubscribeMany(queries, tx, request_sender))
             .await?;
+        let subscriptions = rx.await.map_err(|_| self.disconnected())??;
+        Ok(subscriptions
+            .into_iter()
+            .map(|subscription| self.with_coalescing(subscription))
+            .collect())
+    }
 
-        let res = rx.await?;
-        Ok(res)
+    /// Apply the client's [`AdaptiveCoalescing`], if any, to `subscription`.
+    fn with_coalescing(&self, subscription: QuerySubscription) -> QuerySubscription {
+        match self.coalescing {
+            Some(config) => subscription.coalesce(config),
+            None => subscription,
+        }
     }
 
     /// Make a oneshot request to a query `name` with `args`.
@@ -209,6 +580,12 @@ impl ConvexClient {
     /// It is equivalent to `client.subscribe(name,
     /// args).await?.next().unwrap()`
     ///
+    /// If the client was built with
+    /// [`ConvexClientBuilder::query_cache_ttl`], a result fetched by an
+    /// identical query within the TTL is returned without contacting the
+    /// deployment, even if it has changed since. Subscriptions never use the
+    /// cache.
+    ///
     /// ```no_run
     /// # use convex::ConvexClient;
     /// # use futures::StreamExt;
@@ -224,17 +601,135 @@ impl ConvexClient {
         name: &str,
         args: BTreeMap<String, Value>,
     ) -> anyhow::Result<FunctionResult> {
-        Ok(self
-            .subscribe(name, args)
+        let Some(cache) = self.query_cache.clone() else {
+            return self.query_uncached(name, args).await;
+        };
+        let udf_path: UdfPath = name.parse()?;
+        let key = (
+            udf_path.canonicalize(),
+            Value::Object(args.clone()).stable_hash(),
+        );
+        if let Some(value) = cache.get(&key) {
+            return Ok(FunctionResult::Value(value));
+        }
+        let generation = cache.generation();
+        let result = self.query_uncached(name, args).await?;
+        // Errors aren't cached, so that the next query retries.
+        if let FunctionResult::Value(value) = &result {
+            cache.insert(key, value.clone(), generation);
+        }
+        Ok(result)
+    }
+
+    async fn query_uncached(
+        &mut self,
+        name: &str,
+        args: BTreeMap<String, Value>,
+    ) -> anyhow::Result<FunctionResult> {
+        self.subscribe(name, args)
             .await?
             .next()
             .await
-            .expect("INTERNAL BUG: Convex Client dropped prematurely."))
+            .ok_or_else(|| self.disconnected())
+    }
+
+    /// Make a oneshot request to a query `name`, passing the fields of `args`
+    /// as its arguments.
+    ///
+    /// `args` can be any type implementing [`serde::Serialize`] that
+    /// serializes to a map, such as a struct deriving `Serialize`. It is
+    /// converted with [`Value::from_serialize`], so see there for how numbers
+    /// are encoded. Fails if `args` does not serialize to an object.
+    ///
+    /// ```no_run
+    /// # use convex::ConvexClient;
+    /// #[derive(serde::Serialize)]
+    /// struct ListMessages {
+    ///     channel: String,
+    /// }
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut client = ConvexClient::new("https://cool-music-123.convex.cloud").await?;
+    /// let args = ListMessages {
+    ///     channel: "general".into(),
+    /// };
+    /// let result = client.query_serialized("listMessages", &args).await?;
+    /// println!("{result:?}");
+    /// # Ok(())
+    /// # }
+    pub async fn query_serialized<A: Serialize + ?Sized>(
+        &mut self,
+        name: &str,
+        args: &A,
+    ) -> anyhow::Result<FunctionResult> {
+        let args = match Value::from_serialize(args)? {
+            Value::Object(fields) => fields,
+            value => anyhow::bail!("Query arguments must serialize to an object, found {value:?}"),
+        };
+        self.query(name, args).await
+    }
+
+    /// Subscribe to query `name` with `args`, collect the next `n` values it
+    /// produces, and then unsubscribe.
+    ///
+    /// This is primarily a testing helper for asserting on reactive behavior,
+    /// e.g. that a query emitted a specific sequence of values after a few
+    /// mutations. If the query already has a result, it counts as the first
+    /// collected value.
+    ///
+    /// Fails if fewer than `n` values arrive within `timeout`, or if the query
+    /// returns an error. The subscription is dropped (and thus unsubscribed)
+    /// in every case. Note that, as with [`QuerySubscription`], a consumer
+    /// that falls behind may skip intermediate values, since Convex only
+    /// guarantees that each value is newer than the previous one.
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use convex::ConvexClient;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut client = ConvexClient::new("https://cool-music-123.convex.cloud").await?;
+    /// let values = client
+    ///     .collect_updates("getCounter", maplit::btreemap!{}, 3, Duration::from_secs(5))
+    ///     .await?;
+    /// println!("{values:?}");
+    /// # Ok(())
+    /// # }
+    pub async fn collect_updates(
+        &mut self,
+        name: &str,
+        args: BTreeMap<String, Value>,
+        n: usize,
+        timeout: Duration,
+    ) -> anyhow::Result<Vec<Value>> {
+        let deadline = self.clock.now() + timeout;
+        let mut subscription = self.subscribe(name, args).await?;
+        let collect = async {
+            let mut values = Vec::with_capacity(n);
+            while values.len() < n {
+                match subscription.next().await {
+                    Some(FunctionResult::Value(value)) => values.push(value),
+                    Some(FunctionResult::ErrorMessage(error)) => anyhow::bail!(error),
+                    None => anyhow::bail!("Convex Client dropped before {n} updates arrived"),
+                }
+            }
+            Ok(values)
+        };
+        timeout_at(&*self.clock, deadline, collect)
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Timed out after {timeout:?} waiting for {n} updates"))?
     }
 
     /// Perform a mutation `name` with `args` and return a future
     /// containing the return value of the mutation once it completes.
     ///
+    /// Every [`Value::Id`] in `args` is checked with
+    /// [`DocumentId::parse`](crate::DocumentId::parse) first, and every field
+    /// name with [`Value::check_field_names`], allowing system fields like
+    /// `_id`, so that a malformed ID or name fails here rather than in the
+    /// deployment.
+    ///
     /// ```no_run
     /// # use convex::ConvexClient;
     /// # use futures::StreamExt;
@@ -252,23 +747,230 @@ impl ConvexClient {
         &mut self,
         name: &str,
         args: BTreeMap<String, Value>,
+    ) -> anyhow::Result<FunctionResult> {
+        self.mutation_with_priority(name, args, RequestPriority::Normal)
+            .await
+    }
+
+    /// Like [`mutation`](Self::mutation), but sent according to `priority`.
+    ///
+    /// A [`RequestPriority::High`] mutation is sent ahead of the
+    /// subscriptions and unsubscriptions that were requested concurrently and
+    /// are still waiting to be sent, e.g. so that a mutation triggered by user
+    /// input doesn't wait behind a page of newly subscribed queries. It's
+    /// still sent after earlier mutations and actions, and it doesn't change
+    /// how the server schedules it.
+    pub async fn mutation_with_priority(
+        &mut self,
+        name: &str,
+        args: BTreeMap<String, Value>,
+        priority: RequestPriority,
     ) -> anyhow::Result<FunctionResult> {
         let (tx, rx) = oneshot::channel();
 
         let udf_path: UdfPath = name.parse()?;
-        let request = MutationRequest { udf_path, args };
+        args.values().try_for_each(Value::check_ids)?;
+        check_arg_names(&args)?;
+        self.check_argument_size(&args)?;
+        let retained = self
+            .failed_mutations
+            .as_ref()
+            .map(|_| (udf_path.clone(), args.clone()));
+        let request = MutationRequest {
+            udf_path,
+            args,
+            priority,
+        };
 
-        self.request_sender
-            .send(ClientRequest::Mutation(request, tx))
-            .await?;
+        let result = async {
+            self.send_request(ClientRequest::Mutation(request, tx))
+                .await?;
+            let res = rx.await.map_err(|_| self.disconnected())?;
+            res.await.map_err(|_| self.disconnected())
+        }
+        .await;
+        self.record_failure(retained, result.as_ref());
+        result
+    }
+
+    /// Check `args` against the limit set with
+    /// [`ConvexClientBuilder::max_argument_size`], if any.
+    fn check_argument_size(&self, args: &BTreeMap<String, Value>) -> Result<(), ConvexError> {
+        let Some(limit) = self.max_argument_size else {
+            return Ok(());
+        };
+        let size = serde_json::Value::from(Value::Object(args.clone()))
+            .to_string()
+            .len();
+        if size > limit {
+            return Err(ConvexError::ArgumentTooLarge { size, limit });
+        }
+        Ok(())
+    }
+
+    /// Keep the mutation `retained` if it failed with `result`, when enabled
+    /// with [`ConvexClientBuilder::retain_failed_mutations`].
+    fn record_failure(
+        &self,
+        retained: Option<(UdfPath, BTreeMap<String, Value>)>,
+        result: Result<&FunctionResult, &anyhow::Error>,
+    ) {
+        let (Some(failed_mutations), Some((udf_path, args))) = (&self.failed_mutations, retained)
+        else {
+            return;
+        };
+        let error = match result {
+            Ok(FunctionResult::Value(_)) => return,
+            Ok(FunctionResult::ErrorMessage(message)) => message.clone(),
+            Err(e) => e.to_string(),
+        };
+        failed_mutations.insert(udf_path, args, error);
+    }
+
+    /// The mutations that failed, oldest first, if enabled with
+    /// [`ConvexClientBuilder::retain_failed_mutations`]. Otherwise, this is
+    /// always empty.
+    ///
+    /// ```no_run
+    /// # use convex::{ConvexClientBuilder, FunctionResult};
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut client = ConvexClientBuilder::new("https://cool-music-123.convex.cloud")
+    ///     .retain_failed_mutations(100)
+    ///     .build()
+    ///     .await?;
+    /// // Later, e.g. once the user asks to retry:
+    /// for failed in client.failed_mutations() {
+    ///     println!("Retrying {} after {}", failed.udf_path, failed.error);
+    ///     if let FunctionResult::ErrorMessage(error) = client.retry(failed).await? {
+    ///         println!("Failed again: {error}");
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn failed_mutations(&self) -> Vec<FailedMutation> {
+        self.failed_mutations
+            .as_ref()
+            .map(|failed_mutations| failed_mutations.list())
+            .unwrap_or_default()
+    }
+
+    /// Run a mutation from [`ConvexClient::failed_mutations`] again, removing
+    /// it from the list. If it fails again, it's added back as a new failure.
+    pub async fn retry(&mut self, mutation: FailedMutation) -> anyhow::Result<FunctionResult> {
+        if let Some(failed_mutations) = &self.failed_mutations {
+            failed_mutations.remove(&mutation);
+        }
+        let FailedMutation { udf_path, args, .. } = mutation;
+        self.mutation(&String::from(udf_path), args).await
+    }
+
+    /// Like [`mutation`](Self::mutation), but the result comes with the
+    /// timestamp the mutation committed at, or `None` if it didn't write
+    /// anything, e.g. because it only read or validated its arguments.
+    ///
+    /// Either way, the client's queries reflect the mutation by the time it
+    /// returns. Pass the timestamp to
+    /// [`await_timestamp`](Self::await_timestamp) on another client, or after
+    /// resubscribing, to read your writes there: for a mutation that didn't
+    /// write, there's nothing to wait for and it returns immediately.
+    ///
+    /// ```no_run
+    /// # use convex::ConvexClient;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut client = ConvexClient::new("https://cool-music-123.convex.cloud").await?;
+    /// let mut reader = client.clone();
+    /// let outcome = client.mutation_with_ts("sendMessage", maplit::btreemap!{
+    ///     "body".into() => "Let it be.".into(),
+    /// }).await?;
+    /// reader.await_timestamp(outcome.ts).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn mutation_with_ts(
+        &mut self,
+        name: &str,
+        args: BTreeMap<String, Value>,
+    ) -> anyhow::Result<MutationOutcome> {
+        let (tx, rx) = oneshot::channel();
+
+        let udf_path: UdfPath = name.parse()?;
+        args.values().try_for_each(Value::check_ids)?;
+        check_arg_names(&args)?;
+        self.check_argument_size(&args)?;
+        let retained = self
+            .failed_mutations
+            .as_ref()
+            .map(|_| (udf_path.clone(), args.clone()));
+        let request = MutationRequest {
+            udf_path,
+            args,
+            priority: RequestPriority::Normal,
+        };
+
+        let result = async {
+            self.send_request(ClientRequest::MutationWithTs(request, tx))
+                .await?;
+            let res = rx.await.map_err(|_| self.disconnected())?;
+            res.await.map_err(|_| self.disconnected())
+        }
+        .await;
+        self.record_failure(retained, result.as_ref().map(|outcome| &outcome.result));
+        result
+    }
 
-        let res = rx.await?;
-        Ok(res.await?)
+    /// Like [`mutation_with_ts`](Self::mutation_with_ts), but fails if the
+    /// client's queries don't reflect the mutation within `timeout`, as
+    /// measured by the client's [`Clock`].
+    ///
+    /// The timeout covers sending the mutation, running it, and the client
+    /// catching up to its timestamp, e.g. while it's reconnecting. A timeout
+    /// doesn't cancel the mutation: it may still commit afterwards.
+    ///
+    /// A successful mutation without a timestamp usually didn't write, but an
+    /// older server may also omit the timestamp of one that did. So instead
+    /// of returning right away, this waits up to a second, bounded by
+    /// `timeout`, for the client to apply any newer transition, and returns
+    /// the outcome either way. The guarantee is weaker in that case: the
+    /// queries reflect the mutation only if the server sent its writes in the
+    /// first transition after the response.
+    pub async fn mutation_consistent(
+        &mut self,
+        name: &str,
+        args: BTreeMap<String, Value>,
+        timeout: Duration,
+    ) -> anyhow::Result<MutationOutcome> {
+        let deadline = self.clock.now() + timeout;
+        let clock = self.clock.clone();
+        let outcome = timeout_at(&*clock, deadline, self.mutation_with_ts(name, args))
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Mutation {name} not reflected after {timeout:?}"))??;
+        if outcome.ts.is_none() && matches!(outcome.result, FunctionResult::Value(_)) {
+            // Transitions applied before the response can't include the
+            // mutation's writes, so only wait for one after it.
+            let start_ts = *self.timestamp.borrow();
+            let next_transition = self.timestamp.wait_for(|latest| *latest > start_ts);
+            let deadline = cmp::min(deadline, clock.now() + MISSING_TS_WAIT);
+            // If no transition arrives, the mutation most likely didn't write.
+            let _ = timeout_at(&*clock, deadline, next_transition).await;
+        }
+        Ok(outcome)
     }
 
     /// Perform an action `name` with `args` and return a future
     /// containing the return value of the action once it completes.
     ///
+    /// The lines the action logs are logged with [`tracing`] at the debug
+    /// level once it completes, like those of mutations. The sync protocol has
+    /// no messages for an action that's still running, so its logs can't be
+    /// followed while it runs. To monitor a long-running action, have it write
+    /// its progress to a table, and subscribe to a query that reads it.
+    ///
+    /// Like [`subscribe`](Self::subscribe), fails without sending anything if
+    /// `args` has a field name Convex doesn't accept.
+    ///
     /// ```no_run
     /// # use convex::ConvexClient;
     /// # use futures::StreamExt;
@@ -286,18 +988,34 @@ impl ConvexClient {
         &mut self,
         name: &str,
         args: BTreeMap<String, Value>,
+    ) -> anyhow::Result<FunctionResult> {
+        self.action_with_priority(name, args, RequestPriority::Normal)
+            .await
+    }
+
+    /// Like [`action`](Self::action), but sent according to `priority`. See
+    /// [`mutation_with_priority`](Self::mutation_with_priority).
+    pub async fn action_with_priority(
+        &mut self,
+        name: &str,
+        args: BTreeMap<String, Value>,
+        priority: RequestPriority,
     ) -> anyhow::Result<FunctionResult> {
         let (tx, rx) = oneshot::channel();
 
         let udf_path: UdfPath = name.parse()?;
-        let request = ActionRequest { udf_path, args };
+        check_arg_names(&args)?;
+        let request = ActionRequest {
+            udf_path,
+            args,
+            priority,
+        };
 
-        self.request_sender
-            .send(ClientRequest::Action(request, tx))
+        self.send_request(ClientRequest::Action(request, tx))
             .await?;
 
-        let res = rx.await?;
-        Ok(res.await?)
+        let res = rx.await.map_err(|_| self.disconnected())?;
+        res.await.map_err(|_| self.disconnected())
     }
 
     /// Get a consistent view of the results of multiple queries (query set).
@@ -334,25 +1052,222 @@ impl ConvexClient {
         QuerySetSubscription::new(BroadcastStream::new(self.watch_receiver.resubscribe()))
     }
 
-    /// Set auth for use when calling Convex functions.
+    /// Get the current result of every query subscribed to by this client
+    /// and its clones, along with the canonicalized path of its function (e.g.
+    /// `listMessages.js:default` for `listMessages`).
     ///
-    /// Set it with a token that you get from your auth provider via their login
-    /// flow. If `None` is passed as the token, then auth is unset (logging
-    /// out).
-    pub async fn set_auth(&mut self, token: Option<String>) {
-        let req = AuthenticateRequest {
-            token: match token {
-                None => AuthenticationToken::None,
-                Some(token) => AuthenticationToken::User(token),
-            },
-        };
-        self.request_sender
-            .send(ClientRequest::Authenticate(req))
-            .await
-            .expect("INTERNAL BUG: Worker has gone away");
-    }
-
-    /// Set admin auth for use when calling Convex functions as a deployment
+    /// This is a read-only dump of the client's reactive state, e.g. for
+    /// attaching to bug reports. The results are those of the latest
+    /// transition applied by the client. Since the server sends updates to
+    /// all queries in a single transition that is applied atomically, the
+    /// results are consistent with each other, as with
+    /// [`ConvexClient::watch_all`]. Queries that don't have a result yet are
+    /// left out.
+    ///
+    /// ```no_run
+    /// # use convex::ConvexClient;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut client = ConvexClient::new("https://cool-music-123.convex.cloud").await?;
+    /// let sub = client.subscribe("listMessages", maplit::btreemap!{}).await?;
+    /// for (query_id, (udf_path, result)) in client.snapshot().await? {
+    ///     println!("{query_id} {udf_path}: {result:?}");
+    /// }
+    /// # Ok(())
+    /// # }
+    pub async fn snapshot(&mut self) -> anyhow::Result<QuerySnapshot> {
+        let (tx, rx) = oneshot::channel();
+        self.send_request(ClientRequest::Snapshot(tx)).await?;
+        rx.await.map_err(|_| self.disconnected())
+    }
+
+    /// Get the function and arguments of every subscribed query, in the
+    /// order they were first subscribed, e.g. to save them to disk and
+    /// restore them with [`ConvexClient::import_subscriptions`] on the next
+    /// launch.
+    ///
+    /// Queries subscribed more than once are only listed once. Query journals
+    /// aren't included, so restored queries start fresh, e.g. from the first
+    /// page of a paginated query. To resume from a journal, subscribe with
+    /// [`QueryBuilder::journal`] and [`ConvexClient::subscribe_queries`]
+    /// instead.
+    ///
+    /// ```no_run
+    /// # use convex::ConvexClient;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut client = ConvexClient::new("https://cool-music-123.convex.cloud").await?;
+    /// let sub = client.subscribe("listMessages", maplit::btreemap!{}).await?;
+    /// let saved = client.export_subscriptions().await?;
+    ///
+    /// // After a restart:
+    /// let mut client = ConvexClient::new("https://cool-music-123.convex.cloud").await?;
+    /// let subscriptions = client.import_subscriptions(saved).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn export_subscriptions(
+        &mut self,
+    ) -> anyhow::Result<Vec<(UdfPath, BTreeMap<String, Value>)>> {
+        let (tx, rx) = oneshot::channel();
+        self.send_request(ClientRequest::Subscriptions(tx)).await?;
+        rx.await.map_err(|_| self.disconnected())
+    }
+
+    /// Subscribe to queries exported with
+    /// [`ConvexClient::export_subscriptions`], in a single request to the
+    /// server. Returns one subscription per query, in the same order.
+    pub async fn import_subscriptions(
+        &mut self,
+        subscriptions: Vec<(UdfPath, BTreeMap<String, Value>)>,
+    ) -> anyhow::Result<Vec<QuerySubscription>> {
+        let queries = subscriptions
+            .into_iter()
+            .map(|(udf_path, args)| QueryBuilder::new(&udf_path.to_string()).args(args))
+            .collect();
+        self.subscribe_queries(queries).await
+    }
+
+    /// Get what the deployment reported about itself when the client last
+    /// connected, e.g. to warn users that this client version is deprecated.
+    ///
+    /// See [`DeploymentInfo`] for what's available. This is populated by the
+    /// time [`ConvexClient::new`] returns, and updated on every reconnect.
+    pub async fn deployment_info(&mut self) -> anyhow::Result<DeploymentInfo> {
+        let (tx, rx) = oneshot::channel();
+        self.send_request(ClientRequest::DeploymentInfo(tx)).await?;
+        let info = rx.await.map_err(|_| self.disconnected())?;
+        Ok(info.unwrap_or_default())
+    }
+
+    /// Get the versions of the latest transition applied by the client and
+    /// the queries it affected, or `None` if no transition was applied yet.
+    ///
+    /// This is a debugging aid to answer "did the server send an update for my
+    /// query?", without capturing every message. Compare the affected ids
+    /// with [`SubscriberId::query_id`]. It's cheap: only the latest
+    /// transition's summary is kept.
+    ///
+    /// ```no_run
+    /// # use convex::ConvexClient;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut client = ConvexClient::new("https://cool-music-123.convex.cloud").await?;
+    /// let sub = client.subscribe("listMessages", maplit::btreemap!{}).await?;
+    /// if let Some(transition) = client.last_transition().await? {
+    ///     let updated = transition.affected_queries.contains(&sub.query_id());
+    ///     println!("{:?}: updated {updated}", transition.end_version);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn last_transition(&mut self) -> anyhow::Result<Option<TransitionInfo>> {
+        let (tx, rx) = oneshot::channel();
+        self.send_request(ClientRequest::LastTransition(tx)).await?;
+        rx.await.map_err(|_| self.disconnected())
+    }
+
+    /// Wait until every listed query has a result from the same transition,
+    /// e.g. to render several subscriptions together without a torn view
+    /// where some reflect a write and others don't.
+    ///
+    /// The server updates all subscribed queries in a single transition to a
+    /// new [`StateVersion`](convex_sync_types::StateVersion), which the client
+    /// applies atomically. So once every listed query has a result, the latest
+    /// results of all of them are at a common version, and stay consistent
+    /// until they're read unless another transition arrives in between. Read
+    /// them with [`ConvexClient::snapshot`], which is consistent on its own,
+    /// or from the subscriptions. Queries are only waited on until they have a
+    /// result, which may be an error. After a reconnect, results must arrive
+    /// again.
+    ///
+    /// This waits indefinitely, e.g. while the client is reconnecting. Wrap it
+    /// in [`tokio::time::timeout`] to bound the wait. Fails if one of the
+    /// queries isn't subscribed, or if the client disconnects permanently.
+    ///
+    /// ```no_run
+    /// # use convex::ConvexClient;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut client = ConvexClient::new("https://cool-music-123.convex.cloud").await?;
+    /// let messages = client.subscribe("listMessages", maplit::btreemap!{}).await?;
+    /// let users = client.subscribe("listUsers", maplit::btreemap!{}).await?;
+    /// client.await_consistent(&[messages.query_id(), users.query_id()]).await?;
+    /// let snapshot = client.snapshot().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn await_consistent(&mut self, query_ids: &[QueryId]) -> anyhow::Result<()> {
+        // Watch before checking so that a transition applied after the check
+        // isn't missed.
+        let mut watch = BroadcastStream::new(self.watch_receiver.resubscribe());
+        loop {
+            let (tx, rx) = oneshot::channel();
+            self.send_request(ClientRequest::IsConsistent(query_ids.to_vec(), tx))
+                .await?;
+            if rx.await.map_err(|_| self.disconnected())?? {
+                return Ok(());
+            }
+            // Lagging only means several transitions were applied, so any
+            // item is a reason to check again.
+            if watch.next().await.is_none() {
+                return Err(self.disconnected());
+            }
+        }
+    }
+
+    /// Wait until the client has applied a transition at or after `ts`, so
+    /// that its query results reflect every write committed up to `ts`, e.g.
+    /// to read your own writes.
+    ///
+    /// The server may send transitions without modifications only to advance
+    /// the timestamp. These heartbeats satisfy this wait, but don't wake
+    /// subscriptions, since no result changed.
+    ///
+    /// `ts` may be the [`ts`](MutationOutcome::ts) of a mutation's outcome:
+    /// `None` means the mutation didn't write, so there's nothing to wait for
+    /// and this returns immediately.
+    ///
+    /// This waits indefinitely, e.g. while the client is reconnecting. Wrap it
+    /// in [`tokio::time::timeout`] to bound the wait. Fails if the client
+    /// disconnects permanently.
+    pub async fn await_timestamp(
+        &mut self,
+        ts: impl Into<Option<Timestamp>>,
+    ) -> anyhow::Result<()> {
+        let Some(ts) = ts.into() else {
+            return Ok(());
+        };
+        let reached = self
+            .timestamp
+            .wait_for(|latest| matches!(latest, Some(latest) if *latest >= ts))
+            .await
+            .is_ok();
+        if !reached {
+            return Err(self.disconnected());
+        }
+        Ok(())
+    }
+
+    /// Set auth for use when calling Convex functions.
+    ///
+    /// Set it with a token that you get from your auth provider via their login
+    /// flow. If `None` is passed as the token, then auth is unset (logging
+    /// out). Query results cached with
+    /// [`ConvexClientBuilder::query_cache_ttl`] are dropped.
+    pub async fn set_auth(&mut self, token: Option<String>) {
+        let token = match token {
+            None => AuthenticationToken::None,
+            Some(token) => AuthenticationToken::User(token),
+        };
+        *self.auth_token.lock().expect("Auth token lock poisoned") = token.clone();
+        self.clear_query_cache();
+        let req = AuthenticateRequest { token };
+        // Auth no longer matters once the client has disconnected permanently.
+        let _ = self.send_request(ClientRequest::Authenticate(req)).await;
+    }
+
+    /// Set admin auth for use when calling Convex functions as a deployment
     /// admin. Not typically required.
     ///
     /// You can get a deploy_key from the Convex dashboard's deployment settings
@@ -364,13 +1279,227 @@ impl ConvexClient {
         deploy_key: String,
         acting_as: Option<UserIdentityAttributes>,
     ) {
-        let req = AuthenticateRequest {
-            token: AuthenticationToken::Admin(deploy_key, acting_as),
-        };
-        self.request_sender
-            .send(ClientRequest::Authenticate(req))
+        let token = AuthenticationToken::Admin(deploy_key, acting_as);
+        *self.auth_token.lock().expect("Auth token lock poisoned") = token.clone();
+        self.clear_query_cache();
+        let req = AuthenticateRequest { token };
+        // Auth no longer matters once the client has disconnected permanently.
+        let _ = self.send_request(ClientRequest::Authenticate(req)).await;
+    }
+
+    /// The error that permanently disconnected the client, or `None` while it
+    /// is connected or reconnecting.
+    ///
+    /// Once disconnected, the client stays disconnected: subscriptions end,
+    /// and outstanding and future calls fail with this error.
+    pub fn disconnect_error(&self) -> Option<ConvexError> {
+        self.disconnect_error
+            .lock()
+            .expect("Disconnect error lock poisoned")
+            .clone()
+    }
+
+    /// The bytes this client and its clones have sent to and received from
+    /// the deployment so far, e.g. to monitor usage on metered connections.
+    /// See [`ConvexClientBuilder::bandwidth_cap`] to limit it.
+    pub fn bandwidth_usage(&self) -> BandwidthUsage {
+        self.bandwidth.usage()
+    }
+
+    /// Wait until the client is connected to the deployment, i.e. its
+    /// websocket is open and the `Connect` handshake completed, for at most
+    /// `timeout`.
+    ///
+    /// The client is connected once it's constructed, so this only waits while
+    /// it's reconnecting. Calls don't need to wait: they're queued until the
+    /// client reconnects (see [Calls while
+    /// disconnected](ConvexClient#calls-while-disconnected)). Fails if the
+    /// client isn't connected within `timeout`, or with the reason it
+    /// disconnected permanently.
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use convex::ConvexClient;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut client = ConvexClient::new("https://cool-music-123.convex.cloud").await?;
+    /// client.wait_connected(Duration::from_secs(10)).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn wait_connected(&mut self, timeout: Duration) -> anyhow::Result<()> {
+        let deadline = self.clock.now() + timeout;
+        let connected = self.connected.wait_for(|connected| *connected);
+        let waited = timeout_at(&*self.clock, deadline, connected)
             .await
-            .expect("INTERNAL BUG: Worker has gone away");
+            .map(|result| result.is_ok());
+        match waited {
+            Some(true) => Ok(()),
+            // The worker stopped, i.e. the client disconnected permanently.
+            Some(false) => Err(self.disconnected()),
+            None => anyhow::bail!("Not connected after {timeout:?}"),
+        }
+    }
+
+    /// **Unstable:** send `message` to the deployment exactly as given, e.g.
+    /// to experiment with or debug the sync protocol. Requires the `unstable`
+    /// feature.
+    ///
+    /// **Warning:** the message bypasses the client's state, so using this
+    /// alongside the rest of the API has undefined results. A message that
+    /// changes the protocol's state, like a `ModifyQuerySet`, `Mutation` or
+    /// `Authenticate`, desynchronizes the client from the server, which may
+    /// make the client fail, reconnect, or deliver wrong results. Messages
+    /// aren't resent after a reconnect. This API may change or be removed in
+    /// any release.
+    #[cfg(feature = "unstable")]
+    pub async fn send_raw(
+        &mut self,
+        message: convex_sync_types::ClientMessage,
+    ) -> anyhow::Result<()> {
+        self.send_request(ClientRequest::SendRaw(message)).await
+    }
+
+    /// **Unstable:** a stream of every message received from the deployment
+    /// from now on, before the client applies it. Requires the `unstable`
+    /// feature.
+    ///
+    /// The client still handles every message as usual, so this is only a
+    /// view of the protocol, see [`send_raw`](Self::send_raw) for the
+    /// warnings about driving it. A stream that falls more than 256 messages
+    /// behind skips the oldest ones. The stream ends if the client
+    /// disconnects permanently. This API may change or be removed in any
+    /// release.
+    #[cfg(feature = "unstable")]
+    pub async fn raw_messages(
+        &mut self,
+    ) -> anyhow::Result<impl Stream<Item = convex_sync_types::ServerMessage<Value>>> {
+        let (tx, rx) = oneshot::channel();
+        self.send_request(ClientRequest::RawMessages(tx)).await?;
+        let receiver = rx.await.map_err(|_| self.disconnected())?;
+        Ok(BroadcastStream::new(receiver).filter_map(|message| {
+            futures::future::ready(match message {
+                Ok(message) => Some(message),
+                Err(BroadcastStreamRecvError::Lagged(n)) => {
+                    tracing::warn!("Raw message stream skipped {n} messages");
+                    None
+                },
+            })
+        }))
+    }
+
+    /// Close the connection to the deployment, for this client and all of its
+    /// clones.
+    ///
+    /// Once `close` starts, every new call, like
+    /// [`subscribe`](Self::subscribe), [`query`](Self::query),
+    /// [`mutation`](Self::mutation) or [`action`](Self::action), fails
+    /// immediately with [`ConvexError::Closed`], including calls made
+    /// concurrently from other tasks through clones. Calls already in
+    /// flight fail with the same error instead of waiting for a response,
+    /// and subscriptions end. Mutations that were already sent may or may
+    /// not have been applied by the deployment.
+    /// [`disconnect_error`](Self::disconnect_error) returns
+    /// [`ConvexError::Closed`] afterwards, unless the client had already
+    /// disconnected permanently for another reason.
+    pub fn close(&self) {
+        self.closing.store(true, Ordering::SeqCst);
+        self.disconnect_error
+            .lock()
+            .expect("Disconnect error lock poisoned")
+            .get_or_insert(ConvexError::Closed);
+        if let Some(listen_handle) = &self.listen_handle {
+            listen_handle.abort();
+        }
+    }
+
+    /// Drop the results cached by [`query`](Self::query), since results can
+    /// depend on who is authenticated.
+    fn clear_query_cache(&self) {
+        if let Some(cache) = &self.query_cache {
+            cache.clear();
+        }
+    }
+
+    /// Send `request` to the worker, failing with the reason the client
+    /// disconnected if the worker has stopped, or with
+    /// [`ConvexError::Closed`] once [`close`](Self::close) has started.
+    async fn send_request(&mut self, request: ClientRequest) -> anyhow::Result<()> {
+        if self.closing.load(Ordering::SeqCst) {
+            return Err(ConvexError::Closed.into());
+        }
+        if let Some(rate_limiter) = &self.rate_limiter {
+            let limited = matches!(
+                request,
+                ClientRequest::Mutation(..)
+                    | ClientRequest::MutationWithTs(..)
+                    | ClientRequest::Action(..)
+                    | ClientRequest::Subscribe(..)
+                    | ClientRequest::SubscribeMany(..)
+            );
+            if limited {
+                rate_limiter.acquire().await;
+            }
+        }
+        if self.request_sender.send(request).await.is_err() {
+            return Err(self.disconnected());
+        }
+        Ok(())
+    }
+
+    /// The error for a request the worker dropped without handling.
+    fn disconnected(&self) -> anyhow::Error {
+        match self.disconnect_error() {
+            Some(error) => error.into(),
+            None => anyhow::anyhow!("INTERNAL BUG: Worker has gone away"),
+        }
+    }
+}
+
+/// A deployment URL that has been checked with
+/// [`ConvexClient::validate_url`], ready to [connect](ConvexClient::connect)
+/// to.
+///
+/// It's guaranteed to be an absolute URL with a host and an `http`, `https`,
+/// `ws` or `wss` scheme. It's normalized to the deployment's origin: `ws` and
+/// `wss` become `http` and `https`, and the path is dropped, since the client
+/// derives the paths it needs. Validation doesn't resolve the host or check
+/// that it's a Convex deployment, so connecting can still fail.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ValidatedUrl(Url);
+
+impl ValidatedUrl {
+    fn parse(deployment_url: &str) -> anyhow::Result<Self> {
+        let mut url: Url = deployment_url
+            .parse()
+            .with_context(|| format!("Invalid deployment URL {deployment_url:?}"))?;
+        let scheme = match url.scheme() {
+            "http" | "ws" => "http",
+            "https" | "wss" => "https",
+            scheme => anyhow::bail!("Unknown scheme {scheme}. Expected http or https."),
+        };
+        anyhow::ensure!(
+            url.host().is_some(),
+            "Deployment URL {deployment_url:?} has no host"
+        );
+        url.set_scheme(scheme).expect("Scheme not supported");
+        url.set_path("/");
+        Ok(Self(url))
+    }
+
+    /// The normalized URL, e.g. `https://cool-music-123.convex.cloud/`.
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    pub(crate) fn url(&self) -> &Url {
+        &self.0
+    }
+}
+
+impl fmt::Display for ValidatedUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
     }
 }
 
@@ -387,11 +1516,39 @@ fn deployment_to_ws_url(mut deployment_url: Url) -> anyhow::Result<Url> {
     Ok(deployment_url)
 }
 
+/// Whether the client was built with a TLS implementation for `wss` URLs.
+const TLS_ENABLED: bool = cfg!(any(
+    feature = "native-tls",
+    feature = "rustls-tls-native-roots",
+    feature = "rustls-tls-webpki-roots"
+));
+
+/// Check that the client can connect to `ws_url`, given whether it was built
+/// with TLS and whether it requires TLS, rather than failing each connection
+/// attempt or connecting in the clear.
+fn check_tls_scheme(
+    ws_url: &Url,
+    tls_enabled: bool,
+    tls_required: bool,
+) -> Result<(), ConvexError> {
+    let secure = ws_url.scheme() == "wss";
+    if secure && !tls_enabled || !secure && tls_required {
+        return Err(ConvexError::TlsSchemeMismatch {
+            url: ws_url.to_string(),
+        });
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 pub mod tests {
     use std::{
         str::FromStr,
-        sync::Arc,
+        sync::{
+            atomic::AtomicBool,
+            Arc,
+            Mutex,
+        },
         time::Duration,
     };
 
@@ -409,30 +1566,61 @@ pub mod tests {
     };
     use futures::{
         channel::mpsc,
+        FutureExt,
         StreamExt,
     };
     use maplit::btreemap;
     use pretty_assertions::assert_eq;
     use serde_json::json;
-    use tokio::sync::broadcast;
+    use tokio::sync::{
+        broadcast,
+        watch,
+    };
+    use url::Url;
 
-    use super::ConvexClient;
+    use super::{
+        ConvexClient,
+        MISSING_TS_WAIT,
+    };
     use crate::{
-        base_client::FunctionResult,
+        base_client::{
+            BaseConvexClient,
+            FunctionResult,
+            QueryBuilder,
+            QueryPriority,
+            RequestPriority,
+            TransitionInfo,
+        },
         client::{
+            check_tls_scheme,
+            clock::{
+                SystemClock,
+                TestClock,
+            },
             deployment_to_ws_url,
+            failed_mutations::FailedMutations,
+            query_cache::QueryCache,
+            rate_limit::RateLimiter,
+            subscription::{
+                AdaptiveCoalescing,
+                QueryState,
+            },
             worker::worker,
-            BaseConvexClient,
         },
         sync::{
             testing::TestProtocolManager,
+            ProtocolConfig,
             ServerMessage,
             SyncProtocol,
         },
         value::Value,
+        ConvexError,
+        Source,
+        Update,
     };
 
     impl ConvexClient {
+        /// Construct a client backed by a [`TestProtocolManager`].
         pub async fn with_test_protocol() -> anyhow::Result<(Self, TestProtocolManager)> {
             let _ = tracing_subscriber::fmt()
                 .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
@@ -445,22 +1633,47 @@ pub mod tests {
             // Listener for when each transaction completes
             let (watch_sender, watch_receiver) = broadcast::channel(1);
 
-            let test_protocol =
-                TestProtocolManager::open("ws://test.com".parse()?, response_sender).await?;
+            let test_protocol = TestProtocolManager::open(
+                "ws://test.com".parse()?,
+                response_sender,
+                ProtocolConfig::default(),
+            )
+            .await?;
             let base_client = BaseConvexClient::new();
+            let disconnect_error = Arc::new(Mutex::new(None));
+            let (connected_sender, connected) = watch::channel(true);
+            let (timestamp_sender, timestamp) = watch::channel(None);
 
             let listen_handle = tokio::spawn(worker(
                 response_receiver,
                 request_receiver,
                 watch_sender,
+                connected_sender,
+                timestamp_sender,
                 base_client,
                 test_protocol.clone(),
+                disconnect_error.clone(),
+                None,
             ));
 
             let client = ConvexClient {
                 listen_handle: Some(Arc::new(listen_handle)),
                 request_sender,
                 watch_receiver,
+                deployment_url: "https://test.convex.cloud".parse()?,
+                auth_token: Arc::new(Mutex::new(AuthenticationToken::None)),
+                http_client: reqwest::Client::new(),
+                disconnect_error,
+                closing: Arc::new(AtomicBool::new(false)),
+                bandwidth: Arc::default(),
+                query_cache: None,
+                failed_mutations: None,
+                rate_limiter: None,
+                max_argument_size: None,
+                connected,
+                timestamp,
+                coalescing: None,
+                clock: Arc::new(SystemClock),
             };
             Ok((client, test_protocol))
         }
@@ -485,7 +1698,7 @@ pub mod tests {
         }
     }
 
-    fn fake_transition(
+    pub(crate) fn fake_transition(
         start_version: StateVersion,
         modifications: Vec<(QueryId, Value)>,
     ) -> (ServerMessage, StateVersion) {
@@ -544,88 +1757,588 @@ pub mod tests {
     }
 
     #[tokio::test]
-    async fn test_mutation_error() -> anyhow::Result<()> {
+    async fn test_mutation_with_ts() -> anyhow::Result<()> {
         let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
         test_protocol.take_sent().await;
 
-        let res =
-            tokio::spawn(async move { client.mutation("incrementCounter", btreemap! {}).await });
+        // A mutation that wrote completes with its timestamp once the client
+        // has caught up to it.
+        let mut writer = client.clone();
+        let mut res =
+            tokio::spawn(async move { writer.mutation_with_ts("sendMessage", btreemap! {}).await });
         test_protocol.wait_until_n_messages_sent(1).await;
         test_protocol.take_sent().await;
+        let (mut_resp, transition) = fake_mutation_response(FunctionResult::Value(Value::Null));
+        let ServerMessage::MutationResponse { ts: Some(ts), .. } = mut_resp else {
+            panic!("Expected a mutation response with a timestamp");
+        };
+        test_protocol.fake_server_response(mut_resp).await?;
+        tokio::time::timeout(Duration::from_millis(50), &mut res)
+            .await
+            .unwrap_err();
+        test_protocol.fake_server_response(transition).await?;
+        let outcome = res.await??;
+        assert_eq!(outcome.result, FunctionResult::Value(Value::Null));
+        assert_eq!(outcome.ts, Some(ts));
+        client.await_timestamp(outcome.ts).await?;
+
+        // A mutation that didn't write completes right away, without a
+        // timestamp to wait for.
+        let mut writer = client.clone();
+        let res = tokio::spawn(async move {
+            writer
+                .mutation_with_ts("validateMessage", btreemap! {})
+                .await
+        });
+        test_protocol.wait_until_n_messages_sent(1).await;
+        test_protocol.take_sent().await;
+        test_protocol
+            .fake_server_response(ServerMessage::MutationResponse {
+                request_id: 1,
+                result: Ok(Value::from("valid")),
+                ts: None,
+                log_lines: vec![],
+            })
+            .await?;
+        let outcome = res.await??;
+        assert_eq!(outcome.result, FunctionResult::Value(Value::from("valid")));
+        assert_eq!(outcome.ts, None);
+        tokio::time::timeout(
+            Duration::from_millis(50),
+            client.await_timestamp(outcome.ts),
+        )
+        .await??;
+        Ok(())
+    }
 
-        let mutation_result = FunctionResult::ErrorMessage("JEEPERS".into());
-        let (mut_resp, _transition) = fake_mutation_response(mutation_result.clone());
+    #[tokio::test]
+    async fn test_mutation_consistent_timeout() -> anyhow::Result<()> {
+        let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        let clock = TestClock::new();
+        client.clock = Arc::new(clock.clone());
+        test_protocol.take_sent().await;
+
+        let res = tokio::spawn(async move {
+            client
+                .mutation_consistent("sendMessage", btreemap! {}, Duration::from_secs(10))
+                .await
+        });
+        test_protocol.wait_until_n_messages_sent(1).await;
+        test_protocol.take_sent().await;
+        // The mutation committed, but the client never catches up to it.
+        let (mut_resp, _transition) = fake_mutation_response(FunctionResult::Value(Value::Null));
         test_protocol.fake_server_response(mut_resp).await?;
-        // Errors should be ready immediately (no transition needed)
-        assert_eq!(res.await??, mutation_result);
 
+        clock.advance(Duration::from_secs(9));
+        tokio::task::yield_now().await;
+        assert!(!res.is_finished());
+        clock.advance(Duration::from_secs(1));
+        let err = res.await?.unwrap_err();
+        assert!(err.to_string().contains("not reflected after 10s"), "{err}");
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_action() -> anyhow::Result<()> {
+    async fn test_mutation_consistent_missing_ts() -> anyhow::Result<()> {
         let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        let clock = TestClock::new();
+        client.clock = Arc::new(clock.clone());
         test_protocol.take_sent().await;
 
-        let action_result = FunctionResult::Value(Value::Null);
-        let server_message = fake_action_response(action_result.clone());
-
-        let res = tokio::spawn(async move { client.action("runAction:hello", btreemap! {}).await });
+        // The server omits the timestamp of a mutation that wrote, so the
+        // client waits for the next transition instead.
+        let mut writer = client.clone();
+        let res = tokio::spawn(async move {
+            writer
+                .mutation_consistent("sendMessage", btreemap! {}, Duration::from_secs(10))
+                .await
+        });
         test_protocol.wait_until_n_messages_sent(1).await;
-
-        assert_eq!(
-            test_protocol.take_sent().await,
-            vec![ClientMessage::Action {
+        test_protocol.take_sent().await;
+        // A transition applied before the response doesn't count.
+        let (transition, version) = fake_transition(StateVersion::initial(), vec![]);
+        test_protocol.fake_server_response(transition).await?;
+        client
+            .timestamp
+            .clone()
+            .wait_for(|latest| *latest == Some(version.ts))
+            .await?;
+        test_protocol
+            .fake_server_response(ServerMessage::MutationResponse {
                 request_id: 0,
-                udf_path: UdfPath::from_str("runAction:hello")?,
-                args: vec![json!({})],
-            }]
-        );
-
-        test_protocol.fake_server_response(server_message).await?;
-        assert_eq!(res.await??, action_result);
+                result: Ok(Value::Null),
+                ts: None,
+                log_lines: vec![],
+            })
+            .await?;
+        tokio::task::yield_now().await;
+        assert!(!res.is_finished());
+        let (transition, version) = fake_transition(version, vec![]);
+        test_protocol.fake_server_response(transition).await?;
+        let outcome = res.await??;
+        assert_eq!(outcome.ts, None);
+        assert_eq!(*client.timestamp.borrow(), Some(version.ts));
+
+        // Without a transition, it gives up after a second rather than
+        // hanging.
+        let res = tokio::spawn(async move {
+            client
+                .mutation_consistent("validateMessage", btreemap! {}, Duration::from_secs(10))
+                .await
+        });
+        test_protocol.wait_until_n_messages_sent(1).await;
+        test_protocol.take_sent().await;
+        test_protocol
+            .fake_server_response(ServerMessage::MutationResponse {
+                request_id: 1,
+                result: Ok(Value::from("valid")),
+                ts: None,
+                log_lines: vec![],
+            })
+            .await?;
+        // The wait starts once the worker delivers the response, so keep
+        // advancing the clock until it's over.
+        while !res.is_finished() {
+            clock.advance(MISSING_TS_WAIT);
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        let outcome = res.await??;
+        assert_eq!(outcome.result, FunctionResult::Value(Value::from("valid")));
+        assert_eq!(outcome.ts, None);
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_auth() -> anyhow::Result<()> {
-        let (mut client, test_protocol) = ConvexClient::with_test_protocol().await?;
+    async fn test_retry_failed_mutation() -> anyhow::Result<()> {
+        let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        client.failed_mutations = Some(Arc::new(FailedMutations::new(10)));
         test_protocol.take_sent().await;
 
-        // Set token
-        client.set_auth(Some("myauthtoken".into())).await;
+        let mut writer = client.clone();
+        let res = tokio::spawn(async move {
+            writer
+                .mutation("sendMessage", btreemap! {"body".into() => "hi".into()})
+                .await
+        });
         test_protocol.wait_until_n_messages_sent(1).await;
-        assert_eq!(
-            test_protocol.take_sent().await,
-            vec![ClientMessage::Authenticate {
-                base_version: 0,
-                token: AuthenticationToken::User("myauthtoken".into()),
-            }]
-        );
+        test_protocol.take_sent().await;
+        test_protocol
+            .fake_server_response(ServerMessage::MutationResponse {
+                request_id: 0,
+                result: Err("Service unavailable".into()),
+                ts: None,
+                log_lines: vec![],
+            })
+            .await?;
+        res.await??;
 
-        // Unset token
-        client.set_auth(None).await;
+        let failed = client.failed_mutations();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].udf_path, "sendMessage".parse()?);
+        assert_eq!(failed[0].args, btreemap! {"body".into() => "hi".into()});
+        assert_eq!(failed[0].error, "Service unavailable");
+
+        let mut writer = client.clone();
+        let failed = failed[0].clone();
+        let res = tokio::spawn(async move { writer.retry(failed).await });
         test_protocol.wait_until_n_messages_sent(1).await;
         assert_eq!(
             test_protocol.take_sent().await,
-            vec![ClientMessage::Authenticate {
-                base_version: 1,
-                token: AuthenticationToken::None,
+            vec![ClientMessage::Mutation {
+                request_id: 1,
+                udf_path: "sendMessage".parse()?,
+                args: vec![json!({"body": "hi"})],
             }]
         );
+        test_protocol
+            .fake_server_response(ServerMessage::MutationResponse {
+                request_id: 1,
+                result: Ok(Value::from("sent")),
+                ts: None,
+                log_lines: vec![],
+            })
+            .await?;
+        assert_eq!(res.await??, FunctionResult::Value(Value::from("sent")));
+        assert!(client.failed_mutations().is_empty());
+        Ok(())
+    }
 
-        // Set admin auth
-        client.set_admin_auth("myadminauth".into(), None).await;
-        test_protocol.wait_until_n_messages_sent(1).await;
+    #[tokio::test]
+    async fn test_max_argument_size() -> anyhow::Result<()> {
+        let (mut client, test_protocol) = ConvexClient::with_test_protocol().await?;
+        client.max_argument_size = Some(100);
+        test_protocol.take_sent().await;
+
+        let body = "x".repeat(100);
+        let err = client
+            .mutation("sendMessage", btreemap! {"body".into() => body.into()})
+            .await
+            .unwrap_err();
         assert_eq!(
-            test_protocol.take_sent().await,
-            vec![ClientMessage::Authenticate {
-                base_version: 2,
-                token: AuthenticationToken::Admin("myadminauth".into(), None),
-            }]
+            err.downcast_ref::<ConvexError>(),
+            Some(&ConvexError::ArgumentTooLarge {
+                size: 111,
+                limit: 100
+            })
         );
-
-        // Set admin auth acting as user
+        assert!(test_protocol.take_sent().await.is_empty());
+
+        // Arguments within the limit are sent as usual.
+        let mut writer = client.clone();
+        tokio::spawn(async move {
+            writer
+                .mutation("sendMessage", btreemap! {"body".into() => "hi".into()})
+                .await
+        });
+        test_protocol.wait_until_n_messages_sent(1).await;
+        Ok(())
+    }
+
+    // Paused, tokio's time only advances once every task is idle, so
+    // sleeping waits for the worker to send everything it can without
+    // depending on real timing. The limiter itself runs on the `TestClock`.
+    #[tokio::test(start_paused = true)]
+    async fn test_request_rate_limit() -> anyhow::Result<()> {
+        let (mut client, test_protocol) = ConvexClient::with_test_protocol().await?;
+        let clock = TestClock::new();
+        client.clock = Arc::new(clock.clone());
+        client.rate_limiter = Some(Arc::new(RateLimiter::new(2, client.clock.clone())));
+        test_protocol.take_sent().await;
+
+        let mut burst = vec![];
+        for i in 0..5 {
+            let mut writer = client.clone();
+            burst.push(tokio::spawn(async move {
+                writer
+                    .mutation("sendMessage", btreemap! {"i".into() => i.into()})
+                    .await
+            }));
+        }
+        let sent = || {
+            let test_protocol = &test_protocol;
+            async move {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                test_protocol.take_sent().await.len()
+            }
+        };
+        // A second's worth of requests goes out right away, and the rest are
+        // spread out at the limit.
+        assert_eq!(sent().await, 2);
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(sent().await, 1);
+
+        // A request dropped while it waits puts its token back, so after the
+        // last two, the next request doesn't have to wait.
+        burst[3].abort();
+        assert!(burst.remove(3).await.unwrap_err().is_cancelled());
+        clock.advance(Duration::from_millis(1000));
+        assert_eq!(sent().await, 1);
+        let mut writer = client.clone();
+        burst.push(tokio::spawn(async move {
+            writer.mutation("sendMessage", btreemap! {}).await
+        }));
+        assert_eq!(sent().await, 1);
+        for request in burst {
+            request.abort();
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_high_priority_mutation() -> anyhow::Result<()> {
+        let (client, test_protocol) = ConvexClient::with_test_protocol().await?;
+        test_protocol.take_sent().await;
+
+        // Queue all the requests before the worker runs, like a burst of
+        // concurrent calls.
+        let (mut c0, mut c1, mut c2, mut c3) =
+            (client.clone(), client.clone(), client.clone(), client);
+        let mut normal = Box::pin(c0.mutation("first", btreemap! {}));
+        let mut subscribe1 = Box::pin(c1.subscribe("getValue1", btreemap! {}));
+        let mut subscribe2 = Box::pin(c2.subscribe("getValue2", btreemap! {}));
+        let mut high =
+            Box::pin(c3.mutation_with_priority("urgent", btreemap! {}, RequestPriority::High));
+        assert!(futures::poll!(&mut normal).is_pending());
+        assert!(futures::poll!(&mut subscribe1).is_pending());
+        assert!(futures::poll!(&mut subscribe2).is_pending());
+        assert!(futures::poll!(&mut high).is_pending());
+
+        test_protocol.wait_until_n_messages_sent(4).await;
+        let sent: Vec<_> = test_protocol
+            .take_sent()
+            .await
+            .into_iter()
+            .map(|message| match message {
+                ClientMessage::Mutation { udf_path, .. } => udf_path.to_string(),
+                ClientMessage::ModifyQuerySet { new_version, .. } => {
+                    format!("query set {new_version}")
+                },
+                message => panic!("Unexpected message {message:?}"),
+            })
+            .collect();
+        // The high-priority mutation jumps the subscriptions, but not the
+        // earlier mutation.
+        assert_eq!(sent, ["first", "urgent", "query set 1", "query set 2"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mutation_id_arg() -> anyhow::Result<()> {
+        let (mut client, test_protocol) = ConvexClient::with_test_protocol().await?;
+        test_protocol.take_sent().await;
+
+        let args = btreemap! { "messageId".into() => Value::id("k57c2kd8") };
+        let _res = tokio::spawn(async move { client.mutation("deleteMessage", args).await });
+        test_protocol.wait_until_n_messages_sent(1).await;
+
+        // IDs are sent in their `$id` form, unlike strings.
+        assert_eq!(
+            test_protocol.take_sent().await,
+            vec![ClientMessage::Mutation {
+                request_id: 0,
+                udf_path: UdfPath::from_str("deleteMessage")?,
+                args: vec![json!({"messageId": {"$id": "k57c2kd8"}})],
+            }]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mutation_malformed_id_arg() -> anyhow::Result<()> {
+        let (mut client, test_protocol) = ConvexClient::with_test_protocol().await?;
+        test_protocol.take_sent().await;
+
+        let args = btreemap! { "messageId".into() => Value::id("messages:k57c2kd8") };
+        let err = client.mutation("deleteMessage", args).await.unwrap_err();
+        assert!(err.to_string().contains("messages:k57c2kd8"), "{err}");
+
+        // Rejected before anything was sent: requests reach the protocol in
+        // order, so the next mutation is the first message sent.
+        let args = btreemap! { "messageId".into() => Value::id("k57c2kd8") };
+        let _res = tokio::spawn(async move { client.mutation("deleteMessage", args).await });
+        test_protocol.wait_until_n_messages_sent(1).await;
+        assert_eq!(
+            test_protocol.take_sent().await,
+            vec![ClientMessage::Mutation {
+                request_id: 0,
+                udf_path: UdfPath::from_str("deleteMessage")?,
+                args: vec![json!({"messageId": {"$id": "k57c2kd8"}})],
+            }]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_malformed_field_name_args() -> anyhow::Result<()> {
+        let (mut client, test_protocol) = ConvexClient::with_test_protocol().await?;
+        test_protocol.take_sent().await;
+
+        let bad = |path: &str| {
+            let mut fields = btreemap! { "$body".into() => "hi".into() };
+            for name in path.split('.').rev() {
+                fields = btreemap! { name.into() => Value::Object(fields) };
+            }
+            fields
+        };
+        let err = client
+            .query("listMessages", bad("filter"))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().ends_with(r#"at "filter.$body""#), "{err}");
+        let err = client.action("sendGif", bad("gif")).await.unwrap_err();
+        assert!(err.to_string().ends_with(r#"at "gif.$body""#), "{err}");
+        let err = client
+            .mutation("sendMessage", bad("message"))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().ends_with(r#"at "message.$body""#), "{err}");
+        let results = client
+            .subscribe_many(vec![("listMessages", bad("filter"))])
+            .await?;
+        assert!(results[0].is_err());
+
+        // System fields are allowed, to pass documents back. Requests reach
+        // the protocol in order, so this is the first message sent.
+        let document = btreemap! {
+            "_id".into() => Value::id("k57c2kd8"),
+            "_creationTime".into() => Value::Float64(1.0),
+        };
+        let args = btreemap! { "message".into() => Value::Object(document) };
+        let _res = tokio::spawn(async move { client.mutation("editMessage", args).await });
+        test_protocol.wait_until_n_messages_sent(1).await;
+        assert_eq!(
+            test_protocol.take_sent().await,
+            vec![ClientMessage::Mutation {
+                request_id: 0,
+                udf_path: UdfPath::from_str("editMessage")?,
+                args: vec![json!({"message": {"_id": {"$id": "k57c2kd8"}, "_creationTime": 1.0}})],
+            }]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mutation_error() -> anyhow::Result<()> {
+        let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        test_protocol.take_sent().await;
+
+        let res =
+            tokio::spawn(async move { client.mutation("incrementCounter", btreemap! {}).await });
+        test_protocol.wait_until_n_messages_sent(1).await;
+        test_protocol.take_sent().await;
+
+        let mutation_result = FunctionResult::ErrorMessage("JEEPERS".into());
+        let (mut_resp, _transition) = fake_mutation_response(mutation_result.clone());
+        test_protocol.fake_server_response(mut_resp).await?;
+        // Errors should be ready immediately (no transition needed)
+        assert_eq!(res.await??, mutation_result);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_permanent_close() -> anyhow::Result<()> {
+        let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        test_protocol.take_sent().await;
+        client.wait_connected(Duration::ZERO).await?;
+        let mut subscription = client.subscribe("getValue", btreemap! {}).await?;
+
+        let mut client_ = client.clone();
+        let res =
+            tokio::spawn(async move { client_.mutation("incrementCounter", btreemap! {}).await });
+        test_protocol.wait_until_n_messages_sent(2).await;
+        let mut client_ = client.clone();
+        let query = tokio::spawn(async move { client_.query("listMessages", btreemap! {}).await });
+        test_protocol.wait_until_n_messages_sent(3).await;
+
+        let error = ConvexError::from_close_frame(1008, "Client version is unsupported");
+        test_protocol.fake_permanent_close(error.clone()).await?;
+        let e = res.await?.unwrap_err();
+        assert_eq!(e.downcast_ref::<ConvexError>(), Some(&error));
+        let e = query.await?.unwrap_err();
+        assert_eq!(e.downcast_ref::<ConvexError>(), Some(&error));
+        assert_eq!(subscription.next().await, None);
+        assert_eq!(client.disconnect_error(), Some(error.clone()));
+        let e = client
+            .mutation("incrementCounter", btreemap! {})
+            .await
+            .unwrap_err();
+        assert_eq!(e.downcast_ref::<ConvexError>(), Some(&error));
+        let e = client
+            .query("listMessages", btreemap! {})
+            .await
+            .unwrap_err();
+        assert_eq!(e.downcast_ref::<ConvexError>(), Some(&error));
+        let e = client.wait_connected(Duration::ZERO).await.unwrap_err();
+        assert_eq!(e.downcast_ref::<ConvexError>(), Some(&error));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_close() -> anyhow::Result<()> {
+        let (mut client, test_protocol) = ConvexClient::with_test_protocol().await?;
+        test_protocol.take_sent().await;
+        let mut subscription = client.subscribe("getValue", btreemap! {}).await?;
+
+        let mut client_ = client.clone();
+        let in_flight =
+            tokio::spawn(async move { client_.mutation("incrementCounter", btreemap! {}).await });
+        test_protocol.wait_until_n_messages_sent(2).await;
+        let mut client_ = client.clone();
+        let in_flight_query =
+            tokio::spawn(async move { client_.query("listMessages", btreemap! {}).await });
+        test_protocol.wait_until_n_messages_sent(3).await;
+
+        // Close from another task while this one keeps making calls.
+        let closer = client.clone();
+        tokio::spawn(async move { closer.close() }).await?;
+        let is_closed =
+            |e: anyhow::Error| e.downcast_ref::<ConvexError>() == Some(&ConvexError::Closed);
+        let fail_fast = Duration::from_millis(100);
+        let e = tokio::time::timeout(fail_fast, client.mutation("incrementCounter", btreemap! {}))
+            .await?
+            .unwrap_err();
+        assert!(is_closed(e));
+        let e = tokio::time::timeout(fail_fast, client.subscribe("getValue", btreemap! {}))
+            .await?
+            .unwrap_err();
+        assert!(is_closed(e));
+        let e = tokio::time::timeout(fail_fast, client.query("listMessages", btreemap! {}))
+            .await?
+            .unwrap_err();
+        assert!(is_closed(e));
+
+        assert!(is_closed(in_flight.await?.unwrap_err()));
+        assert!(is_closed(in_flight_query.await?.unwrap_err()));
+        assert_eq!(subscription.next().await, None);
+        assert_eq!(client.disconnect_error(), Some(ConvexError::Closed));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_action() -> anyhow::Result<()> {
+        let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        test_protocol.take_sent().await;
+
+        let action_result = FunctionResult::Value(Value::Null);
+        let server_message = fake_action_response(action_result.clone());
+
+        let res = tokio::spawn(async move { client.action("runAction:hello", btreemap! {}).await });
+        test_protocol.wait_until_n_messages_sent(1).await;
+
+        assert_eq!(
+            test_protocol.take_sent().await,
+            vec![ClientMessage::Action {
+                request_id: 0,
+                udf_path: UdfPath::from_str("runAction:hello")?,
+                args: vec![json!({})],
+            }]
+        );
+
+        test_protocol.fake_server_response(server_message).await?;
+        assert_eq!(res.await??, action_result);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_auth() -> anyhow::Result<()> {
+        let (mut client, test_protocol) = ConvexClient::with_test_protocol().await?;
+        test_protocol.take_sent().await;
+
+        // Set token
+        client.set_auth(Some("myauthtoken".into())).await;
+        test_protocol.wait_until_n_messages_sent(1).await;
+        assert_eq!(
+            test_protocol.take_sent().await,
+            vec![ClientMessage::Authenticate {
+                base_version: 0,
+                token: AuthenticationToken::User("myauthtoken".into()),
+            }]
+        );
+
+        // Unset token
+        client.set_auth(None).await;
+        test_protocol.wait_until_n_messages_sent(1).await;
+        assert_eq!(
+            test_protocol.take_sent().await,
+            vec![ClientMessage::Authenticate {
+                base_version: 1,
+                token: AuthenticationToken::None,
+            }]
+        );
+
+        // Set admin auth
+        client.set_admin_auth("myadminauth".into(), None).await;
+        test_protocol.wait_until_n_messages_sent(1).await;
+        assert_eq!(
+            test_protocol.take_sent().await,
+            vec![ClientMessage::Authenticate {
+                base_version: 2,
+                token: AuthenticationToken::Admin("myadminauth".into(), None),
+            }]
+        );
+
+        // Set admin auth acting as user
         let acting_as = UserIdentityAttributes {
             name: Some("Barbara Liskov".into()),
             ..Default::default()
@@ -644,6 +2357,20 @@ pub mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_auth_shared_with_clones() -> anyhow::Result<()> {
+        let (mut client, test_protocol) = ConvexClient::with_test_protocol().await?;
+        let clone = client.clone();
+        client.set_auth(Some("myauthtoken".into())).await;
+        test_protocol.wait_until_n_messages_sent(1).await;
+        // HTTP actions on a clone use the token set on the original.
+        assert_eq!(
+            *clone.auth_token.lock().unwrap(),
+            AuthenticationToken::User("myauthtoken".into())
+        );
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_client_single_subscription() -> anyhow::Result<()> {
         let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
@@ -703,6 +2430,84 @@ pub mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_heartbeat_transition() -> anyhow::Result<()> {
+        let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        let mut subscription = client.subscribe("getValue", btreemap! {}).await?;
+        let mut watch = client.watch_all();
+        let (transition, version) = fake_transition(
+            StateVersion::initial(),
+            vec![(subscription.query_id(), 10.into())],
+        );
+        test_protocol.fake_server_response(transition).await?;
+        assert_eq!(
+            subscription.next().await,
+            Some(FunctionResult::Value(10.into()))
+        );
+        assert!(watch.next().await.is_some());
+
+        // A transition to the same version, and one that only advances the
+        // timestamp.
+        test_protocol
+            .fake_server_response(ServerMessage::Transition {
+                start_version: version,
+                end_version: version,
+                modifications: vec![],
+            })
+            .await?;
+        let (heartbeat, version) = fake_transition(version, vec![]);
+        test_protocol.fake_server_response(heartbeat).await?;
+        tokio::time::timeout(Duration::from_secs(5), client.await_timestamp(version.ts)).await??;
+        assert_eq!(
+            client.last_transition().await?.map(|t| t.end_version),
+            Some(version)
+        );
+
+        // Neither wakes the subscriptions.
+        tokio::time::timeout(Duration::from_millis(50), subscription.next())
+            .await
+            .unwrap_err();
+        tokio::time::timeout(Duration::from_millis(50), watch.next())
+            .await
+            .unwrap_err();
+        let (transition, _) = fake_transition(version, vec![(subscription.query_id(), 11.into())]);
+        test_protocol.fake_server_response(transition).await?;
+        assert_eq!(
+            subscription.next().await,
+            Some(FunctionResult::Value(11.into()))
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "unstable")]
+    #[tokio::test]
+    async fn test_raw_messages() -> anyhow::Result<()> {
+        use convex_sync_types::types::ClientEvent;
+
+        let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        test_protocol.take_sent().await;
+        let mut raw_messages = Box::pin(client.raw_messages().await?);
+
+        let event = ClientMessage::Event(ClientEvent {
+            event_type: "ping".into(),
+            event: json!({ "debug": true }),
+        });
+        client.send_raw(event.clone()).await?;
+        test_protocol.wait_until_n_messages_sent(1).await;
+        assert_eq!(test_protocol.take_sent().await, vec![event]);
+
+        test_protocol
+            .fake_server_response(ServerMessage::Ping)
+            .await?;
+        let (transition, _) = fake_transition(StateVersion::initial(), vec![]);
+        test_protocol
+            .fake_server_response(transition.clone())
+            .await?;
+        assert_eq!(raw_messages.next().await, Some(ServerMessage::Ping));
+        assert_eq!(raw_messages.next().await, Some(transition));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_client_consistent_view_watch() -> anyhow::Result<()> {
         let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
@@ -894,15 +2699,1007 @@ pub mod tests {
         Ok(())
     }
 
-    #[test]
-    fn test_deployment_url() -> anyhow::Result<()> {
+    #[tokio::test]
+    async fn test_query_serialized() -> anyhow::Result<()> {
+        #[derive(serde::Serialize)]
+        struct Args {
+            channel: String,
+            limit: u32,
+        }
+
+        let (client, test_protocol) = ConvexClient::with_test_protocol().await?;
+        test_protocol.take_sent().await;
+        let args = Args {
+            channel: "general".into(),
+            limit: 10,
+        };
+        let mut client_ = client.clone();
+        tokio::spawn(async move { client_.query_serialized("listMessages", &args).await });
+        test_protocol.wait_until_n_messages_sent(1).await;
         assert_eq!(
-            deployment_to_ws_url("http://flying-shark-123.convex.cloud".parse()?)?.to_string(),
-            "ws://flying-shark-123.convex.cloud/api/sync",
+            test_protocol.take_sent().await,
+            vec![ClientMessage::ModifyQuerySet {
+                base_version: 0,
+                new_version: 1,
+                modifications: vec![QuerySetModification::Add(Query {
+                    query_id: QueryId::new(0),
+                    udf_path: "listMessages".parse()?,
+                    args: vec![json!({"channel": "general", "limit": 10.0})],
+                    journal: None,
+                })],
+            }]
         );
+
+        let mut client_ = client.clone();
+        let err = client_
+            .query_serialized("listMessages", &vec![1, 2])
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("must serialize to an object"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_next_or_pending() -> anyhow::Result<()> {
+        let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        let mut subscription = client.subscribe("slowQuery", btreemap! {}).await?;
+        test_protocol.take_sent().await;
+
+        // Past the deadline, the wait fails but the query stays subscribed.
+        let err = subscription
+            .next_or_pending(Duration::from_millis(50))
+            .await
+            .unwrap_err();
         assert_eq!(
-            deployment_to_ws_url("https://flying-shark-123.convex.cloud".parse()?)?.to_string(),
-            "wss://flying-shark-123.convex.cloud/api/sync",
+            err.downcast_ref::<ConvexError>(),
+            Some(&ConvexError::QueryPending {
+                waited: Duration::from_millis(50)
+            })
+        );
+        assert_eq!(test_protocol.take_sent().await, vec![]);
+
+        test_protocol
+            .fake_server_response(
+                fake_transition(
+                    StateVersion::initial(),
+                    vec![(subscription.query_id(), 10.into())],
+                )
+                .0,
+            )
+            .await?;
+        assert_eq!(
+            subscription.next_or_pending(Duration::from_secs(5)).await?,
+            FunctionResult::Value(10.into())
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_queries() -> anyhow::Result<()> {
+        let (mut client, test_protocol) = ConvexClient::with_test_protocol().await?;
+        test_protocol.take_sent().await;
+
+        let _subscriptions = client
+            .subscribe_queries(vec![
+                QueryBuilder::new("listMessages")
+                    .arg("limit", 10)
+                    .journal("page1".into()),
+                QueryBuilder::new("listUsers"),
+            ])
+            .await?;
+        let modifications = |messages: &str, users: &str| -> anyhow::Result<_> {
+            Ok(vec![
+                QuerySetModification::Add(Query {
+                    query_id: QueryId::new(0),
+                    udf_path: messages.parse()?,
+                    args: vec![json!({"limit": {"$integer": "CgAAAAAAAAA="}})],
+                    journal: Some(Some("page1".into())),
+                }),
+                QuerySetModification::Add(Query {
+                    query_id: QueryId::new(1),
+                    udf_path: users.parse()?,
+                    args: vec![json!({})],
+                    journal: None,
+                }),
+            ])
+        };
+        assert_eq!(
+            test_protocol.take_sent().await,
+            vec![ClientMessage::ModifyQuerySet {
+                base_version: 0,
+                new_version: 1,
+                modifications: modifications("listMessages", "listUsers")?,
+            }]
+        );
+
+        // The journal is sent again when the query is re-added on reconnect.
+        let mut base_client = BaseConvexClient::new();
+        base_client.subscribe_queries(vec![
+            QueryBuilder::new("listMessages")
+                .arg("limit", 10)
+                .journal("page1".into()),
+            QueryBuilder::new("listUsers"),
+        ])?;
+        base_client.pop_next_message();
+        base_client.resend_ongoing_queries_mutations();
+        assert_eq!(
+            base_client.pop_next_message(),
+            Some(ClientMessage::ModifyQuerySet {
+                base_version: 0,
+                new_version: 1,
+                modifications: modifications("listMessages.js:default", "listUsers.js:default")?,
+            })
+        );
+
+        // An invalid name fails the whole batch.
+        let result = client
+            .subscribe_queries(vec![
+                QueryBuilder::new("listMessages"),
+                QueryBuilder::new("not a path!"),
+            ])
+            .await;
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_reconnect_sends_latest_journal() -> anyhow::Result<()> {
+        let mut base_client = BaseConvexClient::new();
+        base_client.subscribe_queries(vec![
+            QueryBuilder::new("listMessages").journal("page1".into())
+        ])?;
+        base_client.pop_next_message();
+        let query_id = QueryId::new(0);
+        base_client
+            .receive_message(ServerMessage::Transition {
+                start_version: StateVersion::initial(),
+                end_version: StateVersion {
+                    ts: StateVersion::initial().ts.succ()?,
+                    ..StateVersion::initial()
+                },
+                modifications: vec![StateModification::QueryUpdated {
+                    query_id,
+                    value: 1.into(),
+                    journal: Some("page2".into()),
+                    log_lines: vec![],
+                }],
+            })
+            .unwrap();
+
+        // The query resumes from the journal of its latest result.
+        base_client.resend_ongoing_queries_mutations();
+        assert_eq!(
+            base_client.pop_next_message(),
+            Some(ClientMessage::ModifyQuerySet {
+                base_version: 0,
+                new_version: 1,
+                modifications: vec![QuerySetModification::Add(Query {
+                    query_id,
+                    udf_path: "listMessages.js:default".parse()?,
+                    args: vec![json!({})],
+                    journal: Some(Some("page2".into())),
+                })],
+            })
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_with_priority() -> anyhow::Result<()> {
+        let added_paths = |message: Option<ClientMessage>| -> Vec<String> {
+            let Some(ClientMessage::ModifyQuerySet { modifications, .. }) = message else {
+                panic!("Expected a query set modification, got {message:?}");
+            };
+            modifications
+                .into_iter()
+                .map(|modification| match modification {
+                    QuerySetModification::Add(query) => String::from(query.udf_path),
+                    QuerySetModification::Remove { .. } => panic!("Unexpected removal"),
+                })
+                .collect()
+        };
+
+        // While connected, subscriptions are sent in the order they're made.
+        let (mut client, test_protocol) = ConvexClient::with_test_protocol().await?;
+        test_protocol.take_sent().await;
+        let _stats = client
+            .subscribe_with_priority("getStats", btreemap! {}, QueryPriority::Low)
+            .await?;
+        let _messages = client
+            .subscribe_with_priority("listMessages", btreemap! {}, QueryPriority::High)
+            .await?;
+        let sent: Vec<_> = test_protocol
+            .take_sent()
+            .await
+            .into_iter()
+            .flat_map(|message| added_paths(Some(message)))
+            .collect();
+        assert_eq!(sent, ["getStats", "listMessages"]);
+
+        // After a reconnect, they're re-added by priority, and otherwise in
+        // their usual order. A query shared by two subscriptions takes the
+        // higher priority.
+        let mut base_client = BaseConvexClient::new();
+        base_client.subscribe_queries(vec![
+            QueryBuilder::new("getStats").priority(QueryPriority::Low),
+            QueryBuilder::new("listChannels"),
+            QueryBuilder::new("listUsers"),
+            QueryBuilder::new("listMessages").priority(QueryPriority::High),
+        ])?;
+        base_client.subscribe_queries(vec![
+            QueryBuilder::new("listUsers").priority(QueryPriority::High)
+        ])?;
+        base_client.pop_next_message();
+        base_client.resend_ongoing_queries_mutations();
+        assert_eq!(
+            added_paths(base_client.pop_next_message()),
+            [
+                "listMessages.js:default",
+                "listUsers.js:default",
+                "listChannels.js:default",
+                "getStats.js:default",
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_export_import_subscriptions() -> anyhow::Result<()> {
+        let (mut client, _test_protocol) = ConvexClient::with_test_protocol().await?;
+        let _users = client.subscribe("listUsers", btreemap! {}).await?;
+        let _messages = client
+            .subscribe("listMessages", btreemap! {"limit".into() => 10.into()})
+            .await?;
+        let _messages_again = client
+            .subscribe("listMessages", btreemap! {"limit".into() => 10.into()})
+            .await?;
+        let exported = client.export_subscriptions().await?;
+        assert_eq!(
+            exported,
+            vec![
+                ("listUsers.js:default".parse()?, btreemap! {}),
+                (
+                    "listMessages.js:default".parse()?,
+                    btreemap! {"limit".into() => 10.into()}
+                ),
+            ]
+        );
+
+        let (mut restarted, test_protocol) = ConvexClient::with_test_protocol().await?;
+        test_protocol.take_sent().await;
+        let subscriptions = restarted.import_subscriptions(exported.clone()).await?;
+        assert_eq!(subscriptions.len(), 2);
+        assert_eq!(
+            test_protocol.take_sent().await,
+            vec![ClientMessage::ModifyQuerySet {
+                base_version: 0,
+                new_version: 1,
+                modifications: vec![
+                    QuerySetModification::Add(Query {
+                        query_id: QueryId::new(0),
+                        udf_path: "listUsers.js:default".parse()?,
+                        args: vec![json!({})],
+                        journal: None,
+                    }),
+                    QuerySetModification::Add(Query {
+                        query_id: QueryId::new(1),
+                        udf_path: "listMessages.js:default".parse()?,
+                        args: vec![json!({"limit": {"$integer": "CgAAAAAAAAA="}})],
+                        journal: None,
+                    }),
+                ],
+            }]
+        );
+        assert_eq!(restarted.export_subscriptions().await?, exported);
+        Ok(())
+    }
+
+    #[test]
+    fn test_resend_drops_queued_messages() {
+        let mut base_client = BaseConvexClient::new();
+        let token = AuthenticationToken::User("token".into());
+        base_client.set_auth(AuthenticationToken::User("old".into()));
+        base_client.set_auth(token.clone());
+        base_client.subscribe("listMessages".parse().unwrap(), btreemap! {});
+
+        // Only the current state is resent, starting from version 0.
+        base_client.resend_ongoing_queries_mutations();
+        let resent: Vec<_> = std::iter::from_fn(|| base_client.pop_next_message()).collect();
+        assert!(
+            matches!(
+                &resent[..],
+                [
+                    ClientMessage::Authenticate {
+                        base_version: 0,
+                        token: resent_token,
+                    },
+                    ClientMessage::ModifyQuerySet {
+                        base_version: 0,
+                        new_version: 1,
+                        ..
+                    },
+                ] if *resent_token == token
+            ),
+            "{resent:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_many_partial_failure() -> anyhow::Result<()> {
+        let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        test_protocol.take_sent().await;
+
+        let mut subscriptions = client
+            .subscribe_many(vec![
+                ("listMessages", btreemap! {}),
+                ("listMessages", btreemap! { "limit".into() => "ten".into() }),
+                ("listUsers", btreemap! {}),
+            ])
+            .await?;
+        assert_eq!(
+            test_protocol.take_sent().await,
+            vec![ClientMessage::ModifyQuerySet {
+                base_version: 0,
+                new_version: 1,
+                modifications: vec![
+                    QuerySetModification::Add(Query {
+                        query_id: QueryId::new(0),
+                        udf_path: "listMessages".parse()?,
+                        args: vec![json!({})],
+                        journal: None,
+                    }),
+                    QuerySetModification::Add(Query {
+                        query_id: QueryId::new(1),
+                        udf_path: "listMessages".parse()?,
+                        args: vec![json!({"limit": "ten"})],
+                        journal: None,
+                    }),
+                    QuerySetModification::Add(Query {
+                        query_id: QueryId::new(2),
+                        udf_path: "listUsers".parse()?,
+                        args: vec![json!({})],
+                        journal: None,
+                    }),
+                ],
+            }]
+        );
+
+        let end_version = StateVersion {
+            ts: StateVersion::initial().ts.succ()?,
+            ..StateVersion::initial()
+        };
+        test_protocol
+            .fake_server_response(ServerMessage::Transition {
+                start_version: StateVersion::initial(),
+                end_version,
+                modifications: vec![
+                    StateModification::QueryUpdated {
+                        query_id: QueryId::new(0),
+                        value: Value::Array(vec![]),
+                        log_lines: vec![],
+                        journal: None,
+                    },
+                    StateModification::QueryFailed {
+                        query_id: QueryId::new(1),
+                        error_message: "Invalid limit".into(),
+                        log_lines: vec![],
+                        journal: None,
+                    },
+                    StateModification::QueryUpdated {
+                        query_id: QueryId::new(2),
+                        value: Value::Int64(2),
+                        log_lines: vec![],
+                        journal: None,
+                    },
+                ],
+            })
+            .await?;
+        let mut users = subscriptions.pop().unwrap()?;
+        let mut failing = subscriptions.pop().unwrap()?;
+        let mut messages = subscriptions.pop().unwrap()?;
+        assert_eq!(
+            messages.next().await,
+            Some(FunctionResult::Value(Value::Array(vec![])))
+        );
+        assert_eq!(
+            failing.next().await,
+            Some(FunctionResult::ErrorMessage("Invalid limit".into()))
+        );
+        assert_eq!(
+            users.next().await,
+            Some(FunctionResult::Value(Value::Int64(2)))
+        );
+
+        // The query set version advanced once for the whole batch.
+        let _subscription = client.subscribe("getCount", btreemap! {}).await?;
+        let sent = test_protocol.take_sent().await;
+        let [ClientMessage::ModifyQuerySet {
+            base_version: 1,
+            new_version: 2,
+            ..
+        }] = &sent[..]
+        else {
+            panic!("Unexpected messages {sent:?}");
+        };
+
+        // An invalid function path fails only its own entry.
+        let results = client
+            .subscribe_many(vec![
+                ("listChannels", btreemap! {}),
+                ("bad path!", btreemap! {}),
+            ])
+            .await?;
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+        let sent = test_protocol.take_sent().await;
+        let [ClientMessage::ModifyQuerySet { modifications, .. }] = &sent[..] else {
+            panic!("Unexpected messages {sent:?}");
+        };
+        assert_eq!(modifications.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_snapshot() -> anyhow::Result<()> {
+        let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        let messages = client.subscribe("listMessages", btreemap! {}).await?;
+        let users = client.subscribe("listUsers", btreemap! {}).await?;
+        let _pending = client.subscribe("getPending", btreemap! {}).await?;
+        assert_eq!(client.snapshot().await?, btreemap! {});
+
+        let (transition, _) = fake_transition(
+            StateVersion::initial(),
+            vec![
+                (messages.query_id(), Value::Array(vec![])),
+                (users.query_id(), Value::Int64(2)),
+            ],
+        );
+        test_protocol.fake_server_response(transition).await?;
+        client.watch_all().next().await;
+        assert_eq!(
+            client.snapshot().await?,
+            btreemap! {
+                messages.query_id() => ("listMessages.js:default".parse()?, Ok(Value::Array(vec![]))),
+                users.query_id() => ("listUsers.js:default".parse()?, Ok(Value::Int64(2))),
+            }
+        );
+
+        drop(users);
+        assert_eq!(client.snapshot().await?.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_last_transition() -> anyhow::Result<()> {
+        let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        let messages = client.subscribe("listMessages", btreemap! {}).await?;
+        let users = client.subscribe("listUsers", btreemap! {}).await?;
+        assert_eq!(client.last_transition().await?, None);
+
+        let (transition, version) = fake_transition(
+            StateVersion::initial(),
+            vec![
+                (messages.query_id(), Value::Array(vec![])),
+                (users.query_id(), Value::Int64(2)),
+            ],
+        );
+        test_protocol.fake_server_response(transition).await?;
+        client.watch_all().next().await;
+
+        let (transition, end_version) =
+            fake_transition(version, vec![(users.query_id(), Value::Int64(3))]);
+        test_protocol.fake_server_response(transition).await?;
+        client.watch_all().next().await;
+        assert_eq!(
+            client.last_transition().await?,
+            Some(TransitionInfo {
+                start_version: version,
+                end_version,
+                affected_queries: vec![users.query_id()],
+            })
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_await_consistent() -> anyhow::Result<()> {
+        let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        let messages = client.subscribe("listMessages", btreemap! {}).await?;
+        let users = client.subscribe("listUsers", btreemap! {}).await?;
+        let query_ids = [messages.query_id(), users.query_id()];
+
+        let mut client_ = client.clone();
+        let consistent = tokio::spawn(async move { client_.await_consistent(&query_ids).await });
+
+        // Only one of the queries has a result after the first transition.
+        let (transition, version) = fake_transition(
+            StateVersion::initial(),
+            vec![(messages.query_id(), Value::Array(vec![]))],
+        );
+        test_protocol.fake_server_response(transition).await?;
+        client.watch_all().next().await;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!consistent.is_finished());
+
+        let (transition, _) = fake_transition(version, vec![(users.query_id(), Value::Int64(2))]);
+        test_protocol.fake_server_response(transition).await?;
+        consistent.await??;
+
+        // Once consistent, it resolves immediately.
+        client.await_consistent(&query_ids).await?;
+        assert!(client.await_consistent(&[QueryId::new(5)]).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_collect_updates() -> anyhow::Result<()> {
+        let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        test_protocol.take_sent().await;
+
+        // Poll the collector by hand, so that it takes each update before the
+        // next one is sent.
+        let mut client_ = client.clone();
+        let mut collect =
+            Box::pin(client_.collect_updates("getValue", btreemap! {}, 3, Duration::from_secs(2)));
+        assert!(futures::poll!(&mut collect).is_pending());
+        test_protocol.wait_until_n_messages_sent(1).await;
+        test_protocol.take_sent().await;
+        // Another subscriber to the query shows when an update was delivered.
+        let mut delivered = client.subscribe("getValue", btreemap! {}).await?;
+
+        let query_id = QueryId::new(0);
+        let mut version = StateVersion::initial();
+        let mut collected = None;
+        for i in 1..4 {
+            assert!(collected.is_none());
+            let (transition, new_version) = fake_transition(version, vec![(query_id, i.into())]);
+            test_protocol.fake_server_response(transition).await?;
+            version = new_version;
+            delivered.next().await;
+            if let std::task::Poll::Ready(values) = futures::poll!(&mut collect) {
+                collected = Some(values?);
+            }
+        }
+        assert_eq!(collected, Some(vec![1.into(), 2.into(), 3.into()]));
+
+        // The collector unsubscribed once it was done, so the query is removed
+        // along with the other subscriber.
+        drop(delivered);
+        test_protocol.wait_until_n_messages_sent(1).await;
+        assert_eq!(
+            test_protocol.take_sent().await,
+            vec![ClientMessage::ModifyQuerySet {
+                base_version: 1,
+                new_version: 2,
+                modifications: vec![QuerySetModification::Remove { query_id }],
+            }]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_collect_updates_timeout() -> anyhow::Result<()> {
+        let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        let res = tokio::spawn(async move {
+            client
+                .collect_updates("getValue", btreemap! {}, 2, Duration::from_millis(50))
+                .await
+        });
+        test_protocol.wait_until_n_messages_sent(2).await;
+        let (transition, _) =
+            fake_transition(StateVersion::initial(), vec![(QueryId::new(0), 1.into())]);
+        test_protocol.fake_server_response(transition).await?;
+        assert!(res.await?.unwrap_err().to_string().contains("Timed out"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_replay_current() -> anyhow::Result<()> {
+        let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        let mut subscription1 = client.subscribe("getValue", btreemap! {}).await?;
+        let query_id = subscription1.query_id();
+
+        let (transition, version) =
+            fake_transition(StateVersion::initial(), vec![(query_id, 1.into())]);
+        test_protocol.fake_server_response(transition).await?;
+        assert_eq!(
+            subscription1.next().await,
+            Some(FunctionResult::Value(1.into()))
+        );
+
+        let mut replayed = client.subscribe("getValue", btreemap! {}).await?;
+        let mut not_replayed = client
+            .subscribe("getValue", btreemap! {})
+            .await?
+            .replay_current(false);
+
+        let (transition, _) = fake_transition(version, vec![(query_id, 2.into())]);
+        test_protocol.fake_server_response(transition).await?;
+        assert_eq!(replayed.next().await, Some(FunctionResult::Value(1.into())));
+        assert_eq!(replayed.next().await, Some(FunctionResult::Value(2.into())));
+        assert_eq!(
+            not_replayed.next().await,
+            Some(FunctionResult::Value(2.into()))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_with_source() -> anyhow::Result<()> {
+        let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        let subscription = client.subscribe("getValue", btreemap! {}).await?;
+        let query_id = subscription.query_id();
+        let mut first = subscription.with_source();
+        let update = |value: i64, source| Update {
+            value: FunctionResult::Value(value.into()),
+            source,
+        };
+
+        let (transition, version) =
+            fake_transition(StateVersion::initial(), vec![(query_id, 1.into())]);
+        test_protocol.fake_server_response(transition).await?;
+        assert_eq!(first.next().await, Some(update(1, Source::Server)));
+
+        let mut second = client
+            .subscribe("getValue", btreemap! {})
+            .await?
+            .with_source();
+        let (transition, _) = fake_transition(version, vec![(query_id, 2.into())]);
+        test_protocol.fake_server_response(transition).await?;
+        assert_eq!(second.next().await, Some(update(1, Source::Cache)));
+        assert_eq!(second.next().await, Some(update(2, Source::Server)));
+        assert_eq!(first.next().await, Some(update(2, Source::Server)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_state() -> anyhow::Result<()> {
+        let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        let mut states = client.subscribe_state("getCounter", btreemap! {}).await?;
+        test_protocol.take_sent().await;
+        assert_eq!(states.next().await, Some(QueryState::Loading));
+
+        let query_id = QueryId::new(0);
+        let (transition, version) =
+            fake_transition(StateVersion::initial(), vec![(query_id, Value::Int64(1))]);
+        test_protocol.fake_server_response(transition).await?;
+        assert_eq!(
+            states.next().await,
+            Some(QueryState::Loaded(Value::Int64(1)))
+        );
+
+        test_protocol
+            .fake_server_response(ServerMessage::Transition {
+                start_version: version,
+                end_version: StateVersion {
+                    ts: version.ts.succ()?,
+                    ..version
+                },
+                modifications: vec![StateModification::QueryFailed {
+                    query_id,
+                    error_message: "boom".into(),
+                    journal: None,
+                    log_lines: vec![],
+                }],
+            })
+            .await?;
+        assert_eq!(states.next().await, Some(QueryState::Error("boom".into())));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_reduce() -> anyhow::Result<()> {
+        let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        let mut history = client
+            .subscribe_reduce("latestSale", btreemap! {}, vec![], |mut sales, value| {
+                sales.push(value);
+                sales
+            })
+            .await?;
+        test_protocol.take_sent().await;
+
+        let query_id = QueryId::new(0);
+        let mut version = StateVersion::initial();
+        for (sale, expected) in [(5, vec![5]), (3, vec![5, 3])] {
+            let (transition, end_version) =
+                fake_transition(version, vec![(query_id, Value::Int64(sale))]);
+            version = end_version;
+            test_protocol.fake_server_response(transition).await?;
+            let expected: Vec<_> = expected.into_iter().map(Value::Int64).collect();
+            assert_eq!(history.next().await, Some(Ok(expected)));
+        }
+
+        // A failed result passes through without changing the state.
+        test_protocol
+            .fake_server_response(ServerMessage::Transition {
+                start_version: version,
+                end_version: StateVersion {
+                    ts: version.ts.succ()?,
+                    ..version
+                },
+                modifications: vec![StateModification::QueryFailed {
+                    query_id,
+                    error_message: "boom".into(),
+                    journal: None,
+                    log_lines: vec![],
+                }],
+            })
+            .await?;
+        assert_eq!(history.next().await, Some(Err("boom".into())));
+        version.ts = version.ts.succ()?;
+        let (transition, _) = fake_transition(version, vec![(query_id, Value::Int64(8))]);
+        test_protocol.fake_server_response(transition).await?;
+        assert_eq!(
+            history.next().await,
+            Some(Ok(vec![Value::Int64(5), Value::Int64(3), Value::Int64(8)]))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_map_filter_values() -> anyhow::Result<()> {
+        let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        let subscription = client.subscribe("getCounter", btreemap! {}).await?;
+        let query_id = subscription.query_id();
+        let mut counts = subscription.map_values(|value| match value {
+            Value::Object(mut fields) => fields.remove("count"),
+            _ => None,
+        });
+        let mut filtered = client
+            .subscribe("getCounter", btreemap! {})
+            .await?
+            .filter_values(|value| match value {
+                Value::Object(fields) => fields.get("count") != Some(&Value::Int64(2)),
+                _ => false,
+            });
+        test_protocol.take_sent().await;
+
+        let counter = |count: i64| Value::Object(btreemap! { "count".into() => count.into() });
+        let updated = |count: i64| StateModification::QueryUpdated {
+            query_id,
+            value: counter(count),
+            journal: None,
+            log_lines: vec![],
+        };
+        let mut version = StateVersion::initial();
+        let mut transition = |modification| {
+            let start_version = version;
+            version.ts = version.ts.succ().expect("Succ failed");
+            ServerMessage::Transition {
+                start_version,
+                end_version: version,
+                modifications: vec![modification],
+            }
+        };
+
+        test_protocol
+            .fake_server_response(transition(updated(1)))
+            .await?;
+        assert_eq!(counts.next().await, Some(Ok(Some(1.into()))));
+        assert_eq!(
+            filtered.next().await,
+            Some(FunctionResult::Value(counter(1)))
+        );
+
+        test_protocol
+            .fake_server_response(transition(updated(2)))
+            .await?;
+        assert_eq!(counts.next().await, Some(Ok(Some(2.into()))));
+        assert_eq!(filtered.next().now_or_never(), None);
+
+        let failed = StateModification::QueryFailed {
+            query_id,
+            error_message: "boom".into(),
+            journal: None,
+            log_lines: vec![],
+        };
+        test_protocol
+            .fake_server_response(transition(failed))
+            .await?;
+        assert_eq!(counts.next().await, Some(Err("boom".into())));
+        assert_eq!(
+            filtered.next().await,
+            Some(FunctionResult::ErrorMessage("boom".into()))
+        );
+
+        // Dropping the streams unsubscribes.
+        drop(counts);
+        drop(filtered);
+        test_protocol.wait_until_n_messages_sent(1).await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_query_cache() -> anyhow::Result<()> {
+        let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        client.query_cache = Some(Arc::new(QueryCache::new(
+            Duration::from_secs(60),
+            Arc::new(SystemClock),
+        )));
+        test_protocol.take_sent().await;
+
+        let mut client_ = client.clone();
+        let first = tokio::spawn(async move {
+            client_
+                .query(
+                    "listMessages",
+                    btreemap! { "channel".into() => "general".into() },
+                )
+                .await
+        });
+        test_protocol.wait_until_n_messages_sent(1).await;
+        let (transition, _) =
+            fake_transition(StateVersion::initial(), vec![(QueryId::new(0), 1.into())]);
+        test_protocol.fake_server_response(transition).await?;
+        assert_eq!(first.await??, FunctionResult::Value(1.into()));
+        // The query is unsubscribed once it has its result.
+        test_protocol.wait_until_n_messages_sent(2).await;
+        test_protocol.take_sent().await;
+
+        // An identical query within the TTL hits the cache, including with the
+        // canonical form of the path.
+        let result = client
+            .query(
+                "listMessages:default",
+                btreemap! { "channel".into() => "general".into() },
+            )
+            .await?;
+        assert_eq!(result, FunctionResult::Value(1.into()));
+
+        // Other arguments, or new credentials, miss the cache. Requests reach
+        // the protocol in order, so the first message sent from here on is
+        // this query's: the cache hit above sent nothing.
+        let mut client_ = client.clone();
+        tokio::spawn(async move {
+            client_
+                .query(
+                    "listMessages",
+                    btreemap! { "channel".into() => "random".into() },
+                )
+                .await
+        });
+        test_protocol.wait_until_n_messages_sent(1).await;
+        let sent = test_protocol.take_sent().await;
+        let ClientMessage::ModifyQuerySet { modifications, .. } = &sent[0] else {
+            panic!("Unexpected message {:?}", sent[0]);
+        };
+        let [QuerySetModification::Add(query)] = &modifications[..] else {
+            panic!("Unexpected modifications {modifications:?}");
+        };
+        assert_eq!(query.args, vec![json!({ "channel": "random" })]);
+        client.set_auth(Some("token".into())).await;
+        let mut client_ = client.clone();
+        tokio::spawn(async move {
+            client_
+                .query(
+                    "listMessages",
+                    btreemap! { "channel".into() => "general".into() },
+                )
+                .await
+        });
+        test_protocol.wait_until_n_messages_sent(2).await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_query_cache_cleared_in_flight() -> anyhow::Result<()> {
+        let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        let cache = Arc::new(QueryCache::new(
+            Duration::from_secs(60),
+            Arc::new(SystemClock),
+        ));
+        client.query_cache = Some(cache.clone());
+        test_protocol.take_sent().await;
+
+        let args = btreemap! { "channel".into() => "general".into() };
+        let mut client_ = client.clone();
+        let args_ = args.clone();
+        let query = tokio::spawn(async move { client_.query("listMessages", args_).await });
+        test_protocol.wait_until_n_messages_sent(1).await;
+
+        // The credentials change while the query is in flight, so its result
+        // is returned but not cached.
+        client.set_auth(Some("token".into())).await;
+        let (transition, _) =
+            fake_transition(StateVersion::initial(), vec![(QueryId::new(0), 1.into())]);
+        test_protocol.fake_server_response(transition).await?;
+        assert_eq!(query.await??, FunctionResult::Value(1.into()));
+        let key = (
+            "listMessages".parse::<UdfPath>()?.canonicalize(),
+            Value::Object(args).stable_hash(),
+        );
+        assert_eq!(cache.get(&key), None);
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_adaptive_coalescing() -> anyhow::Result<()> {
+        let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        let window = Duration::from_millis(200);
+        let mut subscription =
+            client
+                .subscribe("getValue", btreemap! {})
+                .await?
+                .coalesce(AdaptiveCoalescing {
+                    quiet_interval: Duration::from_millis(500),
+                    window,
+                });
+        let query_id = subscription.query_id();
+
+        // The first update of a quiet query is yielded immediately.
+        let start = tokio::time::Instant::now();
+        let (transition, mut version) =
+            fake_transition(StateVersion::initial(), vec![(query_id, 1.into())]);
+        test_protocol.fake_server_response(transition).await?;
+        assert_eq!(
+            subscription.next().await,
+            Some(FunctionResult::Value(1.into()))
+        );
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        // A burst is coalesced into its latest result at the end of the window.
+        let start = tokio::time::Instant::now();
+        for value in [2, 3] {
+            let (transition, end_version) =
+                fake_transition(version, vec![(query_id, value.into())]);
+            test_protocol.fake_server_response(transition).await?;
+            version = end_version;
+        }
+        assert_eq!(
+            subscription.next().await,
+            Some(FunctionResult::Value(3.into()))
+        );
+        assert_eq!(start.elapsed(), window);
+
+        // Once the query goes quiet, updates are immediate again. Time is
+        // paused, so this sleep only advances the clock.
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        let start = tokio::time::Instant::now();
+        let (transition, _) = fake_transition(version, vec![(query_id, 4.into())]);
+        test_protocol.fake_server_response(transition).await?;
+        assert_eq!(
+            subscription.next().await,
+            Some(FunctionResult::Value(4.into()))
+        );
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_url() -> anyhow::Result<()> {
+        for (url, normalized) in [
+            (
+                "https://flying-shark-123.convex.cloud",
+                "https://flying-shark-123.convex.cloud/",
+            ),
+            (
+                "wss://flying-shark-123.convex.cloud/api/sync",
+                "https://flying-shark-123.convex.cloud/",
+            ),
+            ("ws://127.0.0.1:3210", "http://127.0.0.1:3210/"),
+        ] {
+            assert_eq!(ConvexClient::validate_url(url)?.as_str(), normalized);
+        }
+        for url in [
+            "flying-shark-123.convex.cloud",
+            "ftp://flying-shark-123.convex.cloud",
+            "https://",
+            "",
+        ] {
+            assert!(ConvexClient::validate_url(url).is_err(), "{url}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_deployment_url() -> anyhow::Result<()> {
+        assert_eq!(
+            deployment_to_ws_url("http://flying-shark-123.convex.cloud".parse()?)?.to_string(),
+            "ws://flying-shark-123.convex.cloud/api/sync",
+        );
+        assert_eq!(
+            deployment_to_ws_url("https://flying-shark-123.convex.cloud".parse()?)?.to_string(),
+            "wss://flying-shark-123.convex.cloud/api/sync",
         );
         assert_eq!(
             deployment_to_ws_url("ws://flying-shark-123.convex.cloud".parse()?)?.to_string(),
@@ -920,4 +3717,27 @@ pub mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_check_tls_scheme() -> anyhow::Result<()> {
+        let secure: Url = "wss://flying-shark-123.convex.cloud/api/sync".parse()?;
+        let plain: Url = "ws://127.0.0.1:3210/api/sync".parse()?;
+        let mismatch = |url: &Url| {
+            Err(ConvexError::TlsSchemeMismatch {
+                url: url.to_string(),
+            })
+        };
+        // `wss` without TLS built in.
+        assert_eq!(check_tls_scheme(&secure, false, false), mismatch(&secure));
+        assert_eq!(check_tls_scheme(&secure, false, true), mismatch(&secure));
+        // `ws` with TLS required.
+        assert_eq!(check_tls_scheme(&plain, true, true), mismatch(&plain));
+        assert_eq!(check_tls_scheme(&plain, false, true), mismatch(&plain));
+
+        assert_eq!(check_tls_scheme(&secure, true, false), Ok(()));
+        assert_eq!(check_tls_scheme(&secure, true, true), Ok(()));
+        assert_eq!(check_tls_scheme(&plain, false, false), Ok(()));
+        assert_eq!(check_tls_scheme(&plain, true, false), Ok(()));
+        Ok(())
+    }
 }
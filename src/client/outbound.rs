@@ -0,0 +1,176 @@
+//! Summaries of the mutations and actions sent by a [`ConvexClient`], e.g.
+//! for audit trails.
+
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::SystemTime,
+};
+
+use convex_sync_types::ClientMessage;
+
+use crate::Value;
+#[cfg(doc)]
+use crate::{
+    ConvexClient,
+    ConvexClientBuilder,
+};
+
+/// Whether an [`OutboundCall`] is a mutation or an action.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OutboundCallKind {
+    /// A call to [`ConvexClient::mutation`].
+    Mutation,
+    /// A call to [`ConvexClient::action`].
+    Action,
+}
+
+/// A summary of a mutation or action sent to the deployment, as passed to the
+/// callback set with [`ConvexClientBuilder::on_outbound`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutboundCall {
+    /// Whether this is a mutation or an action.
+    pub kind: OutboundCallKind,
+    /// The path of the function, e.g. `messages:send`.
+    pub udf_path: String,
+    /// The ID of the request within the client's session. A call that's
+    /// resent after a reconnect keeps its ID, so it can be used to
+    /// deduplicate summaries.
+    pub request_id: u32,
+    /// The [`Value::stable_hash`] of the arguments, as a [`Value::Object`].
+    pub args_hash: u128,
+    /// The arguments themselves, only if enabled with
+    /// [`ConvexClientBuilder::outbound_include_args`].
+    pub args: Option<BTreeMap<String, Value>>,
+    /// When the call was handed to the websocket.
+    pub sent_at: SystemTime,
+}
+
+pub(crate) type OutboundCallback = Arc<dyn Fn(&OutboundCall) + Send + Sync>;
+
+/// The callback for [`OutboundCall`]s, and whether they include arguments.
+#[derive(Clone)]
+pub(crate) struct OutboundHook {
+    pub(crate) callback: OutboundCallback,
+    pub(crate) include_args: bool,
+}
+
+impl OutboundHook {
+    /// Report `message` to the callback if it's a mutation or an action.
+    pub(crate) fn observe(&self, message: &ClientMessage) {
+        let (kind, request_id, udf_path, args) = match message {
+            ClientMessage::Mutation {
+                request_id,
+                udf_path,
+                args,
+            } => (OutboundCallKind::Mutation, request_id, udf_path, args),
+            ClientMessage::Action {
+                request_id,
+                udf_path,
+                args,
+            } => (OutboundCallKind::Action, request_id, udf_path, args),
+            _ => return,
+        };
+        // The client always sends the arguments as a single object.
+        let args = match Value::values_from_json_args(args.clone()).as_deref_mut() {
+            Ok([Value::Object(args)]) => std::mem::take(args),
+            _ => {
+                tracing::warn!("Unexpected arguments for request {request_id}");
+                BTreeMap::new()
+            },
+        };
+        let args = Value::Object(args);
+        let call = OutboundCall {
+            kind,
+            udf_path: udf_path.to_string(),
+            request_id: *request_id,
+            args_hash: args.stable_hash(),
+            args: match args {
+                Value::Object(args) if self.include_args => Some(args),
+                _ => None,
+            },
+            sent_at: SystemTime::now(),
+        };
+        (self.callback)(&call);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        Mutex,
+    };
+
+    use convex_sync_types::{
+        ClientMessage,
+        QueryId,
+        QuerySetModification,
+    };
+    use maplit::btreemap;
+
+    use super::{
+        OutboundCall,
+        OutboundCallKind,
+        OutboundHook,
+    };
+    use crate::Value;
+
+    fn recording_hook(include_args: bool) -> (OutboundHook, Arc<Mutex<Vec<OutboundCall>>>) {
+        let calls = Arc::new(Mutex::new(vec![]));
+        let hook = OutboundHook {
+            callback: Arc::new({
+                let calls = calls.clone();
+                move |call: &OutboundCall| calls.lock().unwrap().push(call.clone())
+            }),
+            include_args,
+        };
+        (hook, calls)
+    }
+
+    #[test]
+    fn test_observe() -> anyhow::Result<()> {
+        let args = btreemap! { "body".to_string() => Value::from("secret") };
+        let mutation = ClientMessage::Mutation {
+            request_id: 3,
+            udf_path: "messages:send".parse()?,
+            args: vec![Value::Object(args.clone()).into()],
+        };
+        let action = ClientMessage::Action {
+            request_id: 4,
+            udf_path: "messages:translate".parse()?,
+            args: vec![Value::Object(args.clone()).into()],
+        };
+        let modify_query_set = ClientMessage::ModifyQuerySet {
+            base_version: 0,
+            new_version: 1,
+            modifications: vec![QuerySetModification::Remove {
+                query_id: QueryId::new(0),
+            }],
+        };
+
+        let (hook, calls) = recording_hook(false);
+        for message in [&mutation, &action, &modify_query_set] {
+            hook.observe(message);
+        }
+        let calls = calls.lock().unwrap().clone();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].kind, OutboundCallKind::Mutation);
+        assert_eq!(calls[0].udf_path, "messages:send");
+        assert_eq!(calls[0].request_id, 3);
+        assert_eq!(
+            calls[0].args_hash,
+            Value::Object(args.clone()).stable_hash()
+        );
+        // Argument values aren't exposed by default.
+        assert_eq!(calls[0].args, None);
+        assert_eq!(calls[1].kind, OutboundCallKind::Action);
+        assert_eq!(calls[1].request_id, 4);
+        assert_eq!(calls[1].args_hash, calls[0].args_hash);
+
+        let (hook, calls) = recording_hook(true);
+        hook.observe(&mutation);
+        assert_eq!(calls.lock().unwrap()[0].args, Some(args));
+        Ok(())
+    }
+}
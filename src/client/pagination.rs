@@ -0,0 +1,287 @@
+//! Navigation helpers for [paginated queries](https://docs.convex.dev/database/pagination).
+
+use std::collections::BTreeMap;
+
+use crate::Value;
+
+/// The `paginationOpts` argument of a paginated query: how many items to load
+/// and where to start.
+///
+/// Start with [`PaginationOpts::first_page`], then navigate with the cursors
+/// of the returned [`PaginationResult`]s:
+/// - [`PaginationResult::next_page`] loads the page after a result.
+/// - [`PaginationOpts::load_more`] reloads from the same cursor with a bigger
+///   page size, e.g. for an infinite list that grows one subscription instead
+///   of adding one per page.
+/// - [`PageNavigator`] goes back and forth between pages of a fixed size.
+///
+/// Pass it to the query with [`PaginationOpts::into_args`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PaginationOpts {
+    /// The number of items to load.
+    pub num_items: u32,
+    /// The cursor to start from, or `None` for the first page.
+    pub cursor: Option<String>,
+}
+
+impl PaginationOpts {
+    /// Load the first `num_items` items.
+    pub fn first_page(num_items: u32) -> Self {
+        Self {
+            num_items,
+            cursor: None,
+        }
+    }
+
+    /// Load `additional` more items from the same cursor.
+    pub fn load_more(self, additional: u32) -> Self {
+        Self {
+            num_items: self.num_items.saturating_add(additional),
+            ..self
+        }
+    }
+
+    /// Add these options to `args` as the `paginationOpts` argument.
+    ///
+    /// ```
+    /// # use convex::PaginationOpts;
+    /// let args = PaginationOpts::first_page(10).into_args(maplit::btreemap! {
+    ///     "channel".into() => "general".into(),
+    /// });
+    /// assert!(args.contains_key("paginationOpts"));
+    /// ```
+    pub fn into_args(self, mut args: BTreeMap<String, Value>) -> BTreeMap<String, Value> {
+        args.insert("paginationOpts".to_string(), self.into());
+        args
+    }
+}
+
+impl From<PaginationOpts> for Value {
+    fn from(opts: PaginationOpts) -> Value {
+        // `numItems` is validated as a `v.number()`, i.e. a float.
+        Value::Object(BTreeMap::from([
+            ("numItems".to_string(), f64::from(opts.num_items).into()),
+            ("cursor".to_string(), opts.cursor.into()),
+        ]))
+    }
+}
+
+/// The result of a paginated query: a page of items and the cursor to
+/// continue from.
+///
+/// Parse it from a query's value with `PaginationResult::try_from`. Only the
+/// result is parsed: a query's journal, as passed to
+/// [`QueryBuilder::journal`](crate::QueryBuilder::journal), is opaque to the
+/// client and must be passed back unchanged.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PaginationResult {
+    /// The items of this page.
+    pub page: Vec<Value>,
+    /// Whether this is the last page.
+    pub is_done: bool,
+    /// The cursor to continue from to load the page after this one.
+    pub continue_cursor: String,
+}
+
+impl PaginationResult {
+    /// Whether there's a page after this one.
+    pub fn has_next_page(&self) -> bool {
+        !self.is_done
+    }
+
+    /// The options to load up to `num_items` items of the page after this
+    /// one, or `None` if this is the last page.
+    pub fn next_page(&self, num_items: u32) -> Option<PaginationOpts> {
+        self.has_next_page().then(|| PaginationOpts {
+            num_items,
+            cursor: Some(self.continue_cursor.clone()),
+        })
+    }
+}
+
+impl TryFrom<Value> for PaginationResult {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Value) -> anyhow::Result<Self> {
+        let Value::Object(mut fields) = value else {
+            anyhow::bail!("Expected a pagination result object, found {value:?}");
+        };
+        let page = match fields.remove("page") {
+            Some(Value::Array(page)) => page,
+            page => anyhow::bail!("Expected an array for page, found {page:?}"),
+        };
+        let is_done = match fields.remove("isDone") {
+            Some(Value::Boolean(is_done)) => is_done,
+            is_done => anyhow::bail!("Expected a boolean for isDone, found {is_done:?}"),
+        };
+        let continue_cursor = match fields.remove("continueCursor") {
+            Some(Value::String(cursor)) => cursor,
+            cursor => anyhow::bail!("Expected a string for continueCursor, found {cursor:?}"),
+        };
+        Ok(Self {
+            page,
+            is_done,
+            continue_cursor,
+        })
+    }
+}
+
+/// Navigation forward and backward through the pages of a paginated query,
+/// one page of `num_items` items at a time.
+///
+/// Cursors only point forward, so the navigator remembers the cursor each
+/// page it moved through started at, and goes back by reloading from it.
+///
+/// ```
+/// # use convex::{PageNavigator, PaginationOpts};
+/// let mut pages = PageNavigator::new(10);
+/// assert_eq!(pages.current(), PaginationOpts::first_page(10));
+/// assert_eq!(pages.previous_page(), None);
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PageNavigator {
+    num_items: u32,
+    /// The cursor of every page up to the current one, which is last.
+    cursors: Vec<Option<String>>,
+}
+
+impl PageNavigator {
+    /// Start at the first page, with `num_items` items per page.
+    pub fn new(num_items: u32) -> Self {
+        Self {
+            num_items,
+            cursors: vec![None],
+        }
+    }
+
+    /// The options to load the current page.
+    pub fn current(&self) -> PaginationOpts {
+        PaginationOpts {
+            num_items: self.num_items,
+            cursor: self.cursors.last().cloned().flatten(),
+        }
+    }
+
+    /// The index of the current page, starting from 0.
+    pub fn page_index(&self) -> usize {
+        self.cursors.len() - 1
+    }
+
+    /// Whether there's a page before the current one.
+    pub fn has_previous_page(&self) -> bool {
+        self.page_index() > 0
+    }
+
+    /// Move to the page after the current one, given the current page's
+    /// `result`, and return the options to load it. Returns `None` and stays
+    /// put if the current page is the last.
+    pub fn next_page(&mut self, result: &PaginationResult) -> Option<PaginationOpts> {
+        let opts = result.next_page(self.num_items)?;
+        self.cursors.push(opts.cursor.clone());
+        Some(opts)
+    }
+
+    /// Move to the page before the current one and return the options to
+    /// reload it. Returns `None` and stays put on the first page.
+    pub fn previous_page(&mut self) -> Option<PaginationOpts> {
+        if !self.has_previous_page() {
+            return None;
+        }
+        self.cursors.pop();
+        Some(self.current())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use maplit::btreemap;
+
+    use super::{
+        PageNavigator,
+        PaginationOpts,
+        PaginationResult,
+    };
+    use crate::Value;
+
+    /// Paginate `rows` like the server, using the index of the next row as
+    /// the cursor.
+    fn paginate(rows: &[i64], args: &BTreeMap<String, Value>) -> Value {
+        let Value::Object(opts) = &args["paginationOpts"] else {
+            panic!("Missing paginationOpts");
+        };
+        let start = match &opts["cursor"] {
+            Value::String(cursor) => cursor.parse().unwrap(),
+            _ => 0,
+        };
+        let Value::Float64(num_items) = opts["numItems"] else {
+            panic!("numItems must be a float");
+        };
+        let end = rows.len().min(start + num_items as usize);
+        Value::Object(btreemap! {
+            "page".into() => Value::Array(rows[start..end].iter().map(|&n| n.into()).collect()),
+            "isDone".into() => (end == rows.len()).into(),
+            "continueCursor".into() => end.to_string().into(),
+        })
+    }
+
+    #[test]
+    fn test_pages() -> anyhow::Result<()> {
+        let rows: Vec<i64> = (0..5).collect();
+        let args = btreemap! { "channel".into() => "general".into() };
+
+        let first = PaginationOpts::first_page(2).into_args(args.clone());
+        let first = PaginationResult::try_from(paginate(&rows, &first))?;
+        assert!(first.has_next_page());
+        let second = first.next_page(2).unwrap().into_args(args.clone());
+        let second = PaginationResult::try_from(paginate(&rows, &second))?;
+        let third = second.next_page(2).unwrap().into_args(args.clone());
+        let third = PaginationResult::try_from(paginate(&rows, &third))?;
+        assert!(!third.has_next_page());
+        assert_eq!(third.next_page(2), None);
+
+        let seen: Vec<Value> = [first, second, third]
+            .into_iter()
+            .flat_map(|result| result.page)
+            .collect();
+        assert_eq!(seen, rows.iter().map(|&n| n.into()).collect::<Vec<Value>>());
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_navigator() -> anyhow::Result<()> {
+        let rows: Vec<i64> = (0..5).collect();
+        let load = |opts: PaginationOpts| {
+            PaginationResult::try_from(paginate(&rows, &opts.into_args(btreemap! {})))
+        };
+        let mut pages = PageNavigator::new(2);
+        let first = load(pages.current())?;
+        let second = load(pages.next_page(&first).unwrap())?;
+        let third = load(pages.next_page(&second).unwrap())?;
+        assert_eq!(pages.page_index(), 2);
+        assert_eq!(pages.next_page(&third), None);
+        assert_eq!(pages.page_index(), 2);
+
+        // Going back reloads the same rows.
+        assert_eq!(load(pages.previous_page().unwrap())?, second);
+        assert_eq!(load(pages.previous_page().unwrap())?, first);
+        assert!(!pages.has_previous_page());
+        assert_eq!(pages.previous_page(), None);
+        assert_eq!(pages.current(), PaginationOpts::first_page(2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_more() -> anyhow::Result<()> {
+        let rows: Vec<i64> = (0..5).collect();
+        let opts = PaginationOpts::first_page(2).load_more(2);
+        assert_eq!(opts, PaginationOpts::first_page(4));
+        let result = PaginationResult::try_from(paginate(&rows, &opts.into_args(btreemap! {})))?;
+        assert_eq!(result.page.len(), 4);
+        assert!(result.has_next_page());
+
+        assert!(PaginationResult::try_from(Value::Null).is_err());
+        Ok(())
+    }
+}
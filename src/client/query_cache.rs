@@ -0,0 +1,94 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        Mutex,
+    },
+    time::Duration,
+};
+
+use convex_sync_types::CanonicalizedUdfPath;
+use tokio::time::Instant;
+
+use crate::{
+    client::clock::Clock,
+    value::Value,
+};
+
+/// The key of a cached query result: the function and the stable hash of its
+/// arguments.
+pub type QueryCacheKey = (CanonicalizedUdfPath, u128);
+
+/// Results of one-shot queries, kept for `ttl` after they were fetched. See
+/// [`ConvexClientBuilder::query_cache_ttl`](crate::ConvexClientBuilder::query_cache_ttl).
+pub struct QueryCache {
+    ttl: Duration,
+    clock: Arc<dyn Clock>,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    /// Bumped by every [`QueryCache::clear`], so that results fetched before
+    /// it aren't cached after it.
+    generation: u64,
+    results: HashMap<QueryCacheKey, (Instant, Value)>,
+}
+
+impl QueryCache {
+    pub fn new(ttl: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            ttl,
+            clock,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// The result cached for `key`, unless it's older than the TTL.
+    pub fn get(&self, key: &QueryCacheKey) -> Option<Value> {
+        let mut entries = self.entries.lock().expect("Query cache lock poisoned");
+        match entries.results.get(key) {
+            Some((fetched, value)) if self.is_fresh(*fetched) => Some(value.clone()),
+            Some(_) => {
+                entries.results.remove(key);
+                None
+            },
+            None => None,
+        }
+    }
+
+    /// The current generation, to pass to [`insert`](Self::insert) once the
+    /// result has been fetched.
+    pub fn generation(&self) -> u64 {
+        self.entries
+            .lock()
+            .expect("Query cache lock poisoned")
+            .generation
+    }
+
+    /// Cache `value` for `key`, dropping any expired results. Does nothing if
+    /// the cache was cleared since `generation` was read, since the value may
+    /// have been fetched with other credentials.
+    pub fn insert(&self, key: QueryCacheKey, value: Value, generation: u64) {
+        let mut entries = self.entries.lock().expect("Query cache lock poisoned");
+        if entries.generation != generation {
+            return;
+        }
+        entries
+            .results
+            .retain(|_, (fetched, _)| self.is_fresh(*fetched));
+        entries.results.insert(key, (self.clock.now(), value));
+    }
+
+    fn is_fresh(&self, fetched: Instant) -> bool {
+        self.clock.now().duration_since(fetched) < self.ttl
+    }
+
+    /// Drop every cached result, e.g. because they were fetched with other
+    /// credentials.
+    pub fn clear(&self) {
+        let mut entries = self.entries.lock().expect("Query cache lock poisoned");
+        entries.generation += 1;
+        entries.results.clear();
+    }
+}
@@ -0,0 +1,76 @@
+//! Spreading a client's requests out over time, to stay under a deployment's
+//! rate limits. See
+//! [`ConvexClientBuilder::request_rate_limit`](crate::ConvexClientBuilder::request_rate_limit).
+
+use std::{
+    mem,
+    sync::{
+        Arc,
+        Mutex,
+    },
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+use crate::client::clock::Clock;
+
+/// A token bucket shared by a client and its clones, to spread requests out.
+/// See [`ConvexClientBuilder::request_rate_limit`](crate::ConvexClientBuilder::request_rate_limit).
+pub struct RateLimiter {
+    requests_per_second: f64,
+    clock: Arc<dyn Clock>,
+    /// The tokens left, which go negative while requests wait for theirs, as
+    /// of the given time.
+    bucket: Mutex<(f64, Instant)>,
+}
+
+impl RateLimiter {
+    /// A limiter that allows `requests_per_second`, treating 0 as 1 since
+    /// requests would never go out otherwise.
+    pub fn new(requests_per_second: u32, clock: Arc<dyn Clock>) -> Self {
+        let requests_per_second = f64::from(requests_per_second.max(1));
+        let now = clock.now();
+        Self {
+            requests_per_second,
+            clock,
+            bucket: Mutex::new((requests_per_second, now)),
+        }
+    }
+
+    /// Take a token, waiting for one to be refilled if the bucket is empty.
+    /// Requests get their tokens in the order they ask for them.
+    ///
+    /// A request that waits reserves its token up front, to keep its place
+    /// in line. If the future is dropped before it's done waiting, the token
+    /// is put back.
+    pub async fn acquire(&self) {
+        let wait = {
+            let mut bucket = self.bucket.lock().expect("Rate limiter lock poisoned");
+            let (tokens, refilled_at) = &mut *bucket;
+            let now = self.clock.now().max(*refilled_at);
+            let refill = (now - *refilled_at).as_secs_f64() * self.requests_per_second;
+            *tokens = (*tokens + refill).min(self.requests_per_second) - 1.0;
+            *refilled_at = now;
+            if *tokens >= 0.0 {
+                return;
+            }
+            Duration::from_secs_f64(-*tokens / self.requests_per_second)
+        };
+        let reservation = Reservation(self);
+        let deadline = self.clock.now() + wait;
+        self.clock.sleep_until(deadline).await;
+        mem::forget(reservation);
+    }
+}
+
+/// A token taken by a request that's still waiting for its turn, which is
+/// put back if the request is dropped.
+struct Reservation<'a>(&'a RateLimiter);
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        let mut bucket = self.0.bucket.lock().expect("Rate limiter lock poisoned");
+        bucket.0 += 1.0;
+    }
+}
@@ -0,0 +1,185 @@
+//! A subscription to a single query over a dedicated connection.
+
+use std::{
+    collections::BTreeMap,
+    ops::{
+        Deref,
+        DerefMut,
+    },
+};
+
+use futures::StreamExt;
+use tokio::sync::watch;
+
+use crate::{
+    ConvexClient,
+    ConvexClientBuilder,
+    Value,
+};
+
+/// The latest result of one query, kept up to date over a connection
+/// dedicated to it. Created with [`ConvexQuerySubscription::connect`].
+///
+/// This is a convenience for programs that only ever watch one query, like an
+/// embedded widget: it derefs to a [`watch::Receiver`] holding the query's
+/// latest result, and the connection, including reconnects, is managed in the
+/// background. The receiver can be cloned, and the connection is closed once
+/// the subscription and all its clones are dropped. After the client gives up
+/// on the connection, e.g. on a permanent [`ConvexError`](crate::ConvexError),
+/// the receiver's [`changed`](watch::Receiver::changed) fails.
+///
+/// ## Resource cost
+/// Each `ConvexQuerySubscription` has its own [`ConvexClient`]: a websocket,
+/// a background task and a copy of the client's state. A single
+/// `ConvexClient` shares all of these across any number of queries,
+/// deduplicates identical queries, and gives a consistent view of all of
+/// them with [`ConvexClient::watch_all`]. Watching several queries with
+/// separate `ConvexQuerySubscription`s opens as many connections to the
+/// deployment, so use one `ConvexClient` for them instead.
+///
+/// ```no_run
+/// # use convex::ConvexQuerySubscription;
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// let mut messages = ConvexQuerySubscription::connect(
+///     "https://cool-music-123.convex.cloud",
+///     "messages:list",
+///     maplit::btreemap! {},
+/// )
+/// .await?;
+/// loop {
+///     println!("{:?}", *messages.borrow_and_update());
+///     messages.changed().await?;
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ConvexQuerySubscription {
+    receiver: watch::Receiver<Result<Value, String>>,
+}
+
+impl ConvexQuerySubscription {
+    /// Connect to `deployment_url` and subscribe to query `name` called with
+    /// `args`.
+    ///
+    /// Waits for the query's first result, so the receiver always holds a
+    /// result: the query's value, or its error message if it failed. Fails
+    /// like [`ConvexClient::new`] if the connection can't be opened, and if
+    /// `name` isn't a valid function path.
+    pub async fn connect(
+        deployment_url: &str,
+        name: &str,
+        args: BTreeMap<String, Value>,
+    ) -> anyhow::Result<Self> {
+        let client = ConvexClientBuilder::new(deployment_url).build().await?;
+        Self::with_client(client, name, args).await
+    }
+
+    async fn with_client(
+        mut client: ConvexClient,
+        name: &str,
+        args: BTreeMap<String, Value>,
+    ) -> anyhow::Result<Self> {
+        let mut subscription = client.subscribe(name, args).await?;
+        let first = subscription
+            .next()
+            .await
+            .ok_or_else(|| client.disconnected())?;
+        let (sender, receiver) = watch::channel(first.into());
+        tokio::spawn(async move {
+            // The client's connection lives as long as this task.
+            let _client = client;
+            loop {
+                tokio::select! {
+                    _ = sender.closed() => break,
+                    result = subscription.next() => {
+                        let Some(result) = result else { break };
+                        if sender.send(result.into()).is_err() {
+                            break;
+                        }
+                    },
+                }
+            }
+        });
+        Ok(Self { receiver })
+    }
+
+    /// The underlying [`watch::Receiver`].
+    pub fn into_receiver(self) -> watch::Receiver<Result<Value, String>> {
+        self.receiver
+    }
+}
+
+impl Deref for ConvexQuerySubscription {
+    type Target = watch::Receiver<Result<Value, String>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.receiver
+    }
+}
+
+impl DerefMut for ConvexQuerySubscription {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.receiver
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use convex_sync_types::{
+        ClientMessage,
+        QueryId,
+        QuerySetModification,
+        StateVersion,
+    };
+    use maplit::btreemap;
+
+    use super::ConvexQuerySubscription;
+    use crate::{
+        client::tests::fake_transition,
+        ConvexClient,
+        Value,
+    };
+
+    #[tokio::test]
+    async fn test_single_query() -> anyhow::Result<()> {
+        let (client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        let query_id = QueryId::new(0);
+        // Keep a clone of the client so that its worker outlives the
+        // subscription's, to observe the query being removed.
+        let subscription = tokio::spawn(ConvexQuerySubscription::with_client(
+            client.clone(),
+            "getValue",
+            btreemap! {},
+        ));
+        test_protocol.wait_until_n_messages_sent(2).await;
+        test_protocol.take_sent().await;
+
+        let (transition, version) =
+            fake_transition(StateVersion::initial(), vec![(query_id, Value::Int64(1))]);
+        test_protocol.fake_server_response(transition).await?;
+        let mut subscription = subscription.await??;
+        assert_eq!(*subscription.borrow_and_update(), Ok(Value::Int64(1)));
+
+        let (transition, _) = fake_transition(version, vec![(query_id, Value::Int64(2))]);
+        test_protocol.fake_server_response(transition).await?;
+        subscription.changed().await?;
+        assert_eq!(*subscription.borrow_and_update(), Ok(Value::Int64(2)));
+
+        // The query is removed once every receiver is dropped.
+        let receiver = subscription.clone().into_receiver();
+        drop(subscription);
+        assert!(test_protocol.take_sent().await.is_empty());
+        drop(receiver);
+        test_protocol.wait_until_n_messages_sent(1).await;
+        assert_eq!(
+            test_protocol.take_sent().await,
+            vec![ClientMessage::ModifyQuerySet {
+                base_version: 1,
+                new_version: 2,
+                modifications: vec![QuerySetModification::Remove { query_id }],
+            }]
+        );
+        Ok(())
+    }
+}
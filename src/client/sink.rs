@@ -0,0 +1,153 @@
+//! Pushing the results of a subscription into a sink, for pipelines that feed
+//! another system like a database or a file.
+
+use async_trait::async_trait;
+use futures::StreamExt;
+
+#[cfg(doc)]
+use crate::ConvexClient;
+use crate::{
+    base_client::FunctionResult,
+    QuerySubscription,
+    Value,
+};
+
+/// A destination for the results of a query, as driven by
+/// [`QuerySubscription::drain_into`] and [`ConvexClient::subscribe_to_sink`].
+///
+/// The methods are async, and the next result is only pushed once the
+/// previous call returns, so a slow sink applies backpressure to its own
+/// subscription. It doesn't hold up the client, which keeps receiving
+/// results meanwhile: like a lagging stream consumer, a sink that falls
+/// behind skips the intermediate results and gets the latest one next.
+///
+/// Returning an error from either method stops the subscription.
+///
+/// ```no_run
+/// # use convex::{ConvexClient, UpdateSink, Value};
+/// struct Printer;
+///
+/// #[async_trait::async_trait]
+/// impl UpdateSink for Printer {
+///     async fn on_update(&mut self, value: Value) -> anyhow::Result<()> {
+///         println!("{value:?}");
+///         Ok(())
+///     }
+///
+///     async fn on_error(&mut self, error_message: String) -> anyhow::Result<()> {
+///         anyhow::bail!("Query failed: {error_message}")
+///     }
+/// }
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// let mut client = ConvexClient::new("https://cool-music-123.convex.cloud").await?;
+/// let handle = client
+///     .subscribe_to_sink("listMessages", maplit::btreemap!{}, Printer)
+///     .await?;
+/// handle.await??;
+/// # Ok(())
+/// # }
+/// ```
+#[async_trait]
+pub trait UpdateSink: Send {
+    /// Handle a new value of the query.
+    async fn on_update(&mut self, value: Value) -> anyhow::Result<()>;
+
+    /// Handle a failed result of the query. The subscription continues unless
+    /// this returns an error, and a later result may be a value again.
+    async fn on_error(&mut self, error_message: String) -> anyhow::Result<()>;
+}
+
+impl QuerySubscription {
+    /// Push each result of the query into `sink`, until the client is dropped
+    /// or the sink returns an error, and then return the sink, e.g. to flush
+    /// it. See [`UpdateSink`] for the backpressure semantics.
+    pub async fn drain_into<S: UpdateSink>(mut self, mut sink: S) -> anyhow::Result<S> {
+        while let Some(result) = self.next().await {
+            match result {
+                FunctionResult::Value(value) => sink.on_update(value).await?,
+                FunctionResult::ErrorMessage(error) => sink.on_error(error).await?,
+            }
+        }
+        Ok(sink)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use convex_sync_types::{
+        QueryId,
+        StateModification,
+        StateVersion,
+    };
+    use maplit::btreemap;
+    use tokio::sync::mpsc;
+
+    use super::UpdateSink;
+    use crate::{
+        client::tests::fake_transition,
+        sync::ServerMessage,
+        ConvexClient,
+        Value,
+    };
+
+    /// Forwards results to a channel, and fails on a value of `-1`.
+    struct ChannelSink(mpsc::UnboundedSender<Result<Value, String>>);
+
+    #[async_trait]
+    impl UpdateSink for ChannelSink {
+        async fn on_update(&mut self, value: Value) -> anyhow::Result<()> {
+            anyhow::ensure!(value != Value::Int64(-1), "Bad value");
+            self.0.send(Ok(value))?;
+            Ok(())
+        }
+
+        async fn on_error(&mut self, error_message: String) -> anyhow::Result<()> {
+            self.0.send(Err(error_message))?;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_to_sink() -> anyhow::Result<()> {
+        let (mut client, mut test_protocol) = ConvexClient::with_test_protocol().await?;
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let handle = client
+            .subscribe_to_sink("getCounter", btreemap! {}, ChannelSink(sender))
+            .await?;
+        test_protocol.take_sent().await;
+
+        let query_id = QueryId::new(0);
+        let (transition, version) =
+            fake_transition(StateVersion::initial(), vec![(query_id, Value::Int64(1))]);
+        test_protocol.fake_server_response(transition).await?;
+        assert_eq!(receiver.recv().await, Some(Ok(Value::Int64(1))));
+
+        let failed = StateVersion {
+            ts: version.ts.succ()?,
+            ..version
+        };
+        test_protocol
+            .fake_server_response(ServerMessage::Transition {
+                start_version: version,
+                end_version: failed,
+                modifications: vec![StateModification::QueryFailed {
+                    query_id,
+                    error_message: "boom".into(),
+                    journal: None,
+                    log_lines: vec![],
+                }],
+            })
+            .await?;
+        assert_eq!(receiver.recv().await, Some(Err("boom".into())));
+
+        // An error from the sink ends the subscription.
+        let (transition, _) = fake_transition(failed, vec![(query_id, Value::Int64(-1))]);
+        test_protocol.fake_server_response(transition).await?;
+        let err = handle.await?.err().expect("The sink should have failed");
+        assert_eq!(err.to_string(), "Bad value");
+        assert_eq!(receiver.recv().await, None);
+        Ok(())
+    }
+}
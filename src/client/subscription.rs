@@ -1,36 +1,147 @@
 use std::{
     ops::Deref,
     pin::Pin,
+    time::Duration,
 };
 
 use futures::{
     channel::mpsc::UnboundedSender,
+    future,
+    stream,
     task,
+    FutureExt,
     Stream,
     StreamExt,
 };
+use tokio::time::{
+    Instant,
+    Sleep,
+};
 use tokio_stream::wrappers::{
     errors::BroadcastStreamRecvError,
     BroadcastStream,
 };
 
+#[cfg(doc)]
+use crate::ConvexClient;
 use crate::{
     base_client::{
         FunctionResult,
         QueryResults,
+        ResultVersion,
         SubscriberId,
     },
     client::worker::{
         ClientRequest,
         UnsubscribeRequest,
     },
-};
-#[cfg(doc)]
-use crate::{
-    ConvexClient,
+    ConvexError,
     Value,
 };
 
+/// Where the value of an [`Update`] came from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Source {
+    /// The cached result of another active subscription to the same query
+    /// and args. It may be stale.
+    Cache,
+    /// A result sent by the server after subscribing.
+    Server,
+}
+
+/// A query result along with its [`Source`], as yielded by
+/// [`QuerySubscription::with_source`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Update {
+    /// The result of the query.
+    pub value: FunctionResult,
+    /// Whether the result came from the cache or the server.
+    pub source: Source,
+}
+
+/// A query result tagged with its position in the stream, as yielded by
+/// [`QuerySubscription::sequenced`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Sequenced {
+    /// The result of the query.
+    pub value: FunctionResult,
+    /// The position of this item in the stream, starting at 0.
+    pub seq: u64,
+    /// How many times the client had reconnected when the result was
+    /// received.
+    pub connection: u64,
+    /// Whether this is the resynced result after a reconnect, i.e. the first
+    /// item received on a later connection than the previous item.
+    pub resync: bool,
+}
+
+/// The state of a query for a UI, as yielded by
+/// [`QuerySubscription::states`] and [`ConvexClient::subscribe_state`], like
+/// the result of `useQuery` in Convex's React client.
+///
+/// A stream of states starts with [`Loading`](Self::Loading), and then
+/// yields [`Loaded`](Self::Loaded) or [`Error`](Self::Error) each time the
+/// query's result changes, in any order. It never goes back to `Loading`:
+/// while the client reconnects, the last state stays current, and the query's
+/// resynced result replaces it once the server sends it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum QueryState {
+    /// No result has arrived yet.
+    Loading,
+    /// The query returned a value.
+    Loaded(Value),
+    /// The query failed with an error message.
+    Error(String),
+}
+
+/// How a [`QuerySubscription`] coalesces bursts of updates, as set with
+/// [`QuerySubscription::coalesce`] or for every subscription of a client with
+/// [`ConvexClientBuilder::adaptive_coalescing`](crate::ConvexClientBuilder::adaptive_coalescing).
+///
+/// The window adapts to how often the query updates. An update that arrives
+/// at least `quiet_interval` after the previous update of the query is
+/// yielded immediately, so occasional updates have no added latency. An
+/// update that arrives sooner than that, i.e. while the query is busy, is
+/// held back for `window`, and only the latest result at the end of the
+/// window is yielded. Every update resets the measurement, so a query
+/// switches back to immediate delivery as soon as it goes quiet for
+/// `quiet_interval`.
+///
+/// Arrival is measured when the subscription is polled, so a consumer that
+/// falls behind sees its backlog as a burst. Coalescing never yields a result
+/// older than one yielded without it, only fewer intermediate results.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AdaptiveCoalescing {
+    /// How long a query must go without updates to count as quiet. Defaults
+    /// to 100ms.
+    pub quiet_interval: Duration,
+    /// How long the updates of a busy query are coalesced for. Defaults to
+    /// 50ms.
+    pub window: Duration,
+}
+
+impl Default for AdaptiveCoalescing {
+    fn default() -> Self {
+        Self {
+            quiet_interval: Duration::from_millis(100),
+            window: Duration::from_millis(50),
+        }
+    }
+}
+
+/// The state of a subscription's [`AdaptiveCoalescing`].
+struct Coalescer {
+    config: AdaptiveCoalescing,
+    last_arrival: Option<Instant>,
+    pending: Option<PendingResult>,
+}
+
+/// The latest result held back by a [`Coalescer`], and when to yield it.
+struct PendingResult {
+    item: (FunctionResult, ResultVersion),
+    deadline: Pin<Box<Sleep>>,
+}
+
 /// This structure represents a single subscription to a query with args.
 /// For convenience, [`QuerySubscription`] also implements
 /// [`Stream`]<[`FunctionResult`]>, giving a stream of results to the query.
@@ -40,19 +151,341 @@ use crate::{
 ///
 /// For a consistent [`QueryResults`] of all your queries, use
 /// [`ConvexClient::watch_all()`] instead.
+///
+/// The stream yields a result each time the query's result changes. After the
+/// client reconnects, the query's current result is yielded exactly once when
+/// the server resends it, even if it's unchanged, and results from before the
+/// reconnect are never yielded after it. Use [`sequenced`](Self::sequenced)
+/// to detect these resyncs. As with any result, a stream that isn't polled
+/// before a newer result arrives skips to the newer one.
 pub struct QuerySubscription {
     pub(super) subscriber_id: SubscriberId,
     pub(super) request_sender: UnboundedSender<ClientRequest>,
     pub(super) watch: BroadcastStream<QueryResults>,
-    pub(super) initial: Option<FunctionResult>,
+    pub(super) initial: Option<(FunctionResult, ResultVersion)>,
+    /// The version of the latest result yielded, or of the cached result at
+    /// subscription time if it wasn't replayed.
+    last_version: Option<ResultVersion>,
+    coalescer: Option<Coalescer>,
 }
 impl QuerySubscription {
+    pub(super) fn new(
+        subscriber_id: SubscriberId,
+        request_sender: UnboundedSender<ClientRequest>,
+        watch: BroadcastStream<QueryResults>,
+        latest_results: &QueryResults,
+    ) -> Self {
+        let initial = latest_results
+            .get_current(&subscriber_id)
+            .map(|(result, version)| (result.clone(), version));
+        Self {
+            subscriber_id,
+            request_sender,
+            watch,
+            last_version: initial.as_ref().map(|(_, version)| *version),
+            initial,
+            coalescer: None,
+        }
+    }
+
     /// Returns an identifier for this subscription based on its query and args.
     /// This identifier can be used to find the result within a
     /// [`QuerySetSubscription`] as returned by [`ConvexClient::watch_all()`]
     pub fn id(&self) -> &SubscriberId {
         &self.subscriber_id
     }
+
+    /// Control whether the query's current result is replayed to this
+    /// subscription. Defaults to `true`.
+    ///
+    /// When another subscription to the same query and args is already active,
+    /// its latest result is cached, and a new [`QuerySubscription`] yields that
+    /// cached result first. With `replay_current(false)`, the cached result is
+    /// dropped and the stream only yields results that arrive after
+    /// subscribing. This suits consumers that treat the stream as a log of
+    /// changes rather than a snapshot of state.
+    pub fn replay_current(mut self, replay: bool) -> Self {
+        if !replay {
+            self.initial = None;
+        }
+        self
+    }
+
+    /// Wait for the query's next result, e.g. its first one, but only until
+    /// `deadline`: if no result arrives by then, fail with
+    /// [`ConvexError::QueryPending`] so the caller can show a "still loading"
+    /// state.
+    ///
+    /// The deadline is informational. The subscription stays active, so call
+    /// this again, or poll the stream, to keep waiting for the result. To give
+    /// up on the query instead, wrap the wait in [`tokio::time::timeout`] and
+    /// drop the subscription when it elapses.
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use convex::{ConvexClient, ConvexError};
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut client = ConvexClient::new("https://cool-music-123.convex.cloud").await?;
+    /// let mut messages = client.subscribe("listMessages", maplit::btreemap!{}).await?;
+    /// let result = loop {
+    ///     match messages.next_or_pending(Duration::from_secs(1)).await {
+    ///         Err(e) if matches!(e.downcast_ref(), Some(ConvexError::QueryPending { .. })) => {
+    ///             println!("Still loading...");
+    ///         },
+    ///         result => break result?,
+    ///     }
+    /// };
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn next_or_pending(&mut self, deadline: Duration) -> anyhow::Result<FunctionResult> {
+        match tokio::time::timeout(deadline, self.next()).await {
+            Ok(Some(result)) => Ok(result),
+            Ok(None) => anyhow::bail!("Subscription ended before a result arrived"),
+            Err(_) => Err(ConvexError::QueryPending { waited: deadline }.into()),
+        }
+    }
+
+    /// Coalesce bursts of updates to the query with `config`, trading a little
+    /// latency on busy queries for fewer intermediate results. See
+    /// [`AdaptiveCoalescing`] for the heuristic.
+    ///
+    /// ```no_run
+    /// # use convex::{AdaptiveCoalescing, ConvexClient};
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut client = ConvexClient::new("https://cool-music-123.convex.cloud").await?;
+    /// let prices = client
+    ///     .subscribe("listPrices", maplit::btreemap!{})
+    ///     .await?
+    ///     .coalesce(AdaptiveCoalescing::default());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn coalesce(mut self, config: AdaptiveCoalescing) -> Self {
+        self.coalescer = Some(Coalescer {
+            config,
+            last_arrival: None,
+            pending: None,
+        });
+        self
+    }
+
+    /// Yield each result along with whether it came from the cache or the
+    /// server, e.g. to show a "loading fresh data" indicator while displaying
+    /// cached content.
+    ///
+    /// At most one [`Source::Cache`] update is emitted, first, and only if
+    /// another subscription to the same query and args was already active
+    /// (see [`replay_current`](Self::replay_current)). All further updates
+    /// are [`Source::Server`]. The returned stream owns the subscription, so
+    /// dropping it unsubscribes.
+    pub fn with_source(mut self) -> impl Stream<Item = Update> + Unpin {
+        let cached = self.initial.take().map(|(value, _)| Update {
+            value,
+            source: Source::Cache,
+        });
+        stream::iter(cached).chain(StreamExt::map(self, |value| Update {
+            value,
+            source: Source::Server,
+        }))
+    }
+
+    /// Yield each result tagged with its position in the stream and the
+    /// connection it was received on, e.g. for consumers that treat the
+    /// stream as an ordered log and must handle resyncs after a reconnect.
+    ///
+    /// `seq` increases by one with each item. `connection` never decreases,
+    /// and the first item with a higher `connection` is marked as a `resync`:
+    /// it's the query's current result, which may equal the previous item.
+    /// The returned stream owns the subscription, so dropping it
+    /// unsubscribes.
+    pub fn sequenced(mut self) -> impl Stream<Item = Sequenced> + Unpin {
+        let mut seq = 0;
+        let mut prev_connection = None;
+        stream::poll_fn(move |cx| self.poll_next_versioned(cx)).map(move |(value, version)| {
+            let item = Sequenced {
+                value,
+                seq,
+                connection: version.connection,
+                resync: matches!(prev_connection, Some(prev) if prev < version.connection),
+            };
+            seq += 1;
+            prev_connection = Some(version.connection);
+            item
+        })
+    }
+
+    fn poll_next_versioned(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Option<(FunctionResult, ResultVersion)>> {
+        if self.coalescer.is_none() {
+            return self.poll_next_uncoalesced(cx);
+        }
+        loop {
+            let item = self.poll_next_uncoalesced(cx);
+            let coalescer = self.coalescer.as_mut().expect("Checked above");
+            match item {
+                task::Poll::Ready(Some(item)) => {
+                    let now = Instant::now();
+                    let busy = coalescer.last_arrival.is_some_and(|last| {
+                        now.duration_since(last) < coalescer.config.quiet_interval
+                    });
+                    coalescer.last_arrival = Some(now);
+                    match &mut coalescer.pending {
+                        Some(pending) => pending.item = item,
+                        None if busy => {
+                            let deadline = Box::pin(tokio::time::sleep(coalescer.config.window));
+                            coalescer.pending = Some(PendingResult { item, deadline });
+                        },
+                        None => return task::Poll::Ready(Some(item)),
+                    }
+                },
+                task::Poll::Ready(None) => {
+                    return task::Poll::Ready(coalescer.pending.take().map(|pending| pending.item));
+                },
+                task::Poll::Pending => {
+                    let Some(pending) = &mut coalescer.pending else {
+                        return task::Poll::Pending;
+                    };
+                    if pending.deadline.poll_unpin(cx).is_pending() {
+                        return task::Poll::Pending;
+                    }
+                    let pending = coalescer.pending.take().expect("Checked above");
+                    return task::Poll::Ready(Some(pending.item));
+                },
+            }
+        }
+    }
+
+    fn poll_next_uncoalesced(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Option<(FunctionResult, ResultVersion)>> {
+        if let Some(initial) = self.initial.take() {
+            return task::Poll::Ready(Some(initial));
+        }
+        loop {
+            return match self.watch.poll_next_unpin(cx) {
+                // Ok to be lagged (skip intermediate values) - since Convex
+                // only guarantees a newer value than the previous value.
+                task::Poll::Ready(Some(Err(BroadcastStreamRecvError::Lagged(_amt)))) => continue,
+                task::Poll::Ready(Some(Ok(map))) => {
+                    let Some((value, version)) = map.get_current(self.id()) else {
+                        // No result yet in the query result set, or only one
+                        // from before a reconnect. Keep polling.
+                        continue;
+                    };
+                    if self.last_version == Some(version) {
+                        // Another query's result changed.
+                        continue;
+                    }
+                    self.last_version = Some(version);
+                    task::Poll::Ready(Some((value.clone(), version)))
+                },
+                task::Poll::Ready(None) => task::Poll::Ready(None),
+                task::Poll::Pending => task::Poll::Pending,
+            };
+        }
+    }
+
+    /// Transform each value of the query with `f`, e.g. to project a single
+    /// field of the result for a UI.
+    ///
+    /// Error messages pass through untransformed as `Err`. The returned
+    /// stream owns the subscription, so dropping it unsubscribes. No extra
+    /// task is spawned: `f` runs when the stream is polled.
+    ///
+    /// ```no_run
+    /// # use convex::{ConvexClient, Value};
+    /// # use futures::StreamExt;
+    /// # #[tokio::main]
+    /// # async fn main() -> anyhow::Result<()> {
+    /// let mut client = ConvexClient::new("https://cool-music-123.convex.cloud").await?;
+    /// let mut counts = client
+    ///     .subscribe("getCounter", maplit::btreemap!{})
+    ///     .await?
+    ///     .map_values(|value| match value {
+    ///         Value::Object(mut fields) => fields.remove("count"),
+    ///         _ => None,
+    ///     });
+    /// while let Some(count) = counts.next().await {
+    ///     println!("{count:?}");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn map_values<T, F>(self, mut f: F) -> impl Stream<Item = Result<T, String>> + Unpin
+    where
+        F: FnMut(Value) -> T,
+    {
+        StreamExt::map(self, move |result| match result {
+            FunctionResult::Value(value) => Ok(f(value)),
+            FunctionResult::ErrorMessage(error) => Err(error),
+        })
+    }
+
+    /// Fold each value of the query into an accumulated state with `f`,
+    /// starting from `initial`, and yield the state after each update, e.g.
+    /// for UI state derived from the sequence of results rather than only the
+    /// latest one.
+    ///
+    /// `f` runs once per value, in the order the values are delivered, so it
+    /// must be deterministic for the state to only depend on the results.
+    /// Results skipped by the client, e.g. because the consumer lagged or the
+    /// subscription is [coalesced](Self::coalesce), are never folded in. Error
+    /// messages pass through as `Err` without changing the state. As with
+    /// [`map_values`](Self::map_values), the returned stream owns the
+    /// subscription and no extra task is spawned.
+    pub fn reduce_values<S, F>(
+        self,
+        initial: S,
+        mut f: F,
+    ) -> impl Stream<Item = Result<S, String>> + Unpin
+    where
+        S: Clone,
+        F: FnMut(S, Value) -> S,
+    {
+        let mut state = Some(initial);
+        StreamExt::map(self, move |result| match result {
+            FunctionResult::Value(value) => {
+                let reduced = f(state.take().expect("State is always restored"), value);
+                state = Some(reduced.clone());
+                Ok(reduced)
+            },
+            FunctionResult::ErrorMessage(error) => Err(error),
+        })
+    }
+
+    /// Yield the [`QueryState`] of the query: first
+    /// [`Loading`](QueryState::Loading), then a state for each result. See
+    /// [`QueryState`] for the transitions. The returned stream owns the
+    /// subscription, so dropping it unsubscribes.
+    pub fn states(self) -> impl Stream<Item = QueryState> + Unpin {
+        stream::iter([QueryState::Loading]).chain(StreamExt::map(self, |result| match result {
+            FunctionResult::Value(value) => QueryState::Loaded(value),
+            FunctionResult::ErrorMessage(error) => QueryState::Error(error),
+        }))
+    }
+
+    /// Skip values of the query for which `predicate` returns `false`.
+    ///
+    /// Error messages always pass through. As with
+    /// [`map_values`](Self::map_values), the returned stream owns the
+    /// subscription and no extra task is spawned.
+    pub fn filter_values<F>(self, mut predicate: F) -> impl Stream<Item = FunctionResult> + Unpin
+    where
+        F: FnMut(&Value) -> bool,
+    {
+        StreamExt::filter(self, move |result| {
+            future::ready(match result {
+                FunctionResult::Value(value) => predicate(value),
+                FunctionResult::ErrorMessage(_) => true,
+            })
+        })
+    }
 }
 impl std::fmt::Debug for QuerySubscription {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -84,25 +517,8 @@ impl Stream for QuerySubscription {
         mut self: Pin<&mut Self>,
         cx: &mut task::Context<'_>,
     ) -> task::Poll<Option<Self::Item>> {
-        if let Some(initial) = self.initial.take() {
-            return task::Poll::Ready(Some(initial));
-        }
-        loop {
-            return match self.watch.poll_next_unpin(cx) {
-                // Ok to be lagged (skip intermediate values) - since Convex
-                // only guarantees a newer value than the previous value.
-                task::Poll::Ready(Some(Err(BroadcastStreamRecvError::Lagged(_amt)))) => continue,
-                task::Poll::Ready(Some(Ok(map))) => {
-                    let Some(value) = map.get(self.id()) else {
-                        // No result yet in the query result set. Keep polling.
-                        continue;
-                    };
-                    task::Poll::Ready(Some(value.clone()))
-                },
-                task::Poll::Ready(None) => task::Poll::Ready(None),
-                task::Poll::Pending => task::Poll::Pending,
-            };
-        }
+        self.poll_next_versioned(cx)
+            .map(|item| item.map(|(value, _)| value))
     }
 }
 
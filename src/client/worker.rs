@@ -1,12 +1,17 @@
 use std::{
     collections::BTreeMap,
-    convert::Infallible,
+    sync::{
+        Arc,
+        Mutex,
+    },
     time::Duration,
 };
 
 use convex_sync_types::{
     backoff::Backoff,
     AuthenticationToken,
+    QueryId,
+    Timestamp,
     UdfPath,
 };
 use futures::{
@@ -18,15 +23,25 @@ use futures::{
     FutureExt,
     StreamExt,
 };
-use tokio::sync::broadcast;
+use tokio::sync::{
+    broadcast,
+    watch,
+};
 use tokio_stream::wrappers::BroadcastStream;
 
 use crate::{
     base_client::{
         BaseConvexClient,
+        DeploymentInfo,
+        MutationOutcome,
+        QueryBuilder,
+        QuerySnapshot,
+        RequestPriority,
         SubscriberId,
+        TransitionInfo,
     },
     client::{
+        outbound::OutboundHook,
         QueryResults,
         QuerySubscription,
     },
@@ -37,17 +52,23 @@ use crate::{
         SyncProtocol,
     },
     value::Value,
+    ConvexError,
     FunctionResult,
 };
 
 const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
 const MAX_BACKOFF: Duration = Duration::from_secs(15);
 
+#[allow(clippy::large_enum_variant)]
 pub enum ClientRequest {
     Mutation(
         MutationRequest,
         oneshot::Sender<tokio::sync::oneshot::Receiver<FunctionResult>>,
     ),
+    MutationWithTs(
+        MutationRequest,
+        oneshot::Sender<tokio::sync::oneshot::Receiver<MutationOutcome>>,
+    ),
     Action(
         ActionRequest,
         oneshot::Sender<tokio::sync::oneshot::Receiver<FunctionResult>>,
@@ -57,18 +78,34 @@ pub enum ClientRequest {
         oneshot::Sender<QuerySubscription>,
         mpsc::UnboundedSender<ClientRequest>,
     ),
+    SubscribeMany(
+        Vec<QueryBuilder>,
+        oneshot::Sender<anyhow::Result<Vec<QuerySubscription>>>,
+        mpsc::UnboundedSender<ClientRequest>,
+    ),
     Unsubscribe(UnsubscribeRequest),
     Authenticate(AuthenticateRequest),
+    Snapshot(oneshot::Sender<QuerySnapshot>),
+    Subscriptions(oneshot::Sender<Vec<(UdfPath, BTreeMap<String, Value>)>>),
+    LastTransition(oneshot::Sender<Option<TransitionInfo>>),
+    DeploymentInfo(oneshot::Sender<Option<DeploymentInfo>>),
+    IsConsistent(Vec<QueryId>, oneshot::Sender<anyhow::Result<bool>>),
+    #[cfg(feature = "unstable")]
+    SendRaw(convex_sync_types::ClientMessage),
+    #[cfg(feature = "unstable")]
+    RawMessages(oneshot::Sender<broadcast::Receiver<crate::sync::ServerMessage>>),
 }
 
 pub struct MutationRequest {
     pub udf_path: UdfPath,
     pub args: BTreeMap<String, Value>,
+    pub priority: RequestPriority,
 }
 
 pub struct ActionRequest {
     pub udf_path: UdfPath,
     pub args: BTreeMap<String, Value>,
+    pub priority: RequestPriority,
 }
 
 pub struct SubscribeRequest {
@@ -85,14 +122,27 @@ pub struct UnsubscribeRequest {
     pub subscriber_id: SubscriberId,
 }
 
+/// Drive the client until the connection is closed permanently, returning
+/// the reason. The reason is also stored in `disconnect_error` before
+/// outstanding requests are failed.
+///
+/// `connected` tracks whether the protocol is connected. It starts out
+/// connected, since the protocol is opened before the worker starts.
+/// `timestamp` tracks the latest timestamp of the transitions applied.
+/// `on_outbound` is told about every mutation and action as it's sent.
+#[allow(clippy::too_many_arguments)]
 pub async fn worker<T: SyncProtocol>(
     mut protocol_response_receiver: mpsc::Receiver<ProtocolResponse>,
 
     mut client_request_receiver: mpsc::UnboundedReceiver<ClientRequest>,
     mut watch_sender: broadcast::Sender<QueryResults>,
+    connected: watch::Sender<bool>,
+    timestamp: watch::Sender<Option<Timestamp>>,
     mut base_client: BaseConvexClient,
     mut protocol_manager: T,
-) -> Infallible {
+    disconnect_error: Arc<Mutex<Option<ConvexError>>>,
+    on_outbound: Option<OutboundHook>,
+) -> ConvexError {
     let mut backoff = Backoff::new(INITIAL_BACKOFF, MAX_BACKOFF);
     loop {
         let e = loop {
@@ -100,16 +150,30 @@ pub async fn worker<T: SyncProtocol>(
                 &mut protocol_response_receiver,
                 &mut client_request_receiver,
                 &mut watch_sender,
+                &connected,
+                &timestamp,
                 &mut base_client,
                 &mut protocol_manager,
+                on_outbound.as_ref(),
             )
             .await
             {
-                Ok(()) => backoff.reset(),
+                Ok(None) => backoff.reset(),
+                Ok(Some(error)) => {
+                    connected.send_replace(false);
+                    *disconnect_error
+                        .lock()
+                        .expect("Disconnect error lock poisoned") = Some(error.clone());
+                    // Returning drops the other requests, so that they fail
+                    // with the disconnect error, like after `close`.
+                    base_client.complete_executed_requests();
+                    return error;
+                },
                 Err(e) => break e,
             }
         };
 
+        connected.send_replace(false);
         let delay = backoff.fail(&mut rand::thread_rng());
         tracing::error!(
             "Convex Client Worker failed: {e:?}. Backing off for {delay:?} and retrying."
@@ -124,19 +188,30 @@ pub async fn worker<T: SyncProtocol>(
             })
             .await;
         base_client.resend_ongoing_queries_mutations();
-        flush_messages(&mut base_client, &mut protocol_manager).await;
+        flush_messages(
+            &mut base_client,
+            &mut protocol_manager,
+            on_outbound.as_ref(),
+        )
+        .await;
         tokio::time::sleep(delay).await;
     }
 }
 
+/// Handle a single protocol response or client request. Returns an error if
+/// the connection was closed permanently.
+#[allow(clippy::too_many_arguments)]
 async fn _worker_once<T: SyncProtocol>(
     protocol_response_receiver: &mut mpsc::Receiver<ProtocolResponse>,
 
     client_request_receiver: &mut mpsc::UnboundedReceiver<ClientRequest>,
     watch_sender: &mut broadcast::Sender<QueryResults>,
+    connected: &watch::Sender<bool>,
+    timestamp: &watch::Sender<Option<Timestamp>>,
     base_client: &mut BaseConvexClient,
     protocol_manager: &mut T,
-) -> Result<(), ReconnectProtocolReason> {
+    on_outbound: Option<&OutboundHook>,
+) -> Result<Option<ConvexError>, ReconnectProtocolReason> {
     select_biased! {
         protocol_response = protocol_response_receiver.next().fuse() => {
             match protocol_response {
@@ -145,71 +220,156 @@ async fn _worker_once<T: SyncProtocol>(
                         // Notify watchers of the new consistent query results at new timestamp
                         let _ = watch_sender.send(subscriber_id_to_latest_value);
                     }
+                    if let Some(transition) = base_client.last_transition() {
+                        let ts = transition.end_version.ts;
+                        timestamp.send_if_modified(|latest| {
+                            let advanced = latest.map_or(true, |latest| latest < ts);
+                            if advanced {
+                                *latest = Some(ts);
+                            }
+                            advanced
+                        });
+                    }
                 },
                 Some(ProtocolResponse::Failure) => {
                     return Err("ProtocolFailure".into());
                 },
+                Some(ProtocolResponse::Connected(info)) => {
+                    base_client.set_deployment_info(info);
+                    connected.send_replace(true);
+                },
+                Some(ProtocolResponse::Fatal(error)) => return Ok(Some(error)),
                 None => {},
             }
         }
         client_request = client_request_receiver.select_next_some() => {
-            match client_request {
-                ClientRequest::Subscribe(query, tx, request_sender) => {
-                    let watch = watch_sender.subscribe();
-                    let SubscribeRequest {
-                        udf_path,
-                        args,
-                    } =  query;
-                    let subscriber_id = base_client.subscribe(udf_path, args);
-                    flush_messages(base_client, protocol_manager).await;
-
-                    let watch = BroadcastStream::new(watch);
-                    let subscription = QuerySubscription {
-                        subscriber_id,
-                        request_sender,
-                        watch,
-                        initial: base_client.latest_results().get(&subscriber_id).cloned(),
-                    };
-                    let _ = tx.send(subscription);
-                },
-                ClientRequest::Mutation(mutation, tx) => {
-                    let MutationRequest {
-                        udf_path,
-                        args,
-                    } = mutation;
-                    let result_receiver = base_client
-                        .mutation(udf_path, args);
-                    flush_messages(base_client, protocol_manager).await;
-                    let _ = tx.send(result_receiver);
-                },
-                ClientRequest::Action(action, tx) => {
-                    let ActionRequest {
-                        udf_path,
-                        args,
-                    } = action;
-                    let result_receiver = base_client
-                        .action(udf_path, args);
-                    flush_messages(base_client, protocol_manager).await;
-                    let _ = tx.send(result_receiver);
-                },
-                ClientRequest::Unsubscribe(unsubscribe) => {
-                    let UnsubscribeRequest {subscriber_id} = unsubscribe;
-                    base_client.unsubscribe(subscriber_id);
-                    flush_messages(base_client, protocol_manager).await;
-                },
-                ClientRequest::Authenticate(authenticate) => {
-                    base_client.set_auth(authenticate.token);
-                    flush_messages(base_client, protocol_manager).await;
-                },
+            handle_client_request(client_request, watch_sender, base_client);
+            // Handle the requests that are already waiting before flushing, so
+            // that high-priority ones among them are sent ahead of the query
+            // set modifications of the others.
+            while let Ok(client_request) = client_request_receiver.try_recv() {
+                handle_client_request(client_request, watch_sender, base_client);
             }
+            flush_messages(base_client, protocol_manager, on_outbound).await;
         }
     }
-    Ok(())
+    Ok(None)
+}
+
+/// Apply a request from the client to `base_client`. The messages it queues
+/// are sent by the caller.
+fn handle_client_request(
+    client_request: ClientRequest,
+    watch_sender: &broadcast::Sender<QueryResults>,
+    base_client: &mut BaseConvexClient,
+) {
+    match client_request {
+        ClientRequest::Subscribe(query, tx, request_sender) => {
+            let watch = watch_sender.subscribe();
+            let SubscribeRequest { udf_path, args } = query;
+            let subscriber_id = base_client.subscribe(udf_path, args);
+
+            let watch = BroadcastStream::new(watch);
+            let subscription = QuerySubscription::new(
+                subscriber_id,
+                request_sender,
+                watch,
+                base_client.latest_results(),
+            );
+            let _ = tx.send(subscription);
+        },
+        ClientRequest::SubscribeMany(queries, tx, request_sender) => {
+            let subscriber_ids = match base_client.subscribe_queries(queries) {
+                Ok(subscriber_ids) => subscriber_ids,
+                Err(e) => {
+                    let _ = tx.send(Err(e));
+                    return;
+                },
+            };
+
+            let subscriptions = subscriber_ids
+                .into_iter()
+                .map(|subscriber_id| {
+                    QuerySubscription::new(
+                        subscriber_id,
+                        request_sender.clone(),
+                        BroadcastStream::new(watch_sender.subscribe()),
+                        base_client.latest_results(),
+                    )
+                })
+                .collect();
+            let _ = tx.send(Ok(subscriptions));
+        },
+        ClientRequest::Mutation(mutation, tx) => {
+            let MutationRequest {
+                udf_path,
+                args,
+                priority,
+            } = mutation;
+            let result_receiver = base_client.mutation_with_priority(udf_path, args, priority);
+            let _ = tx.send(result_receiver);
+        },
+        ClientRequest::MutationWithTs(mutation, tx) => {
+            let MutationRequest {
+                udf_path,
+                args,
+                priority,
+            } = mutation;
+            let outcome_receiver = base_client.mutation_with_ts(udf_path, args, priority);
+            let _ = tx.send(outcome_receiver);
+        },
+        ClientRequest::Action(action, tx) => {
+            let ActionRequest {
+                udf_path,
+                args,
+                priority,
+            } = action;
+            let result_receiver = base_client.action_with_priority(udf_path, args, priority);
+            let _ = tx.send(result_receiver);
+        },
+        ClientRequest::Unsubscribe(unsubscribe) => {
+            let UnsubscribeRequest { subscriber_id } = unsubscribe;
+            base_client.unsubscribe(subscriber_id);
+        },
+        ClientRequest::Authenticate(authenticate) => {
+            base_client.set_auth(authenticate.token);
+        },
+        ClientRequest::Snapshot(tx) => {
+            let _ = tx.send(base_client.snapshot());
+        },
+        ClientRequest::Subscriptions(tx) => {
+            let _ = tx.send(base_client.subscriptions());
+        },
+        ClientRequest::LastTransition(tx) => {
+            let _ = tx.send(base_client.last_transition().cloned());
+        },
+        ClientRequest::DeploymentInfo(tx) => {
+            let _ = tx.send(base_client.deployment_info().cloned());
+        },
+        ClientRequest::IsConsistent(query_ids, tx) => {
+            let _ = tx.send(base_client.is_consistent(&query_ids));
+        },
+        #[cfg(feature = "unstable")]
+        ClientRequest::SendRaw(message) => {
+            base_client.push_raw_message(message);
+        },
+        #[cfg(feature = "unstable")]
+        ClientRequest::RawMessages(tx) => {
+            let _ = tx.send(base_client.raw_messages());
+        },
+    }
 }
 
 /// Flush all messages to the protocol
-async fn flush_messages<P: SyncProtocol>(base_client: &mut BaseConvexClient, protocol: &mut P) {
+async fn flush_messages<P: SyncProtocol>(
+    base_client: &mut BaseConvexClient,
+    protocol: &mut P,
+    on_outbound: Option<&OutboundHook>,
+) {
     while let Some(modification) = base_client.pop_next_message() {
+        if let Some(on_outbound) = on_outbound {
+            on_outbound.observe(&modification);
+        }
         let _ = protocol.send(modification).await;
     }
 }
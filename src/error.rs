@@ -0,0 +1,477 @@
+use std::{
+    fmt,
+    time::Duration,
+};
+
+/// An error describing why the connection to a Convex deployment failed.
+///
+/// ## Close codes
+/// When the server closes the websocket, the close frame's code is mapped to
+/// one of these variants. Most closures are transient and the client
+/// reconnects with backoff, as it does for network failures. Some mean that
+/// reconnecting cannot succeed, in which case the client stops: in-flight
+/// mutations and actions that the server hadn't executed yet fail with an
+/// `Err` that downcasts to the `ConvexError`, subscriptions end, and further
+/// requests fail the same way.
+///
+/// | Close code             | Variant                           | Behavior  |
+/// |------------------------|-----------------------------------|-----------|
+/// | 1002, 1003, 1007, 1009 | [`ConvexError::ProtocolError`]    | Reconnect |
+/// | 1008                   | [`ConvexError::PolicyViolation`]  | Permanent |
+/// | 1011                   | [`ConvexError::ServerError`]      | Reconnect |
+/// | 4000 - 4499            | [`ConvexError::Application`]      | Permanent |
+/// | 4500 - 4999            | [`ConvexError::Application`]      | Reconnect |
+/// | anything else          | [`ConvexError::ConnectionClosed`] | Reconnect |
+///
+/// Application-defined codes follow HTTP conventions: `4xxx` codes below
+/// 4500 signal a problem with the client's request (like an HTTP 4xx), and
+/// the rest a problem on the server (like an HTTP 5xx).
+///
+/// ## Connect failures
+/// Failures to open the initial connection in
+/// [`ConvexClient::new`](crate::ConvexClient::new) are classified as follows.
+/// Transient ones are retried with backoff, up to
+/// [`ConvexClientBuilder::connect_retries`](crate::ConvexClientBuilder::connect_retries)
+/// times, while permanent ones fail immediately.
+///
+/// | Failure                             | Variant                            | Behavior  |
+/// |-------------------------------------|------------------------------------|-----------|
+/// | The host name doesn't resolve       | [`ConvexError::DnsFailure`]        | Permanent |
+/// | TLS is needed but not built in      | [`ConvexError::TlsSchemeMismatch`] | Permanent |
+/// | TLS is required but the URL is `ws` | [`ConvexError::TlsSchemeMismatch`] | Permanent |
+/// | The TLS handshake fails             | [`ConvexError::TlsFailure`]        | Permanent |
+/// | The connection is refused or reset  | [`ConvexError::ConnectionRefused`] | Retry     |
+/// | The connect timeout elapses         | [`ConvexError::HandshakeTimeout`]  | Retry     |
+///
+/// Once the client has connected, failures to reconnect are retried, since
+/// they are usually caused by a temporary loss of network. If
+/// [`ConvexClientBuilder::max_reconnect_attempts`](crate::ConvexClientBuilder::max_reconnect_attempts)
+/// is set, the client gives up after that many consecutive failed attempts
+/// with [`ConvexError::ConnectionExhausted`], which is permanent. Likewise,
+/// hitting the cap set with
+/// [`ConvexClientBuilder::bandwidth_cap`](crate::ConvexClientBuilder::bandwidth_cap)
+/// stops the client with [`ConvexError::BandwidthLimited`].
+///
+/// With
+/// [`ConvexClientBuilder::retry_budget`](crate::ConvexClientBuilder::retry_budget),
+/// connect retries and reconnect attempts also take from a budget shared by
+/// all of them. Once it's spent, the initial connect fails with the error of
+/// its last attempt, and a reconnect stops the client with
+/// [`ConvexError::RetryBudgetExhausted`], which is permanent. With
+/// [`ConvexClientBuilder::wait_for_retry_budget`](crate::ConvexClientBuilder::wait_for_retry_budget),
+/// a reconnect waits for the budget to refill instead.
+///
+/// ## Pending queries
+/// [`QuerySubscription::next_or_pending`](crate::QuerySubscription::next_or_pending)
+/// fails with [`ConvexError::QueryPending`] when a query takes longer than a
+/// deadline to produce a result. Unlike the errors above, it doesn't mean
+/// anything is wrong with the connection, and the subscription stays active.
+///
+/// ## Oversized arguments
+/// With
+/// [`ConvexClientBuilder::max_argument_size`](crate::ConvexClientBuilder::max_argument_size),
+/// mutations whose arguments are over the limit fail with
+/// [`ConvexError::ArgumentTooLarge`] before they're sent. The client stays
+/// connected.
+///
+/// ## HTTP status codes
+/// For web services that call Convex from their handlers,
+/// [`ConvexError::http_status`] maps each error to the status to respond
+/// with. With the `axum` feature, `ConvexError` implements axum's
+/// `IntoResponse`, and with the `actix-web` feature actix's `ResponseError`,
+/// so handlers can return it with `?`. The response body is the error's
+/// `Display` text.
+///
+/// | Variant                                            | Status                  |
+/// |----------------------------------------------------|-------------------------|
+/// | [`ConvexError::Application`] with code 4400 - 4499 | code - 4000, e.g. 401   |
+/// | [`ConvexError::Application`] with code 4000 - 4399 | 400 Bad Request         |
+/// | [`ConvexError::Application`] with code 4500 - 4599 | code - 4000, e.g. 503   |
+/// | [`ConvexError::Application`] with code 4600 - 4999 | 500 Internal Error      |
+/// | [`ConvexError::ProtocolError`]                     | 500 Internal Error      |
+/// | [`ConvexError::TlsSchemeMismatch`]                 | 500 Internal Error      |
+/// | [`ConvexError::ArgumentTooLarge`]                  | 413 Payload Too Large   |
+/// | anything else                                      | 503 Service Unavailable |
+///
+/// Application codes follow the HTTP convention above, so e.g. a deployment
+/// rejecting the client's credentials with 4401 maps to 401 Unauthorized. The
+/// remaining errors mean the deployment couldn't be reached, so the request
+/// may succeed if retried later. Errors thrown by Convex functions aren't
+/// `ConvexError`s: they're returned as a [`FunctionResult::ErrorMessage`].
+///
+/// [`FunctionResult::ErrorMessage`]: crate::FunctionResult::ErrorMessage
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConvexError {
+    /// The connection was closed, e.g. because the server is restarting.
+    ConnectionClosed {
+        /// The websocket close code.
+        code: u16,
+        /// The reason given by the server.
+        reason: String,
+    },
+    /// The server could not understand a message from the client.
+    ProtocolError {
+        /// The websocket close code.
+        code: u16,
+        /// The reason given by the server.
+        reason: String,
+    },
+    /// The server refused the connection, e.g. because this client version is
+    /// no longer supported.
+    PolicyViolation {
+        /// The reason given by the server.
+        reason: String,
+    },
+    /// The server hit an internal error.
+    ServerError {
+        /// The reason given by the server.
+        reason: String,
+    },
+    /// The server closed the connection with an application-defined code.
+    Application {
+        /// The websocket close code, between 4000 and 4999.
+        code: u16,
+        /// The reason given by the server.
+        reason: String,
+    },
+    /// The deployment's host name could not be resolved.
+    DnsFailure {
+        /// A description of the underlying error.
+        message: String,
+    },
+    /// The TLS handshake failed, e.g. because the server's certificate is
+    /// invalid.
+    TlsFailure {
+        /// A description of the underlying error.
+        message: String,
+    },
+    /// The deployment URL is `https` or `wss`, but the client was built
+    /// without a TLS implementation, i.e. without any of the `native-tls`,
+    /// `rustls-tls-native-roots` or `rustls-tls-webpki-roots` features.
+    /// Enable one of them, or use an `http` or `ws` URL for a deployment that
+    /// doesn't use TLS, e.g. a local one.
+    ///
+    /// Or the other way around: the deployment URL is `http` or `ws`, but the
+    /// client was set to
+    /// [`require_tls`](crate::ConvexClientBuilder::require_tls).
+    TlsSchemeMismatch {
+        /// The websocket URL that was connected to.
+        url: String,
+    },
+    /// The deployment refused or reset the connection.
+    ConnectionRefused {
+        /// A description of the underlying error.
+        message: String,
+    },
+    /// Connecting did not complete within the connect timeout.
+    HandshakeTimeout,
+    /// The connection was lost and could not be reestablished within the
+    /// maximum number of reconnect attempts.
+    ConnectionExhausted {
+        /// The number of reconnect attempts made.
+        attempts: u32,
+    },
+    /// The connection was lost and reconnecting would have exceeded the retry
+    /// budget set with
+    /// [`ConvexClientBuilder::retry_budget`](crate::ConvexClientBuilder::retry_budget).
+    RetryBudgetExhausted {
+        /// The retries allowed per window.
+        retries: u32,
+        /// The window.
+        window: Duration,
+    },
+    /// The client was closed with
+    /// [`ConvexClient::close`](crate::ConvexClient::close).
+    Closed,
+    /// Sending a message would have exceeded the cap on bandwidth set with
+    /// [`ConvexClientBuilder::bandwidth_cap`](crate::ConvexClientBuilder::bandwidth_cap).
+    BandwidthLimited {
+        /// The cap, in bytes sent and received.
+        cap: u64,
+    },
+    /// A query's result didn't arrive within the deadline passed to
+    /// [`QuerySubscription::next_or_pending`](crate::QuerySubscription::next_or_pending).
+    /// The query is still subscribed, so its result may arrive later.
+    QueryPending {
+        /// How long the query was waited for.
+        waited: Duration,
+    },
+    /// A mutation's arguments were larger than the limit set with
+    /// [`ConvexClientBuilder::max_argument_size`](crate::ConvexClientBuilder::max_argument_size),
+    /// so it wasn't sent.
+    ArgumentTooLarge {
+        /// The size of the arguments, in bytes of JSON.
+        size: usize,
+        /// The limit, in bytes.
+        limit: usize,
+    },
+}
+
+impl ConvexError {
+    /// Map a websocket close frame's `code` and `reason` to an error.
+    pub fn from_close_frame(code: u16, reason: impl Into<String>) -> Self {
+        let reason = reason.into();
+        match code {
+            1002 | 1003 | 1007 | 1009 => ConvexError::ProtocolError { code, reason },
+            1008 => ConvexError::PolicyViolation { reason },
+            1011 => ConvexError::ServerError { reason },
+            4000..=4999 => ConvexError::Application { code, reason },
+            _ => ConvexError::ConnectionClosed { code, reason },
+        }
+    }
+
+    /// The websocket close code this error was created from, if the server
+    /// closed the connection.
+    pub fn close_code(&self) -> Option<u16> {
+        match self {
+            ConvexError::ConnectionClosed { code, .. }
+            | ConvexError::ProtocolError { code, .. }
+            | ConvexError::Application { code, .. } => Some(*code),
+            ConvexError::PolicyViolation { .. } => Some(1008),
+            ConvexError::ServerError { .. } => Some(1011),
+            ConvexError::DnsFailure { .. }
+            | ConvexError::TlsFailure { .. }
+            | ConvexError::TlsSchemeMismatch { .. }
+            | ConvexError::ConnectionRefused { .. }
+            | ConvexError::HandshakeTimeout
+            | ConvexError::ConnectionExhausted { .. }
+            | ConvexError::RetryBudgetExhausted { .. }
+            | ConvexError::Closed
+            | ConvexError::BandwidthLimited { .. }
+            | ConvexError::QueryPending { .. }
+            | ConvexError::ArgumentTooLarge { .. } => None,
+        }
+    }
+
+    /// Whether the client should stop instead of trying to connect again.
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            ConvexError::PolicyViolation { .. }
+            | ConvexError::DnsFailure { .. }
+            | ConvexError::TlsFailure { .. }
+            | ConvexError::TlsSchemeMismatch { .. }
+            | ConvexError::ConnectionExhausted { .. }
+            | ConvexError::RetryBudgetExhausted { .. }
+            | ConvexError::Closed
+            | ConvexError::BandwidthLimited { .. } => true,
+            ConvexError::Application { code, .. } => *code < 4500,
+            ConvexError::ConnectionClosed { .. }
+            | ConvexError::ProtocolError { .. }
+            | ConvexError::ServerError { .. }
+            | ConvexError::ConnectionRefused { .. }
+            | ConvexError::HandshakeTimeout
+            | ConvexError::QueryPending { .. }
+            | ConvexError::ArgumentTooLarge { .. } => false,
+        }
+    }
+
+    /// The HTTP status code for a response reporting this error. See the
+    /// [table](ConvexError#http-status-codes) for the mapping.
+    pub fn http_status(&self) -> u16 {
+        match self {
+            ConvexError::Application { code, .. } => match code.saturating_sub(4000) {
+                status @ 400..=599 => status,
+                0..=499 => 400,
+                _ => 500,
+            },
+            ConvexError::ProtocolError { .. } | ConvexError::TlsSchemeMismatch { .. } => 500,
+            ConvexError::ArgumentTooLarge { .. } => 413,
+            ConvexError::ConnectionClosed { .. }
+            | ConvexError::PolicyViolation { .. }
+            | ConvexError::ServerError { .. }
+            | ConvexError::DnsFailure { .. }
+            | ConvexError::TlsFailure { .. }
+            | ConvexError::ConnectionRefused { .. }
+            | ConvexError::HandshakeTimeout
+            | ConvexError::ConnectionExhausted { .. }
+            | ConvexError::RetryBudgetExhausted { .. }
+            | ConvexError::Closed
+            | ConvexError::BandwidthLimited { .. }
+            | ConvexError::QueryPending { .. } => 503,
+        }
+    }
+}
+
+impl fmt::Display for ConvexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConvexError::ConnectionClosed { code, reason } => {
+                write!(f, "ConnectionClosed ({code}): {reason}")
+            },
+            ConvexError::ProtocolError { code, reason } => {
+                write!(f, "ProtocolError ({code}): {reason}")
+            },
+            ConvexError::PolicyViolation { reason } => {
+                write!(f, "PolicyViolation (1008): {reason}")
+            },
+            ConvexError::ServerError { reason } => write!(f, "ServerError (1011): {reason}"),
+            ConvexError::Application { code, reason } => {
+                write!(f, "Application ({code}): {reason}")
+            },
+            ConvexError::DnsFailure { message } => write!(f, "DnsFailure: {message}"),
+            ConvexError::TlsFailure { message } => write!(f, "TlsFailure: {message}"),
+            ConvexError::TlsSchemeMismatch { url } if url.starts_with("wss:") => write!(
+                f,
+                "TlsSchemeMismatch: {url} requires TLS, but the client was built without a TLS \
+                 feature"
+            ),
+            ConvexError::TlsSchemeMismatch { url } => write!(
+                f,
+                "TlsSchemeMismatch: {url} doesn't use TLS, but the client requires it"
+            ),
+            ConvexError::ConnectionRefused { message } => write!(f, "ConnectionRefused: {message}"),
+            ConvexError::HandshakeTimeout => write!(f, "HandshakeTimeout"),
+            ConvexError::ConnectionExhausted { attempts } => {
+                write!(
+                    f,
+                    "ConnectionExhausted: gave up after {attempts} reconnect attempts"
+                )
+            },
+            ConvexError::RetryBudgetExhausted { retries, window } => {
+                write!(
+                    f,
+                    "RetryBudgetExhausted: over the budget of {retries} retries per {window:?}"
+                )
+            },
+            ConvexError::Closed => write!(f, "Closed: the client was closed"),
+            ConvexError::BandwidthLimited { cap } => {
+                write!(
+                    f,
+                    "BandwidthLimited: sending would exceed the cap of {cap} bytes"
+                )
+            },
+            ConvexError::QueryPending { waited } => {
+                write!(f, "QueryPending: no result after {waited:?}")
+            },
+            ConvexError::ArgumentTooLarge { size, limit } => {
+                write!(
+                    f,
+                    "ArgumentTooLarge: the arguments are {size} bytes, over the limit of {limit}"
+                )
+            },
+        }
+    }
+}
+
+impl std::error::Error for ConvexError {}
+
+#[cfg(feature = "axum")]
+impl axum_core::response::IntoResponse for ConvexError {
+    fn into_response(self) -> axum_core::response::Response {
+        let status = http::StatusCode::from_u16(self.http_status())
+            .expect("ConvexError::http_status is always a valid status");
+        (status, self.to_string()).into_response()
+    }
+}
+
+#[cfg(feature = "actix-web")]
+impl actix_web::ResponseError for ConvexError {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        actix_web::http::StatusCode::from_u16(self.http_status())
+            .expect("ConvexError::http_status is always a valid status")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::ConvexError;
+
+    #[test]
+    fn test_from_close_frame() {
+        let cases = [
+            (1000, "ConnectionClosed", false),
+            (1001, "ConnectionClosed", false),
+            (1002, "ProtocolError", false),
+            (1008, "PolicyViolation", true),
+            (1011, "ServerError", false),
+            (1013, "ConnectionClosed", false),
+            (4000, "Application", true),
+            (4499, "Application", true),
+            (4500, "Application", false),
+        ];
+        for (code, kind, permanent) in cases {
+            let error = ConvexError::from_close_frame(code, "reason");
+            assert_eq!(error.close_code(), Some(code));
+            assert_eq!(error.is_permanent(), permanent, "{error}");
+            assert_eq!(error.to_string(), format!("{kind} ({code}): reason"));
+        }
+    }
+
+    fn all_variants() -> Vec<(ConvexError, u16)> {
+        let message = || "message".to_string();
+        vec![
+            (ConvexError::from_close_frame(4401, "unauthorized"), 401),
+            (ConvexError::from_close_frame(4000, "bad request"), 400),
+            (ConvexError::from_close_frame(4503, "overloaded"), 503),
+            (ConvexError::from_close_frame(4999, "unknown"), 500),
+            (ConvexError::from_close_frame(1002, "bad message"), 500),
+            (ConvexError::from_close_frame(1001, "going away"), 503),
+            (ConvexError::from_close_frame(1008, "unsupported"), 503),
+            (ConvexError::from_close_frame(1011, "internal"), 503),
+            (ConvexError::DnsFailure { message: message() }, 503),
+            (ConvexError::TlsFailure { message: message() }, 503),
+            (
+                ConvexError::TlsSchemeMismatch {
+                    url: "wss://flying-shark-123.convex.cloud/api/sync".into(),
+                },
+                500,
+            ),
+            (ConvexError::ConnectionRefused { message: message() }, 503),
+            (ConvexError::HandshakeTimeout, 503),
+            (ConvexError::ConnectionExhausted { attempts: 3 }, 503),
+            (
+                ConvexError::RetryBudgetExhausted {
+                    retries: 10,
+                    window: Duration::from_secs(60),
+                },
+                503,
+            ),
+            (ConvexError::Closed, 503),
+            (ConvexError::BandwidthLimited { cap: 1024 }, 503),
+            (
+                ConvexError::QueryPending {
+                    waited: Duration::from_secs(5),
+                },
+                503,
+            ),
+            (
+                ConvexError::ArgumentTooLarge {
+                    size: 2048,
+                    limit: 1024,
+                },
+                413,
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_http_status() {
+        for (error, status) in all_variants() {
+            assert_eq!(error.http_status(), status, "{error}");
+        }
+    }
+
+    #[cfg(feature = "axum")]
+    #[test]
+    fn test_into_response() {
+        use axum_core::response::IntoResponse;
+
+        for (error, status) in all_variants() {
+            let response = error.clone().into_response();
+            assert_eq!(response.status().as_u16(), status, "{error}");
+        }
+    }
+
+    #[cfg(feature = "actix-web")]
+    #[test]
+    fn test_response_error() {
+        use actix_web::ResponseError;
+
+        for (error, status) in all_variants() {
+            assert_eq!(error.error_response().status().as_u16(), status, "{error}");
+        }
+    }
+}
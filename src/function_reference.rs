@@ -0,0 +1,258 @@
+//! References to Convex functions whose paths are checked at compile time.
+
+use std::{
+    fmt,
+    ops::Deref,
+};
+
+/// The longest module path component or function name the deployment
+/// accepts.
+const MAX_NAME_LEN: usize = 64;
+
+/// The path of a Convex function, like `messages:list`, checked when the
+/// reference is constructed. Declare references as constants with
+/// [`functions!`](crate::functions), so that a malformed path fails to
+/// compile instead of failing the call.
+///
+/// A reference derefs to its path, so it can be passed wherever the client
+/// takes a function name:
+///
+/// ```no_run
+/// # use convex::{ConvexClient, FunctionReference};
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// const LIST_MESSAGES: FunctionReference = FunctionReference::new("messages:list");
+/// let mut client = ConvexClient::new("https://cool-music-123.convex.cloud").await?;
+/// let messages = client.query(&LIST_MESSAGES, maplit::btreemap!{}).await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The check follows the syntax of function paths: an optional component
+/// path, a module path of `/`-separated components with an optional `.js`
+/// extension, and an optional function name, separated by colons. It
+/// doesn't contact the deployment, so it can't tell whether the function
+/// exists.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Ord, PartialOrd)]
+pub struct FunctionReference {
+    path: &'static str,
+}
+
+impl FunctionReference {
+    /// A reference to the function at `path`. Panics if `path` isn't a valid
+    /// function path, which fails compilation when evaluated in a constant.
+    pub const fn new(path: &'static str) -> Self {
+        check_path(path.as_bytes());
+        Self { path }
+    }
+
+    /// The function's path, as passed to [`new`](Self::new).
+    pub const fn path(&self) -> &'static str {
+        self.path
+    }
+}
+
+impl Deref for FunctionReference {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.path
+    }
+}
+
+impl fmt::Display for FunctionReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.path)
+    }
+}
+
+/// Declare [`FunctionReference`] constants from `(name, "path")` pairs, e.g.
+/// to keep all of an app's function names in one module. A malformed path
+/// fails to compile.
+///
+/// ```
+/// convex::functions![
+///     (pub LIST_MESSAGES, "messages:list"),
+///     (pub SEND_MESSAGE, "messages:send"),
+///     (CHECK_LIMIT, "waitlist/ratelimiter:limits:check"),
+/// ];
+/// assert_eq!(LIST_MESSAGES.path(), "messages:list");
+/// ```
+///
+/// ```compile_fail
+/// convex::functions![(LIST_MESSAGES, "messages:list:all:now")];
+/// ```
+#[macro_export]
+macro_rules! functions {
+    ($(($vis:vis $name:ident, $path:literal)),* $(,)?) => {
+        $(
+            $vis const $name: $crate::FunctionReference = $crate::FunctionReference::new($path);
+        )*
+    };
+}
+
+/// Check `path` like [`UdfPath`](convex_sync_types::UdfPath)'s parser, in a
+/// form that can run in a constant.
+const fn check_path(path: &[u8]) {
+    let mut colons = [0; 2];
+    let mut num_colons = 0;
+    let mut i = 0;
+    while i < path.len() {
+        if path[i] == b':' {
+            if num_colons == 2 {
+                panic!("Function paths have at most two colons");
+            }
+            colons[num_colons] = i;
+            num_colons += 1;
+        }
+        i += 1;
+    }
+    match num_colons {
+        0 => check_module_path(path, 0, path.len()),
+        1 => {
+            check_module_path(path, 0, colons[0]);
+            check_identifier(path, colons[0] + 1, path.len());
+        },
+        _ => {
+            let mut start = 0;
+            let mut i = 0;
+            while i <= colons[0] {
+                if i == colons[0] || path[i] == b'/' {
+                    check_identifier(path, start, i);
+                    start = i + 1;
+                }
+                i += 1;
+            }
+            check_module_path(path, colons[0] + 1, colons[1]);
+            check_identifier(path, colons[1] + 1, path.len());
+        },
+    }
+}
+
+/// Check that `path[start..end]` is a relative module path.
+const fn check_module_path(path: &[u8], start: usize, end: usize) {
+    if start == end {
+        panic!("Module paths must be nonempty");
+    }
+    if path[start] == b'/' {
+        panic!("Module paths must be relative");
+    }
+    let mut component_start = start;
+    let mut i = start;
+    while i <= end {
+        if i == end || path[i] == b'/' {
+            check_path_component(path, component_start, i, i == end);
+            component_start = i + 1;
+        }
+        i += 1;
+    }
+}
+
+/// Check a component of a module path, which for the file name may end in a
+/// `.js` extension.
+const fn check_path_component(path: &[u8], start: usize, end: usize, is_file_name: bool) {
+    if end - start > MAX_NAME_LEN {
+        panic!("Module path components have at most 64 characters");
+    }
+    let mut has_alphanumeric = false;
+    let mut last_dot = None;
+    let mut i = start;
+    while i < end {
+        let c = path[i];
+        if c.is_ascii_alphanumeric() {
+            has_alphanumeric = true;
+        } else if c == b'.' {
+            last_dot = Some(i);
+        } else if c != b'_' {
+            panic!("Module path components can only contain letters, digits, `_` and `.`");
+        }
+        i += 1;
+    }
+    if !has_alphanumeric {
+        panic!("Module path components must have a letter or digit");
+    }
+    if let (true, Some(dot)) = (is_file_name, last_dot) {
+        let is_js = end - dot == 3 && path[dot + 1] == b'j' && path[dot + 2] == b's';
+        if dot > start && !is_js {
+            panic!("Module paths can only have a `.js` extension");
+        }
+    }
+}
+
+/// Check that `path[start..end]` is an identifier, like a function name.
+const fn check_identifier(path: &[u8], start: usize, end: usize) {
+    if start == end {
+        panic!("Function and component names must be nonempty");
+    }
+    if end - start > MAX_NAME_LEN {
+        panic!("Function and component names have at most 64 characters");
+    }
+    if !(path[start].is_ascii_alphabetic() || path[start] == b'_') {
+        panic!("Function and component names must start with a letter or `_`");
+    }
+    let mut only_underscores = true;
+    let mut i = start;
+    while i < end {
+        let c = path[i];
+        if !(c.is_ascii_alphanumeric() || c == b'_') {
+            panic!("Function and component names can only contain letters, digits and `_`");
+        }
+        only_underscores &= c == b'_';
+        i += 1;
+    }
+    if only_underscores {
+        panic!("Function and component names can't be only underscores");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+
+    use convex_sync_types::UdfPath;
+
+    use super::FunctionReference;
+
+    crate::functions![
+        (LIST_MESSAGES, "messages:list"),
+        (SEND_MESSAGE, "messages.js:send"),
+        (DEFAULT_EXPORT, "admin/users"),
+        (CHECK_LIMIT, "waitlist/ratelimiter:limits:check"),
+    ];
+
+    #[test]
+    fn test_functions() -> anyhow::Result<()> {
+        for (reference, path) in [
+            (LIST_MESSAGES, "messages:list"),
+            (SEND_MESSAGE, "messages.js:send"),
+            (DEFAULT_EXPORT, "admin/users"),
+            (CHECK_LIMIT, "waitlist/ratelimiter:limits:check"),
+        ] {
+            assert_eq!(reference.path(), path);
+            assert_eq!(&*reference, path);
+            path.parse::<UdfPath>()?;
+        }
+
+        // Paths rejected when constructed at runtime are rejected by the
+        // parser too.
+        for path in [
+            "",
+            "a:b:c:d",
+            "/messages:list",
+            "messages:",
+            "messages:1list",
+            "messages:li-st",
+            "messages.ts:list",
+            "../messages:list",
+            "wait-list:limits:check",
+            "messages:___",
+        ] {
+            assert!(
+                panic::catch_unwind(|| FunctionReference::new(path)).is_err(),
+                "{path:?} should be rejected"
+            );
+            assert!(path.parse::<UdfPath>().is_err(), "{path:?}");
+        }
+        Ok(())
+    }
+}
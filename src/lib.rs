@@ -40,28 +40,81 @@
 
 #![warn(missing_docs)]
 #![warn(rustdoc::missing_crate_level_docs)]
+// `proptest_derive` 0.3 generates its impls inside an anonymous const.
+#![cfg_attr(test, allow(unknown_lints, non_local_definitions))]
 
 mod value;
 pub use value::{
+    AttributeValue,
+    ComparisonOp,
     DocumentId,
+    InternedValue,
+    ObjectBuilder,
+    ObjectField,
+    StorageMetadata,
+    Validator,
     Value,
 };
 
 mod client;
 pub use client::{
+    builder::ConvexClientBuilder,
+    clock::{
+        Clock,
+        SystemClock,
+        TestClock,
+    },
+    failed_mutations::FailedMutation,
+    function_spec::{
+        FunctionSpec,
+        FunctionType,
+    },
+    http::HttpResponse,
+    outbound::{
+        OutboundCall,
+        OutboundCallKind,
+    },
+    pagination::{
+        PageNavigator,
+        PaginationOpts,
+        PaginationResult,
+    },
+    single_query::ConvexQuerySubscription,
+    sink::UpdateSink,
     subscription::{
+        AdaptiveCoalescing,
         QuerySetSubscription,
+        QueryState,
         QuerySubscription,
+        Sequenced,
+        Source,
+        Update,
     },
     ConvexClient,
+    ValidatedUrl,
 };
 
 pub mod base_client;
 #[doc(inline)]
 pub use base_client::{
+    DeploymentInfo,
     FunctionResult,
+    MutationOutcome,
+    QueryBuilder,
+    QueryPriority,
     QueryResults,
+    RequestPriority,
     SubscriberId,
+    WriteLatency,
 };
 
+pub mod protocol;
+
 mod sync;
+pub use sync::bandwidth::BandwidthUsage;
+
+mod error;
+pub use error::ConvexError;
+
+mod function_reference;
+pub use function_reference::FunctionReference;
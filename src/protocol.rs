@@ -0,0 +1,202 @@
+//! Utilities for testing against the Convex sync protocol.
+
+use std::{
+    collections::BTreeSet,
+    fmt::Write,
+};
+
+use serde_json::Value as JsonValue;
+
+/// A difference between two protocol traces, as reported by [`diff_traces`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    /// The position of the message in both traces.
+    pub index: usize,
+    /// The path of the differing field within the message, e.g.
+    /// `$.modifications[0].value`. `$` is the whole message.
+    pub path: String,
+    /// The field in the first trace, or `None` if it's missing.
+    pub left: Option<JsonValue>,
+    /// The field in the second trace, or `None` if it's missing.
+    pub right: Option<JsonValue>,
+}
+
+/// Compare two traces of protocol messages and report where they first
+/// diverge, e.g. to check a client against a reference implementation or a
+/// prior version.
+///
+/// A trace is a sequence of messages in their JSON wire format, as produced by
+/// converting a `ClientMessage` or `ServerMessage` with
+/// `serde_json::Value::try_from`. Messages are compared pairwise in order, and
+/// every differing field of the first message pair that differs is reported.
+/// If one trace is a prefix of the other, the first extra message is reported
+/// with path `$` and a missing side. An empty result means the traces match.
+///
+/// Fields that legitimately differ between runs, like timestamps or session
+/// IDs, are skipped if their path matches one of the `volatile` patterns.
+/// Patterns use the same syntax as [`Divergence::path`], where `.*` matches
+/// any key and `[*]` matches any array index, e.g. `$.sessionId` or
+/// `$.modifications[*].journal`. A matching field is skipped along with
+/// everything nested in it, and may be missing on either side.
+///
+/// ```
+/// # use convex::protocol::diff_traces;
+/// # use serde_json::json;
+/// let a = vec![
+///     json!({"type": "Connect", "sessionId": "a"}),
+///     json!({"type": "Mutation", "requestId": 0, "args": [{"body": "hi"}]}),
+/// ];
+/// let b = vec![
+///     json!({"type": "Connect", "sessionId": "b"}),
+///     json!({"type": "Mutation", "requestId": 0, "args": [{"body": "bye"}]}),
+/// ];
+/// let divergences = diff_traces(&a, &b, &["$.sessionId"]);
+/// assert_eq!(divergences.len(), 1);
+/// assert_eq!(divergences[0].index, 1);
+/// assert_eq!(divergences[0].path, "$.args[0].body");
+/// ```
+pub fn diff_traces(a: &[JsonValue], b: &[JsonValue], volatile: &[&str]) -> Vec<Divergence> {
+    let volatile: Vec<Vec<&str>> = volatile.iter().map(|pattern| segments(pattern)).collect();
+    let mut divergences = vec![];
+    for index in 0..a.len().max(b.len()) {
+        diff(
+            &mut divergences,
+            index,
+            &mut "$".to_string(),
+            &volatile,
+            a.get(index),
+            b.get(index),
+        );
+        if !divergences.is_empty() {
+            break;
+        }
+    }
+    divergences
+}
+
+/// Split a path like `$.a[0].b` into its segments `.a`, `[0]` and `.b`.
+fn segments(path: &str) -> Vec<&str> {
+    let path = path.strip_prefix('$').unwrap_or(path);
+    let mut segments = vec![];
+    let mut start = 0;
+    for (i, c) in path.char_indices().skip(1) {
+        if c == '.' || c == '[' {
+            segments.push(&path[start..i]);
+            start = i;
+        }
+    }
+    if start < path.len() {
+        segments.push(&path[start..]);
+    }
+    segments
+}
+
+fn is_volatile(path: &str, volatile: &[Vec<&str>]) -> bool {
+    let path = segments(path);
+    volatile.iter().any(|pattern| {
+        pattern.len() == path.len()
+            && pattern.iter().zip(&path).all(|(pattern, segment)| {
+                pattern == segment
+                    || (*pattern == ".*" && segment.starts_with('.'))
+                    || (*pattern == "[*]" && segment.starts_with('['))
+            })
+    })
+}
+
+fn diff(
+    divergences: &mut Vec<Divergence>,
+    index: usize,
+    path: &mut String,
+    volatile: &[Vec<&str>],
+    left: Option<&JsonValue>,
+    right: Option<&JsonValue>,
+) {
+    if is_volatile(path, volatile) {
+        return;
+    }
+    let path_len = path.len();
+    match (left, right) {
+        (Some(JsonValue::Object(a)), Some(JsonValue::Object(b))) => {
+            let keys: BTreeSet<_> = a.keys().chain(b.keys()).collect();
+            for key in keys {
+                write!(path, ".{key}").unwrap();
+                diff(divergences, index, path, volatile, a.get(key), b.get(key));
+                path.truncate(path_len);
+            }
+        },
+        (Some(JsonValue::Array(a)), Some(JsonValue::Array(b))) => {
+            for i in 0..a.len().max(b.len()) {
+                write!(path, "[{i}]").unwrap();
+                diff(divergences, index, path, volatile, a.get(i), b.get(i));
+                path.truncate(path_len);
+            }
+        },
+        (left, right) if left != right => divergences.push(Divergence {
+            index,
+            path: path.clone(),
+            left: left.cloned(),
+            right: right.cloned(),
+        }),
+        _ => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{
+        diff_traces,
+        Divergence,
+    };
+
+    #[test]
+    fn test_diff_traces() {
+        let transition = |ts: &str, value: i64, journal: &str| {
+            json!({
+                "type": "Transition",
+                "endVersion": {"querySet": 1, "identity": 0, "ts": ts},
+                "modifications": [
+                    {"type": "QueryUpdated", "queryId": 0, "value": value, "journal": journal},
+                ],
+            })
+        };
+        let a = vec![
+            json!({"type": "Connect", "sessionId": "a"}),
+            transition("AQAAAAAAAAA=", 1, "x"),
+            transition("AgAAAAAAAAA=", 2, "y"),
+        ];
+        let b = vec![
+            json!({"type": "Connect", "sessionId": "b"}),
+            transition("BQAAAAAAAAA=", 1, "z"),
+            transition("BgAAAAAAAAA=", 3, "w"),
+        ];
+        let volatile = [
+            "$.sessionId",
+            "$.endVersion.ts",
+            "$.modifications[*].journal",
+        ];
+        assert_eq!(
+            diff_traces(&a, &b, &volatile),
+            vec![Divergence {
+                index: 2,
+                path: "$.modifications[0].value".into(),
+                left: Some(json!(2)),
+                right: Some(json!(3)),
+            }]
+        );
+        assert_eq!(diff_traces(&a, &b, &[])[0].index, 0);
+        assert_eq!(diff_traces(&a, &a, &[]), vec![]);
+
+        // A missing message diverges at its index.
+        assert_eq!(
+            diff_traces(&a[..2], &a, &[]),
+            vec![Divergence {
+                index: 2,
+                path: "$".into(),
+                left: None,
+                right: Some(a[2].clone()),
+            }]
+        );
+    }
+}
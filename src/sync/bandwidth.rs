@@ -0,0 +1,53 @@
+use std::sync::atomic::{
+    AtomicU64,
+    Ordering,
+};
+
+/// The bytes a client has sent to and received from the deployment, as
+/// returned by
+/// [`ConvexClient::bandwidth_usage`](crate::ConvexClient::bandwidth_usage).
+///
+/// Counts are cumulative over every connection the client has opened,
+/// including reconnects. They're the sizes of the websocket messages' payloads,
+/// so the websocket framing, TLS and the HTTP upgrade request add a little on
+/// top on the wire.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct BandwidthUsage {
+    /// Bytes sent to the deployment.
+    pub bytes_sent: u64,
+    /// Bytes received from the deployment.
+    pub bytes_received: u64,
+}
+
+impl BandwidthUsage {
+    /// Bytes sent and received.
+    pub fn total(&self) -> u64 {
+        self.bytes_sent + self.bytes_received
+    }
+}
+
+/// The running totals behind [`BandwidthUsage`], shared between the protocol
+/// that updates them and the client that reads them.
+#[derive(Debug, Default)]
+pub struct BandwidthCounters {
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+}
+
+impl BandwidthCounters {
+    pub fn record_sent(&self, bytes: usize) {
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_received(&self, bytes: usize) {
+        self.bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn usage(&self) -> BandwidthUsage {
+        BandwidthUsage {
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -1,13 +1,29 @@
+use std::{
+    sync::Arc,
+    time::Duration,
+};
+
 use async_trait::async_trait;
 use convex_sync_types::{
     ClientMessage,
     Timestamp,
 };
 use futures::channel::mpsc;
+use tokio_tungstenite::tungstenite::http::HeaderMap;
 use url::Url;
 
-use crate::value::Value;
+use self::{
+    bandwidth::BandwidthCounters,
+    retry_budget::RetryBudget,
+};
+use crate::{
+    base_client::DeploymentInfo,
+    value::Value,
+    ConvexError,
+};
 
+pub mod bandwidth;
+pub mod retry_budget;
 #[cfg(any(test, feature = "testing"))]
 pub mod testing;
 pub mod web_socket_manager;
@@ -28,12 +44,69 @@ pub type ServerMessage = convex_sync_types::ServerMessage<Value>;
 pub enum ProtocolResponse {
     ServerMessage(ServerMessage),
     Failure,
+    /// The protocol (re)connected, and the deployment described itself in the
+    /// handshake.
+    Connected(DeploymentInfo),
+    /// The server closed the connection and the protocol will not reconnect.
+    Fatal(ConvexError),
+}
+
+/// Configuration for opening a [`SyncProtocol`].
+#[derive(Clone, Debug)]
+pub struct ProtocolConfig {
+    /// Headers sent on the upgrade request of every connection attempt.
+    pub headers: HeaderMap,
+    /// How many times a transient failure of the initial connect is retried.
+    pub connect_retries: u32,
+    /// How long a single connect attempt may take.
+    pub connect_timeout: Duration,
+    /// How many consecutive reconnect attempts may fail before giving up, or
+    /// `None` to reconnect forever.
+    pub max_reconnect_attempts: Option<u32>,
+    /// The budget every connect retry and reconnect attempt takes from, or
+    /// `None` for no cap on the total.
+    pub retry_budget: Option<Arc<RetryBudget>>,
+    /// Whether a reconnect that finds the retry budget spent waits for it to
+    /// refill, instead of failing with [`ConvexError::RetryBudgetExhausted`].
+    pub wait_for_retry_budget: bool,
+    /// Whether to check that every received `Value` re-encodes to the JSON it
+    /// was decoded from, logging any discrepancy.
+    pub validate_roundtrips: bool,
+    /// The counters to record the bytes sent and received in.
+    pub bandwidth: Arc<BandwidthCounters>,
+    /// The average rate, in bytes per second, to throttle sending to, or
+    /// `None` to send as fast as possible.
+    pub bandwidth_rate_limit: Option<u64>,
+    /// The total bytes that may be sent and received before sending fails
+    /// permanently with [`ConvexError::BandwidthLimited`], or `None` for no
+    /// limit.
+    pub bandwidth_cap: Option<u64>,
+}
+
+impl Default for ProtocolConfig {
+    fn default() -> Self {
+        Self {
+            headers: HeaderMap::new(),
+            connect_retries: 3,
+            connect_timeout: Duration::from_secs(10),
+            max_reconnect_attempts: None,
+            retry_budget: None,
+            wait_for_retry_budget: false,
+            validate_roundtrips: false,
+            bandwidth: Arc::default(),
+            bandwidth_rate_limit: None,
+            bandwidth_cap: None,
+        }
+    }
 }
 
 #[async_trait]
 pub trait SyncProtocol: Send + Sized {
-    async fn open(ws_url: Url, on_response: mpsc::Sender<ProtocolResponse>)
-        -> anyhow::Result<Self>;
+    async fn open(
+        ws_url: Url,
+        on_response: mpsc::Sender<ProtocolResponse>,
+        config: ProtocolConfig,
+    ) -> anyhow::Result<Self>;
     async fn send(&mut self, message: ClientMessage) -> anyhow::Result<()>;
     async fn reconnect(&mut self, request: ReconnectRequest);
 }
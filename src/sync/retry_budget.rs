@@ -0,0 +1,136 @@
+//! A cap on how often a client retries connecting, shared by its connect
+//! retries and reconnect attempts.
+
+use std::{
+    fmt,
+    sync::{
+        Arc,
+        Mutex,
+    },
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+use crate::client::clock::Clock;
+
+/// A token bucket capping the retries of a client over a window, set with
+/// [`ConvexClientBuilder::retry_budget`](crate::ConvexClientBuilder::retry_budget).
+///
+/// The bucket starts full with `retries` tokens, and refills continuously at
+/// `retries` per `window`, as measured by the client's [`Clock`]. Each retry
+/// takes a token.
+pub struct RetryBudget {
+    retries: u32,
+    window: Duration,
+    clock: Arc<dyn Clock>,
+    /// The tokens left, as of the given time.
+    bucket: Mutex<(f64, Instant)>,
+}
+
+impl RetryBudget {
+    pub fn new(retries: u32, window: Duration, clock: Arc<dyn Clock>) -> Self {
+        let now = clock.now();
+        Self {
+            retries,
+            window,
+            clock,
+            bucket: Mutex::new((f64::from(retries), now)),
+        }
+    }
+
+    /// The retries allowed per window.
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// The window the retries are allowed in.
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Take a token for a retry, or return `false` if the budget is spent.
+    pub fn try_acquire(&self) -> bool {
+        self.take().is_ok()
+    }
+
+    /// Take a token for a retry, waiting for one to be refilled if the budget
+    /// is spent. Returns `false` right away if it never refills, i.e. with no
+    /// retries or a zero window.
+    pub async fn acquire(&self) -> bool {
+        loop {
+            let wait = match self.take() {
+                Ok(()) => return true,
+                Err(None) => return false,
+                Err(Some(wait)) => wait,
+            };
+            let deadline = self.clock.now() + wait;
+            self.clock.sleep_until(deadline).await;
+        }
+    }
+
+    /// Take a token, or fail with how long until the next one is refilled,
+    /// if ever.
+    fn take(&self) -> Result<(), Option<Duration>> {
+        let mut bucket = self.bucket.lock().expect("Retry budget lock poisoned");
+        let (tokens, refilled_at) = &mut *bucket;
+        let now = self.clock.now().max(*refilled_at);
+        let capacity = f64::from(self.retries);
+        if !self.window.is_zero() {
+            let refill = (now - *refilled_at).as_secs_f64() / self.window.as_secs_f64();
+            *tokens = (*tokens + refill * capacity).min(capacity);
+        }
+        *refilled_at = now;
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            return Ok(());
+        }
+        if self.window.is_zero() || self.retries == 0 {
+            return Err(None);
+        }
+        Err(Some(self.window.mul_f64((1.0 - *tokens) / capacity)))
+    }
+}
+
+impl fmt::Debug for RetryBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryBudget")
+            .field("retries", &self.retries)
+            .field("window", &self.window)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        time::Duration,
+    };
+
+    use super::RetryBudget;
+    use crate::client::clock::TestClock;
+
+    #[tokio::test]
+    async fn test_acquire_waits_for_refill() {
+        let clock = TestClock::new();
+        let budget = RetryBudget::new(2, Duration::from_secs(10), Arc::new(clock.clone()));
+        assert!(budget.try_acquire());
+        assert!(budget.try_acquire());
+        assert!(!budget.try_acquire());
+
+        // A token is refilled every 5 seconds.
+        let mut acquire = Box::pin(budget.acquire());
+        assert!(futures::poll!(&mut acquire).is_pending());
+        clock.advance(Duration::from_secs(4));
+        assert!(futures::poll!(&mut acquire).is_pending());
+        clock.advance(Duration::from_secs(1));
+        assert!(acquire.await);
+        assert!(!budget.try_acquire());
+
+        // A budget that never refills doesn't wait.
+        let budget = RetryBudget::new(1, Duration::ZERO, Arc::new(clock.clone()));
+        assert!(budget.acquire().await);
+        assert!(!budget.acquire().await);
+    }
+}
@@ -18,10 +18,14 @@ use url::Url;
 use uuid::Uuid;
 
 use super::ReconnectRequest;
-use crate::sync::{
-    ProtocolResponse,
-    ServerMessage,
-    SyncProtocol,
+use crate::{
+    sync::{
+        ProtocolConfig,
+        ProtocolResponse,
+        ServerMessage,
+        SyncProtocol,
+    },
+    ConvexError,
 };
 
 #[derive(Debug)]
@@ -43,6 +47,13 @@ impl TestProtocolManager {
         Ok(())
     }
 
+    pub async fn fake_permanent_close(&mut self, error: ConvexError) -> anyhow::Result<()> {
+        self.response_sender
+            .send(ProtocolResponse::Fatal(error))
+            .await?;
+        Ok(())
+    }
+
     pub async fn wait_until_n_messages_sent(&self, n: usize) {
         tokio::time::timeout(Duration::from_secs(2), async {
             while self.inner.lock().sent_messages.len() < n {
@@ -63,6 +74,7 @@ impl SyncProtocol for TestProtocolManager {
     async fn open(
         _ws_url: Url,
         response_sender: mpsc::Sender<ProtocolResponse>,
+        _config: ProtocolConfig,
     ) -> anyhow::Result<Self> {
         let mut test_protocol = TestProtocolManager {
             inner: Arc::new(Mutex::new(TestProtocolInner {
@@ -1,4 +1,13 @@
-use std::time::Duration;
+use std::{
+    collections::{
+        BTreeMap,
+        VecDeque,
+    },
+    future::Future,
+    io,
+    sync::Arc,
+    time::Duration,
+};
 
 use anyhow::Context;
 use async_trait::async_trait;
@@ -10,6 +19,7 @@ use convex_sync_types::{
     },
     ClientMessage,
     SessionId,
+    StateModification,
     Timestamp,
 };
 use futures::{
@@ -17,11 +27,13 @@ use futures::{
         mpsc,
         oneshot,
     },
+    future,
     select_biased,
     FutureExt,
     SinkExt,
     StreamExt,
 };
+use serde_json::Value as JsonValue;
 use tokio::{
     net::TcpStream,
     task::JoinHandle,
@@ -41,14 +53,29 @@ use tokio_tungstenite::{
     MaybeTlsStream,
     WebSocketStream,
 };
-use url::Url;
+use url::{
+    Host,
+    Url,
+};
 use uuid::Uuid;
 
-use crate::sync::{
-    ProtocolResponse,
-    ReconnectRequest,
-    ServerMessage,
-    SyncProtocol,
+use crate::{
+    base_client::DeploymentInfo,
+    protocol::{
+        diff_traces,
+        Divergence,
+    },
+    sync::{
+        bandwidth::BandwidthCounters,
+        retry_budget::RetryBudget,
+        ProtocolConfig,
+        ProtocolResponse,
+        ReconnectRequest,
+        ServerMessage,
+        SyncProtocol,
+    },
+    ConvexError,
+    Value,
 };
 
 const VERSION: Option<&str> = option_env!("CARGO_PKG_VERSION");
@@ -56,6 +83,7 @@ const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
 const MAX_BACKOFF: Duration = Duration::from_secs(15);
 type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 enum WebSocketRequest {
     SendMessage(ClientMessage, oneshot::Sender<()>),
@@ -65,14 +93,20 @@ enum WebSocketRequest {
 struct WebSocketInternal {
     ws_stream: WsStream,
     last_server_response: Instant,
+    deployment_info: DeploymentInfo,
+    bandwidth: Arc<BandwidthCounters>,
+    bandwidth_cap: Option<u64>,
 }
 struct WebSocketWorker {
     ws_url: Url,
+    config: ProtocolConfig,
     on_response: mpsc::Sender<ProtocolResponse>,
     internal_receiver: mpsc::UnboundedReceiver<WebSocketRequest>,
     ping_ticker: Interval,
     connection_count: u32,
     backoff: Backoff,
+    /// When the next message may be sent under the bandwidth rate limit.
+    next_send: Instant,
 }
 
 pub struct WebSocketManager {
@@ -89,11 +123,35 @@ impl Drop for WebSocketManager {
 impl SyncProtocol for WebSocketManager {
     async fn open(
         ws_url: Url,
-        on_response: mpsc::Sender<ProtocolResponse>,
+        mut on_response: mpsc::Sender<ProtocolResponse>,
+        config: ProtocolConfig,
     ) -> anyhow::Result<Self> {
+        tracing::debug!("trying to connect to {ws_url}");
+        let mut backoff = Backoff::new(INITIAL_BACKOFF, MAX_BACKOFF);
+        let internal = connect_with_retries(
+            config.connect_retries,
+            config.retry_budget.as_deref(),
+            &mut backoff,
+            || WebSocketInternal::new(&ws_url, &config, 0, "InitialConnect".to_string(), None),
+        )
+        .await?;
+        tracing::debug!("completed websocket connect to {ws_url}");
+        // Queue this before any other response so the client knows about the
+        // deployment by the time it's constructed.
+        let _ = on_response
+            .send(ProtocolResponse::Connected(
+                internal.deployment_info.clone(),
+            ))
+            .await;
+
         let (internal_sender, internal_receiver) = mpsc::unbounded();
-        let worker_handle =
-            tokio::spawn(WebSocketWorker::run(ws_url, on_response, internal_receiver));
+        let worker_handle = tokio::spawn(WebSocketWorker::run(
+            ws_url,
+            config,
+            on_response,
+            internal_receiver,
+            internal,
+        ));
 
         Ok(WebSocketManager {
             internal_sender,
@@ -126,25 +184,33 @@ impl WebSocketWorker {
 
     async fn run(
         ws_url: Url,
+        config: ProtocolConfig,
         on_response: mpsc::Sender<ProtocolResponse>,
         internal_receiver: mpsc::UnboundedReceiver<WebSocketRequest>,
+        initial_connection: WebSocketInternal,
     ) -> anyhow::Result<()> {
         let ping_ticker = tokio::time::interval(Self::HEARTBEAT_INTERVAL);
         let backoff = Backoff::new(INITIAL_BACKOFF, MAX_BACKOFF);
 
         let mut worker = Self {
             ws_url,
+            config,
             on_response,
             internal_receiver,
             ping_ticker,
             connection_count: 0,
             backoff,
+            next_send: Instant::now(),
         };
 
+        let mut connection = Some(initial_connection);
         let mut last_close_reason = "InitialConnect".to_string();
         let mut max_observed_timestamp = None;
         loop {
-            let e = match worker.work(last_close_reason, max_observed_timestamp).await {
+            let result = worker
+                .work(connection.take(), last_close_reason, max_observed_timestamp)
+                .await;
+            let e = match result {
                 Ok(reconnect) => {
                     // WS worker exited cleanly because it got a request to reconnect
                     tracing::debug!("Reconnecting websocket due to {}", reconnect.reason);
@@ -154,9 +220,37 @@ impl WebSocketWorker {
                 },
                 Err(e) => e,
             };
+            // Only the server closing the connection, or hitting the bandwidth
+            // cap, is permanent here. Failing to reconnect, even with a DNS or
+            // TLS error, is usually caused by a temporary loss of network.
+            if let Some(error) = e.downcast_ref::<ConvexError>() {
+                let is_closed_or_capped = error.close_code().is_some()
+                    || matches!(error, ConvexError::BandwidthLimited { .. });
+                if is_closed_or_capped && error.is_permanent() {
+                    tracing::error!("Convex WebSocketWorker closed permanently: {error}");
+                    let _ = worker
+                        .on_response
+                        .send(ProtocolResponse::Fatal(error.clone()))
+                        .await;
+                    return Err(e);
+                }
+            }
             worker.connection_count += 1;
             last_close_reason = e.to_string();
             let delay = worker.backoff.fail(&mut rand::thread_rng());
+            // The first failure is losing the connection, each one after that
+            // is a failed reconnect attempt.
+            if let Some(attempts) = worker.config.max_reconnect_attempts {
+                if worker.backoff.failures() > attempts {
+                    let error = ConvexError::ConnectionExhausted { attempts };
+                    tracing::error!("Convex WebSocketWorker failed: {e:?}. {error}");
+                    let _ = worker
+                        .on_response
+                        .send(ProtocolResponse::Fatal(error.clone()))
+                        .await;
+                    return Err(error.into());
+                }
+            }
             tracing::error!(
                 "Convex WebSocketWorker failed: {e:?}. Backing off for {delay:?} and retrying."
             );
@@ -164,18 +258,50 @@ impl WebSocketWorker {
             // Tell the worker that we've failed so it can coordinate the reconnect.
             // The worker will send a Reconnect message and the new query set all together.
             // Drain the input request queue until we get that reconnect message - which
-            // will be followed by the refreshed query set.
-            let _ = worker.on_response.send(ProtocolResponse::Failure).await;
+            // will be followed by the refreshed query set. The queue is drained while the
+            // failure is delivered too, since the client may be waiting for a send to be
+            // acknowledged before it reads it.
             tracing::debug!("Waiting for base client to acknowledge reconnect");
-            loop {
-                let request = worker.internal_receiver.next().await;
-                // TODO: There is a potential issue where we have multiple queued reconnect
-                // requests in which case max_observed_timestamp might be lower than actually
-                // observed. This is fine since it will never cause errors. Will can fix this
-                // when we restructure the wider protocol to be a single routine.
-                if let Some(WebSocketRequest::Reconnect(reconnect)) = request {
-                    max_observed_timestamp = reconnect.max_observed_timestamp;
-                    break;
+            let mut notify = worker.on_response.send(ProtocolResponse::Failure).fuse();
+            let mut notified = false;
+            let mut reconnected = false;
+            while !(notified && reconnected) {
+                select_biased! {
+                    _ = notify => notified = true,
+                    request = worker.internal_receiver.next() => match request {
+                        // TODO: There is a potential issue where we have multiple queued reconnect
+                        // requests in which case max_observed_timestamp might be lower than actually
+                        // observed. This is fine since it will never cause errors. Will can fix this
+                        // when we restructure the wider protocol to be a single routine.
+                        Some(WebSocketRequest::Reconnect(reconnect)) => {
+                            max_observed_timestamp = reconnect.max_observed_timestamp;
+                            reconnected = true;
+                        },
+                        Some(WebSocketRequest::SendMessage(..)) => {},
+                        // The client is gone, so there's nothing to reconnect for.
+                        None => return Ok(()),
+                    },
+                }
+            }
+            if let Some(budget) = &worker.config.retry_budget {
+                // Fail fast once the budget is spent, unless the client was set up to wait for
+                // it to refill.
+                let acquired = budget.try_acquire()
+                    || (worker.config.wait_for_retry_budget && {
+                        tracing::warn!("Retry budget spent, waiting for it to refill to reconnect");
+                        budget.acquire().await
+                    });
+                if !acquired {
+                    let error = ConvexError::RetryBudgetExhausted {
+                        retries: budget.retries(),
+                        window: budget.window(),
+                    };
+                    tracing::error!("Convex WebSocketWorker failed: {error}");
+                    let _ = worker
+                        .on_response
+                        .send(ProtocolResponse::Fatal(error.clone()))
+                        .await;
+                    return Err(error.into());
                 }
             }
             tracing::debug!(
@@ -188,25 +314,45 @@ impl WebSocketWorker {
 
     async fn work(
         &mut self,
+        connection: Option<WebSocketInternal>,
         last_close_reason: String,
         max_seen_transition: Option<Timestamp>,
     ) -> anyhow::Result<ReconnectRequest> {
-        let verb = if self.connection_count == 0 {
-            "connect"
-        } else {
-            "reconnect"
+        let mut internal = match connection {
+            Some(internal) => internal,
+            None => {
+                tracing::debug!("trying to reconnect to {}", self.ws_url);
+                let internal = WebSocketInternal::new(
+                    &self.ws_url,
+                    &self.config,
+                    self.connection_count,
+                    last_close_reason,
+                    max_seen_transition,
+                )
+                .await?;
+                tracing::debug!("completed websocket reconnect to {}", self.ws_url);
+                let _ = self
+                    .on_response
+                    .send(ProtocolResponse::Connected(
+                        internal.deployment_info.clone(),
+                    ))
+                    .await;
+                internal
+            },
         };
-        tracing::debug!("trying to {verb} to {}", self.ws_url);
-        let mut internal = WebSocketInternal::new(
-            self.ws_url.clone(),
-            self.connection_count,
-            last_close_reason,
-            max_seen_transition,
-        )
-        .await?;
-        tracing::debug!("completed websocket {verb} to {}", self.ws_url);
 
+        // Messages waiting for the bandwidth rate limit. They're paced from
+        // here rather than in the select below, so that receiving and
+        // heartbeats carry on while a send waits.
+        let mut outbox = VecDeque::new();
         loop {
+            let next_send = (!outbox.is_empty()).then_some(self.next_send);
+            let send_ready = async move {
+                match next_send {
+                    Some(deadline) => tokio::time::sleep_until(deadline).await,
+                    None => future::pending().await,
+                }
+            };
             select_biased! {
                 _ = self.ping_ticker.tick().fuse() => {
                     let now = Instant::now();
@@ -216,17 +362,31 @@ impl WebSocketWorker {
                 },
                 server_msg = internal.ws_stream.select_next_some() => {
                     internal.last_server_response = Instant::now();
+                    let server_msg = server_msg.context("WebsocketConnectionError")?;
+                    internal.bandwidth.record_received(server_msg.len());
 
-                    match server_msg.context("WebsocketConnectionError")? {
+                    match server_msg {
                         Message::Close(close_frame) => {
                             let close_frame = close_frame.context("CloseMessageWithoutFrame")?;
                             tracing::debug!("Close frame {close_frame}");
-                            let last_close_reason = close_frame.reason.as_ref();
-                            anyhow::bail!("{last_close_reason}");
+                            return Err(ConvexError::from_close_frame(
+                                close_frame.code.into(),
+                                close_frame.reason,
+                            ).into());
                         },
                         Message::Text(t) => {
-                            let json: serde_json::Value = serde_json::from_str(&t).context("JsonDeserializeError")?;
-                            let server_message = json.try_into()?;
+                            let server_message = ServerMessage::from_json_slice(t.as_bytes())?;
+                            if self.config.validate_roundtrips {
+                                let original: JsonValue = serde_json::from_str(&t).context("JsonDeserializeError")?;
+                                for divergence in roundtrip_divergences(&original, &server_message) {
+                                    tracing::warn!(
+                                        "Value at {} doesn't round-trip: received {:?}, re-encoded as {:?}",
+                                        divergence.path,
+                                        divergence.left,
+                                        divergence.right,
+                                    );
+                                }
+                            }
                             match server_message {
                                 ServerMessage::Ping => tracing::trace!("received message {server_message:?}"),
                                 _ => tracing::debug!("received message {server_message:?}"),
@@ -252,31 +412,195 @@ impl WebSocketWorker {
                         WebSocketRequest::SendMessage(message, sender) => {
                             tracing::debug!("Sending {message:?}");
                             let msg = Message::Text(serde_json::Value::try_from(message).context("JsonSerializeError")?.to_string());
-                            internal.send_worker(msg.clone()).await?;
+                            // Queued messages that the connection loses before
+                            // they're sent are resent after the reconnect, like
+                            // those already sent.
+                            outbox.push_back(msg);
                             let _ = sender.send(());
                         },
                         WebSocketRequest::Reconnect(reason) => return Ok(reason),
                     };
                 }
+                () = send_ready.fuse() => {},
             };
+            self.send_due(&mut internal, &mut outbox).await?;
+        }
+    }
+
+    /// Send the messages in `outbox` that the bandwidth rate limit allows by
+    /// now, keeping the rest for later. Without a limit, that's all of them.
+    async fn send_due(
+        &mut self,
+        internal: &mut WebSocketInternal,
+        outbox: &mut VecDeque<Message>,
+    ) -> anyhow::Result<()> {
+        while !outbox.is_empty() {
+            let now = Instant::now();
+            if self.next_send > now {
+                return Ok(());
+            }
+            let msg = outbox.pop_front().expect("Checked above");
+            let bytes = msg.len();
+            internal.send_worker(msg).await?;
+            if let Some(rate) = self.config.bandwidth_rate_limit {
+                self.next_send = now + Duration::from_secs_f64(bytes as f64 / rate.max(1) as f64);
+            }
         }
+        Ok(())
+    }
+}
+
+fn deployment_info(headers: &HeaderMap) -> DeploymentInfo {
+    let headers: BTreeMap<_, _> = headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+    DeploymentInfo {
+        deprecation_state: headers.get(DEPRECATION_STATE_HEADER_NAME).cloned(),
+        deprecation_message: headers.get(DEPRECATION_MSG_HEADER_NAME).cloned(),
+        headers,
     }
 }
 
-fn deprecation_message(headers: &HeaderMap) -> Option<String> {
-    let dep_state = headers.get(DEPRECATION_STATE_HEADER_NAME)?.to_str().ok()?;
-    let msg = headers.get(DEPRECATION_MSG_HEADER_NAME)?.to_str().ok()?;
-    Some(format!("{dep_state}: {msg}"))
+/// Retry `connect` with `backoff` up to `retries` times, unless it fails
+/// with a permanent [`ConvexError`] or `budget` is spent.
+async fn connect_with_retries<T, F, Fut>(
+    retries: u32,
+    budget: Option<&RetryBudget>,
+    backoff: &mut Backoff,
+    mut connect: F,
+) -> anyhow::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let mut attempt = 0;
+    loop {
+        let e = match connect().await {
+            Ok(connection) => return Ok(connection),
+            Err(e) => e,
+        };
+        let permanent = e
+            .downcast_ref::<ConvexError>()
+            .is_some_and(ConvexError::is_permanent);
+        if permanent || attempt >= retries {
+            return Err(e);
+        }
+        if budget.is_some_and(|budget| !budget.try_acquire()) {
+            tracing::warn!("Connect attempt failed: {e}. Not retrying, the retry budget is spent");
+            return Err(e);
+        }
+        attempt += 1;
+        let delay = backoff.fail(&mut rand::thread_rng());
+        tracing::warn!("Connect attempt {attempt} failed: {e}. Retrying in {delay:?}");
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Classify a failure to open the websocket, see [`ConvexError`].
+fn classify_connect_error(ws_url: &Url, e: tungstenite::Error) -> anyhow::Error {
+    let message = format!("Connection to {ws_url} failed: {e}");
+    match e {
+        tungstenite::Error::Io(ref io) => match io.kind() {
+            io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset => {
+                ConvexError::ConnectionRefused { message }.into()
+            },
+            io::ErrorKind::TimedOut => ConvexError::HandshakeTimeout.into(),
+            _ => anyhow::anyhow!(message),
+        },
+        tungstenite::Error::Tls(_) => ConvexError::TlsFailure { message }.into(),
+        tungstenite::Error::Http(ref response) => {
+            let body = response
+                .body()
+                .as_deref()
+                .map(String::from_utf8_lossy)
+                .unwrap_or_default();
+            anyhow::anyhow!("{message}: {body}")
+        },
+        _ => anyhow::anyhow!(message),
+    }
+}
+
+/// Re-encode every `Value` in `message` and compare it to the JSON it was
+/// decoded from, with paths relative to the whole message.
+fn roundtrip_divergences(json: &JsonValue, message: &ServerMessage) -> Vec<Divergence> {
+    let values: Vec<(String, &Value)> = match message {
+        ServerMessage::Transition { modifications, .. } => modifications
+            .iter()
+            .enumerate()
+            .filter_map(|(i, modification)| match modification {
+                StateModification::QueryUpdated { value, .. } => {
+                    Some((format!("$.modifications[{i}].value"), value))
+                },
+                _ => None,
+            })
+            .collect(),
+        ServerMessage::MutationResponse {
+            result: Ok(value), ..
+        }
+        | ServerMessage::ActionResponse {
+            result: Ok(value), ..
+        } => vec![("$.result".to_string(), value)],
+        _ => vec![],
+    };
+    let mut divergences = vec![];
+    for (path, value) in values {
+        let pointer = path[1..].replace(['.', '['], "/").replace(']', "");
+        let Some(original) = json.pointer(&pointer) else {
+            continue;
+        };
+        let original = canonicalize(original.clone());
+        let reencoded = canonicalize(JsonValue::from(value.clone()));
+        for divergence in diff_traces(&[original], &[reencoded], &[]) {
+            divergences.push(Divergence {
+                path: format!("{path}{}", &divergence.path[1..]),
+                ..divergence
+            });
+        }
+    }
+    divergences
+}
+
+/// Convex numbers are always floats, so e.g. `3` decodes to `Float64(3.0)`,
+/// which re-encodes as `3.0`. Convert JSON integers to floats when that's
+/// lossless so that only genuinely lossy numbers are reported. Sets and maps
+/// re-encode in their own order, so their entries are sorted to compare them
+/// as collections; paths within them refer to the sorted order.
+fn canonicalize(json: JsonValue) -> JsonValue {
+    match json {
+        JsonValue::Number(n) => match (n.as_i64(), n.as_u64(), n.as_f64()) {
+            (Some(i), _, Some(f)) if f as i64 == i && f != i64::MAX as f64 => JsonValue::from(f),
+            (None, Some(u), Some(f)) if f as u64 == u && f != u64::MAX as f64 => JsonValue::from(f),
+            _ => JsonValue::Number(n),
+        },
+        JsonValue::Array(items) => items.into_iter().map(canonicalize).collect(),
+        JsonValue::Object(fields) => {
+            let is_collection =
+                fields.len() == 1 && (fields.contains_key("$set") || fields.contains_key("$map"));
+            fields
+                .into_iter()
+                .map(|(k, v)| match canonicalize(v) {
+                    JsonValue::Array(mut items) if is_collection => {
+                        items.sort_by_cached_key(|item| item.to_string());
+                        (k, JsonValue::Array(items))
+                    },
+                    v => (k, v),
+                })
+                .collect()
+        },
+        json => json,
+    }
 }
 
 impl WebSocketInternal {
     async fn new(
-        ws_url: Url,
+        ws_url: &Url,
+        config: &ProtocolConfig,
         connection_count: u32,
         last_close_reason: String,
         _max_observed_timestamp: Option<Timestamp>,
     ) -> anyhow::Result<WebSocketInternal> {
-        let mut request = (&ws_url).into_client_request().context("Bad WS Url")?;
+        let mut request = ws_url.into_client_request().context("Bad WS Url")?;
         let version = VERSION.unwrap_or("unknown");
         request.headers_mut().insert(
             "Convex-Client",
@@ -284,26 +608,50 @@ impl WebSocketInternal {
                 .try_into()
                 .context("Bad version")?,
         );
-        let (ws_stream, response) = connect_async(request).await.map_err(|e| {
-            if let tungstenite::Error::Http(ref response) = e {
-                let body = response
-                    .body()
-                    .as_deref()
-                    .map(String::from_utf8_lossy)
-                    .unwrap_or_default();
-                return anyhow::anyhow!("Connection to {ws_url} failed: {e}: {body}");
+        for (name, value) in &config.headers {
+            request.headers_mut().append(name, value.clone());
+        }
+        let connect = async {
+            // Resolve the host up front, since `connect_async` doesn't
+            // distinguish DNS failures from other IO errors.
+            if let Some(Host::Domain(host)) = ws_url.host() {
+                let port = ws_url
+                    .port_or_known_default()
+                    .context("WS Url without port")?;
+                let resolved = tokio::net::lookup_host((host, port)).await.map_err(|e| {
+                    ConvexError::DnsFailure {
+                        message: format!("Failed to resolve {host}: {e}"),
+                    }
+                })?;
+                if resolved.count() == 0 {
+                    anyhow::bail!(ConvexError::DnsFailure {
+                        message: format!("{host} has no addresses"),
+                    });
+                }
             }
-            anyhow::anyhow!("Connection to {ws_url} failed: {e}")
-        })?;
+            connect_async(request)
+                .await
+                .map_err(|e| classify_connect_error(ws_url, e))
+        };
+        let (ws_stream, response) = tokio::time::timeout(config.connect_timeout, connect)
+            .await
+            .map_err(|_| ConvexError::HandshakeTimeout)??;
 
-        if let Some(msg) = deprecation_message(response.headers()) {
-            tracing::warn!("{msg}");
+        let deployment_info = deployment_info(response.headers());
+        if let (Some(state), Some(msg)) = (
+            &deployment_info.deprecation_state,
+            &deployment_info.deprecation_message,
+        ) {
+            tracing::warn!("{state}: {msg}");
         }
 
         let last_server_response = Instant::now();
         let mut internal = WebSocketInternal {
             ws_stream,
             last_server_response,
+            deployment_info,
+            bandwidth: config.bandwidth.clone(),
+            bandwidth_cap: config.bandwidth_cap,
         };
 
         // Send an initial connect message on the new websocket
@@ -324,9 +672,321 @@ impl WebSocketInternal {
     }
 
     async fn send_worker(&mut self, message: Message) -> anyhow::Result<()> {
+        let len = message.len();
+        if let Some(cap) = self.bandwidth_cap {
+            if self.bandwidth.usage().total() + len as u64 > cap {
+                anyhow::bail!(ConvexError::BandwidthLimited { cap });
+            }
+        }
         self.ws_stream
             .send(message)
             .await
-            .context("WebsocketClosedOnSend")
+            .context("WebsocketClosedOnSend")?;
+        self.bandwidth.record_sent(len);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::Arc,
+        time::Duration,
+    };
+
+    use convex_sync_types::backoff::Backoff;
+    use futures::channel::{
+        mpsc,
+        oneshot,
+    };
+    use serde_json::json;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::{
+        self,
+        handshake::server::{
+            Request,
+            Response,
+        },
+        http::{
+            HeaderMap,
+            HeaderValue,
+        },
+    };
+    use url::Url;
+
+    use super::{
+        classify_connect_error,
+        connect_with_retries,
+        roundtrip_divergences,
+        WebSocketManager,
+    };
+    use crate::{
+        client::clock::TestClock,
+        sync::{
+            retry_budget::RetryBudget,
+            ProtocolConfig,
+            ServerMessage,
+            SyncProtocol,
+        },
+        ConvexError,
+    };
+
+    fn test_backoff() -> Backoff {
+        Backoff::new(Duration::from_millis(1), Duration::from_millis(1))
+    }
+
+    /// Count the attempts `connect_with_retries` makes when every connect
+    /// fails with `error`.
+    async fn count_attempts(retries: u32, error: ConvexError) -> (u32, anyhow::Error) {
+        count_attempts_with_budget(retries, None, error).await
+    }
+
+    async fn count_attempts_with_budget(
+        retries: u32,
+        budget: Option<&RetryBudget>,
+        error: ConvexError,
+    ) -> (u32, anyhow::Error) {
+        let mut attempts = 0;
+        let result: anyhow::Result<()> =
+            connect_with_retries(retries, budget, &mut test_backoff(), || {
+                attempts += 1;
+                let error = error.clone();
+                async move { Err(error.into()) }
+            })
+            .await;
+        (attempts, result.unwrap_err())
+    }
+
+    #[tokio::test]
+    async fn test_connect_retries() {
+        let refused = ConvexError::ConnectionRefused {
+            message: "refused".into(),
+        };
+        let (attempts, e) = count_attempts(3, refused.clone()).await;
+        assert_eq!(attempts, 4);
+        assert_eq!(e.downcast_ref::<ConvexError>(), Some(&refused));
+
+        let (attempts, _) = count_attempts(3, ConvexError::HandshakeTimeout).await;
+        assert_eq!(attempts, 4);
+
+        let tls = ConvexError::TlsFailure {
+            message: "invalid peer certificate".into(),
+        };
+        let (attempts, e) = count_attempts(3, tls.clone()).await;
+        assert_eq!(attempts, 1);
+        assert_eq!(e.downcast_ref::<ConvexError>(), Some(&tls));
+
+        let dns = ConvexError::DnsFailure {
+            message: "no such host".into(),
+        };
+        let (attempts, _) = count_attempts(3, dns).await;
+        assert_eq!(attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_retry_budget() {
+        let refused = ConvexError::ConnectionRefused {
+            message: "refused".into(),
+        };
+        // The clock doesn't move, so the budget doesn't refill.
+        let budget = RetryBudget::new(4, Duration::from_secs(3600), Arc::new(TestClock::new()));
+        let (attempts, _) = count_attempts_with_budget(3, Some(&budget), refused.clone()).await;
+        assert_eq!(attempts, 4);
+        // One retry is left in the budget.
+        let (attempts, _) = count_attempts_with_budget(3, Some(&budget), refused.clone()).await;
+        assert_eq!(attempts, 2);
+        // Once it's spent, connects fail fast.
+        let (attempts, e) = count_attempts_with_budget(3, Some(&budget), refused.clone()).await;
+        assert_eq!(attempts, 1);
+        assert_eq!(e.downcast_ref::<ConvexError>(), Some(&refused));
+    }
+
+    #[tokio::test]
+    async fn test_connect_retries_succeeds() -> anyhow::Result<()> {
+        let mut attempts = 0;
+        let connection = connect_with_retries(3, None, &mut test_backoff(), || {
+            attempts += 1;
+            let attempt = attempts;
+            async move {
+                if attempt < 3 {
+                    anyhow::bail!(ConvexError::HandshakeTimeout);
+                }
+                Ok(attempt)
+            }
+        })
+        .await?;
+        assert_eq!(connection, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_classify_connect_error() -> anyhow::Result<()> {
+        let ws_url: Url = "ws://127.0.0.1:1/api/sync".parse()?;
+        let classify = |kind| {
+            let e = classify_connect_error(&ws_url, tungstenite::Error::Io(io::Error::from(kind)));
+            e.downcast::<ConvexError>().ok()
+        };
+        assert!(matches!(
+            classify(io::ErrorKind::ConnectionRefused),
+            Some(ConvexError::ConnectionRefused { .. })
+        ));
+        assert_eq!(
+            classify(io::ErrorKind::TimedOut),
+            Some(ConvexError::HandshakeTimeout)
+        );
+        assert_eq!(classify(io::ErrorKind::Other), None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_connection_refused() -> anyhow::Result<()> {
+        // Bind and drop a listener to find a port nothing listens on.
+        let addr = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+        let ws_url = format!("ws://{addr}/api/sync").parse()?;
+        let config = ProtocolConfig {
+            connect_retries: 1,
+            ..Default::default()
+        };
+        let (response_sender, _response_receiver) = mpsc::channel(1);
+        let e = WebSocketManager::open(ws_url, response_sender, config)
+            .await
+            .err()
+            .unwrap();
+        assert!(
+            matches!(
+                e.downcast_ref::<ConvexError>(),
+                Some(ConvexError::ConnectionRefused { .. })
+            ),
+            "{e:?}"
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "native-tls")]
+    #[tokio::test]
+    async fn test_tls_failure() -> anyhow::Result<()> {
+        use tokio::io::AsyncWriteExt;
+
+        // A server that answers the TLS handshake with plaintext.
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let ws_url = format!("wss://{}/api/sync", listener.local_addr()?).parse()?;
+        let server = tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let _ = stream.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n").await;
+            }
+        });
+
+        let (response_sender, _response_receiver) = mpsc::channel(1);
+        let e = WebSocketManager::open(ws_url, response_sender, ProtocolConfig::default())
+            .await
+            .err()
+            .unwrap();
+        assert!(
+            matches!(
+                e.downcast_ref::<ConvexError>(),
+                Some(ConvexError::TlsFailure { .. })
+            ),
+            "{e:?}"
+        );
+        server.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_handshake_headers() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let ws_url = format!("ws://{}/api/sync", listener.local_addr()?).parse()?;
+
+        // A mock server that reports the headers of the upgrade request.
+        let (headers_sender, headers_receiver) = oneshot::channel();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            // The error type is dictated by `tungstenite`.
+            #[allow(clippy::result_large_err)]
+            let callback = |request: &Request, response: Response| {
+                let _ = headers_sender.send(request.headers().clone());
+                Ok(response)
+            };
+            anyhow::Ok(tokio_tungstenite::accept_hdr_async(stream, callback).await?)
+        });
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "CF-Access-Client-Id",
+            HeaderValue::from_static("my-client-id"),
+        );
+        let (response_sender, _response_receiver) = mpsc::channel(1);
+        let config = ProtocolConfig {
+            headers,
+            ..Default::default()
+        };
+        let _manager = WebSocketManager::open(ws_url, response_sender, config).await?;
+
+        let handshake_headers = headers_receiver.await?;
+        assert_eq!(handshake_headers["CF-Access-Client-Id"], "my-client-id");
+        assert!(handshake_headers.contains_key("Convex-Client"));
+        let _ws_stream = server.await??;
+        Ok(())
+    }
+
+    #[test]
+    fn test_roundtrip_divergences() -> anyhow::Result<()> {
+        let version = |ts: &str| json!({"querySet": 1, "identity": 0, "ts": ts});
+        let json = json!({
+            "type": "Transition",
+            "startVersion": version("AAAAAAAAAAA="),
+            "endVersion": version("AQAAAAAAAAA="),
+            "modifications": [
+                {
+                    "type": "QueryUpdated",
+                    "queryId": 0,
+                    "value": {"n": 3, "f": 1.5, "i": {"$integer": "AQAAAAAAAAA="}},
+                    "journal": null,
+                },
+                {"type": "QueryRemoved", "queryId": 1},
+                {
+                    "type": "QueryUpdated",
+                    "queryId": 2,
+                    // 2^53 + 1 isn't representable as a float.
+                    "value": [9007199254740993u64],
+                    "journal": null,
+                },
+            ],
+        });
+        let message = ServerMessage::try_from(json.clone())?;
+        let divergences = roundtrip_divergences(&json, &message);
+        assert_eq!(divergences.len(), 1, "{divergences:?}");
+        assert_eq!(divergences[0].path, "$.modifications[2].value[0]");
+        assert_eq!(divergences[0].left, Some(json!(9007199254740993u64)));
+        assert_eq!(divergences[0].right, Some(json!(9007199254740992.0)));
+
+        // Sets and maps compare as collections, regardless of entry order.
+        let json = json!({
+            "type": "MutationResponse",
+            "requestId": 0,
+            "success": true,
+            "result": {
+                "s": {"$set": [2.5, 1.5]},
+                "m": {"$map": [["b", 2.5], ["a", 1.5]]},
+            },
+            "ts": null,
+        });
+        let message = ServerMessage::try_from(json.clone())?;
+        assert!(roundtrip_divergences(&json, &message).is_empty());
+
+        // A lossy number is still reported inside a set.
+        let json = json!({
+            "type": "MutationResponse",
+            "requestId": 0,
+            "success": true,
+            "result": {"$set": [9007199254740993u64, 1.5]},
+            "ts": null,
+        });
+        let message = ServerMessage::try_from(json.clone())?;
+        let divergences = roundtrip_divergences(&json, &message);
+        assert_eq!(divergences.len(), 1, "{divergences:?}");
+        assert_eq!(divergences[0].left, Some(json!(9007199254740993u64)));
+        Ok(())
     }
 }
@@ -0,0 +1,124 @@
+//! Flattening `Value`s into structured attributes for instrumentation.
+
+use crate::value::Value;
+
+/// A scalar attribute value, as produced by [`Value::to_attributes`].
+///
+/// With the `opentelemetry` feature enabled, this converts into an
+/// `opentelemetry::Value` so attributes can be attached to spans directly.
+#[derive(Clone, Debug, PartialEq)]
+#[allow(missing_docs)]
+pub enum AttributeValue {
+    String(String),
+    I64(i64),
+    F64(f64),
+    Bool(bool),
+}
+
+impl Value {
+    /// Flatten this value into a list of key/value attribute pairs, e.g. for
+    /// attaching fields of a query result to a tracing span.
+    ///
+    /// Object fields are flattened recursively with dotted keys under
+    /// `prefix`, so `{"author": {"name": "Ada"}}` with prefix `"doc"` produces
+    /// `("doc.author.name", "Ada")`. A scalar value produces a single pair
+    /// keyed by `prefix` itself.
+    ///
+    /// [`Value::Id`] and [`Value::String`] become strings, [`Value::Int64`],
+    /// [`Value::Float64`] and [`Value::Boolean`] keep their types. Fields
+    /// holding [`Value::Null`], [`Value::Bytes`], [`Value::Array`],
+    /// [`Value::Set`] or [`Value::Map`] are skipped.
+    pub fn to_attributes(&self, prefix: &str) -> Vec<(String, AttributeValue)> {
+        let mut attributes = Vec::new();
+        self.collect_attributes(prefix.to_string(), &mut attributes);
+        attributes
+    }
+
+    fn collect_attributes(&self, key: String, out: &mut Vec<(String, AttributeValue)>) {
+        let value = match self {
+            Value::Id(id) => AttributeValue::String(id.to_string()),
+            Value::Int64(n) => AttributeValue::I64(*n),
+            Value::Float64(n) => AttributeValue::F64(*n),
+            Value::Boolean(b) => AttributeValue::Bool(*b),
+            Value::String(s) => AttributeValue::String(s.clone()),
+            Value::Object(fields) => {
+                for (field, value) in fields {
+                    let key = if key.is_empty() {
+                        field.clone()
+                    } else {
+                        format!("{key}.{field}")
+                    };
+                    value.collect_attributes(key, out);
+                }
+                return;
+            },
+            Value::Null | Value::Bytes(_) | Value::Array(_) | Value::Set(_) | Value::Map(_) => {
+                return
+            },
+        };
+        out.push((key, value));
+    }
+}
+
+#[cfg(feature = "opentelemetry")]
+impl From<AttributeValue> for opentelemetry::Value {
+    fn from(value: AttributeValue) -> Self {
+        match value {
+            AttributeValue::String(s) => s.into(),
+            AttributeValue::I64(n) => n.into(),
+            AttributeValue::F64(n) => n.into(),
+            AttributeValue::Bool(b) => b.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use maplit::btreemap;
+
+    use super::AttributeValue;
+    use crate::Value;
+
+    #[test]
+    fn test_to_attributes() {
+        let value = Value::Object(btreemap! {
+            "author".into() => Value::Object(btreemap! {
+                "name".into() => "Ada".into(),
+                "verified".into() => true.into(),
+            }),
+            "body".into() => "hello".into(),
+            "count".into() => Value::Int64(3),
+            "score".into() => 0.5.into(),
+            "tags".into() => Value::Array(vec!["a".into()]),
+            "missing".into() => Value::Null,
+            "blob".into() => Value::Bytes(vec![1, 2, 3]),
+        });
+        assert_eq!(
+            value.to_attributes("doc"),
+            vec![
+                (
+                    "doc.author.name".to_string(),
+                    AttributeValue::String("Ada".into())
+                ),
+                (
+                    "doc.author.verified".to_string(),
+                    AttributeValue::Bool(true)
+                ),
+                (
+                    "doc.body".to_string(),
+                    AttributeValue::String("hello".into())
+                ),
+                ("doc.count".to_string(), AttributeValue::I64(3)),
+                ("doc.score".to_string(), AttributeValue::F64(0.5)),
+            ]
+        );
+        assert_eq!(
+            Value::Int64(1).to_attributes("n"),
+            vec![("n".to_string(), AttributeValue::I64(1))]
+        );
+        assert_eq!(
+            Value::Object(btreemap! { "a".into() => true.into() }).to_attributes(""),
+            vec![("a".to_string(), AttributeValue::Bool(true))]
+        );
+    }
+}
@@ -0,0 +1,339 @@
+//! Structural comparisons of `Value`s that are looser than `Eq`.
+
+use std::{
+    collections::{
+        BTreeSet,
+        HashSet,
+    },
+    fmt::Write,
+};
+
+use crate::value::Value;
+
+impl Value {
+    /// Compare two values for equality, skipping object fields named in
+    /// `keys`.
+    ///
+    /// Fields are skipped at any depth: objects nested within objects, within
+    /// arrays (e.g. a list of documents), and within map values are all
+    /// compared without the named keys. A skipped field may be present on one
+    /// side and missing on the other. Sets and map keys are compared with
+    /// regular equality, since their elements are identified by their full
+    /// value.
+    ///
+    /// ```
+    /// # use std::collections::HashSet;
+    /// # use convex::Value;
+    /// let a = Value::Object(maplit::btreemap! {
+    ///     "_id".into() => "abc".into(),
+    ///     "body".into() => "hello".into(),
+    /// });
+    /// let b = Value::Object(maplit::btreemap! {
+    ///     "_id".into() => "def".into(),
+    ///     "body".into() => "hello".into(),
+    /// });
+    /// assert!(a.eq_ignoring_keys(&b, &HashSet::from(["_id".to_string()])));
+    /// ```
+    pub fn eq_ignoring_keys(&self, other: &Value, keys: &HashSet<String>) -> bool {
+        match (self, other) {
+            (Value::Object(a), Value::Object(b)) => {
+                let mut a = a.iter().filter(|(k, _)| !keys.contains(*k));
+                let mut b = b.iter().filter(|(k, _)| !keys.contains(*k));
+                loop {
+                    match (a.next(), b.next()) {
+                        (None, None) => return true,
+                        (Some((ka, va)), Some((kb, vb))) => {
+                            if ka != kb || !va.eq_ignoring_keys(vb, keys) {
+                                return false;
+                            }
+                        },
+                        _ => return false,
+                    }
+                }
+            },
+            (Value::Array(a), Value::Array(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .zip(b.iter())
+                        .all(|(a, b)| a.eq_ignoring_keys(b, keys))
+            },
+            (Value::Map(a), Value::Map(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .zip(b.iter())
+                        .all(|((ka, va), (kb, vb))| ka == kb && va.eq_ignoring_keys(vb, keys))
+            },
+            (a, b) => a == b,
+        }
+    }
+
+    /// Compare two values for equality, treating an object field set to
+    /// [`Value::Null`] as equal to a missing field, e.g. `{}` and `{"a":
+    /// null}`.
+    ///
+    /// This deviates from Convex's semantics, where a missing field
+    /// (`undefined` in JavaScript) and a null field are distinct: a validator
+    /// like `v.optional(v.string())` accepts the former but not the latter,
+    /// and the two are stored differently. Use it for ergonomic comparisons,
+    /// e.g. in test assertions, where the distinction doesn't matter.
+    ///
+    /// Fields are compared this way at any depth, like with
+    /// [`Value::eq_ignoring_keys`]. Only object fields are lenient: `[null]`
+    /// and `[]` still differ.
+    ///
+    /// ```
+    /// # use convex::Value;
+    /// let missing = Value::Object(maplit::btreemap! {});
+    /// let null = Value::Object(maplit::btreemap! { "a".into() => Value::Null });
+    /// assert!(missing.eq_lenient_null(&null));
+    /// assert_ne!(missing, null);
+    /// ```
+    pub fn eq_lenient_null(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Object(a), Value::Object(b)) => {
+                let keys: BTreeSet<_> = a.keys().chain(b.keys()).collect();
+                keys.into_iter().all(|key| {
+                    let a = a.get(key).unwrap_or(&Value::Null);
+                    let b = b.get(key).unwrap_or(&Value::Null);
+                    a.eq_lenient_null(b)
+                })
+            },
+            (Value::Array(a), Value::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| a.eq_lenient_null(b))
+            },
+            (Value::Map(a), Value::Map(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .zip(b.iter())
+                        .all(|((ka, va), (kb, vb))| ka == kb && va.eq_lenient_null(vb))
+            },
+            (a, b) => a == b,
+        }
+    }
+
+    /// Compare two values for equality, treating [`Value::Float64`]s as equal
+    /// if they differ by at most `epsilon`, e.g. to assert on numbers a
+    /// function computed.
+    ///
+    /// Only float comparisons are loosened, at any depth within arrays,
+    /// objects and map values: everything else must be exactly equal, and an
+    /// [`Value::Int64`] never equals a [`Value::Float64`]. Sets and map keys
+    /// are compared with regular equality, like with
+    /// [`Value::eq_ignoring_keys`]. Following IEEE 754, NaN doesn't equal
+    /// anything, not even NaN, unlike with `==`. Infinities equal themselves,
+    /// and `0.0` equals `-0.0`.
+    ///
+    /// ```
+    /// # use convex::Value;
+    /// let total = Value::Array(vec![Value::Float64(0.1 + 0.2)]);
+    /// assert!(total.approx_eq(&Value::Array(vec![Value::Float64(0.3)]), 1e-9));
+    /// assert_ne!(total, Value::Array(vec![Value::Float64(0.3)]));
+    /// ```
+    pub fn approx_eq(&self, other: &Value, epsilon: f64) -> bool {
+        match (self, other) {
+            (Value::Float64(a), Value::Float64(b)) => a == b || (a - b).abs() <= epsilon,
+            (Value::Object(a), Value::Object(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .zip(b.iter())
+                        .all(|((ka, va), (kb, vb))| ka == kb && va.approx_eq(vb, epsilon))
+            },
+            (Value::Array(a), Value::Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| a.approx_eq(b, epsilon))
+            },
+            (Value::Map(a), Value::Map(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .zip(b.iter())
+                        .all(|((ka, va), (kb, vb))| ka == kb && va.approx_eq(vb, epsilon))
+            },
+            (a, b) => a == b,
+        }
+    }
+
+    /// Describe every difference between `self` and `other`, for use in test
+    /// failure output. Returns an empty string if the values are equal.
+    ///
+    /// Objects are compared field by field and arrays element by element, so
+    /// each differing leaf is listed separately. Each difference is reported
+    /// as its path, followed by the value on the `self` side prefixed with `-`
+    /// and the value on the `other` side prefixed with `+`. Paths start at `$`
+    /// and use `.field` for object fields and `[index]` for array elements.
+    /// A side that is missing the field or element shows `<missing>`. Other
+    /// values, including sets and maps, are compared as a whole and printed
+    /// with their `Debug` representation.
+    ///
+    /// ```
+    /// # use convex::Value;
+    /// let expected = Value::Object(maplit::btreemap! {
+    ///     "author".into() => "Ada".into(),
+    ///     "likes".into() => Value::Int64(2),
+    /// });
+    /// let actual = Value::Object(maplit::btreemap! {
+    ///     "author".into() => "Grace".into(),
+    /// });
+    /// assert_eq!(
+    ///     expected.diff_report(&actual),
+    ///     "$.author\n\
+    ///      - String(\"Ada\")\n\
+    ///      + String(\"Grace\")\n\
+    ///      $.likes\n\
+    ///      - Int64(2)\n\
+    ///      + <missing>\n",
+    /// );
+    /// ```
+    pub fn diff_report(&self, other: &Value) -> String {
+        let mut report = String::new();
+        diff(&mut report, &mut "$".to_string(), Some(self), Some(other));
+        report
+    }
+}
+
+fn diff(report: &mut String, path: &mut String, left: Option<&Value>, right: Option<&Value>) {
+    let path_len = path.len();
+    match (left, right) {
+        (Some(Value::Object(a)), Some(Value::Object(b))) => {
+            let keys: BTreeSet<_> = a.keys().chain(b.keys()).collect();
+            for key in keys {
+                write!(path, ".{key}").unwrap();
+                diff(report, path, a.get(key), b.get(key));
+                path.truncate(path_len);
+            }
+        },
+        (Some(Value::Array(a)), Some(Value::Array(b))) => {
+            for i in 0..a.len().max(b.len()) {
+                write!(path, "[{i}]").unwrap();
+                diff(report, path, a.get(i), b.get(i));
+                path.truncate(path_len);
+            }
+        },
+        (left, right) if left != right => {
+            let show = |value: Option<&Value>| match value {
+                Some(value) => format!("{value:?}"),
+                None => "<missing>".to_string(),
+            };
+            writeln!(report, "{path}\n- {}\n+ {}", show(left), show(right)).unwrap();
+        },
+        _ => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use maplit::btreemap;
+
+    use crate::Value;
+
+    #[test]
+    fn test_eq_ignoring_keys() {
+        let doc = |id: &str, time: f64, body: &str| {
+            Value::Object(btreemap! {
+                "_id".into() => id.into(),
+                "_creationTime".into() => time.into(),
+                "body".into() => body.into(),
+            })
+        };
+        let keys: HashSet<String> = ["_id".into(), "_creationTime".into()].into();
+
+        let a = Value::Array(vec![doc("a", 1.0, "hi"), doc("b", 2.0, "there")]);
+        let b = Value::Array(vec![doc("c", 3.0, "hi"), doc("d", 4.0, "there")]);
+        assert!(a.eq_ignoring_keys(&b, &keys));
+        assert!(!a.eq_ignoring_keys(&b, &HashSet::new()));
+
+        let c = Value::Array(vec![doc("c", 3.0, "hi"), doc("d", 4.0, "bye")]);
+        assert!(!a.eq_ignoring_keys(&c, &keys));
+
+        // Skipped keys may be missing entirely.
+        let nested = Value::Object(btreemap! {
+            "message".into() => doc("a", 1.0, "hi"),
+        });
+        let nested_without_keys = Value::Object(btreemap! {
+            "message".into() => Value::Object(btreemap! { "body".into() => "hi".into() }),
+        });
+        assert!(nested.eq_ignoring_keys(&nested_without_keys, &keys));
+        assert!(!nested.eq_ignoring_keys(&Value::Null, &keys));
+    }
+
+    #[test]
+    fn test_eq_lenient_null() {
+        let empty = Value::Object(btreemap! {});
+        let null = Value::Object(btreemap! { "a".into() => Value::Null });
+        assert!(empty.eq_lenient_null(&null));
+        assert!(null.eq_lenient_null(&empty));
+        assert!(!empty.eq_lenient_null(&Value::Object(btreemap! { "a".into() => 1.into() })));
+
+        // Nested objects are lenient too, but arrays aren't.
+        let nested =
+            |inner: Value| Value::Array(vec![Value::Object(btreemap! { "inner".into() => inner })]);
+        assert!(nested(empty.clone()).eq_lenient_null(&nested(null.clone())));
+        assert!(nested(Value::Null).eq_lenient_null(&Value::Array(vec![empty.clone()])));
+        assert!(!Value::Array(vec![]).eq_lenient_null(&Value::Array(vec![Value::Null])));
+        assert!(!empty.eq_lenient_null(&Value::Null));
+    }
+
+    #[test]
+    fn test_approx_eq() {
+        let point = |x: f64, y: f64| {
+            Value::Object(btreemap! {
+                "label".into() => "origin".into(),
+                "coords".into() => Value::Array(vec![x.into(), y.into()]),
+            })
+        };
+        let a = point(1.0, 2.0);
+        assert!(a.approx_eq(&point(1.0 + 9e-4, 2.0 - 9e-4), 1e-3));
+        assert!(!a.approx_eq(&point(1.0 + 2e-3, 2.0), 1e-3));
+        assert!(!a.approx_eq(&point(1.0, 2.0 - 2e-3), 1e-3));
+
+        // Everything other than floats is compared exactly.
+        let relabeled = Value::Object(btreemap! {
+            "label".into() => "center".into(),
+            "coords".into() => Value::Array(vec![1.0.into(), 2.0.into()]),
+        });
+        assert!(!a.approx_eq(&relabeled, 1.0));
+        assert!(!Value::Int64(1).approx_eq(&Value::Float64(1.0), 1.0));
+
+        assert!(!Value::Float64(f64::NAN).approx_eq(&Value::Float64(f64::NAN), 1.0));
+        assert!(Value::Float64(f64::INFINITY).approx_eq(&Value::Float64(f64::INFINITY), 0.0));
+        assert!(Value::Float64(0.0).approx_eq(&Value::Float64(-0.0), 0.0));
+    }
+
+    #[test]
+    fn test_diff_report() {
+        let a = Value::Object(btreemap! {
+            "body".into() => "hi".into(),
+            "author".into() => Value::Object(btreemap! {
+                "name".into() => "Ada".into(),
+                "verified".into() => true.into(),
+            }),
+            "tags".into() => Value::Array(vec!["a".into(), "b".into()]),
+            "likes".into() => Value::Int64(2),
+        });
+        let b = Value::Object(btreemap! {
+            "body".into() => "hi".into(),
+            "author".into() => Value::Object(btreemap! {
+                "name".into() => "Grace".into(),
+                "verified".into() => true.into(),
+            }),
+            "tags".into() => Value::Array(vec!["a".into()]),
+            "likes".into() => Value::Float64(2.0),
+            "pinned".into() => false.into(),
+        });
+        let report = a.diff_report(&b);
+        assert_eq!(
+            report
+                .lines()
+                .filter(|l| l.starts_with('$'))
+                .collect::<Vec<_>>(),
+            vec!["$.author.name", "$.likes", "$.pinned", "$.tags[1]"],
+        );
+        assert!(report.contains("$.tags[1]\n- String(\"b\")\n+ <missing>\n"));
+        assert!(report.contains("$.likes\n- Int64(2)\n+ Float64(2.0)\n"));
+        assert_eq!(a.diff_report(&a), "");
+        assert_eq!(
+            Value::Null.diff_report(&Value::Int64(1)),
+            "$\n- Null\n+ Int64(1)\n"
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Exporting `Value`s to configuration formats, e.g. to hand a settings
+//! document stored in Convex to a tool that reads YAML or TOML.
+
+#[cfg(feature = "toml")]
+use anyhow::bail;
+
+use crate::value::Value;
+
+#[cfg(feature = "yaml")]
+impl Value {
+    /// This value as a YAML document. Requires the `yaml` feature.
+    ///
+    /// YAML has fewer types than Convex, so the export is lossy:
+    ///
+    /// - [`Value::Bytes`] become base64 strings, as in Convex's JSON format.
+    /// - [`Value::Id`]s become plain strings.
+    /// - [`Value::Set`]s become lists, in the set's order.
+    /// - [`Value::Map`]s become mappings, which may have keys of any type.
+    /// - [`Value::Int64`] and [`Value::Float64`] become integers and floats,
+    ///   but tools reading the output may not keep them apart, e.g. a `1.0` may
+    ///   read back as an integer.
+    ///
+    /// ```
+    /// # use convex::Value;
+    /// let config: Value = serde_json::json!({"name": "bot", "retries": 3.0}).try_into()?;
+    /// assert_eq!(config.to_yaml_string()?, "name: bot\nretries: 3.0\n");
+    /// # anyhow::Ok(())
+    /// ```
+    pub fn to_yaml_string(&self) -> anyhow::Result<String> {
+        Ok(serde_yaml::to_string(&to_yaml(self))?)
+    }
+}
+
+#[cfg(feature = "yaml")]
+fn to_yaml(value: &Value) -> serde_yaml::Value {
+    match value {
+        Value::Id(id) => serde_yaml::Value::String(id.to_string()),
+        Value::Null => serde_yaml::Value::Null,
+        Value::Int64(n) => serde_yaml::Value::Number((*n).into()),
+        Value::Float64(n) => serde_yaml::Value::Number((*n).into()),
+        Value::Boolean(b) => serde_yaml::Value::Bool(*b),
+        Value::String(s) => serde_yaml::Value::String(s.clone()),
+        Value::Bytes(bytes) => serde_yaml::Value::String(base64::encode(bytes)),
+        Value::Array(values) => serde_yaml::Value::Sequence(values.iter().map(to_yaml).collect()),
+        Value::Set(values) => serde_yaml::Value::Sequence(values.iter().map(to_yaml).collect()),
+        Value::Map(entries) => serde_yaml::Value::Mapping(
+            entries
+                .iter()
+                .map(|(key, value)| (to_yaml(key), to_yaml(value)))
+                .collect(),
+        ),
+        Value::Object(fields) => serde_yaml::Value::Mapping(
+            fields
+                .iter()
+                .map(|(key, value)| (serde_yaml::Value::String(key.clone()), to_yaml(value)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(feature = "toml")]
+impl Value {
+    /// This value as a TOML document. Requires the `toml` feature.
+    ///
+    /// TOML documents are tables, so this value must be a [`Value::Object`],
+    /// or a [`Value::Map`] with string keys. Fails if it isn't, or if it
+    /// contains a null or a map with other keys, which TOML can't represent.
+    /// Otherwise, the export is lossy like
+    /// [`to_yaml_string`](Self::to_yaml_string)'s.
+    ///
+    /// ```
+    /// # use convex::Value;
+    /// let config: Value = serde_json::json!({"name": "bot", "retries": 3.0}).try_into()?;
+    /// assert_eq!(config.to_toml_string()?, "name = \"bot\"\nretries = 3.0\n");
+    /// # anyhow::Ok(())
+    /// ```
+    pub fn to_toml_string(&self) -> anyhow::Result<String> {
+        let table @ toml::Value::Table(_) = to_toml(self, "")? else {
+            bail!("TOML documents are tables, so only objects and maps can be exported");
+        };
+        Ok(toml::to_string(&table)?)
+    }
+}
+
+/// Convert `value`, found at `path` for error messages.
+#[cfg(feature = "toml")]
+fn to_toml(value: &Value, path: &str) -> anyhow::Result<toml::Value> {
+    let field = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{path}.{key}")
+        }
+    };
+    let result = match value {
+        Value::Id(id) => toml::Value::String(id.to_string()),
+        Value::Null => bail!("TOML has no null, found at {path:?}"),
+        Value::Int64(n) => toml::Value::Integer(*n),
+        Value::Float64(n) => toml::Value::Float(*n),
+        Value::Boolean(b) => toml::Value::Boolean(*b),
+        Value::String(s) => toml::Value::String(s.clone()),
+        Value::Bytes(bytes) => toml::Value::String(base64::encode(bytes)),
+        Value::Array(values) => toml::Value::Array(
+            values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| to_toml(value, &field(&i.to_string())))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Value::Set(values) => toml::Value::Array(
+            values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| to_toml(value, &field(&i.to_string())))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Value::Map(entries) => toml::Value::Table(
+            entries
+                .iter()
+                .map(|(key, value)| {
+                    let Value::String(key) = key else {
+                        bail!("TOML keys are strings, found {key:?} at {path:?}");
+                    };
+                    Ok((key.clone(), to_toml(value, &field(key))?))
+                })
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Value::Object(fields) => toml::Value::Table(
+            fields
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), to_toml(value, &field(key))?)))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+    };
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::Value;
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn test_to_yaml_string() -> anyhow::Result<()> {
+        let config: Value = json!({
+            "name": "notifier",
+            "limits": {"perMinute": {"$integer": "PAAAAAAAAAA="}, "burst": 1.5},
+            "channels": ["email", null],
+            "secret": {"$bytes": "aGk="},
+        })
+        .try_into()?;
+        assert_eq!(
+            config.to_yaml_string()?,
+            "channels:\n- email\n- null\nlimits:\n  burst: 1.5\n  perMinute: 60\nname: \
+             notifier\nsecret: aGk=\n"
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_to_toml_string() -> anyhow::Result<()> {
+        let config: Value = json!({
+            "name": "notifier",
+            "limits": {"perMinute": {"$integer": "PAAAAAAAAAA="}},
+            "secret": {"$bytes": "aGk="},
+        })
+        .try_into()?;
+        assert_eq!(
+            config.to_toml_string()?,
+            "name = \"notifier\"\nsecret = \"aGk=\"\n\n[limits]\nperMinute = 60\n"
+        );
+
+        let config: Value = json!({"limits": {"burst": null}}).try_into()?;
+        let err = config.to_toml_string().unwrap_err();
+        assert!(err.to_string().contains("\"limits.burst\""), "{err}");
+        assert!(Value::from("notifier").to_toml_string().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+//! Conventions for storing durations, UUIDs and URLs in `Value`s.
+//!
+//! Convex has no types for these, so each is encoded as a plainer value.
+//! The encodings are a contract between everything that reads and writes a
+//! field, so they're fixed here rather than left to each caller.
+
+use std::time::Duration;
+
+use url::Url;
+use uuid::Uuid;
+
+use crate::value::Value;
+
+impl Value {
+    /// Encode `d` as a [`Value::Int64`] number of whole milliseconds.
+    ///
+    /// Sub-millisecond precision is truncated, so `1.9ms` becomes `Int64(1)`.
+    /// Durations longer than `i64::MAX` milliseconds, about 292 million
+    /// years, saturate to `i64::MAX`.
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use convex::Value;
+    /// let timeout = Value::from_duration(Duration::from_secs(30));
+    /// assert_eq!(timeout, Value::Int64(30_000));
+    /// assert_eq!(timeout.as_duration(), Some(Duration::from_secs(30)));
+    /// ```
+    pub fn from_duration(d: Duration) -> Value {
+        Value::Int64(i64::try_from(d.as_millis()).unwrap_or(i64::MAX))
+    }
+
+    /// Decode a duration encoded by [`Value::from_duration`].
+    ///
+    /// Also accepts a [`Value::Float64`] number of milliseconds, since that's
+    /// how JavaScript code computes durations, e.g. `Date.now() - start`.
+    /// Fractional milliseconds are kept. Returns `None` for any other value,
+    /// or a negative, NaN or infinite number of milliseconds.
+    pub fn as_duration(&self) -> Option<Duration> {
+        match *self {
+            Value::Int64(ms) => u64::try_from(ms).ok().map(Duration::from_millis),
+            // `u64::MAX as f64` rounds up to 2^64, which is out of range.
+            Value::Float64(ms) if ms >= 0.0 && ms / 1000.0 < u64::MAX as f64 => {
+                Some(Duration::from_secs_f64(ms / 1000.0))
+            },
+            _ => None,
+        }
+    }
+
+    /// Encode `u` as a [`Value::String`] in its lowercase hyphenated form,
+    /// like `"67e55044-10b1-426f-9247-bb680e5fe0c8"`, which is what
+    /// JavaScript's `crypto.randomUUID()` returns.
+    ///
+    /// Strings are larger than the 16 bytes of a [`Value::Bytes`], but they
+    /// read well in the dashboard and can be compared with UUIDs from
+    /// JavaScript code directly.
+    ///
+    /// ```
+    /// # use convex::Value;
+    /// let id = uuid::Uuid::new_v4();
+    /// assert_eq!(Value::from_uuid(id).as_uuid(), Some(id));
+    /// ```
+    pub fn from_uuid(u: Uuid) -> Value {
+        Value::String(u.hyphenated().to_string())
+    }
+
+    /// Decode a UUID encoded by [`Value::from_uuid`].
+    ///
+    /// Any string the `uuid` crate parses is accepted, including uppercase
+    /// and unhyphenated forms. Returns `None` for any other value.
+    pub fn as_uuid(&self) -> Option<Uuid> {
+        match self {
+            Value::String(s) => Uuid::parse_str(s).ok(),
+            _ => None,
+        }
+    }
+
+    /// Encode `u` as a [`Value::String`] of its serialization, e.g.
+    /// `"https://example.com/a%20b"`.
+    pub fn from_url(u: &Url) -> Value {
+        Value::String(u.as_str().to_owned())
+    }
+
+    /// Decode a URL encoded by [`Value::from_url`]. Returns `None` for any
+    /// other value or a string that isn't an absolute URL.
+    pub fn as_url(&self) -> Option<Url> {
+        match self {
+            Value::String(s) => Url::parse(s).ok(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use url::Url;
+    use uuid::Uuid;
+
+    use crate::Value;
+
+    #[test]
+    fn test_duration_roundtrips() {
+        for d in [
+            Duration::ZERO,
+            Duration::from_millis(1),
+            Duration::from_secs(86_400),
+        ] {
+            assert_eq!(Value::from_duration(d).as_duration(), Some(d));
+        }
+
+        // Sub-millisecond precision is truncated.
+        let precise = Duration::from_micros(1_999);
+        assert_eq!(Value::from_duration(precise), Value::Int64(1));
+        assert_eq!(
+            Value::from_duration(precise).as_duration(),
+            Some(Duration::from_millis(1))
+        );
+        assert_eq!(Value::from_duration(Duration::MAX), Value::Int64(i64::MAX));
+
+        assert_eq!(
+            Value::Float64(1.5).as_duration(),
+            Some(Duration::from_micros(1_500))
+        );
+        for value in [
+            Value::Int64(-1),
+            Value::Float64(-0.5),
+            Value::Float64(f64::NAN),
+            Value::Float64(f64::INFINITY),
+            Value::from("1s"),
+        ] {
+            assert_eq!(value.as_duration(), None, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn test_uuid_roundtrips() {
+        let u = Uuid::parse_str("67E55044-10B1-426F-9247-BB680E5FE0C8").unwrap();
+        let value = Value::from_uuid(u);
+        assert_eq!(value, Value::from("67e55044-10b1-426f-9247-bb680e5fe0c8"));
+        assert_eq!(value.as_uuid(), Some(u));
+        assert_eq!(
+            Value::from("67e5504410b1426f9247bb680e5fe0c8").as_uuid(),
+            Some(u)
+        );
+        assert_eq!(Value::from("not a uuid").as_uuid(), None);
+        assert_eq!(Value::Bytes(u.as_bytes().to_vec()).as_uuid(), None);
+    }
+
+    #[test]
+    fn test_url_roundtrips() {
+        let u = Url::parse("https://example.com/a b?q=1").unwrap();
+        let value = Value::from_url(&u);
+        assert_eq!(value, Value::from("https://example.com/a%20b?q=1"));
+        assert_eq!(value.as_url(), Some(u));
+        assert_eq!(Value::from("/relative/path").as_url(), None);
+        assert_eq!(Value::Null.as_url(), None);
+    }
+}
@@ -0,0 +1,151 @@
+//! Converting timestamps stored in `Value`s to and from `chrono` types.
+
+use chrono::{
+    DateTime,
+    Utc,
+};
+
+use crate::value::Value;
+
+impl Value {
+    /// Interpret this value as a timestamp, the way Convex apps commonly
+    /// store them. Requires the `chrono` feature.
+    ///
+    /// - A [`Value::Float64`] or [`Value::Int64`] is a number of milliseconds
+    ///   since the Unix epoch, like JavaScript's `Date.now()` or a document's
+    ///   `_creationTime`. Fractional milliseconds are kept.
+    /// - A [`Value::String`] is an RFC 3339 timestamp with an offset, like
+    ///   `"2024-03-01T12:00:00Z"` or `"2024-03-01T13:00:00+01:00"`, which is
+    ///   converted to UTC.
+    ///
+    /// Returns `None` for any other value, a string that isn't RFC 3339, or a
+    /// number that's NaN, infinite or out of range for a `DateTime`.
+    ///
+    /// ```
+    /// # use convex::Value;
+    /// let created = Value::Float64(1_709_294_400_000.0).as_datetime().unwrap();
+    /// assert_eq!(created.to_rfc3339(), "2024-03-01T12:00:00+00:00");
+    /// assert_eq!(Value::from("2024-03-01T12:00:00Z").as_datetime(), Some(created));
+    /// ```
+    pub fn as_datetime(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Value::Int64(ms) => DateTime::from_timestamp_millis(*ms),
+            Value::Float64(ms) => from_float_millis(*ms),
+            Value::String(s) => DateTime::parse_from_rfc3339(s)
+                .ok()
+                .map(|datetime| datetime.with_timezone(&Utc)),
+            _ => None,
+        }
+    }
+
+    /// A [`Value::Float64`] holding the number of milliseconds between the
+    /// Unix epoch and `datetime`, the inverse of [`Value::as_datetime`] for
+    /// numbers. Requires the `chrono` feature.
+    ///
+    /// This matches JavaScript's `Date.now()`, so it validates as a
+    /// `v.number()`. Sub-millisecond precision is limited by the `f64`, to
+    /// about a microsecond for current dates. To store an RFC 3339 string
+    /// instead, use `Value::from(datetime.to_rfc3339())`.
+    pub fn from_datetime(datetime: DateTime<Utc>) -> Value {
+        let sub_millis = datetime.timestamp_subsec_nanos() % 1_000_000;
+        Value::Float64(datetime.timestamp_millis() as f64 + f64::from(sub_millis) / 1e6)
+    }
+}
+
+fn from_float_millis(ms: f64) -> Option<DateTime<Utc>> {
+    let secs = (ms / 1000.0).floor();
+    // `i64::MAX as f64` rounds up to 2^63, which is out of range.
+    if !(secs >= i64::MIN as f64 && secs < i64::MAX as f64) {
+        return None;
+    }
+    let nanos = ((ms - secs * 1000.0) * 1e6).round();
+    let (secs, nanos) = if nanos >= 1e9 {
+        (secs as i64 + 1, 0)
+    } else {
+        (secs as i64, nanos as u32)
+    };
+    DateTime::from_timestamp(secs, nanos)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{
+        DateTime,
+        TimeZone,
+        Utc,
+    };
+
+    use crate::Value;
+
+    #[test]
+    fn test_numeric_millis() {
+        let datetime = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        assert_eq!(
+            Value::Int64(1_709_294_400_000).as_datetime(),
+            Some(datetime)
+        );
+        assert_eq!(
+            Value::Float64(1_709_294_400_000.0).as_datetime(),
+            Some(datetime)
+        );
+        assert_eq!(
+            Value::from_datetime(datetime),
+            Value::Float64(1_709_294_400_000.0)
+        );
+
+        // Fractional milliseconds, as in `_creationTime`.
+        let creation_time = Value::Float64(1_709_294_400_123.5);
+        let datetime = creation_time.as_datetime().unwrap();
+        assert_eq!(datetime.timestamp_millis(), 1_709_294_400_123);
+        assert_eq!(datetime.timestamp_subsec_nanos(), 123_500_000);
+        assert_eq!(Value::from_datetime(datetime), creation_time);
+
+        // Before the epoch.
+        let datetime = Value::Float64(-1.5).as_datetime().unwrap();
+        assert_eq!(datetime.to_rfc3339(), "1969-12-31T23:59:59.998500+00:00");
+        assert_eq!(Value::from_datetime(datetime), Value::Float64(-1.5));
+
+        for value in [
+            Value::Float64(f64::NAN),
+            Value::Float64(f64::INFINITY),
+            Value::Float64(1e300),
+            Value::Int64(i64::MAX),
+            Value::Null,
+            Value::Boolean(true),
+        ] {
+            assert_eq!(value.as_datetime(), None, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn test_rfc3339_strings() {
+        let datetime = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        for s in [
+            "2024-03-01T12:00:00Z",
+            "2024-03-01T12:00:00.000Z",
+            "2024-03-01T13:00:00+01:00",
+            "2024-03-01t12:00:00z",
+        ] {
+            assert_eq!(Value::from(s).as_datetime(), Some(datetime), "{s}");
+        }
+        assert_eq!(
+            Value::from(datetime.to_rfc3339()).as_datetime(),
+            Some(datetime)
+        );
+        let precise = DateTime::parse_from_rfc3339("2024-03-01T12:00:00.123456789Z").unwrap();
+        assert_eq!(
+            Value::from("2024-03-01T12:00:00.123456789Z").as_datetime(),
+            Some(precise.with_timezone(&Utc))
+        );
+
+        for s in [
+            "2024-03-01",
+            "2024-03-01T12:00:00",
+            "1709294400000",
+            "yesterday",
+            "",
+        ] {
+            assert_eq!(Value::from(s).as_datetime(), None, "{s}");
+        }
+    }
+}
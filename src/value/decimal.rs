@@ -0,0 +1,141 @@
+//! A fixed-point decimal convention for `Value`s, e.g. for financial data.
+
+use crate::value::Value;
+
+/// The largest supported scale, since `10^19` overflows an `i64`.
+const MAX_SCALE: u32 = 18;
+
+impl Value {
+    /// Encode the decimal string `d` as a [`Value::Int64`] scaled by
+    /// `10^scale`, e.g. `"12.34"` with scale 2 becomes `Int64(1234)`.
+    ///
+    /// Convex has no decimal type, so this standardizes on scaled integers:
+    /// they're exact, and compare and sort like the decimals they encode as
+    /// long as every value of a field uses the same scale. The scale isn't
+    /// stored, so readers must know it, e.g. 2 for cents.
+    ///
+    /// `d` is an optional sign followed by digits with an optional decimal
+    /// point, like `-0.5` or `+3`. Exponents aren't supported. Fails if `d`
+    /// has more fractional digits than `scale` (it's never rounded), if
+    /// `scale` is above 18, or if the scaled value doesn't fit in an `i64`,
+    /// which limits the magnitude to about `9.2 * 10^(18 - scale)`.
+    ///
+    /// ```
+    /// # use convex::Value;
+    /// let price = Value::from_decimal("12.34", 2)?;
+    /// assert_eq!(price, Value::Int64(1234));
+    /// assert_eq!(price.to_decimal(2).as_deref(), Some("12.34"));
+    /// # anyhow::Ok(())
+    /// ```
+    pub fn from_decimal(d: &str, scale: u32) -> anyhow::Result<Value> {
+        anyhow::ensure!(
+            scale <= MAX_SCALE,
+            "Decimal scale {scale} exceeds {MAX_SCALE}"
+        );
+        let (negative, digits) = match d.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, d.strip_prefix('+').unwrap_or(d)),
+        };
+        let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        anyhow::ensure!(
+            !(integer.is_empty() && fraction.is_empty())
+                && integer
+                    .chars()
+                    .chain(fraction.chars())
+                    .all(|c| c.is_ascii_digit()),
+            "Invalid decimal {d:?}"
+        );
+        anyhow::ensure!(
+            fraction.len() <= scale as usize,
+            "Decimal {d:?} has more than {scale} fractional digits"
+        );
+        // Pad the fraction to `scale` digits and parse the digits as a single
+        // scaled integer. Parse as `i128` so that `i64::MIN` can be negated.
+        let scaled = format!("{integer}{fraction:0<width$}", width = scale as usize);
+        let magnitude: i128 = scaled
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Decimal {d:?} is out of range for scale {scale}"))?;
+        let n = if negative { -magnitude } else { magnitude };
+        let n = i64::try_from(n)
+            .map_err(|_| anyhow::anyhow!("Decimal {d:?} is out of range for scale {scale}"))?;
+        Ok(Value::Int64(n))
+    }
+
+    /// Decode a [`Value::Int64`] encoded by [`Value::from_decimal`] with
+    /// `scale` back into a decimal string with exactly `scale` fractional
+    /// digits, e.g. `Int64(1230)` with scale 2 becomes `"12.30"`.
+    ///
+    /// Returns `None` if the value isn't an `Int64` or `scale` is above 18.
+    pub fn to_decimal(&self, scale: u32) -> Option<String> {
+        let Value::Int64(n) = *self else {
+            return None;
+        };
+        if scale > MAX_SCALE {
+            return None;
+        }
+        let sign = if n < 0 { "-" } else { "" };
+        let magnitude = n.unsigned_abs();
+        if scale == 0 {
+            return Some(format!("{sign}{magnitude}"));
+        }
+        let divisor = 10u64.pow(scale);
+        Some(format!(
+            "{sign}{}.{:0width$}",
+            magnitude / divisor,
+            magnitude % divisor,
+            width = scale as usize
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+
+    #[test]
+    fn test_decimal_roundtrips() -> anyhow::Result<()> {
+        let cases = [
+            ("12.34", 2, 1234, "12.34"),
+            ("-0.05", 2, -5, "-0.05"),
+            ("1.5", 2, 150, "1.50"),
+            ("+7", 2, 700, "7.00"),
+            (".25", 2, 25, "0.25"),
+            ("3.", 0, 3, "3"),
+            ("-0", 3, 0, "0.000"),
+            ("92233720368547758.07", 2, i64::MAX, "92233720368547758.07"),
+            (
+                "-92233720368547758.08",
+                2,
+                i64::MIN,
+                "-92233720368547758.08",
+            ),
+            ("0.000000000000000001", 18, 1, "0.000000000000000001"),
+        ];
+        for (d, scale, scaled, canonical) in cases {
+            let value = Value::from_decimal(d, scale)?;
+            assert_eq!(value, Value::Int64(scaled), "{d}");
+            assert_eq!(value.to_decimal(scale).as_deref(), Some(canonical));
+            assert_eq!(Value::from_decimal(canonical, scale)?, value);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_decimal_errors() {
+        for (d, scale) in [
+            ("1.234", 2),
+            ("92233720368547758.08", 2),
+            ("", 2),
+            (".", 2),
+            ("-", 2),
+            ("1e3", 2),
+            ("1,5", 2),
+            ("--1", 2),
+            ("1", 19),
+        ] {
+            assert!(Value::from_decimal(d, scale).is_err(), "{d}");
+        }
+        assert_eq!(Value::Float64(1.5).to_decimal(2), None);
+        assert_eq!(Value::Int64(1).to_decimal(19), None);
+    }
+}
@@ -0,0 +1,160 @@
+//! Accessing document IDs as strings, and checking their format before
+//! sending them.
+
+use std::{
+    ops::Deref,
+    str::FromStr,
+};
+
+use anyhow::bail;
+
+use crate::value::{
+    DocumentId,
+    Value,
+};
+
+/// The longest encoded ID: a table number of up to 5 bytes, a 16 byte
+/// internal ID and a 2 byte checksum, in base32.
+const MAX_ID_LEN: usize = 37;
+
+impl DocumentId {
+    /// Parse `id`, checking that it's formatted like a Convex document ID: 1
+    /// to 37 ASCII letters and digits. Current IDs are 31 to 37 characters of
+    /// Convex's base32 alphabet, and IDs from older deployments are shorter,
+    /// so both are accepted.
+    ///
+    /// This catches IDs that are empty, mangled or aren't IDs at all, like
+    /// `messages:k57c2kd8`, without a round trip. It can't tell which table
+    /// an ID belongs to, or whether it exists, so the deployment may still
+    /// reject a well-formed ID, e.g. one for the wrong table.
+    ///
+    /// `DocumentId`'s `FromStr` implementation is this function.
+    ///
+    /// ```
+    /// # use convex::DocumentId;
+    /// assert!(DocumentId::parse("k57c2kd8d9cd5f8ga7ey1fa6h16t3s7k").is_ok());
+    /// assert!(DocumentId::parse("k57c2kd8").is_ok());
+    /// assert!(DocumentId::parse("messages:k57c2kd8").is_err());
+    /// ```
+    pub fn parse(id: &str) -> anyhow::Result<DocumentId> {
+        if id.is_empty() || id.len() > MAX_ID_LEN {
+            bail!(
+                "Invalid ID {id:?}: IDs are 1 to {MAX_ID_LEN} characters long, not {}",
+                id.len()
+            );
+        }
+        if let Some(c) = id.chars().find(|c| !c.is_ascii_alphanumeric()) {
+            bail!("Invalid ID {id:?}: IDs can't contain {c:?}");
+        }
+        Ok(DocumentId(id.to_string()))
+    }
+
+    /// The ID as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for DocumentId {
+    type Err = anyhow::Error;
+
+    fn from_str(id: &str) -> anyhow::Result<Self> {
+        Self::parse(id)
+    }
+}
+
+impl Deref for DocumentId {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for DocumentId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Value {
+    /// Check the format of every [`Value::Id`] in this value with
+    /// [`DocumentId::parse`].
+    pub(crate) fn check_ids(&self) -> anyhow::Result<()> {
+        match self {
+            Value::Id(id) => DocumentId::parse(id).map(|_| ()),
+            Value::Array(values) => values.iter().try_for_each(Value::check_ids),
+            Value::Set(values) => values.iter().try_for_each(Value::check_ids),
+            Value::Map(entries) => entries
+                .iter()
+                .try_for_each(|(key, value)| key.check_ids().and_then(|()| value.check_ids())),
+            Value::Object(fields) => fields.values().try_for_each(Value::check_ids),
+            Value::Null
+            | Value::Int64(_)
+            | Value::Float64(_)
+            | Value::Boolean(_)
+            | Value::String(_)
+            | Value::Bytes(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use maplit::btreemap;
+
+    use crate::{
+        DocumentId,
+        Value,
+    };
+
+    #[test]
+    fn test_parse() {
+        for id in ["k57c2kd8d9cd5f8ga7ey1fa6h16t3s7k", "k57c2kd8", "Jd8fK2mQ"] {
+            assert_eq!(DocumentId::parse(id).unwrap().as_str(), id);
+            assert_eq!(id.parse::<DocumentId>().unwrap().as_str(), id);
+        }
+        for id in [
+            "",
+            "k57c2kd8d9cd5f8ga7ey1fa6h16t3s7kk57c2kd8",
+            "k57c2kd8d9cd5f8ga7ey1fa6h16t3s-k",
+            "messages:k57c2kd8",
+            " k57c2kd8",
+        ] {
+            assert!(DocumentId::parse(id).is_err(), "{id:?} should be rejected");
+            assert!(
+                id.parse::<DocumentId>().is_err(),
+                "{id:?} should be rejected"
+            );
+        }
+
+        let nested = Value::Object(btreemap! {
+            "messageId".into() => Value::id("k57c2kd8d9cd5f8ga7ey1fa6h16t3s7k"),
+            "authorIds".into() => Value::Array(vec![Value::id("users:luke")]),
+        });
+        assert!(nested.check_ids().is_err());
+    }
+
+    #[test]
+    fn test_string_access() -> anyhow::Result<()> {
+        let id = DocumentId::parse("k57c2kd8d9cd5f8ga7ey1fa6h16t3s7k")?;
+        assert_eq!(id.to_string(), "k57c2kd8d9cd5f8ga7ey1fa6h16t3s7k");
+        assert_eq!(
+            format!("message {id}"),
+            "message k57c2kd8d9cd5f8ga7ey1fa6h16t3s7k"
+        );
+        assert_eq!(id.as_str(), "k57c2kd8d9cd5f8ga7ey1fa6h16t3s7k");
+
+        fn takes_str(s: &str) -> usize {
+            s.len()
+        }
+        fn takes_as_ref(s: impl AsRef<str>) -> usize {
+            s.as_ref().len()
+        }
+        assert_eq!(takes_str(&id), 32);
+        assert_eq!(takes_as_ref(&*id), 32);
+        assert_eq!(takes_as_ref(id.clone()), 32);
+        assert!(id.starts_with("k57"));
+        Ok(())
+    }
+}
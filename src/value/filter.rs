@@ -0,0 +1,117 @@
+//! Comparing fields of `Value`s, e.g. to filter query results locally.
+
+use std::cmp::Ordering;
+
+use crate::value::Value;
+
+/// A comparison for [`Value::matches`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ComparisonOp {
+    /// The field equals the operand.
+    Eq,
+    /// The field doesn't equal the operand, or is missing.
+    Ne,
+    /// The field sorts before the operand.
+    Lt,
+    /// The field sorts before or equals the operand.
+    Le,
+    /// The field sorts after the operand.
+    Gt,
+    /// The field sorts after or equals the operand.
+    Ge,
+}
+
+impl ComparisonOp {
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            ComparisonOp::Eq => ordering == Ordering::Equal,
+            ComparisonOp::Ne => ordering != Ordering::Equal,
+            ComparisonOp::Lt => ordering == Ordering::Less,
+            ComparisonOp::Le => ordering != Ordering::Greater,
+            ComparisonOp::Gt => ordering == Ordering::Greater,
+            ComparisonOp::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+impl Value {
+    /// Whether the value at `field_path` compares to `operand` as `op` says,
+    /// e.g. to filter the documents of a query result that returns more than
+    /// the UI shows.
+    ///
+    /// `field_path` is as for [`get_path`](Self::get_path). Values compare by
+    /// their `Ord`, like the server's indexes: values of different types
+    /// order by type, with ids first, then null, `Int64`, `Float64`, boolean,
+    /// string, bytes, array, set, map and object. So `Int64(1)` is less than
+    /// `Float64(0.5)`, and numbers of different types are never equal. A
+    /// missing field sorts before every value, like `undefined` in Convex: it
+    /// matches [`Ne`](ComparisonOp::Ne), [`Lt`](ComparisonOp::Lt) and
+    /// [`Le`](ComparisonOp::Le), and nothing else.
+    ///
+    /// ```
+    /// # use convex::{ComparisonOp, Value};
+    /// let messages: Vec<Value> = vec![
+    ///     serde_json::json!({"author": "Luke", "likes": {"$integer": "AwAAAAAAAAA="}}).try_into()?,
+    ///     serde_json::json!({"author": "Leia"}).try_into()?,
+    /// ];
+    /// let popular: Vec<_> = messages
+    ///     .iter()
+    ///     .filter(|m| m.matches("likes", ComparisonOp::Gt, &Value::Int64(2)))
+    ///     .collect();
+    /// assert_eq!(popular, [&messages[0]]);
+    /// # anyhow::Ok(())
+    /// ```
+    pub fn matches(&self, field_path: &str, op: ComparisonOp, operand: &Value) -> bool {
+        op.holds(self.get_path(field_path).cmp(&Some(operand)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use maplit::btreemap;
+
+    use super::ComparisonOp;
+    use crate::Value;
+
+    #[test]
+    fn test_matches() {
+        let order = |status: &str, total: Option<i64>| {
+            let mut fields = btreemap! {"status".into() => Value::from(status)};
+            if let Some(total) = total {
+                fields.insert("total".into(), Value::Int64(total));
+            }
+            Value::Object(fields)
+        };
+        let orders = [
+            order("shipped", Some(120)),
+            order("pending", Some(80)),
+            order("shipped", Some(40)),
+            order("pending", None),
+        ];
+        let filter = |op, path, operand: Value| -> Vec<usize> {
+            (0..orders.len())
+                .filter(|&i| orders[i].matches(path, op, &operand))
+                .collect()
+        };
+
+        assert_eq!(filter(ComparisonOp::Gt, "total", Value::Int64(50)), [0, 1]);
+        assert_eq!(
+            filter(ComparisonOp::Le, "total", Value::Int64(80)),
+            [1, 2, 3]
+        );
+        assert_eq!(filter(ComparisonOp::Eq, "status", "shipped".into()), [0, 2]);
+        assert_eq!(filter(ComparisonOp::Ne, "status", "shipped".into()), [1, 3]);
+        assert_eq!(filter(ComparisonOp::Ge, "total", Value::Null), [0, 1, 2]);
+
+        // Values of different types order by type, so an `Int64` is less than
+        // any `Float64` and greater than null.
+        assert_eq!(
+            filter(ComparisonOp::Lt, "total", Value::Float64(0.5)),
+            [0, 1, 2, 3]
+        );
+        assert_eq!(
+            filter(ComparisonOp::Eq, "total", Value::Float64(40.0)),
+            [] as [usize; 0]
+        );
+    }
+}
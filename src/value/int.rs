@@ -0,0 +1,74 @@
+//! Bounds-checked conversions from `Value::Int64` to smaller integer types.
+
+use std::any::type_name;
+
+use crate::value::Value;
+
+impl Value {
+    /// Get a [`Value::Int64`] as a `u32`, failing if the value isn't an
+    /// `Int64` or doesn't fit in a `u32`.
+    ///
+    /// ```
+    /// # use convex::Value;
+    /// assert_eq!(Value::Int64(42).as_u32().unwrap(), 42);
+    /// assert!(Value::Int64(-1).as_u32().is_err());
+    /// ```
+    pub fn as_u32(&self) -> anyhow::Result<u32> {
+        self.as_int()
+    }
+
+    /// Get a [`Value::Int64`] as an `i32`, failing if the value isn't an
+    /// `Int64` or doesn't fit in an `i32`.
+    pub fn as_i32(&self) -> anyhow::Result<i32> {
+        self.as_int()
+    }
+
+    /// Get a [`Value::Int64`] as a `usize`, failing if the value isn't an
+    /// `Int64` or doesn't fit in a `usize` on this platform.
+    pub fn as_usize(&self) -> anyhow::Result<usize> {
+        self.as_int()
+    }
+
+    /// Convert an `Int64` without truncating. Floats are rejected even if
+    /// they're integral, since Convex treats the two types strictly.
+    fn as_int<T: TryFrom<i64>>(&self) -> anyhow::Result<T> {
+        let Value::Int64(n) = *self else {
+            anyhow::bail!("Expected an Int64, found {self:?}");
+        };
+        T::try_from(n).map_err(|_| anyhow::anyhow!("{n} overflows {}", type_name::<T>()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+
+    #[test]
+    fn test_as_u32() {
+        assert_eq!(Value::Int64(0).as_u32().unwrap(), 0);
+        assert_eq!(Value::Int64(u32::MAX.into()).as_u32().unwrap(), u32::MAX);
+        let err = Value::Int64(i64::from(u32::MAX) + 1).as_u32().unwrap_err();
+        assert_eq!(err.to_string(), "4294967296 overflows u32");
+        assert!(Value::Int64(-1).as_u32().is_err());
+    }
+
+    #[test]
+    fn test_as_i32() {
+        assert_eq!(Value::Int64(i32::MIN.into()).as_i32().unwrap(), i32::MIN);
+        assert_eq!(Value::Int64(i32::MAX.into()).as_i32().unwrap(), i32::MAX);
+        assert!(Value::Int64(i64::from(i32::MIN) - 1).as_i32().is_err());
+        assert!(Value::Int64(i64::from(i32::MAX) + 1).as_i32().is_err());
+    }
+
+    #[test]
+    fn test_as_usize() {
+        assert_eq!(Value::Int64(0).as_usize().unwrap(), 0);
+        assert_eq!(
+            Value::Int64(i64::MAX).as_usize().ok(),
+            usize::try_from(i64::MAX).ok()
+        );
+        assert!(Value::Int64(-1).as_usize().is_err());
+        assert!(Value::Float64(3.0).as_usize().is_err());
+        assert!(Value::Null.as_usize().is_err());
+    }
+}
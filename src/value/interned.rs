@@ -0,0 +1,389 @@
+//! A representation of `Value` trees that shares storage between equal
+//! strings, for large, repetitive query results.
+
+use std::{
+    collections::{
+        BTreeMap,
+        HashSet,
+    },
+    fmt,
+    sync::Arc,
+};
+
+use anyhow::Context;
+use serde::de::{
+    self,
+    DeserializeSeed,
+    Deserializer,
+    MapAccess,
+    SeqAccess,
+    Visitor,
+};
+use serde_json::Value as JsonValue;
+
+use crate::value::{
+    DocumentId,
+    Value,
+};
+
+/// The keys of the JSON special forms, like `{"$integer": ...}`.
+const SPECIAL_FORM_KEYS: [&str; 6] = ["$id", "$bytes", "$integer", "$float", "$set", "$map"];
+
+/// A [`Value`] whose strings are interned: equal strings anywhere in the
+/// tree, including object field names, string values and document IDs,
+/// share a single `Arc<str>`.
+///
+/// A query returning thousands of documents repeats the same field names in
+/// every document, and often the same enum-like string values too. As a
+/// [`Value`], each of those is its own `String` allocation. Interning keeps
+/// one copy of each distinct string per tree, at the cost of hashing every
+/// string while decoding. Strings are only shared within the tree they were
+/// interned in.
+///
+/// Create one with [`Value::intern`] or [`InternedValue::from_json_slice`],
+/// and convert back with `Value::from`. The variants mirror [`Value`]'s,
+/// except that:
+/// - Strings are `Arc<str>` instead of `String`. Use [`InternedValue::as_str`]
+///   to read a [`InternedValue::String`] or [`InternedValue::Id`].
+/// - Objects are keyed by `Arc<str>`. Use [`InternedValue::get`] to look up a
+///   field by name.
+/// - Sets and maps are `Vec`s in the order of the original [`Value`], which is
+///   sorted and free of duplicates.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InternedValue {
+    /// A [`Value::Id`].
+    Id(Arc<str>),
+    /// A [`Value::Null`].
+    Null,
+    /// A [`Value::Int64`].
+    Int64(i64),
+    /// A [`Value::Float64`].
+    Float64(f64),
+    /// A [`Value::Boolean`].
+    Boolean(bool),
+    /// A [`Value::String`].
+    String(Arc<str>),
+    /// A [`Value::Bytes`]. Bytes aren't interned.
+    Bytes(Vec<u8>),
+    /// A [`Value::Array`].
+    Array(Vec<InternedValue>),
+    /// A [`Value::Set`], with its elements in sorted order.
+    Set(Vec<InternedValue>),
+    /// A [`Value::Map`], with its entries in sorted order of their keys.
+    Map(Vec<(InternedValue, InternedValue)>),
+    /// A [`Value::Object`].
+    Object(BTreeMap<Arc<str>, InternedValue>),
+}
+
+impl InternedValue {
+    /// Decode an interned value from its JSON encoding, with the same result
+    /// as [`Value::from_json_slice`] followed by [`Value::intern`].
+    ///
+    /// Strings are interned as they're decoded, so each distinct string is
+    /// only allocated once, and no intermediate [`Value`] is built. The
+    /// contents of sets and maps are the exception: they're decoded as a
+    /// [`Value`] first, to sort them and reject duplicates.
+    pub fn from_json_slice(bytes: &[u8]) -> anyhow::Result<InternedValue> {
+        let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+        let value = (&mut Interner::default())
+            .deserialize(&mut deserializer)
+            .context("JsonDeserializeError")?;
+        deserializer.end().context("JsonDeserializeError")?;
+        Ok(value)
+    }
+
+    /// The text of a [`InternedValue::String`] or [`InternedValue::Id`].
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            InternedValue::Id(s) | InternedValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The field `name` of an [`InternedValue::Object`].
+    pub fn get(&self, name: &str) -> Option<&InternedValue> {
+        match self {
+            InternedValue::Object(fields) => fields.get(name),
+            _ => None,
+        }
+    }
+}
+
+impl Value {
+    /// Convert this value to an [`InternedValue`], sharing storage between
+    /// its equal strings.
+    ///
+    /// ```
+    /// # use convex::Value;
+    /// let rows: Vec<Value> = (0..1000)
+    ///     .map(|i| Value::Object(maplit::btreemap! {
+    ///         "status".into() => Value::from(if i % 2 == 0 { "active" } else { "idle" }),
+    ///     }))
+    ///     .collect();
+    /// let interned = Value::Array(rows).intern();
+    /// # let _ = interned;
+    /// ```
+    pub fn intern(self) -> InternedValue {
+        Interner::default().intern(self)
+    }
+}
+
+/// The distinct strings of the tree being interned.
+#[derive(Default)]
+struct Interner {
+    strings: HashSet<Arc<str>>,
+}
+
+impl Interner {
+    fn intern(&mut self, value: Value) -> InternedValue {
+        match value {
+            Value::Id(id) => InternedValue::Id(self.intern_str(&id.0)),
+            Value::Null => InternedValue::Null,
+            Value::Int64(n) => InternedValue::Int64(n),
+            Value::Float64(n) => InternedValue::Float64(n),
+            Value::Boolean(b) => InternedValue::Boolean(b),
+            Value::String(s) => InternedValue::String(self.intern_str(&s)),
+            Value::Bytes(b) => InternedValue::Bytes(b),
+            Value::Array(values) => {
+                InternedValue::Array(values.into_iter().map(|v| self.intern(v)).collect())
+            },
+            Value::Set(values) => {
+                InternedValue::Set(values.into_iter().map(|v| self.intern(v)).collect())
+            },
+            Value::Map(entries) => InternedValue::Map(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| (self.intern(k), self.intern(v)))
+                    .collect(),
+            ),
+            Value::Object(fields) => InternedValue::Object(
+                fields
+                    .into_iter()
+                    .map(|(k, v)| (self.intern_str(&k), self.intern(v)))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn intern_str(&mut self, s: &str) -> Arc<str> {
+        if let Some(interned) = self.strings.get(s) {
+            return interned.clone();
+        }
+        let interned: Arc<str> = s.into();
+        self.strings.insert(interned.clone());
+        interned
+    }
+
+    /// Convert JSON that was buffered to check for a special form.
+    fn intern_json<E: de::Error>(&mut self, json: JsonValue) -> Result<InternedValue, E> {
+        let value = Value::try_from(json).map_err(E::custom)?;
+        Ok(self.intern(value))
+    }
+}
+
+/// Decodes JSON like `Value::try_from` does, interning strings as they're
+/// read.
+impl<'de> DeserializeSeed<'de> for &mut Interner {
+    type Value = InternedValue;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<InternedValue, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for &mut Interner {
+    type Value = InternedValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON-encoded Convex value")
+    }
+
+    fn visit_unit<E>(self) -> Result<InternedValue, E> {
+        Ok(InternedValue::Null)
+    }
+
+    fn visit_bool<E>(self, b: bool) -> Result<InternedValue, E> {
+        Ok(InternedValue::Boolean(b))
+    }
+
+    // JSON numbers are always floats, as in `Value::try_from`.
+    fn visit_i64<E>(self, n: i64) -> Result<InternedValue, E> {
+        Ok(InternedValue::Float64(n as f64))
+    }
+
+    fn visit_u64<E>(self, n: u64) -> Result<InternedValue, E> {
+        Ok(InternedValue::Float64(n as f64))
+    }
+
+    fn visit_f64<E>(self, n: f64) -> Result<InternedValue, E> {
+        Ok(InternedValue::Float64(n))
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<InternedValue, E> {
+        Ok(InternedValue::String(self.intern_str(s)))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<InternedValue, A::Error> {
+        let mut values = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(value) = seq.next_element_seed(&mut *self)? {
+            values.push(value);
+        }
+        Ok(InternedValue::Array(values))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<InternedValue, A::Error> {
+        let mut fields = BTreeMap::new();
+        let mut next_key = map.next_key_seed(FieldName(self))?;
+        // An object is a special form only if the form's key is its only
+        // key, so buffer the value until the next key shows whether it is.
+        if matches!(&next_key, Some(key) if SPECIAL_FORM_KEYS.contains(&&**key)) {
+            let key = next_key.take().expect("Checked above");
+            let value: JsonValue = map.next_value()?;
+            next_key = map.next_key_seed(FieldName(self))?;
+            if next_key.is_none() {
+                let form = serde_json::Map::from_iter([(key.to_string(), value)]);
+                return self.intern_json(JsonValue::Object(form));
+            }
+            fields.insert(key, self.intern_json(value)?);
+        }
+        while let Some(key) = next_key {
+            fields.insert(key, map.next_value_seed(&mut *self)?);
+            next_key = map.next_key_seed(FieldName(self))?;
+        }
+        Ok(InternedValue::Object(fields))
+    }
+}
+
+/// Reads an object's field name, interning it.
+struct FieldName<'a>(&'a mut Interner);
+
+impl<'de> DeserializeSeed<'de> for FieldName<'_> {
+    type Value = Arc<str>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Arc<str>, D::Error> {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'de> Visitor<'de> for FieldName<'_> {
+    type Value = Arc<str>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a field name")
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<Arc<str>, E> {
+        Ok(self.0.intern_str(s))
+    }
+}
+
+impl From<InternedValue> for Value {
+    fn from(value: InternedValue) -> Value {
+        match value {
+            InternedValue::Id(id) => Value::Id(DocumentId(id.to_string())),
+            InternedValue::Null => Value::Null,
+            InternedValue::Int64(n) => Value::Int64(n),
+            InternedValue::Float64(n) => Value::Float64(n),
+            InternedValue::Boolean(b) => Value::Boolean(b),
+            InternedValue::String(s) => Value::String(s.to_string()),
+            InternedValue::Bytes(b) => Value::Bytes(b),
+            InternedValue::Array(values) => {
+                Value::Array(values.into_iter().map(Value::from).collect())
+            },
+            InternedValue::Set(values) => Value::Set(values.into_iter().map(Value::from).collect()),
+            InternedValue::Map(entries) => Value::Map(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| (Value::from(k), Value::from(v)))
+                    .collect(),
+            ),
+            InternedValue::Object(fields) => Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(k, v)| (k.to_string(), Value::from(v)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use maplit::{
+        btreemap,
+        btreeset,
+    };
+    use proptest::prelude::*;
+
+    use super::InternedValue;
+    use crate::Value;
+
+    #[test]
+    fn test_intern_shares_strings() -> anyhow::Result<()> {
+        let interned = InternedValue::from_json_slice(
+            br#"[{"status": "active", "owner": {"$id": "active"}}, {"status": "active"}]"#,
+        )?;
+        let InternedValue::Array(rows) = &interned else {
+            panic!("Expected an array: {interned:?}");
+        };
+        let (InternedValue::String(first), InternedValue::String(second)) = (
+            rows[0].get("status").unwrap(),
+            rows[1].get("status").unwrap(),
+        ) else {
+            panic!("Expected strings: {rows:?}");
+        };
+        assert!(Arc::ptr_eq(first, second));
+        assert_eq!(
+            rows[0].get("owner").and_then(InternedValue::as_str),
+            Some("active")
+        );
+
+        // Field names are shared with string values too.
+        let value = Value::Object(btreemap! { "kind".into() => "kind".into() }).intern();
+        let InternedValue::Object(fields) = &value else {
+            panic!("Expected an object: {value:?}");
+        };
+        let (name, InternedValue::String(kind)) = fields.iter().next().unwrap() else {
+            panic!("Expected a string: {fields:?}");
+        };
+        assert!(Arc::ptr_eq(name, kind));
+        Ok(())
+    }
+
+    #[test]
+    fn test_intern_sets_and_maps() {
+        let value = Value::Map(btreemap! {
+            Value::from("k") => Value::Set(btreeset! { Value::from("k"), Value::Null }),
+        });
+        assert_eq!(Value::from(value.clone().intern()), value);
+
+        // Decoding checks special forms like `Value::from_json_slice` does.
+        assert!(InternedValue::from_json_slice(br#"{"$set": [1, 1]}"#).is_err());
+        assert!(InternedValue::from_json_slice(br#"{"$integer": "short"}"#).is_err());
+        assert!(InternedValue::from_json_slice(b"{} {}").is_err());
+        let object = InternedValue::from_json_slice(br#"{"$integer": "short", "a": 1}"#).unwrap();
+        assert_eq!(
+            object.get("$integer").and_then(InternedValue::as_str),
+            Some("short")
+        );
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig { failure_persistence: None, ..ProptestConfig::default() })]
+
+        #[test]
+        fn test_intern_roundtrips(v in any::<Value>()) {
+            prop_assert_eq!(Value::from(v.clone().intern()), v);
+        }
+
+        #[test]
+        fn test_from_json_slice_matches_intern(v in any::<Value>()) {
+            let json = serde_json::Value::from(v.clone()).to_string();
+            let interned = InternedValue::from_json_slice(json.as_bytes()).unwrap();
+            // `Value`'s equality, unlike `InternedValue`'s, treats NaNs as equal.
+            prop_assert_eq!(Value::from(interned), v);
+        }
+    }
+}
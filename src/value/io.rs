@@ -0,0 +1,74 @@
+//! Helpers for streaming `Value::Bytes` to and from async IO.
+
+use tokio::io::{
+    AsyncRead,
+    AsyncReadExt,
+    AsyncWrite,
+    AsyncWriteExt,
+};
+
+use crate::value::Value;
+
+impl Value {
+    /// Write the contents of a [`Value::Bytes`] to `writer`, without copying
+    /// them. Fails if this value is not [`Value::Bytes`].
+    pub async fn write_bytes_to<W: AsyncWrite + Unpin>(
+        &self,
+        writer: &mut W,
+    ) -> anyhow::Result<()> {
+        let Value::Bytes(bytes) = self else {
+            anyhow::bail!("Expected Bytes, found {self:?}");
+        };
+        writer.write_all(bytes).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+
+    /// Read `reader` to the end into a [`Value::Bytes`].
+    ///
+    /// At most `max_len` bytes are buffered. If `reader` has more than
+    /// `max_len` bytes, this fails instead of returning a truncated value, so
+    /// `max_len` should be no larger than the largest value you intend to send
+    /// to Convex.
+    pub async fn read_bytes_from<R: AsyncRead + Unpin>(
+        reader: &mut R,
+        max_len: usize,
+    ) -> anyhow::Result<Value> {
+        let mut bytes = Vec::new();
+        // Read one byte past the cap so we can tell a value of exactly
+        // `max_len` bytes apart from a longer one.
+        reader
+            .take(max_len as u64 + 1)
+            .read_to_end(&mut bytes)
+            .await?;
+        anyhow::ensure!(
+            bytes.len() <= max_len,
+            "Bytes exceed maximum length of {max_len}"
+        );
+        Ok(Value::Bytes(bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+
+    #[tokio::test]
+    async fn test_bytes_roundtrip() -> anyhow::Result<()> {
+        let value = Value::Bytes((0..=255).collect());
+        let mut buffer = Vec::new();
+        value.write_bytes_to(&mut buffer).await?;
+        assert_eq!(buffer.len(), 256);
+
+        let read = Value::read_bytes_from(&mut &buffer[..], 256).await?;
+        assert_eq!(read, value);
+
+        let err = Value::read_bytes_from(&mut &buffer[..], 255)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("maximum length"));
+
+        assert!(Value::Null.write_bytes_to(&mut buffer).await.is_err());
+        Ok(())
+    }
+}
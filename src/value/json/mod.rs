@@ -1,4 +1,5 @@
 use std::{
+    cell::Cell,
     cmp::Ordering,
     collections::{
         btree_map::Entry,
@@ -9,6 +10,13 @@ use std::{
 };
 
 use anyhow::Context;
+use serde::{
+    ser::SerializeMap,
+    Deserialize,
+    Deserializer,
+    Serialize,
+    Serializer,
+};
 use serde_json::{
     json,
     Value as JsonValue,
@@ -23,6 +31,33 @@ mod bytes;
 mod float;
 mod integer;
 
+thread_local! {
+    /// Whether a [`Value::from_serialize`] call is running on this thread, so
+    /// that [`DocumentId`]s serialize in the JSON encoding of [`Value::Id`].
+    static IN_FROM_SERIALIZE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Serializes as `{"$id": ...}` inside [`Value::from_serialize`], so that it
+/// converts to a [`Value::Id`], and as the ID's string everywhere else.
+impl Serialize for DocumentId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if !IN_FROM_SERIALIZE.with(Cell::get) {
+            return serializer.serialize_str(&self.0);
+        }
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry("$id", &self.0)?;
+        map.end()
+    }
+}
+
+/// Deserializes from the ID's string, matching how it serializes outside of
+/// [`Value::from_serialize`].
+impl<'de> Deserialize<'de> for DocumentId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(DocumentId)
+    }
+}
+
 /// Is a floating point number native zero?
 fn is_negative_zero(n: f64) -> bool {
     matches!(n.total_cmp(&-0.0), Ordering::Equal)
@@ -70,6 +105,19 @@ impl From<Value> for JsonValue {
     }
 }
 
+/// Decode a `Value` from its JSON encoding.
+///
+/// `null`, `[]` and `{}` decode to [`Value::Null`], an empty [`Value::Array`]
+/// and an empty [`Value::Object`] respectively. An object is a special form,
+/// like `{"$integer": ...}`, only if its single key is one of `$id`, `$bytes`,
+/// `$integer`, `$float`, `$set` or `$map`, and then its value must match the
+/// form. Any other object, including one with an unknown `$`-prefixed key or
+/// with a special form key next to other keys, decodes to a plain
+/// [`Value::Object`].
+///
+/// Convex rejects field names starting with `$`, so an object whose single
+/// field is named like a special form doesn't round trip: it encodes to, and
+/// decodes as, that special form.
 impl TryFrom<JsonValue> for Value {
     type Error = anyhow::Error;
 
@@ -84,7 +132,7 @@ impl TryFrom<JsonValue> for Value {
                     .context("Arbitrary precision JSON integers unsupported")?;
                 Value::from(n)
             },
-            JsonValue::String(s) => Self::try_from(s)?,
+            JsonValue::String(s) => Self::from(s),
             JsonValue::Array(arr) => {
                 let mut out = Vec::with_capacity(arr.len());
                 for a in arr {
@@ -93,6 +141,7 @@ impl TryFrom<JsonValue> for Value {
                 Value::Array(out)
             },
             JsonValue::Object(map) => {
+                // Special forms have exactly one key, so `{}` is an object.
                 if map.len() == 1 {
                     let (key, value) = map.into_iter().next().unwrap();
                     match &key[..] {
@@ -167,13 +216,98 @@ impl TryFrom<JsonValue> for Value {
     }
 }
 
+impl Value {
+    /// Convert any [`Serialize`] type to a `Value` by way of its JSON
+    /// representation.
+    ///
+    /// JSON numbers, including integers, become [`Value::Float64`], matching
+    /// how Convex treats JavaScript numbers. `Value` itself doesn't implement
+    /// [`Serialize`], so to pass a 64-bit integer, insert a [`Value::Int64`]
+    /// into the converted object instead. Use [`DocumentId`] fields for
+    /// document IDs so that they become [`Value::Id`]s rather than strings.
+    pub fn from_serialize<T: Serialize + ?Sized>(value: &T) -> anyhow::Result<Value> {
+        /// Restores the flag even if `serialize` panics, and supports nested
+        /// calls from within a `Serialize` impl.
+        struct Reset(bool);
+        impl Drop for Reset {
+            fn drop(&mut self) {
+                IN_FROM_SERIALIZE.with(|flag| flag.set(self.0));
+            }
+        }
+        let _reset = Reset(IN_FROM_SERIALIZE.with(|flag| flag.replace(true)));
+        let json = serde_json::to_value(value).context("JsonSerializeError")?;
+        Value::try_from(json)
+    }
+
+    /// Decode a `Value` from its JSON encoding, e.g. a function result read
+    /// from a file or another untrusted source.
+    ///
+    /// Malformed input fails with an error and never panics. That includes
+    /// invalid JSON, malformed `$`-forms like `{"$integer": "short"}`,
+    /// duplicate set elements or map keys, and nesting deeper than 128 levels,
+    /// which is rejected to bound stack usage.
+    ///
+    /// ```
+    /// # use convex::Value;
+    /// let value = Value::from_json_slice(br#"{"n": {"$integer": "AQAAAAAAAAA="}}"#)?;
+    /// assert_eq!(value, Value::Object(maplit::btreemap! { "n".into() => Value::Int64(1) }));
+    /// assert!(Value::from_json_slice(br#"{"$set": [1, 1]}"#).is_err());
+    /// # anyhow::Ok(())
+    /// ```
+    pub fn from_json_slice(bytes: &[u8]) -> anyhow::Result<Value> {
+        let json: JsonValue = serde_json::from_slice(bytes).context("JsonDeserializeError")?;
+        Value::try_from(json)
+    }
+
+    /// Encode the arguments of a function call for the `args` of a
+    /// [`ClientMessage`](convex_sync_types::ClientMessage), e.g. to build
+    /// raw messages.
+    ///
+    /// By convention, Convex functions take a single object of named
+    /// arguments, so the client always sends a list holding one
+    /// [`Value::Object`]: pass `vec![Value::Object(args)]`, even for a
+    /// function without arguments. Each value is encoded like
+    /// `JsonValue::from`. The inverse is [`Value::values_from_json_args`].
+    ///
+    /// ```
+    /// # use convex::Value;
+    /// let args = maplit::btreemap! { "n".to_string() => Value::Float64(1.0) };
+    /// let json_args = Value::json_args_from_values(vec![Value::Object(args)]);
+    /// assert_eq!(json_args, vec![serde_json::json!({"n": 1.0})]);
+    /// ```
+    pub fn json_args_from_values(args: Vec<Value>) -> Vec<JsonValue> {
+        args.into_iter().map(JsonValue::from).collect()
+    }
+
+    /// Decode the `args` of a
+    /// [`ClientMessage`](convex_sync_types::ClientMessage), the inverse of
+    /// [`Value::json_args_from_values`].
+    ///
+    /// Fails if any argument isn't a valid encoding of a `Value`. The shape of
+    /// the list isn't checked: a message sent by this client holds a single
+    /// [`Value::Object`], which callers can match on.
+    pub fn values_from_json_args(args: Vec<JsonValue>) -> anyhow::Result<Vec<Value>> {
+        args.into_iter()
+            .enumerate()
+            .map(|(i, arg)| Value::try_from(arg).with_context(|| format!("Invalid argument {i}")))
+            .collect()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use convex_sync_types::testing::assert_roundtrips;
+    use maplit::btreemap;
     use proptest::prelude::*;
-    use serde_json::Value as JsonValue;
+    use serde_json::{
+        json,
+        Value as JsonValue,
+    };
 
-    use crate::Value;
+    use crate::{
+        DocumentId,
+        Value,
+    };
 
     proptest! {
         #![proptest_config(ProptestConfig { failure_persistence: None, .. ProptestConfig::default() })]
@@ -184,15 +318,193 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_from_serialize_ids() -> anyhow::Result<()> {
+        #[derive(serde::Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Args {
+            message_id: DocumentId,
+            author_ids: Vec<DocumentId>,
+            body: String,
+        }
+        let args = Value::from_serialize(&Args {
+            message_id: "m1".to_string().into(),
+            author_ids: vec!["a1".to_string().into()],
+            body: "m1".into(),
+        })?;
+        assert_eq!(
+            args,
+            Value::Object(maplit::btreemap! {
+                "messageId".into() => Value::id("m1"),
+                "authorIds".into() => Value::Array(vec![Value::id("a1")]),
+                "body".into() => Value::String("m1".into()),
+            })
+        );
+
+        // Other serializers see the plain string, and it roundtrips.
+        let id = DocumentId::from("m1".to_string());
+        assert_eq!(serde_json::to_value(&id)?, json!("m1"));
+        assert_eq!(serde_json::from_value::<DocumentId>(json!("m1"))?, id);
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_args_roundtrip() -> anyhow::Result<()> {
+        let object = Value::Object(maplit::btreemap! {
+            "id".into() => Value::id("k57"),
+            "count".into() => Value::Int64(1),
+        });
+        for (args, json_args) in [
+            (vec![], vec![]),
+            (
+                vec![object.clone()],
+                vec![json!({"id": {"$id": "k57"}, "count": {"$integer": "AQAAAAAAAAA="}})],
+            ),
+            (
+                vec![object, Value::Null, Value::Float64(1.5)],
+                vec![
+                    json!({"id": {"$id": "k57"}, "count": {"$integer": "AQAAAAAAAAA="}}),
+                    json!(null),
+                    json!(1.5),
+                ],
+            ),
+        ] {
+            assert_eq!(Value::json_args_from_values(args.clone()), json_args);
+            assert_eq!(Value::values_from_json_args(json_args)?, args);
+        }
+
+        let error =
+            Value::values_from_json_args(vec![json!({}), json!({"$integer": 1})]).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid argument 1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_json_slice_errors() {
+        let deep = format!("{}{}", "[".repeat(200), "]".repeat(200));
+        let shallow = format!("{}{}", "[".repeat(100), "]".repeat(100));
+        assert!(Value::from_json_slice(deep.as_bytes()).is_err());
+        assert!(Value::from_json_slice(shallow.as_bytes()).is_ok());
+        for input in [
+            &b""[..],
+            b"{",
+            b"\xff",
+            br#"{"$integer": "AQ=="}"#,
+            br#"{"$integer": 1}"#,
+            br#"{"$float": "AAAAAAAA8D8="}"#,
+            br#"{"$bytes": "%%%"}"#,
+            br#"{"$map": [[1, 2], [1, 3]]}"#,
+            br#"{"$map": [[1]]}"#,
+            br#"{"$set": {}}"#,
+            br#"{"$id": null}"#,
+            b"1e400",
+        ] {
+            let result = Value::from_json_slice(input);
+            assert!(result.is_err(), "{}", String::from_utf8_lossy(input));
+        }
+
+        // Huge integers are approximated as floats, like in JavaScript.
+        assert_eq!(
+            Value::from_json_slice(b"123456789012345678901234567890").ok(),
+            Some(Value::Float64(1.2345678901234568e29))
+        );
+    }
+
+    #[test]
+    fn test_decode_empty_and_special_forms() -> anyhow::Result<()> {
+        let object = |fields: Vec<(&str, Value)>| {
+            Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(k, v)| (k.to_string(), v))
+                    .collect(),
+            )
+        };
+        let cases = [
+            ("null", Value::Null),
+            ("[]", Value::Array(vec![])),
+            ("{}", object(vec![])),
+            ("[null]", Value::Array(vec![Value::Null])),
+            ("[[]]", Value::Array(vec![Value::Array(vec![])])),
+            ("[{}]", Value::Array(vec![object(vec![])])),
+            (r#"{"a": null}"#, object(vec![("a", Value::Null)])),
+            (r#"{"a": []}"#, object(vec![("a", Value::Array(vec![]))])),
+            (r#"{"a": {}}"#, object(vec![("a", object(vec![]))])),
+            (r#"{"$set": []}"#, Value::Set(Default::default())),
+            (r#"{"$map": []}"#, Value::Map(Default::default())),
+            (r#"{"$bytes": ""}"#, Value::Bytes(vec![])),
+            (r#"{"$id": ""}"#, Value::id("")),
+            // Special form keys next to other keys are plain fields.
+            (
+                r#"{"$id": "x", "other": 1}"#,
+                object(vec![("$id", "x".into()), ("other", 1.0.into())]),
+            ),
+            (
+                r#"{"$integer": "AQAAAAAAAAA=", "$float": 1}"#,
+                object(vec![
+                    ("$integer", "AQAAAAAAAAA=".into()),
+                    ("$float", 1.0.into()),
+                ]),
+            ),
+            (
+                r#"{"$set": [], "$map": []}"#,
+                object(vec![
+                    ("$set", Value::Array(vec![])),
+                    ("$map", Value::Array(vec![])),
+                ]),
+            ),
+            // Unknown `$` keys aren't special forms.
+            (
+                r#"{"$other": {}}"#,
+                object(vec![("$other", object(vec![]))]),
+            ),
+            (r#"{"$ID": "x"}"#, object(vec![("$ID", "x".into())])),
+        ];
+        for (json, expected) in cases {
+            assert_eq!(Value::from_json_slice(json.as_bytes())?, expected, "{json}");
+        }
+
+        // The empty values stay distinct when encoded.
+        assert_eq!(JsonValue::from(Value::Null), serde_json::json!(null));
+        assert_eq!(JsonValue::from(Value::Array(vec![])), serde_json::json!([]));
+        assert_eq!(JsonValue::from(object(vec![])), serde_json::json!({}));
+        assert_eq!(
+            JsonValue::from(Value::Set(Default::default())),
+            serde_json::json!({"$set": []})
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_value_roundtrips_trophies() {
         let trophies = vec![
             Value::Float64(1.0),
             Value::Float64(f64::NAN),
             Value::Array(vec![Value::Float64(f64::NAN)]),
+            Value::Object(btreemap! {"ratio".into() => Value::Float64(f64::NAN)}),
+            Value::Object(btreemap! {
+                "a".into() => Value::Array(vec![Value::Object(btreemap! {
+                    "b".into() => Value::Array(vec![
+                        Value::Float64(f64::INFINITY),
+                        Value::Float64(-0.0),
+                    ]),
+                })]),
+            }),
+            Value::Map(btreemap! {
+                Value::Float64(f64::NEG_INFINITY) => Value::Set([Value::Float64(f64::NAN)].into()),
+            }),
         ];
         for trophy in trophies {
             assert_roundtrips::<Value, JsonValue>(trophy);
         }
+
+        // Special floats get the `$float` encoding wherever they are.
+        let nested = Value::Object(btreemap! {
+            "ratio".into() => Value::Array(vec![Value::Float64(f64::INFINITY)]),
+        });
+        assert_eq!(
+            JsonValue::from(nested),
+            json!({"ratio": [{"$float": "AAAAAAAA8H8="}]})
+        );
     }
 }
@@ -0,0 +1,158 @@
+//! Checking the keys of `Value::Map`s before sending them.
+
+use crate::value::Value;
+
+impl Value {
+    /// Check that every key of every [`Value::Map`] within this value is
+    /// accepted by `is_valid_key`, recursing into arrays, sets, maps (both
+    /// keys and values) and objects.
+    ///
+    /// Convex accepts any value as a map key, including objects, arrays and
+    /// other maps, and the `$map` encoding represents all of them, so the
+    /// client never rejects a map because of its keys. This is a hook for
+    /// stricter rules of your own, e.g. to only allow string keys when the
+    /// map is read by code that expects them, so that a bad key fails here
+    /// with the path to the map rather than later on the server or in
+    /// another client.
+    ///
+    /// Fails on the first rejected key, in key order, with its path in the
+    /// syntax of [`Value::get_path`], extended with `[key]` for map values,
+    /// `{key}` for map keys and `{element}` for set elements.
+    ///
+    /// ```
+    /// # use convex::Value;
+    /// let scores = Value::Map(maplit::btreemap! { Value::from("ada") => Value::Int64(3) });
+    /// scores.check_map_keys(|key| matches!(key, Value::String(_)))?;
+    /// # anyhow::Ok(())
+    /// ```
+    pub fn check_map_keys(&self, is_valid_key: impl Fn(&Value) -> bool) -> anyhow::Result<()> {
+        self.check_map_keys_at(&mut String::new(), &is_valid_key)
+    }
+
+    /// Check this value, found at `path`.
+    fn check_map_keys_at(
+        &self,
+        path: &mut String,
+        is_valid_key: &impl Fn(&Value) -> bool,
+    ) -> anyhow::Result<()> {
+        match self {
+            Value::Array(values) => {
+                for (i, value) in values.iter().enumerate() {
+                    check_map_keys_at(path, format!("[{i}]"), value, is_valid_key)?;
+                }
+            },
+            Value::Set(values) => {
+                for value in values {
+                    check_map_keys_at(
+                        path,
+                        format!("{{{}}}", value.to_truncated_string(40)),
+                        value,
+                        is_valid_key,
+                    )?;
+                }
+            },
+            Value::Map(entries) => {
+                for (key, value) in entries {
+                    let key_string = key.to_truncated_string(40);
+                    if !is_valid_key(key) {
+                        let location = if path.is_empty() {
+                            "the top level".to_string()
+                        } else {
+                            format!("`{path}`")
+                        };
+                        anyhow::bail!("Unsupported map key {key_string} in the map at {location}");
+                    }
+                    check_map_keys_at(path, format!("{{{key_string}}}"), key, is_valid_key)?;
+                    check_map_keys_at(path, format!("[{key_string}]"), value, is_valid_key)?;
+                }
+            },
+            Value::Object(fields) => {
+                for (name, value) in fields {
+                    let separator = if path.is_empty() { "" } else { "." };
+                    check_map_keys_at(path, format!("{separator}{name}"), value, is_valid_key)?;
+                }
+            },
+            Value::Id(_)
+            | Value::Null
+            | Value::Int64(_)
+            | Value::Float64(_)
+            | Value::Boolean(_)
+            | Value::String(_)
+            | Value::Bytes(_) => {},
+        }
+        Ok(())
+    }
+}
+
+/// Check `value`, found at `path` followed by `segment`.
+fn check_map_keys_at(
+    path: &mut String,
+    segment: String,
+    value: &Value,
+    is_valid_key: &impl Fn(&Value) -> bool,
+) -> anyhow::Result<()> {
+    let len = path.len();
+    path.push_str(&segment);
+    let result = value.check_map_keys_at(path, is_valid_key);
+    path.truncate(len);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use maplit::btreemap;
+    use serde_json::Value as JsonValue;
+
+    use crate::Value;
+
+    fn is_string(key: &Value) -> bool {
+        matches!(key, Value::String(_))
+    }
+
+    #[test]
+    fn test_check_map_keys() -> anyhow::Result<()> {
+        let string_keys = Value::Object(btreemap! {
+            "scores".into() => Value::Map(btreemap! {
+                "ada".into() => Value::Int64(3),
+                "grace".into() => Value::Int64(5),
+            }),
+        });
+        string_keys.check_map_keys(is_string)?;
+
+        let object_key = Value::Object(btreemap! {
+            "teams".into() => Value::Array(vec![Value::Map(btreemap! {
+                Value::Object(btreemap! { "name".into() => "red".into() }) => Value::Int64(1),
+            })]),
+        });
+        let err = object_key.check_map_keys(is_string).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"Unsupported map key {"name": "red"} in the map at `teams[0]`"#
+        );
+
+        // Convex accepts any key, so the map is encoded as-is.
+        object_key.check_map_keys(|_| true)?;
+        assert_eq!(
+            Value::try_from(JsonValue::from(object_key.clone()))?,
+            object_key
+        );
+
+        // Keys of maps nested in map values and keys are checked too.
+        let nested = Value::Map(btreemap! {
+            "outer".into() => Value::Map(btreemap! { Value::Int64(1) => Value::Null }),
+        });
+        let err = nested.check_map_keys(is_string).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"Unsupported map key 1 in the map at `["outer"]`"#
+        );
+        let err = Value::Map(btreemap! { Value::Null => Value::Null })
+            .check_map_keys(is_string)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unsupported map key null in the map at the top level"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+//! Opt-in normalization of numeric `Value`s.
+
+use crate::value::Value;
+
+impl Value {
+    /// Convert every [`Value::Float64`] that holds an exact integer in the
+    /// range of an `i64` into a [`Value::Int64`], recursing into arrays, sets,
+    /// maps and objects. Other floats, like `3.5`, NaN or `1e20`, are left
+    /// as-is.
+    ///
+    /// Convex distinguishes `Int64` from `Float64` strictly, so values built
+    /// from numeric literals like `3.0` are sent as floats. This is an opt-in
+    /// pass for lenient numeric handling before sending values. Be careful:
+    /// it changes the type the function receives, so a field declared as
+    /// `v.float64()` will fail validation once its value happens to be
+    /// integral. `-0.0` becomes `Int64(0)`, and set elements or map keys that
+    /// become equal after normalization are merged.
+    ///
+    /// ```
+    /// # use convex::Value;
+    /// assert_eq!(Value::Float64(3.0).normalize_integral_floats(), Value::Int64(3));
+    /// assert_eq!(Value::Float64(3.5).normalize_integral_floats(), Value::Float64(3.5));
+    /// ```
+    pub fn normalize_integral_floats(self) -> Value {
+        match self {
+            Value::Float64(n) if is_integral_i64(n) => Value::Int64(n as i64),
+            Value::Array(values) => Value::Array(
+                values
+                    .into_iter()
+                    .map(Value::normalize_integral_floats)
+                    .collect(),
+            ),
+            Value::Set(values) => Value::Set(
+                values
+                    .into_iter()
+                    .map(Value::normalize_integral_floats)
+                    .collect(),
+            ),
+            Value::Map(entries) => Value::Map(
+                entries
+                    .into_iter()
+                    .map(|(k, v)| (k.normalize_integral_floats(), v.normalize_integral_floats()))
+                    .collect(),
+            ),
+            Value::Object(fields) => Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(k, v)| (k, v.normalize_integral_floats()))
+                    .collect(),
+            ),
+            value => value,
+        }
+    }
+}
+
+fn is_integral_i64(n: f64) -> bool {
+    // `i64::MAX as f64` rounds up to 2^63, which is out of range.
+    n.fract() == 0.0 && n >= i64::MIN as f64 && n < -(i64::MIN as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use maplit::btreemap;
+
+    use crate::Value;
+
+    #[test]
+    fn test_normalize_integral_floats() {
+        let value = Value::Object(btreemap! {
+            "count".into() => Value::Float64(3.0),
+            "ratio".into() => Value::Float64(3.5),
+            "ints".into() => Value::Array(vec![Value::Float64(-2.0), Value::Int64(7)]),
+            "huge".into() => Value::Float64(1e20),
+            "nan".into() => Value::Float64(f64::NAN),
+            "name".into() => "three".into(),
+        });
+        let normalized = value.normalize_integral_floats();
+        let Value::Object(fields) = normalized else {
+            panic!("Expected an object, got {normalized:?}");
+        };
+        assert_eq!(fields["count"], Value::Int64(3));
+        assert_eq!(fields["ratio"], Value::Float64(3.5));
+        assert_eq!(
+            fields["ints"],
+            Value::Array(vec![Value::Int64(-2), Value::Int64(7)])
+        );
+        assert_eq!(fields["huge"], Value::Float64(1e20));
+        assert!(matches!(fields["nan"], Value::Float64(n) if n.is_nan()));
+        assert_eq!(fields["name"], "three".into());
+
+        assert_eq!(
+            Value::Float64(i64::MIN as f64).normalize_integral_floats(),
+            Value::Int64(i64::MIN)
+        );
+        assert_eq!(
+            Value::Float64(9223372036854775808.0).normalize_integral_floats(),
+            Value::Float64(9223372036854775808.0)
+        );
+    }
+}
@@ -0,0 +1,246 @@
+//! Building and checking objects whose field names Convex will accept.
+
+use std::collections::BTreeMap;
+
+use anyhow::bail;
+
+use crate::value::Value;
+
+/// The longest field name Convex accepts, in bytes.
+const MAX_FIELD_NAME_LEN: usize = 1024;
+
+/// Builds a [`Value::Object`], checking each field name against Convex's
+/// rules as it's added, so that a bad name fails locally instead of in the
+/// deployment. Create one with [`Value::object_builder`].
+///
+/// A field name must:
+/// - be nonempty and at most 1024 bytes long,
+/// - not start with `$`, which Convex reserves for its encoding of values,
+/// - not start with `_`, which Convex reserves for system fields like `_id` and
+///   `_creationTime`,
+/// - only contain printable ASCII characters, i.e. no control characters or
+///   non-ASCII text. Spaces and punctuation are allowed, so names don't have to
+///   be identifiers.
+///
+/// System fields, e.g. to pass a document back to a mutation, can be added
+/// without the checks with [`field_unchecked`](Self::field_unchecked). To
+/// check the field names of a value built some other way, e.g. decoded from
+/// JSON, use [`Value::check_field_names`].
+///
+/// ```
+/// # use convex::Value;
+/// let message = Value::object_builder()
+///     .field("author", "Luke Skywalker")?
+///     .field("body", "Tatooine Sunrise.")?
+///     .build();
+/// assert!(Value::object_builder().field("$author", "Luke").is_err());
+/// # anyhow::Ok(())
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ObjectBuilder {
+    fields: BTreeMap<String, Value>,
+}
+
+impl ObjectBuilder {
+    /// Add the field `name`, replacing any earlier field with that name.
+    /// Fails if Convex doesn't allow `name` for a user field.
+    pub fn field(self, name: impl Into<String>, value: impl Into<Value>) -> anyhow::Result<Self> {
+        let name = name.into();
+        check_field_name(&name, false)?;
+        Ok(self.field_unchecked(name, value))
+    }
+
+    /// Add the field `name` without checking it, e.g. for a system field
+    /// like `_id`. The deployment still rejects names it doesn't accept.
+    pub fn field_unchecked(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.fields.insert(name.into(), value.into());
+        self
+    }
+
+    /// The object with the fields added so far.
+    pub fn build(self) -> Value {
+        Value::Object(self.fields)
+    }
+}
+
+impl Value {
+    /// Start building a [`Value::Object`] whose field names are checked as
+    /// they're added. See [`ObjectBuilder`] for the rules.
+    pub fn object_builder() -> ObjectBuilder {
+        ObjectBuilder::default()
+    }
+
+    /// Check the field names of every object in this value, including
+    /// objects nested in arrays, sets and maps, against the rules of
+    /// [`ObjectBuilder`]. Fails with the path to the first bad name, e.g. to
+    /// catch a `$`-prefixed field before it's encoded, where it could be
+    /// mistaken for one of the special forms like `{"$integer": ...}`.
+    ///
+    /// Names starting with `_` are reserved for system fields, so they're
+    /// rejected unless `allow_system_fields` is set, e.g. to pass a document
+    /// with its `_id` and `_creationTime` back to a mutation. Names starting
+    /// with `$` are always rejected.
+    ///
+    /// ```
+    /// # use convex::Value;
+    /// let message: Value = serde_json::json!({"author": "Luke", "$body": "Hi"}).try_into()?;
+    /// let err = message.check_field_names(false).unwrap_err();
+    /// assert_eq!(err.to_string(), "Field name $body cannot start with '$', at \"$body\"");
+    /// # anyhow::Ok(())
+    /// ```
+    pub fn check_field_names(&self, allow_system_fields: bool) -> anyhow::Result<()> {
+        check_field_names(self, allow_system_fields, "")
+    }
+}
+
+/// Check the names of the arguments `args` and of the fields of every object
+/// in their values, before sending them to a function. System fields are
+/// allowed, so that documents with their `_id` and `_creationTime` can be
+/// passed back to the deployment.
+pub(crate) fn check_arg_names(args: &BTreeMap<String, Value>) -> anyhow::Result<()> {
+    check_fields(args, true, "")
+}
+
+/// Check the field names of `value`, found at `path` for error messages.
+fn check_field_names(value: &Value, allow_system_fields: bool, path: &str) -> anyhow::Result<()> {
+    let element = |i: usize| format!("{path}[{i}]");
+    match value {
+        Value::Array(values) => {
+            for (i, value) in values.iter().enumerate() {
+                check_field_names(value, allow_system_fields, &element(i))?;
+            }
+        },
+        Value::Set(values) => {
+            for (i, value) in values.iter().enumerate() {
+                check_field_names(value, allow_system_fields, &element(i))?;
+            }
+        },
+        Value::Map(entries) => {
+            for (i, (key, value)) in entries.iter().enumerate() {
+                let entry = element(i);
+                check_field_names(key, allow_system_fields, &format!("{entry}.key"))?;
+                check_field_names(value, allow_system_fields, &format!("{entry}.value"))?;
+            }
+        },
+        Value::Object(fields) => check_fields(fields, allow_system_fields, path)?,
+        _ => {},
+    }
+    Ok(())
+}
+
+/// Check the names and values of the object `fields`, found at `path`.
+fn check_fields(
+    fields: &BTreeMap<String, Value>,
+    allow_system_fields: bool,
+    path: &str,
+) -> anyhow::Result<()> {
+    for (name, value) in fields {
+        let path = if path.is_empty() {
+            name.clone()
+        } else {
+            format!("{path}.{name}")
+        };
+        if let Err(e) = check_field_name(name, allow_system_fields) {
+            bail!("{e}, at {path:?}");
+        }
+        check_field_names(value, allow_system_fields, &path)?;
+    }
+    Ok(())
+}
+
+fn check_field_name(name: &str, allow_system_fields: bool) -> anyhow::Result<()> {
+    if name.is_empty() {
+        bail!("Field name cannot be empty");
+    }
+    if name.len() > MAX_FIELD_NAME_LEN {
+        bail!(
+            "Field name is too long ({} > maximum {MAX_FIELD_NAME_LEN})",
+            name.len()
+        );
+    }
+    if name.starts_with('$') {
+        bail!("Field name {name} cannot start with '$'");
+    }
+    if name.starts_with('_') && !allow_system_fields {
+        bail!("Field name {name} cannot start with '_', which is reserved for system fields");
+    }
+    if let Some(c) = name.chars().find(|c| !(' '..='~').contains(c)) {
+        bail!("Field name {name:?} has invalid character {c:?}: only printable ASCII is allowed");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use maplit::btreemap;
+    use serde_json::json;
+
+    use crate::Value;
+
+    #[test]
+    fn test_field_name_rules() -> anyhow::Result<()> {
+        let value = Value::object_builder()
+            .field_unchecked("_id", Value::id("jd7f2kd8d9cd5f8ga7ey1fa6h"))
+            .field("name", "Luke")?
+            .field("home planet", "Tatooine")?
+            .field("x".repeat(1024), 1i64)?
+            .build();
+        assert_eq!(
+            value,
+            Value::Object(btreemap! {
+                "_id".into() => Value::id("jd7f2kd8d9cd5f8ga7ey1fa6h"),
+                "name".into() => "Luke".into(),
+                "home planet".into() => "Tatooine".into(),
+                "x".repeat(1024) => Value::Int64(1),
+            })
+        );
+
+        for name in [
+            String::new(),
+            "x".repeat(1025),
+            "$type".into(),
+            "_id".into(),
+            "tab\there".into(),
+            "café".into(),
+        ] {
+            assert!(
+                Value::object_builder()
+                    .field(name.clone(), Value::Null)
+                    .is_err(),
+                "{name:?} should be rejected"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_field_names() -> anyhow::Result<()> {
+        let document: Value = json!({
+            "_id": {"$id": "jd7f2kd8d9cd5f8ga7ey1fa6h"},
+            "name": "Luke",
+            "ships": [{"model": "X-wing"}],
+        })
+        .try_into()?;
+        document.check_field_names(true)?;
+        let err = document.check_field_names(false).unwrap_err();
+        assert!(err.to_string().ends_with(r#"at "_id""#), "{err}");
+
+        let nested: Value = json!({"ships": [{"model": "X-wing"}, {"$foo": 1}]}).try_into()?;
+        for allow_system_fields in [false, true] {
+            let err = nested.check_field_names(allow_system_fields).unwrap_err();
+            assert!(err.to_string().ends_with(r#"at "ships[1].$foo""#), "{err}");
+        }
+
+        // A map's keys and values have their own paths.
+        let map = |key: Value, value: Value| Value::Map([(key, value)].into());
+        let bad: Value = json!({"$foo": 1}).try_into()?;
+        let good: Value = json!({"foo": 1}).try_into()?;
+        let err = map(bad.clone(), good.clone())
+            .check_field_names(false)
+            .unwrap_err();
+        assert!(err.to_string().ends_with(r#"at "[0].key.$foo""#), "{err}");
+        let err = map(good, bad).check_field_names(false).unwrap_err();
+        assert!(err.to_string().ends_with(r#"at "[0].value.$foo""#), "{err}");
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+//! Navigating nested `Value`s with dotted paths.
+
+use crate::value::Value;
+
+impl Value {
+    /// The value at `path` within this value, or `None` if there's nothing
+    /// there.
+    ///
+    /// `path` is a list of object field names separated by `.`, e.g.
+    /// `"user.address.city"`. A field name can be followed by one or more
+    /// array indexes, like `"items[0].name"` or `"matrix[1][2]"`, and the path
+    /// can start with an index to index into this value itself, like
+    /// `"[0].name"`. An empty path is this value. Maps and sets aren't
+    /// navigated.
+    ///
+    /// The path is split on every `.` and `[`, with no escaping, so a field
+    /// whose name contains one of them, like `"a.b"`, can't be reached this
+    /// way: `"a.b"` always means the field `b` of the field `a`. Convex
+    /// field names are identifiers, so this only comes up for
+    /// [`Value::Object`]s built by hand. For those, get the parent with
+    /// `get_path` and look the field up in its map directly.
+    ///
+    /// ```
+    /// # use convex::Value;
+    /// let json = serde_json::json!({
+    ///     "user": {"address": {"city": "Paris"}},
+    ///     "items": [{"name": "book"}],
+    /// });
+    /// let value = Value::try_from(json)?;
+    /// assert_eq!(value.get_path("user.address.city"), Some(&Value::from("Paris")));
+    /// assert_eq!(value.get_path("items[0].name"), Some(&Value::from("book")));
+    /// assert_eq!(value.get_path("items[1].name"), None);
+    /// # anyhow::Ok(())
+    /// ```
+    pub fn get_path(&self, path: &str) -> Option<&Value> {
+        if path.is_empty() {
+            return Some(self);
+        }
+        path.split('.').try_fold(self, |value, segment| {
+            let (field, mut indexes) = segment.split_at(segment.find('[').unwrap_or(segment.len()));
+            let mut value = match value {
+                _ if field.is_empty() && !indexes.is_empty() => value,
+                Value::Object(fields) => fields.get(field)?,
+                _ => return None,
+            };
+            while !indexes.is_empty() {
+                let (index, rest) = indexes.strip_prefix('[')?.split_once(']')?;
+                let Value::Array(values) = value else {
+                    return None;
+                };
+                value = values.get(index.parse::<usize>().ok()?)?;
+                indexes = rest;
+            }
+            Some(value)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use maplit::btreemap;
+    use serde_json::json;
+
+    use crate::Value;
+
+    #[test]
+    fn test_get_path() -> anyhow::Result<()> {
+        let value = Value::try_from(json!({
+            "user": {"name": "Ada", "tags": ["admin", "dev"]},
+            "matrix": [[1, 2], [3, 4]],
+            "count": 3,
+        }))?;
+        assert_eq!(value.get_path(""), Some(&value));
+        assert_eq!(value.get_path("user.name"), Some(&"Ada".into()));
+        assert_eq!(value.get_path("user.tags[1]"), Some(&"dev".into()));
+        assert_eq!(value.get_path("matrix[1][0]"), Some(&Value::Float64(3.0)));
+        assert_eq!(
+            value.get_path("matrix[0]"),
+            Some(&Value::Array(vec![
+                Value::Float64(1.0),
+                Value::Float64(2.0)
+            ]))
+        );
+        let array = Value::Array(vec![value.clone()]);
+        assert_eq!(array.get_path("[0].user.name"), Some(&"Ada".into()));
+
+        for missing in [
+            "user.email",
+            "user.name.first",
+            "count.value",
+            "user.tags[2]",
+            "user.tags[-1]",
+            "user.tags[x]",
+            "user.tags[0",
+            "user[0]",
+            "matrix[0][0][0]",
+            "user.",
+            ".user",
+        ] {
+            assert_eq!(value.get_path(missing), None, "{missing}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_path_dotted_keys() {
+        // Dots always separate fields, so a field with a dot in its name is
+        // only reachable through its parent's map.
+        let value = Value::Object(btreemap! {
+            "a.b".into() => Value::Int64(1),
+            "a".into() => Value::Object(btreemap! { "b".into() => Value::Int64(2) }),
+        });
+        assert_eq!(value.get_path("a.b"), Some(&Value::Int64(2)));
+        let Value::Object(fields) = &value else {
+            unreachable!()
+        };
+        assert_eq!(fields.get("a.b"), Some(&Value::Int64(1)));
+    }
+}
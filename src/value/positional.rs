@@ -0,0 +1,109 @@
+//! Destructuring fixed-length [`Value::Array`]s into Rust arrays and tuples.
+
+use anyhow::bail;
+
+use crate::value::Value;
+
+/// The elements of `value`, checking that it's an array of `len` elements.
+fn elements(value: Value, len: usize) -> anyhow::Result<Vec<Value>> {
+    let Value::Array(elements) = value else {
+        bail!("Expected an array of {len} elements, found {value:?}");
+    };
+    if elements.len() != len {
+        bail!(
+            "Expected an array of {len} elements, found {} elements",
+            elements.len()
+        );
+    }
+    Ok(elements)
+}
+
+/// Converts a [`Value::Array`] of exactly `N` elements, converting each with
+/// `T::try_from`, e.g. to `[Value; 3]` or `[f64; 2]`.
+///
+/// Fails if the value isn't an array, if its length isn't `N`, with an error
+/// giving both lengths, or if an element doesn't convert.
+///
+/// ```
+/// # use convex::Value;
+/// let value = Value::Array(vec![1.5.into(), 2.5.into()]);
+/// let [x, y]: [f64; 2] = value.clone().try_into()?;
+/// assert_eq!((x, y), (1.5, 2.5));
+///
+/// let err = <[f64; 3]>::try_from(value).unwrap_err();
+/// assert_eq!(err.to_string(), "Expected an array of 3 elements, found 2 elements");
+/// # anyhow::Ok(())
+/// ```
+impl<T, const N: usize> TryFrom<Value> for [T; N]
+where
+    T: TryFrom<Value>,
+    anyhow::Error: From<T::Error>,
+{
+    type Error = anyhow::Error;
+
+    fn try_from(value: Value) -> anyhow::Result<Self> {
+        let elements = elements(value, N)?
+            .into_iter()
+            .map(T::try_from)
+            .collect::<Result<Vec<T>, _>>()?;
+        match elements.try_into() {
+            Ok(array) => Ok(array),
+            Err(_) => unreachable!("The length was checked above"),
+        }
+    }
+}
+
+macro_rules! impl_try_from_value_for_tuple {
+    ($len:literal: $($name:ident),+) => {
+        /// Converts a [`Value::Array`] of the tuple's length, converting each
+        /// element to its type. Fails like the conversion to `[T; N]`.
+        impl<$($name),+> TryFrom<Value> for ($($name,)+)
+        where
+            $($name: TryFrom<Value>, anyhow::Error: From<$name::Error>,)+
+        {
+            type Error = anyhow::Error;
+
+            fn try_from(value: Value) -> anyhow::Result<Self> {
+                let mut elements = elements(value, $len)?.into_iter();
+                Ok(($($name::try_from(elements.next().expect("The length was checked above"))?,)+))
+            }
+        }
+    };
+}
+
+impl_try_from_value_for_tuple!(1: A);
+impl_try_from_value_for_tuple!(2: A, B);
+impl_try_from_value_for_tuple!(3: A, B, C);
+impl_try_from_value_for_tuple!(4: A, B, C, D);
+impl_try_from_value_for_tuple!(5: A, B, C, D, E);
+impl_try_from_value_for_tuple!(6: A, B, C, D, E, F);
+
+#[cfg(test)]
+mod tests {
+    use crate::Value;
+
+    #[test]
+    fn test_positional() -> anyhow::Result<()> {
+        let point = Value::Array(vec![52.52.into(), 13.405.into()]);
+        let (lat, lng): (f64, f64) = point.clone().try_into()?;
+        assert_eq!((lat, lng), (52.52, 13.405));
+
+        let row = Value::Array(vec!["Ada".into(), Value::Int64(36), true.into()]);
+        let (name, age, admin): (String, i64, bool) = row.clone().try_into()?;
+        assert_eq!((name.as_str(), age, admin), ("Ada", 36, true));
+        let values: [Value; 3] = row.clone().try_into()?;
+        assert_eq!(values[1], Value::Int64(36));
+
+        // The length must match exactly.
+        let err = <(f64, f64, f64)>::try_from(point.clone()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Expected an array of 3 elements, found 2 elements"
+        );
+        assert!(<[Value; 1]>::try_from(point).is_err());
+        // So must each element's type.
+        assert!(<(String, f64, bool)>::try_from(row).is_err());
+        assert!(<[Value; 0]>::try_from(Value::Null).is_err());
+        Ok(())
+    }
+}
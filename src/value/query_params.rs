@@ -0,0 +1,92 @@
+//! Encoding `Value`s as URL query parameters, e.g. for HTTP actions.
+
+use crate::value::Value;
+
+impl Value {
+    /// Encode a [`Value::Object`] of scalars as `key=value` query parameter
+    /// pairs, in key order.
+    ///
+    /// Each field must be one of:
+    /// - [`Value::String`] or [`Value::Id`], passed through as-is.
+    /// - [`Value::Boolean`], as `true` or `false`.
+    /// - [`Value::Int64`], in decimal, e.g. `-12`.
+    /// - [`Value::Float64`], formatted as the shortest decimal string that
+    ///   parses back to the same number, without an exponent or a trailing
+    ///   `.0`, e.g. `1.5` or `3`. NaN and infinities are rejected.
+    ///
+    /// Fails if `self` is not an object, or if any field is null, bytes, or a
+    /// nested array, set, map or object. The pairs are not percent-encoded;
+    /// pass them to something like [`url::Url::query_pairs_mut`] to build a
+    /// URL.
+    pub fn to_query_params(&self) -> anyhow::Result<Vec<(String, String)>> {
+        let Value::Object(fields) = self else {
+            anyhow::bail!("Query parameters must be an object, found {self:?}");
+        };
+        let mut params = Vec::with_capacity(fields.len());
+        for (key, value) in fields {
+            let param = match value {
+                Value::String(s) => s.clone(),
+                Value::Id(id) => id.to_string(),
+                Value::Boolean(b) => b.to_string(),
+                Value::Int64(n) => n.to_string(),
+                Value::Float64(n) => {
+                    anyhow::ensure!(
+                        n.is_finite(),
+                        "Query parameter {key} must be a finite number, found {n}"
+                    );
+                    n.to_string()
+                },
+                Value::Null
+                | Value::Bytes(_)
+                | Value::Array(_)
+                | Value::Set(_)
+                | Value::Map(_)
+                | Value::Object(_) => {
+                    anyhow::bail!("Query parameter {key} must be a scalar, found {value:?}")
+                },
+            };
+            params.push((key.clone(), param));
+        }
+        Ok(params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use maplit::btreemap;
+
+    use crate::Value;
+
+    #[test]
+    fn test_to_query_params() -> anyhow::Result<()> {
+        let value = Value::Object(btreemap! {
+            "channel".into() => "general".into(),
+            "limit".into() => Value::Int64(-12),
+            "score".into() => 1.5.into(),
+            "whole".into() => 3.0.into(),
+            "unread".into() => true.into(),
+        });
+        assert_eq!(
+            value.to_query_params()?,
+            vec![
+                ("channel".to_string(), "general".to_string()),
+                ("limit".to_string(), "-12".to_string()),
+                ("score".to_string(), "1.5".to_string()),
+                ("unread".to_string(), "true".to_string()),
+                ("whole".to_string(), "3".to_string()),
+            ]
+        );
+
+        assert!(Value::Null.to_query_params().is_err());
+        for field in [
+            Value::Null,
+            Value::Float64(f64::NAN),
+            Value::Array(vec![]),
+            Value::Object(btreemap! {}),
+        ] {
+            let value = Value::Object(btreemap! { "field".into() => field });
+            assert!(value.to_query_params().is_err());
+        }
+        Ok(())
+    }
+}
@@ -57,7 +57,7 @@ impl Ord for TotalOrdF64 {
 }
 impl PartialOrd for TotalOrdF64 {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.0.total_cmp(&other.0))
+        Some(self.cmp(other))
     }
 }
 impl PartialEq for TotalOrdF64 {
@@ -0,0 +1,223 @@
+//! A hash of `Value`s that's stable across crate versions, e.g. for
+//! persistent cache keys.
+
+use crate::value::Value;
+
+/// The BLAKE3 key derivation context of version 1 of the scheme. Changing the
+/// encoding requires a new version, and so a new context.
+const CONTEXT_V1: &str = "convex-rs Value::stable_hash v1";
+
+impl Value {
+    /// A 128-bit hash of this value that's stable across crate versions and
+    /// platforms, so it can be persisted, e.g. as a cache key.
+    ///
+    /// Unlike the [`Hash`](std::hash::Hash)-based hashers of the standard
+    /// library, whose output may change between releases, the output of this
+    /// function is a stability contract: a value hashes the same in every
+    /// release of this crate. Equal values have equal hashes.
+    ///
+    /// The scheme (version 1) hashes a canonical encoding of the value with
+    /// BLAKE3 in key derivation mode, with the context
+    /// `"convex-rs Value::stable_hash v1"`, and returns the first 16
+    /// bytes of the output as a little-endian `u128`. The encoding is a tag
+    /// byte for the type followed by its contents. Integers are little-endian,
+    /// and lengths are `u64`s:
+    ///
+    /// | Type      | Tag | Contents                                          |
+    /// |-----------|-----|---------------------------------------------------|
+    /// | `Null`    | 0   | none                                              |
+    /// | `Int64`   | 1   | the `i64`                                         |
+    /// | `Float64` | 2   | its IEEE 754 bits, as a `u64`                     |
+    /// | `Boolean` | 3   | 0 or 1, as a byte                                 |
+    /// | `String`  | 4   | the length of its UTF-8 encoding, then the bytes  |
+    /// | `Bytes`   | 5   | the length, then the bytes                        |
+    /// | `Array`   | 6   | the length, then each element                     |
+    /// | `Set`     | 7   | the length, then each element in order            |
+    /// | `Map`     | 8   | the length, then each key and its value in order  |
+    /// | `Object`  | 9   | the length, then each field name (as the contents |
+    /// |           |     | of a `String`) and its value in order             |
+    /// | `Id`      | 10  | the ID, as the contents of a `String`             |
+    ///
+    /// Floats are hashed by their bits, like they're compared: `-0.0` and
+    /// `0.0` have different hashes, and so do NaNs with different payloads.
+    /// An `Int64` and a `Float64` holding the same number differ too. Sets,
+    /// maps and objects are encoded in their iteration order, i.e. sorted by
+    /// the [`Ord`] of their elements or keys, which is part of the contract.
+    ///
+    /// ```
+    /// # use convex::Value;
+    /// let key = Value::from("general").stable_hash();
+    /// assert_eq!(key, Value::from("general").stable_hash());
+    /// assert_ne!(key, Value::from("random").stable_hash());
+    /// ```
+    pub fn stable_hash(&self) -> u128 {
+        let mut hasher = blake3::Hasher::new_derive_key(CONTEXT_V1);
+        encode(self, &mut hasher);
+        let mut output = [0; 16];
+        hasher.finalize_xof().fill(&mut output);
+        u128::from_le_bytes(output)
+    }
+}
+
+fn encode(value: &Value, hasher: &mut blake3::Hasher) {
+    match value {
+        Value::Null => {
+            hasher.update(&[0]);
+        },
+        Value::Int64(n) => {
+            hasher.update(&[1]);
+            hasher.update(&n.to_le_bytes());
+        },
+        Value::Float64(n) => {
+            hasher.update(&[2]);
+            hasher.update(&n.to_bits().to_le_bytes());
+        },
+        Value::Boolean(b) => {
+            hasher.update(&[3, u8::from(*b)]);
+        },
+        Value::String(s) => {
+            hasher.update(&[4]);
+            encode_bytes(s.as_bytes(), hasher);
+        },
+        Value::Bytes(bytes) => {
+            hasher.update(&[5]);
+            encode_bytes(bytes, hasher);
+        },
+        Value::Array(values) => {
+            hasher.update(&[6]);
+            encode_len(values.len(), hasher);
+            for value in values {
+                encode(value, hasher);
+            }
+        },
+        Value::Set(values) => {
+            hasher.update(&[7]);
+            encode_len(values.len(), hasher);
+            for value in values {
+                encode(value, hasher);
+            }
+        },
+        Value::Map(entries) => {
+            hasher.update(&[8]);
+            encode_len(entries.len(), hasher);
+            for (key, value) in entries {
+                encode(key, hasher);
+                encode(value, hasher);
+            }
+        },
+        Value::Object(fields) => {
+            hasher.update(&[9]);
+            encode_len(fields.len(), hasher);
+            for (name, value) in fields {
+                encode_bytes(name.as_bytes(), hasher);
+                encode(value, hasher);
+            }
+        },
+        Value::Id(id) => {
+            hasher.update(&[10]);
+            encode_bytes(id.as_bytes(), hasher);
+        },
+    }
+}
+
+fn encode_bytes(bytes: &[u8], hasher: &mut blake3::Hasher) {
+    encode_len(bytes.len(), hasher);
+    hasher.update(bytes);
+}
+
+fn encode_len(len: usize, hasher: &mut blake3::Hasher) {
+    hasher.update(&(len as u64).to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{
+        BTreeMap,
+        BTreeSet,
+    };
+
+    use maplit::btreemap;
+    use proptest::prelude::*;
+    use serde_json::Value as JsonValue;
+
+    use crate::Value;
+
+    /// Known hashes of version 1 of the scheme. These must never change: if
+    /// this test fails, the change breaks every hash persisted by users.
+    #[test]
+    fn test_stable_hash_known_values() {
+        let cases: Vec<(Value, u128)> = vec![
+            (Value::Null, 0x4fcec68a280bf5902fca68a6d60daf0f),
+            (Value::Int64(1), 0xe7bbf567b71553a9b952bd28ea7cc38f),
+            (Value::Float64(1.0), 0xe3412bfc865495a9de9129aba0a88c5a),
+            (Value::Float64(-0.0), 0xc8d2d7a3113d8ab98e95e4a24e08b5f1),
+            (Value::Boolean(true), 0x0a6b38927e0d0f78e4c561d68993d0f2),
+            (Value::String("".into()), 0x0becc9bbda106e8e7b53587d11feaab5),
+            (
+                Value::String("hello".into()),
+                0x552a71652c0f75c15af46f4f30a4d7ea,
+            ),
+            (
+                Value::Bytes(vec![0, 1, 2]),
+                0xb4fa3883cf95351b30a5a8a7fb5f51a3,
+            ),
+            (
+                Value::Array(vec![Value::Null, Value::Int64(1)]),
+                0x469a5517028460de811dc0ade002a02d,
+            ),
+            (
+                Value::Set(BTreeSet::from([Value::Int64(1), Value::Int64(2)])),
+                0x673acac81c2f6c8a8b4ed54bdb7e0234,
+            ),
+            (
+                Value::Map(BTreeMap::from([(Value::Int64(1), "one".into())])),
+                0x86e4722d497f703e5c2647aa866d615e,
+            ),
+            (
+                Value::Object(btreemap! {
+                    "channel".into() => "general".into(),
+                    "limit".into() => Value::Int64(10),
+                }),
+                0x0d38bd006fa3b1aa58a92ae1b6ee26ba,
+            ),
+            (
+                Value::id("k57c2kd8d9cd5f8ga7ey1fa6h16t3s7k"),
+                0xb26bceafb9ffeb771c4a6909faa66a2b,
+            ),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(value.stable_hash(), expected, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn test_stable_hash_distinguishes_encodings() {
+        let values = [
+            Value::Int64(1),
+            Value::Float64(1.0),
+            Value::String("1".into()),
+            Value::id("1"),
+            Value::Bytes(b"1".to_vec()),
+            Value::Array(vec!["ab".into()]),
+            Value::Array(vec!["a".into(), "b".into()]),
+            Value::Object(btreemap! { "a".into() => "b".into() }),
+            Value::Map(BTreeMap::from([("a".into(), "b".into())])),
+            Value::Float64(0.0),
+            Value::Float64(-0.0),
+        ];
+        let hashes: BTreeSet<u128> = values.iter().map(Value::stable_hash).collect();
+        assert_eq!(hashes.len(), values.len());
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig { failure_persistence: None, ..ProptestConfig::default() })]
+
+        #[test]
+        fn proptest_equal_values_hash_equal(value in any::<Value>()) {
+            // Rebuild the value from scratch through its JSON encoding.
+            let rebuilt = Value::try_from(JsonValue::from(value.clone())).unwrap();
+            prop_assert_eq!(&rebuilt, &value);
+            prop_assert_eq!(rebuilt.stable_hash(), value.stable_hash());
+        }
+    }
+}
@@ -0,0 +1,249 @@
+//! Typed representations of documents in Convex system tables, and typed
+//! access to the system fields every document has.
+
+use crate::value::{
+    DocumentId,
+    Value,
+};
+
+/// The field holding a document's ID.
+const ID_FIELD: &str = "_id";
+/// The field holding when a document was created.
+const CREATION_TIME_FIELD: &str = "_creationTime";
+
+impl Value {
+    /// The ID of a document, from its `_id` system field.
+    ///
+    /// Every Convex document has two system fields, `_id` and
+    /// `_creationTime`, which arrive in query results as ordinary object
+    /// fields next to the document's own. Field names starting with `_` are
+    /// reserved for them, see [`ObjectBuilder`](crate::ObjectBuilder).
+    ///
+    /// The ID is accepted both as a [`Value::Id`], i.e. encoded as
+    /// `{"$id": ...}`, and as a [`Value::String`], as the deployment sends
+    /// it, so it's returned by value. Returns `None` if this isn't an object,
+    /// or `_id` is missing or of another type.
+    ///
+    /// ```
+    /// # use convex::Value;
+    /// let message: Value = serde_json::json!({
+    ///     "_id": "k57c2kd8d9cd5f8ga7ey1fa6h16t3s7k",
+    ///     "_creationTime": 1709294400000.0,
+    ///     "body": "Hello",
+    /// })
+    /// .try_into()?;
+    /// assert_eq!(message.document_id().unwrap().as_str(), "k57c2kd8d9cd5f8ga7ey1fa6h16t3s7k");
+    /// assert_eq!(message.creation_time(), Some(1709294400000.0));
+    /// # anyhow::Ok(())
+    /// ```
+    pub fn document_id(&self) -> Option<DocumentId> {
+        let Value::Object(fields) = self else {
+            return None;
+        };
+        match fields.get(ID_FIELD)? {
+            Value::Id(id) => Some(id.clone()),
+            Value::String(id) => Some(DocumentId(id.clone())),
+            _ => None,
+        }
+    }
+
+    /// When a document was created, in milliseconds since the Unix epoch,
+    /// from its `_creationTime` system field. See
+    /// [`document_id`](Self::document_id) for the system fields.
+    ///
+    /// Returns `None` if this isn't an object, or `_creationTime` is missing
+    /// or isn't a [`Value::Float64`].
+    pub fn creation_time(&self) -> Option<f64> {
+        let Value::Object(fields) = self else {
+            return None;
+        };
+        match fields.get(CREATION_TIME_FIELD)? {
+            Value::Float64(creation_time) => Some(*creation_time),
+            _ => None,
+        }
+    }
+}
+
+/// The metadata of a file in [file storage](https://docs.convex.dev/file-storage),
+/// i.e. a document of the `_storage` system table, as returned by a query
+/// that calls `ctx.db.system.get(storageId)`.
+///
+/// Parse it from a query's value with `StorageMetadata::try_from`. Only the
+/// `_storage` table is covered so far. Documents of other system tables, like
+/// `_scheduled_functions`, stay plain [`Value::Object`]s.
+///
+/// Fields that aren't part of the `_storage` shape are ignored, so documents
+/// with fields added by newer deployments still parse. A value that isn't a
+/// storage document, e.g. because a field is missing or has the wrong type,
+/// fails to parse with an error naming the field, so that the query's
+/// result can be handled as a plain [`Value`] instead.
+///
+/// ```
+/// # use convex::{StorageMetadata, Value};
+/// let document = Value::Object(maplit::btreemap! {
+///     "_id".into() => Value::id("kg2fdmbaj9mc2gacp8n7ppz3jx6zsxzy"),
+///     "_creationTime".into() => Value::Float64(1709294400000.0),
+///     "sha256".into() => "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=".into(),
+///     "size".into() => Value::Float64(1024.0),
+///     "contentType".into() => "image/png".into(),
+/// });
+/// let metadata = StorageMetadata::try_from(document)?;
+/// assert_eq!(metadata.size, 1024);
+/// assert_eq!(metadata.content_type.as_deref(), Some("image/png"));
+/// # anyhow::Ok(())
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct StorageMetadata {
+    /// The ID of the file, i.e. its storage ID.
+    pub id: DocumentId,
+    /// When the file was stored, in milliseconds since the Unix epoch.
+    pub creation_time: f64,
+    /// The base64-encoded SHA-256 checksum of the file's contents.
+    pub sha256: String,
+    /// The size of the file in bytes.
+    pub size: u64,
+    /// The file's MIME type, if it was given when the file was stored.
+    pub content_type: Option<String>,
+}
+
+impl TryFrom<Value> for StorageMetadata {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Value) -> anyhow::Result<Self> {
+        let Value::Object(mut fields) = value else {
+            anyhow::bail!("Expected a _storage document, found {value:?}");
+        };
+        let id = match fields.remove(ID_FIELD) {
+            // Like in `Value::document_id`, the ID may be a plain string.
+            Some(Value::Id(id)) => id,
+            Some(Value::String(id)) => DocumentId(id),
+            id => anyhow::bail!("Expected an ID for _id, found {id:?}"),
+        };
+        let creation_time = match fields.remove(CREATION_TIME_FIELD) {
+            Some(Value::Float64(creation_time)) => creation_time,
+            creation_time => {
+                anyhow::bail!("Expected a number for _creationTime, found {creation_time:?}")
+            },
+        };
+        let sha256 = match fields.remove("sha256") {
+            Some(Value::String(sha256)) => sha256,
+            sha256 => anyhow::bail!("Expected a string for sha256, found {sha256:?}"),
+        };
+        // Sizes are stored as numbers, but accept an int64 too.
+        let size = match fields.remove("size") {
+            Some(Value::Float64(size)) if size >= 0.0 && size.fract() == 0.0 => size as u64,
+            Some(Value::Int64(size)) if size >= 0 => size as u64,
+            size => anyhow::bail!("Expected a size in bytes for size, found {size:?}"),
+        };
+        let content_type = match fields.remove("contentType") {
+            None | Some(Value::Null) => None,
+            Some(Value::String(content_type)) => Some(content_type),
+            content_type => {
+                anyhow::bail!("Expected a string for contentType, found {content_type:?}")
+            },
+        };
+        Ok(Self {
+            id,
+            creation_time,
+            sha256,
+            size,
+            content_type,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use maplit::btreemap;
+
+    use super::StorageMetadata;
+    use crate::{
+        DocumentId,
+        Value,
+    };
+
+    fn document() -> BTreeMap<String, Value> {
+        btreemap! {
+            "_id".into() => Value::id("kg2fdmbaj9mc2gacp8n7ppz3jx6zsxzy"),
+            "_creationTime".into() => Value::Float64(1709294400000.5),
+            "sha256".into() => "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=".into(),
+            "size".into() => Value::Float64(1024.0),
+        }
+    }
+
+    #[test]
+    fn test_storage_metadata() -> anyhow::Result<()> {
+        let expected = StorageMetadata {
+            id: "kg2fdmbaj9mc2gacp8n7ppz3jx6zsxzy".parse()?,
+            creation_time: 1709294400000.5,
+            sha256: "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=".into(),
+            size: 1024,
+            content_type: None,
+        };
+        assert_eq!(
+            StorageMetadata::try_from(Value::Object(document()))?,
+            expected
+        );
+
+        let mut fields = document();
+        fields.insert("contentType".into(), "text/plain".into());
+        fields.insert("size".into(), Value::Int64(1024));
+        fields.insert("addedLater".into(), Value::Boolean(true));
+        assert_eq!(
+            StorageMetadata::try_from(Value::Object(fields))?,
+            StorageMetadata {
+                content_type: Some("text/plain".into()),
+                ..expected.clone()
+            }
+        );
+
+        // The ID may also arrive as a plain string.
+        let mut fields = document();
+        fields.insert("_id".into(), "kg2fdmbaj9mc2gacp8n7ppz3jx6zsxzy".into());
+        assert_eq!(StorageMetadata::try_from(Value::Object(fields))?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_system_fields() -> anyhow::Result<()> {
+        let id = DocumentId::parse("kg2fdmbaj9mc2gacp8n7ppz3jx6zsxzy")?;
+        let value = Value::Object(document());
+        assert_eq!(value.document_id(), Some(id.clone()));
+        assert_eq!(value.creation_time(), Some(1709294400000.5));
+
+        // The ID may also arrive as a plain string.
+        let mut fields = document();
+        fields.insert("_id".into(), id.as_str().into());
+        assert_eq!(Value::Object(fields).document_id(), Some(id));
+
+        let mut fields = document();
+        fields.remove("_id");
+        fields.remove("_creationTime");
+        let value = Value::Object(fields);
+        assert_eq!((value.document_id(), value.creation_time()), (None, None));
+        assert_eq!(Value::Null.document_id(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_storage_metadata_unrecognized() {
+        assert!(StorageMetadata::try_from(Value::Null).is_err());
+        for (field, value) in [
+            ("_id", Value::Int64(1)),
+            ("sha256", Value::Null),
+            ("size", Value::Float64(1.5)),
+            ("size", Value::Int64(-1)),
+            ("contentType", Value::Int64(1)),
+        ] {
+            let mut fields = document();
+            fields.insert(field.into(), value);
+            let error = StorageMetadata::try_from(Value::Object(fields)).unwrap_err();
+            assert!(error.to_string().contains(field), "{error}");
+        }
+        let mut fields = document();
+        fields.remove("_creationTime");
+        assert!(StorageMetadata::try_from(Value::Object(fields)).is_err());
+    }
+}
@@ -0,0 +1,178 @@
+//! Compact, size-bounded rendering of `Value`s for logs.
+
+use std::fmt::Write;
+
+use crate::value::Value;
+
+impl Value {
+    /// Render this value as JSON-like text of roughly at most `max_len` bytes,
+    /// e.g. to log a query result without flooding the logs.
+    ///
+    /// Values that fit are rendered in full. Whatever doesn't fit is replaced
+    /// by a marker:
+    /// - A string is cut short and followed by its length in characters, like
+    ///   `"lorem ip"...(5000 chars)`. IDs are rendered like strings.
+    /// - The items of an array, set, map or object that don't fit are replaced
+    ///   by their count, like `[1, 2, ... 98 items]`, `{"a": 1, ... 3 fields}`,
+    ///   `set{... 10 items}` or `map{... 4 entries}`.
+    /// - Bytes are always rendered as their length, like `bytes(1024)`.
+    ///
+    /// The markers and closing brackets of truncated containers aren't
+    /// counted against `max_len`, so the output can go over it by a few
+    /// bytes per level of nesting.
+    ///
+    /// ```
+    /// # use convex::Value;
+    /// let value = Value::Array((0..100).map(Value::from).collect());
+    /// assert_eq!(value.to_truncated_string(10), "[0, 1, 2, ... 97 items]");
+    /// ```
+    pub fn to_truncated_string(&self, max_len: usize) -> String {
+        let mut out = String::new();
+        self.write_truncated(&mut out, max_len);
+        out
+    }
+
+    /// Append this value to `out`, truncating it to end at `limit` bytes.
+    fn write_truncated(&self, out: &mut String, limit: usize) {
+        match self {
+            Value::Id(id) => write_truncated_str(out, id, limit),
+            Value::Null => out.push_str("null"),
+            Value::Int64(n) => write!(out, "{n}").unwrap(),
+            Value::Float64(n) => write!(out, "{n:?}").unwrap(),
+            Value::Boolean(b) => write!(out, "{b}").unwrap(),
+            Value::String(s) => write_truncated_str(out, s, limit),
+            Value::Bytes(bytes) => write!(out, "bytes({})", bytes.len()).unwrap(),
+            Value::Array(values) => write_truncated_items(
+                out,
+                limit,
+                ("[", "]", "items"),
+                values.iter(),
+                |out, value, limit| value.write_truncated(out, limit),
+            ),
+            Value::Set(values) => write_truncated_items(
+                out,
+                limit,
+                ("set{", "}", "items"),
+                values.iter(),
+                |out, value, limit| value.write_truncated(out, limit),
+            ),
+            Value::Map(entries) => write_truncated_items(
+                out,
+                limit,
+                ("map{", "}", "entries"),
+                entries.iter(),
+                |out, (key, value), limit| {
+                    key.write_truncated(out, limit);
+                    out.push_str(": ");
+                    value.write_truncated(out, limit);
+                },
+            ),
+            Value::Object(fields) => write_truncated_items(
+                out,
+                limit,
+                ("{", "}", "fields"),
+                fields.iter(),
+                |out, (key, value), limit| {
+                    write!(out, "{key:?}: ").unwrap();
+                    value.write_truncated(out, limit);
+                },
+            ),
+        }
+    }
+}
+
+/// Append `s` quoted, cut short to end at `limit` bytes if it doesn't fit.
+fn write_truncated_str(out: &mut String, s: &str, limit: usize) {
+    let start = out.len();
+    write!(out, "{s:?}").unwrap();
+    if out.len() <= limit {
+        return;
+    }
+    out.truncate(start);
+    let num_chars = s.chars().count();
+    let marker = format!("...({num_chars} chars)");
+    let room = limit.saturating_sub(start + marker.len() + 2);
+    let mut len = 0;
+    let prefix: String = s
+        .chars()
+        .take_while(|c| {
+            len += c.escape_debug().len();
+            len <= room
+        })
+        .collect();
+    write!(out, "{prefix:?}{marker}").unwrap();
+}
+
+/// Append `items` between `open` and `close`, replacing the items that don't
+/// fit before `limit` with their count.
+fn write_truncated_items<T>(
+    out: &mut String,
+    limit: usize,
+    (open, close, unit): (&str, &str, &str),
+    items: impl ExactSizeIterator<Item = T>,
+    write_item: impl Fn(&mut String, T, usize),
+) {
+    out.push_str(open);
+    let num_items = items.len();
+    for (i, item) in items.enumerate() {
+        let start = out.len();
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write_item(out, item, limit);
+        if out.len() > limit {
+            out.truncate(start);
+            if i > 0 {
+                out.push_str(", ");
+            }
+            write!(out, "... {} {unit}", num_items - i).unwrap();
+            break;
+        }
+    }
+    out.push_str(close);
+}
+
+#[cfg(test)]
+mod tests {
+    use maplit::{
+        btreemap,
+        btreeset,
+    };
+
+    use crate::Value;
+
+    #[test]
+    fn test_to_truncated_string() {
+        let value = Value::Object(btreemap! {
+            "blob".into() => Value::Bytes(vec![0; 1024]),
+            "n".into() => Value::Float64(1.0),
+            "tags".into() => Value::Set(btreeset! { "a".into(), "b".into() }),
+        });
+        assert_eq!(
+            value.to_truncated_string(1000),
+            r#"{"blob": bytes(1024), "n": 1.0, "tags": set{"a", "b"}}"#
+        );
+        assert_eq!(
+            value.to_truncated_string(30),
+            r#"{"blob": bytes(1024), "n": 1.0, ... 1 fields}"#
+        );
+
+        let text = Value::from("a".repeat(5000));
+        assert_eq!(text.to_truncated_string(20), r#""aaa"...(5000 chars)"#);
+        assert_eq!(text.to_truncated_string(0), r#"""...(5000 chars)"#);
+
+        let nested = Value::Array(vec![
+            Value::from("short"),
+            Value::from("x".repeat(100)),
+            Value::Null,
+        ]);
+        assert_eq!(
+            nested.to_truncated_string(40),
+            r#"["short", "xxxxxxxxxxxxxx"...(100 chars), ... 1 items]"#
+        );
+
+        let map = Value::Map(btreemap! { Value::Int64(1) => Value::Boolean(true) });
+        assert_eq!(map.to_truncated_string(100), "map{1: true}");
+        assert_eq!(map.to_truncated_string(0), "map{... 1 entries}");
+    }
+}
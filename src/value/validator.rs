@@ -0,0 +1,250 @@
+//! Describing the shape of Convex values, e.g. to export it to other tools.
+
+use std::collections::BTreeMap;
+
+use serde_json::{
+    json,
+    Map as JsonMap,
+    Value as JsonValue,
+};
+
+use crate::value::Value;
+
+/// The shape of a Convex value, mirroring the validators of the `v` builder
+/// in the Convex JavaScript library, e.g. `v.object({ name: v.string() })`
+/// is `Validator::Object` with a single required `Validator::String` field.
+///
+/// Convex's deprecated `v.set` and `v.map` validators aren't supported.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Validator {
+    /// `v.id(table)`: a [`Value::Id`] of a document in `table`.
+    Id(String),
+    /// `v.null()`.
+    Null,
+    /// `v.int64()`.
+    Int64,
+    /// `v.float64()`, also known as `v.number()`.
+    Float64,
+    /// `v.boolean()`.
+    Boolean,
+    /// `v.string()`.
+    String,
+    /// `v.bytes()`.
+    Bytes,
+    /// `v.literal(value)`: exactly `value`, a string, number, int64 or
+    /// boolean.
+    Literal(Value),
+    /// `v.array(items)`.
+    Array(Box<Validator>),
+    /// `v.object(fields)`.
+    Object(BTreeMap<String, ObjectField>),
+    /// `v.record(keys, values)`: an object with arbitrary field names
+    /// matching `keys`, each holding a value matching `values`.
+    Record(Box<Validator>, Box<Validator>),
+    /// `v.union(...)`: a value matching any of the validators.
+    Union(Vec<Validator>),
+    /// `v.any()`.
+    Any,
+}
+
+/// A field of a [`Validator::Object`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjectField {
+    /// The shape of the field's value.
+    pub validator: Validator,
+    /// Whether the field may be missing, as with `v.optional(...)`.
+    pub optional: bool,
+}
+
+impl Validator {
+    /// A [JSON Schema](https://json-schema.org/) (draft 2020-12) document
+    /// describing the values this validator accepts, e.g. to generate API
+    /// docs or validate payloads with tools that don't know about Convex.
+    ///
+    /// The schema describes values as plain JSON, the way other tools
+    /// represent them, rather than the `$`-tagged encoding of `JsonValue`'s
+    /// `From<Value>`. Convex types are mapped as follows:
+    ///
+    /// | Validator                | JSON Schema                                              |
+    /// |--------------------------|----------------------------------------------------------|
+    /// | [`Validator::Id`]        | `string`, described as an ID in the table                |
+    /// | [`Validator::Null`]      | `null`                                                   |
+    /// | [`Validator::Int64`]     | `integer`, with the bounds of an `i64`                   |
+    /// | [`Validator::Float64`]   | `number`                                                 |
+    /// | [`Validator::Boolean`]   | `boolean`                                                |
+    /// | [`Validator::String`]    | `string`                                                 |
+    /// | [`Validator::Bytes`]     | `string` with `contentEncoding: base64`                  |
+    /// | [`Validator::Literal`]   | `const`                                                  |
+    /// | [`Validator::Array`]     | `array` with `items`                                     |
+    /// | [`Validator::Object`]    | closed `object` with `properties` and `required`         |
+    /// | [`Validator::Record`]    | `object` with `propertyNames` and `additionalProperties` |
+    /// | [`Validator::Union`]     | `anyOf`                                                  |
+    /// | [`Validator::Any`]       | `{}`, which accepts anything                             |
+    ///
+    /// JSON has no NaN or infinities, so the schema can't describe those
+    /// `Float64`s.
+    ///
+    /// ```
+    /// # use convex::{ObjectField, Validator};
+    /// let message = Validator::Object(maplit::btreemap! {
+    ///     "body".into() => ObjectField { validator: Validator::String, optional: false },
+    /// });
+    /// let schema = message.to_json_schema();
+    /// assert_eq!(schema["properties"]["body"], serde_json::json!({"type": "string"}));
+    /// assert_eq!(schema["required"], serde_json::json!(["body"]));
+    /// ```
+    pub fn to_json_schema(&self) -> JsonValue {
+        let mut schema = self.schema();
+        if let JsonValue::Object(fields) = &mut schema {
+            let mut document = JsonMap::new();
+            document.insert(
+                "$schema".to_string(),
+                "https://json-schema.org/draft/2020-12/schema".into(),
+            );
+            document.append(fields);
+            schema = JsonValue::Object(document);
+        }
+        schema
+    }
+
+    /// The schema for this validator, without the `$schema` keyword that
+    /// only belongs at the root.
+    fn schema(&self) -> JsonValue {
+        match self {
+            Validator::Id(table) => json!({
+                "type": "string",
+                "description": format!("ID of a document in the `{table}` table"),
+            }),
+            Validator::Null => json!({ "type": "null" }),
+            Validator::Int64 => json!({
+                "type": "integer",
+                "minimum": i64::MIN,
+                "maximum": i64::MAX,
+            }),
+            Validator::Float64 => json!({ "type": "number" }),
+            Validator::Boolean => json!({ "type": "boolean" }),
+            Validator::String => json!({ "type": "string" }),
+            Validator::Bytes => json!({ "type": "string", "contentEncoding": "base64" }),
+            Validator::Literal(value) => json!({ "const": plain_json(value) }),
+            Validator::Array(items) => json!({ "type": "array", "items": items.schema() }),
+            Validator::Object(fields) => {
+                let properties: JsonMap<String, JsonValue> = fields
+                    .iter()
+                    .map(|(name, field)| (name.clone(), field.validator.schema()))
+                    .collect();
+                let required: Vec<&String> = fields
+                    .iter()
+                    .filter(|(_, field)| !field.optional)
+                    .map(|(name, _)| name)
+                    .collect();
+                json!({
+                    "type": "object",
+                    "properties": properties,
+                    "required": required,
+                    "additionalProperties": false,
+                })
+            },
+            Validator::Record(keys, values) => json!({
+                "type": "object",
+                "propertyNames": keys.schema(),
+                "additionalProperties": values.schema(),
+            }),
+            Validator::Union(validators) => json!({
+                "anyOf": validators.iter().map(Validator::schema).collect::<Vec<_>>(),
+            }),
+            Validator::Any => json!({}),
+        }
+    }
+}
+
+/// `value` as plain JSON: numbers as JSON numbers, rather than the `$`-tagged
+/// encoding.
+fn plain_json(value: &Value) -> JsonValue {
+    match value {
+        Value::Int64(n) => json!(n),
+        Value::Float64(n) => json!(n),
+        value => JsonValue::from(value.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use maplit::btreemap;
+    use serde_json::json;
+
+    use super::{
+        ObjectField,
+        Validator,
+    };
+    use crate::Value;
+
+    fn required(validator: Validator) -> ObjectField {
+        ObjectField {
+            validator,
+            optional: false,
+        }
+    }
+
+    #[test]
+    fn test_nested_json_schema() {
+        let message = Validator::Object(btreemap! {
+            "author".into() => required(Validator::Id("users".into())),
+            "body".into() => required(Validator::String),
+            "attachment".into() => ObjectField {
+                validator: Validator::Bytes,
+                optional: true,
+            },
+            "likes".into() => required(Validator::Int64),
+            "kind".into() => required(Validator::Union(vec![
+                Validator::Literal("text".into()),
+                Validator::Literal(Value::Int64(2)),
+            ])),
+            "tags".into() => required(Validator::Array(Box::new(Validator::Record(
+                Box::new(Validator::String),
+                Box::new(Validator::Float64),
+            )))),
+        });
+        let schema = message.to_json_schema();
+
+        assert_eq!(
+            schema["$schema"],
+            "https://json-schema.org/draft/2020-12/schema"
+        );
+        assert_eq!(schema["type"], "object");
+        assert_eq!(schema["additionalProperties"], false);
+        assert_eq!(
+            schema["required"],
+            json!(["author", "body", "kind", "likes", "tags"])
+        );
+
+        let properties = &schema["properties"];
+        assert_eq!(properties["author"]["type"], "string");
+        assert_eq!(
+            properties["attachment"],
+            json!({"type": "string", "contentEncoding": "base64"})
+        );
+        assert_eq!(properties["likes"]["type"], "integer");
+        assert_eq!(
+            properties["kind"],
+            json!({"anyOf": [{"const": "text"}, {"const": 2}]})
+        );
+        assert_eq!(
+            properties["tags"],
+            json!({
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "propertyNames": {"type": "string"},
+                    "additionalProperties": {"type": "number"},
+                },
+            })
+        );
+        // Only the root declares the dialect.
+        assert!(properties["author"].get("$schema").is_none());
+
+        assert_eq!(
+            Validator::Any.to_json_schema(),
+            json!({"$schema": "https://json-schema.org/draft/2020-12/schema"})
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+
 use anyhow::bail;
 use serde::{
     Deserialize,
@@ -6,6 +8,7 @@ use serde::{
 };
 use serde_json::{
     json,
+    value::RawValue,
     Value as JsonValue,
 };
 
@@ -25,6 +28,7 @@ use crate::{
     StateModification,
     StateVersion,
     Timestamp,
+    UdfPath,
     UserIdentifier,
     UserIdentityAttributes,
 };
@@ -40,17 +44,73 @@ use crate::{
 ///   { foo: 1152921504606847000 }
 ///
 /// So instead we send it down as a string and unpack it ourselves.
-fn u64_to_string(x: u64) -> String {
+pub(crate) fn u64_to_string(x: u64) -> String {
     base64::encode(x.to_le_bytes())
 }
 
-fn string_to_u64(s: &str) -> anyhow::Result<u64> {
+pub(crate) fn string_to_u64(s: &str) -> anyhow::Result<u64> {
     let bytes: [u8; 8] = base64::decode(s)?
         .try_into()
         .map_err(|_| anyhow::anyhow!("u64 must be 8 bytes"))?;
     Ok(u64::from_le_bytes(bytes))
 }
 
+/// Convex 0.6.0 identified mutations and actions by `mutationId` and
+/// `actionId` instead of `requestId`. With the `legacy-0.6` feature, messages
+/// carry both fields so they can be read by either version.
+#[cfg_attr(not(feature = "legacy-0.6"), allow(unused_mut, unused_variables))]
+fn with_legacy_id(
+    legacy_key: &str,
+    request_id: SessionRequestSeqNumber,
+    mut message: JsonValue,
+) -> JsonValue {
+    #[cfg(feature = "legacy-0.6")]
+    if let JsonValue::Object(ref mut fields) = message {
+        fields.insert(legacy_key.to_string(), request_id.into());
+    }
+    message
+}
+
+fn require_request_id(
+    request_id: Option<SessionRequestSeqNumber>,
+) -> anyhow::Result<SessionRequestSeqNumber> {
+    request_id.ok_or_else(|| {
+        if cfg!(feature = "legacy-0.6") {
+            anyhow::anyhow!("Either requestId or its legacy mutationId/actionId must be set")
+        } else {
+            anyhow::anyhow!(
+                "Missing requestId. Enable the `legacy-0.6` feature to accept messages that only \
+                 set mutationId/actionId"
+            )
+        }
+    })
+}
+
+/// Decode the `udfPath` of a query, mutation or action, moving it into the
+/// component at `componentPath` if there is one.
+fn parse_udf_path(udf_path: &str, component_path: Option<String>) -> anyhow::Result<UdfPath> {
+    let udf_path: UdfPath = udf_path.parse()?;
+    match component_path {
+        Some(component_path) => udf_path.with_component(Some(component_path)),
+        None => Ok(udf_path),
+    }
+}
+
+/// Decode the `args` of a query, mutation or action. They're an array that
+/// holds a single object of named arguments by convention. A bare object is
+/// accepted too, as that single argument, since it's an easy mistake to make
+/// in a hand-written message.
+fn decode_args(args: JsonValue) -> anyhow::Result<Vec<JsonValue>> {
+    match args {
+        JsonValue::Array(args) => Ok(args),
+        JsonValue::Object(_) => Ok(vec![args]),
+        args => bail!(
+            "Invalid args {args}: args must be a JSON array of one element, the object of named \
+             arguments"
+        ),
+    }
+}
+
 /// A custom deserializer for optional fields.
 /// The outer `Option` represents the field being missing and the inner
 /// `Option` represents null.
@@ -69,6 +129,9 @@ struct QueryJson {
     udf_path: String,
     args: JsonValue,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    component_path: Option<String>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(deserialize_with = "double_option")]
     journal: Option<SerializedQueryJournal>,
@@ -90,10 +153,12 @@ impl TryFrom<QuerySetModification> for JsonValue {
     fn try_from(m: QuerySetModification) -> Result<Self, Self::Error> {
         let modification_json = match m {
             QuerySetModification::Add(q) => {
+                let (component_path, udf_path) = q.udf_path.split_component();
                 let query_json = QueryJson {
                     query_id: q.query_id,
-                    udf_path: String::from(q.udf_path),
+                    udf_path: String::from(udf_path),
                     args: JsonValue::from(q.args),
+                    component_path,
                     journal: q.journal,
                 };
                 QuerySetModificationJson::Add(query_json)
@@ -113,11 +178,11 @@ impl TryFrom<JsonValue> for QuerySetModification {
         let m: QuerySetModificationJson = serde_json::from_value(value)?;
         let result = match m {
             QuerySetModificationJson::Add(q) => {
-                let args: Vec<JsonValue> = serde_json::from_value(q.args)?;
+                let args = decode_args(q.args)?;
 
                 let query = Query {
                     query_id: q.query_id,
-                    udf_path: q.udf_path.parse()?,
+                    udf_path: parse_udf_path(&q.udf_path, q.component_path)?,
                     args,
                     journal: q.journal,
                 };
@@ -166,21 +231,23 @@ enum ClientMessageJson {
     },
     #[serde(rename_all = "camelCase")]
     Mutation {
-        // TODO(presley): Delete mutation_id and make request_id non optional
-        // when we deprecate convex 0.6.0
+        #[cfg(feature = "legacy-0.6")]
         mutation_id: Option<u32>,
         request_id: Option<u32>,
         udf_path: String,
         args: JsonValue,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        component_path: Option<String>,
     },
     #[serde(rename_all = "camelCase")]
     Action {
-        // TODO(presley): Delete action_id and make request_id non optional
-        // when we deprecate convex 0.6.0
+        #[cfg(feature = "legacy-0.6")]
         action_id: Option<u32>,
         request_id: Option<u32>,
         udf_path: String,
         args: JsonValue,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        component_path: Option<String>,
     },
     #[serde(rename_all = "camelCase")]
     Authenticate {
@@ -225,21 +292,35 @@ impl TryFrom<ClientMessage> for JsonValue {
                 request_id,
                 udf_path,
                 args,
-            } => ClientMessageJson::Mutation {
-                request_id: Some(request_id),
-                mutation_id: Some(request_id),
-                udf_path: String::from(udf_path),
-                args: JsonValue::Array(args.into_iter().map(JsonValue::from).collect::<Vec<_>>()),
+            } => {
+                let (component_path, udf_path) = udf_path.split_component();
+                ClientMessageJson::Mutation {
+                    request_id: Some(request_id),
+                    #[cfg(feature = "legacy-0.6")]
+                    mutation_id: Some(request_id),
+                    udf_path: String::from(udf_path),
+                    args: JsonValue::Array(
+                        args.into_iter().map(JsonValue::from).collect::<Vec<_>>(),
+                    ),
+                    component_path,
+                }
             },
             ClientMessage::Action {
                 request_id,
                 udf_path,
                 args,
-            } => ClientMessageJson::Action {
-                request_id: Some(request_id),
-                action_id: Some(request_id),
-                udf_path: String::from(udf_path),
-                args: JsonValue::Array(args.into_iter().map(JsonValue::from).collect::<Vec<_>>()),
+            } => {
+                let (component_path, udf_path) = udf_path.split_component();
+                ClientMessageJson::Action {
+                    request_id: Some(request_id),
+                    #[cfg(feature = "legacy-0.6")]
+                    action_id: Some(request_id),
+                    udf_path: String::from(udf_path),
+                    args: JsonValue::Array(
+                        args.into_iter().map(JsonValue::from).collect::<Vec<_>>(),
+                    ),
+                    component_path,
+                }
             },
             ClientMessage::Authenticate {
                 base_version,
@@ -303,43 +384,39 @@ impl TryFrom<JsonValue> for ClientMessage {
             },
             ClientMessageJson::Mutation {
                 request_id,
+                #[cfg(feature = "legacy-0.6")]
                 mutation_id,
                 udf_path,
                 args,
+                component_path,
             } => {
-                let json_args: Vec<JsonValue> = serde_json::from_value(args)?;
+                let json_args = decode_args(args)?;
 
-                let request_id = if let Some(request_id) = request_id {
-                    request_id
-                } else {
-                    mutation_id.ok_or_else(|| {
-                        anyhow::anyhow!("Either mutation_id or request_id must be set")
-                    })?
-                };
+                #[cfg(feature = "legacy-0.6")]
+                let request_id = request_id.or(mutation_id);
+                let request_id = require_request_id(request_id)?;
                 ClientMessage::Mutation {
                     request_id,
-                    udf_path: udf_path.parse()?,
+                    udf_path: parse_udf_path(&udf_path, component_path)?,
                     args: json_args,
                 }
             },
             ClientMessageJson::Action {
                 request_id,
+                #[cfg(feature = "legacy-0.6")]
                 action_id,
                 udf_path,
                 args,
+                component_path,
             } => {
-                let json_args: Vec<JsonValue> = serde_json::from_value(args)?;
+                let json_args = decode_args(args)?;
 
-                let request_id = if let Some(request_id) = request_id {
-                    request_id
-                } else {
-                    action_id.ok_or_else(|| {
-                        anyhow::anyhow!("Either mutation_id or request_id must be set")
-                    })?
-                };
+                #[cfg(feature = "legacy-0.6")]
+                let request_id = request_id.or(action_id);
+                let request_id = require_request_id(request_id)?;
                 ClientMessage::Action {
                     request_id,
-                    udf_path: udf_path.parse()?,
+                    udf_path: parse_udf_path(&udf_path, component_path)?,
                     args: json_args,
                 }
             },
@@ -377,18 +454,18 @@ impl From<StateVersion> for JsonValue {
     }
 }
 
-impl TryFrom<JsonValue> for StateVersion {
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StateVersionJson {
+    query_set: u32,
+    identity: u32,
+    ts: String,
+}
+
+impl TryFrom<StateVersionJson> for StateVersion {
     type Error = anyhow::Error;
 
-    fn try_from(value: JsonValue) -> Result<Self, Self::Error> {
-        #[derive(Deserialize)]
-        #[serde(rename_all = "camelCase")]
-        struct StateVersionJson {
-            query_set: u32,
-            identity: u32,
-            ts: String,
-        }
-        let s: StateVersionJson = serde_json::from_value(value)?;
+    fn try_from(s: StateVersionJson) -> Result<Self, Self::Error> {
         Ok(Self {
             query_set: s.query_set,
             identity: s.identity,
@@ -397,6 +474,15 @@ impl TryFrom<JsonValue> for StateVersion {
     }
 }
 
+impl TryFrom<JsonValue> for StateVersion {
+    type Error = anyhow::Error;
+
+    fn try_from(value: JsonValue) -> Result<Self, Self::Error> {
+        let s: StateVersionJson = serde_json::from_value(value)?;
+        s.try_into()
+    }
+}
+
 impl<V: Into<JsonValue>> From<StateModification<V>> for JsonValue {
     fn from(m: StateModification<V>) -> Self {
         match m {
@@ -435,56 +521,81 @@ impl<V: Into<JsonValue>> From<StateModification<V>> for JsonValue {
     }
 }
 
-impl<V: TryFrom<JsonValue, Error = anyhow::Error>> TryFrom<JsonValue> for StateModification<V> {
-    type Error = anyhow::Error;
+/// The fields of each `StateModification` variant, shared by the
+/// `JsonValue` and text decoders.
+#[allow(clippy::enum_variant_names)]
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum StateModificationJson {
+    QueryUpdated(QueryUpdatedJson),
+    QueryFailed(QueryFailedJson),
+    QueryRemoved(QueryRemovedJson),
+}
 
-    fn try_from(value: JsonValue) -> Result<Self, Self::Error> {
-        #[allow(clippy::enum_variant_names)]
-        #[derive(Deserialize)]
-        #[serde(tag = "type")]
-        pub enum StateModificationJson {
-            #[serde(rename_all = "camelCase")]
-            QueryUpdated {
-                query_id: QueryId,
-                value: JsonValue,
-                log_lines: Vec<String>,
-                journal: SerializedQueryJournal,
-            },
-            #[serde(rename_all = "camelCase")]
-            QueryFailed {
-                query_id: QueryId,
-                error_message: String,
-                log_lines: Vec<String>,
-                journal: SerializedQueryJournal,
-            },
-            #[serde(rename_all = "camelCase")]
-            QueryRemoved { query_id: QueryId },
-        }
-        let s: StateModificationJson = serde_json::from_value(value)?;
-        let result = match s {
-            StateModificationJson::QueryUpdated {
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryUpdatedJson {
+    query_id: QueryId,
+    value: JsonValue,
+    #[serde(default)]
+    log_lines: Vec<String>,
+    journal: SerializedQueryJournal,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryFailedJson {
+    query_id: QueryId,
+    error_message: String,
+    #[serde(default)]
+    log_lines: Vec<String>,
+    journal: SerializedQueryJournal,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueryRemovedJson {
+    query_id: QueryId,
+}
+
+impl StateModificationJson {
+    /// Decode the variant tagged `tag` from the JSON text `json`.
+    fn from_json_str(tag: &str, json: &str) -> anyhow::Result<Self> {
+        Ok(match tag {
+            "QueryUpdated" => Self::QueryUpdated(serde_json::from_str(json)?),
+            "QueryFailed" => Self::QueryFailed(serde_json::from_str(json)?),
+            "QueryRemoved" => Self::QueryRemoved(serde_json::from_str(json)?),
+            _ => bail!("Unknown StateModification type {tag:?}"),
+        })
+    }
+
+    fn decode<V: TryFrom<JsonValue, Error = anyhow::Error>>(
+        self,
+    ) -> anyhow::Result<StateModification<V>> {
+        let result = match self {
+            StateModificationJson::QueryUpdated(QueryUpdatedJson {
                 query_id,
                 value,
                 log_lines,
                 journal,
-            } => StateModification::QueryUpdated {
+            }) => StateModification::QueryUpdated {
                 query_id,
                 value: value.try_into()?,
                 log_lines,
                 journal,
             },
-            StateModificationJson::QueryFailed {
+            StateModificationJson::QueryFailed(QueryFailedJson {
                 query_id,
                 error_message,
                 log_lines,
                 journal,
-            } => StateModification::QueryFailed {
+            }) => StateModification::QueryFailed {
                 query_id,
                 error_message,
                 log_lines,
                 journal,
             },
-            StateModificationJson::QueryRemoved { query_id } => {
+            StateModificationJson::QueryRemoved(QueryRemovedJson { query_id }) => {
                 StateModification::QueryRemoved { query_id }
             },
         };
@@ -492,6 +603,24 @@ impl<V: TryFrom<JsonValue, Error = anyhow::Error>> TryFrom<JsonValue> for StateM
     }
 }
 
+impl<V: TryFrom<JsonValue, Error = anyhow::Error>> TryFrom<JsonValue> for StateModification<V> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: JsonValue) -> Result<Self, Self::Error> {
+        let s: StateModificationJson = serde_json::from_value(value)?;
+        s.decode()
+    }
+}
+
+impl<V: TryFrom<JsonValue, Error = anyhow::Error>> TryFrom<&RawValue> for StateModification<V> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &RawValue) -> Result<Self, Self::Error> {
+        let json = value.get();
+        StateModificationJson::from_json_str(&type_tag(json)?, json)?.decode()
+    }
+}
+
 impl From<QueryFailure> for JsonValue {
     fn from(q: QueryFailure) -> Self {
         json!({
@@ -511,6 +640,7 @@ impl TryFrom<JsonValue> for QueryFailure {
         struct QueryFailureJson {
             query_id: u32,
             message: String,
+            #[serde(default)]
             log_lines: Vec<String>,
         }
         let q: QueryFailureJson = serde_json::from_value(value)?;
@@ -546,61 +676,69 @@ impl<V: Into<JsonValue>> From<ServerMessage<V>> for JsonValue {
                 log_lines,
             } => {
                 let jv: JsonValue = value.into();
-                json!({
-                    "type": "MutationResponse",
-                    // TODO(presley): Delete when we deprecate convex 0.6.0.
-                    "mutationId": request_id,
-                    "requestId": request_id,
-                    "success": true,
-                    "result": jv,
-                    "ts": ts.map(|ts| u64_to_string(ts.into())),
-                    "logLines": log_lines,
-                })
+                with_legacy_id(
+                    "mutationId",
+                    request_id,
+                    json!({
+                        "type": "MutationResponse",
+                        "requestId": request_id,
+                        "success": true,
+                        "result": jv,
+                        "ts": ts.map(|ts| u64_to_string(ts.into())),
+                        "logLines": log_lines,
+                    }),
+                )
             },
             ServerMessage::MutationResponse {
                 request_id,
                 result: Err(s),
                 ts,
                 log_lines,
-            } => json!({
-                "type": "MutationResponse",
-                // TODO(presley): Delete when we deprecate convex 0.6.0.
-                "mutationId": request_id,
-                "requestId": request_id,
-                "success": false,
-                "result": s,
-                "ts": ts.map(|ts| u64_to_string(ts.into())),
-                "logLines": log_lines,
-            }),
+            } => with_legacy_id(
+                "mutationId",
+                request_id,
+                json!({
+                    "type": "MutationResponse",
+                    "requestId": request_id,
+                    "success": false,
+                    "result": s,
+                    "ts": ts.map(|ts| u64_to_string(ts.into())),
+                    "logLines": log_lines,
+                }),
+            ),
             ServerMessage::ActionResponse {
                 request_id,
                 result: Ok(value),
                 log_lines,
             } => {
                 let jv: JsonValue = value.into();
-                json!({
-                    "type": "ActionResponse",
-                    // TODO(presley): Delete when we deprecate convex 0.6.0.
-                    "actionId": request_id,
-                    "requestId": request_id,
-                    "success": true,
-                    "result": jv,
-                    "logLines": log_lines,
-                })
+                with_legacy_id(
+                    "actionId",
+                    request_id,
+                    json!({
+                        "type": "ActionResponse",
+                        "requestId": request_id,
+                        "success": true,
+                        "result": jv,
+                        "logLines": log_lines,
+                    }),
+                )
             },
             ServerMessage::ActionResponse {
                 request_id,
                 result: Err(s),
                 log_lines,
-            } => json!({
-                "type": "ActionResponse",
-                // TODO(presley): Delete when we deprecate convex 0.6.0.
-                "actionId": request_id,
-                "requestId": request_id,
-                "success": false,
-                "result": s,
-                "logLines": log_lines,
-            }),
+            } => with_legacy_id(
+                "actionId",
+                request_id,
+                json!({
+                    "type": "ActionResponse",
+                    "requestId": request_id,
+                    "success": false,
+                    "result": s,
+                    "logLines": log_lines,
+                }),
+            ),
             ServerMessage::AuthError {
                 error_message,
                 base_version,
@@ -620,93 +758,136 @@ impl<V: Into<JsonValue>> From<ServerMessage<V>> for JsonValue {
     }
 }
 
-impl<V: TryFrom<JsonValue, Error = anyhow::Error>> TryFrom<JsonValue> for ServerMessage<V> {
-    type Error = anyhow::Error;
+/// The fields of each `ServerMessage` variant, shared by the `JsonValue` and
+/// text decoders. `M` is the undecoded form of each state modification.
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum ServerMessageJson<M> {
+    Transition(TransitionJson<M>),
+    QueriesFailed(QueriesFailedJson),
+    MutationResponse(MutationResponseJson),
+    ActionResponse(ActionResponseJson),
+    FatalError(FatalErrorJson),
+    AuthError(AuthErrorJson),
+    Ping(PingJson),
+}
 
-    fn try_from(value: JsonValue) -> Result<Self, Self::Error> {
-        #[derive(Deserialize)]
-        #[serde(tag = "type")]
-        pub enum ServerMessageJson {
-            #[serde(rename_all = "camelCase")]
-            Transition {
-                start_version: JsonValue,
-                end_version: JsonValue,
-                modifications: Vec<JsonValue>,
-            },
-            #[serde(rename_all = "camelCase")]
-            QueriesFailed { failures: Vec<JsonValue> },
-            #[serde(rename_all = "camelCase")]
-            MutationResponse {
-                // TODO(presley): Delete mutation_id and make request_id non optional
-                // when we deprecate old 0.6.0
-                request_id: Option<SessionRequestSeqNumber>,
-                mutation_id: Option<SessionRequestSeqNumber>,
-                success: bool,
-                result: JsonValue,
-                ts: Option<String>,
-                log_lines: LogLines,
-            },
-            #[serde(rename_all = "camelCase")]
-            ActionResponse {
-                // TODO(presley): Delete mutation_id and make request_id non optional
-                // when we deprecate old 0.6.0
-                request_id: Option<SessionRequestSeqNumber>,
-                action_id: Option<SessionRequestSeqNumber>,
-                success: bool,
-                result: JsonValue,
-                log_lines: LogLines,
-            },
-            #[serde(rename_all = "camelCase")]
-            FatalError { error: String },
-            #[serde(rename_all = "camelCase")]
-            AuthError {
-                error: String,
-                base_version: Option<IdentityVersion>,
-            },
-            #[serde(rename_all = "camelCase")]
-            Ping {},
-        }
-        let s: ServerMessageJson = serde_json::from_value(value)?;
-        let result = match s {
-            ServerMessageJson::Transition {
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TransitionJson<M> {
+    start_version: StateVersionJson,
+    end_version: StateVersionJson,
+    modifications: Vec<M>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueriesFailedJson {
+    failures: Vec<JsonValue>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MutationResponseJson {
+    request_id: Option<SessionRequestSeqNumber>,
+    #[cfg(feature = "legacy-0.6")]
+    mutation_id: Option<SessionRequestSeqNumber>,
+    success: bool,
+    result: JsonValue,
+    ts: Option<String>,
+    #[serde(default)]
+    log_lines: LogLines,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ActionResponseJson {
+    request_id: Option<SessionRequestSeqNumber>,
+    #[cfg(feature = "legacy-0.6")]
+    action_id: Option<SessionRequestSeqNumber>,
+    success: bool,
+    result: JsonValue,
+    #[serde(default)]
+    log_lines: LogLines,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FatalErrorJson {
+    error: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AuthErrorJson {
+    error: String,
+    base_version: Option<IdentityVersion>,
+}
+
+#[derive(Deserialize)]
+struct PingJson {}
+
+impl<'a> ServerMessageJson<&'a RawValue> {
+    /// Decode the variant tagged `tag` from the JSON text `json`, leaving the
+    /// state modifications undecoded.
+    fn from_json_str(tag: &str, json: &'a str) -> anyhow::Result<Self> {
+        Ok(match tag {
+            "Transition" => Self::Transition(serde_json::from_str(json)?),
+            "QueriesFailed" => Self::QueriesFailed(serde_json::from_str(json)?),
+            "MutationResponse" => Self::MutationResponse(serde_json::from_str(json)?),
+            "ActionResponse" => Self::ActionResponse(serde_json::from_str(json)?),
+            "FatalError" => Self::FatalError(serde_json::from_str(json)?),
+            "AuthError" => Self::AuthError(serde_json::from_str(json)?),
+            "Ping" => Self::Ping(serde_json::from_str(json)?),
+            _ => bail!("Unknown ServerMessage type {tag:?}"),
+        })
+    }
+}
+
+impl<M> ServerMessageJson<M> {
+    fn decode<V: TryFrom<JsonValue, Error = anyhow::Error>>(
+        self,
+        decode_modification: impl Fn(M) -> anyhow::Result<StateModification<V>>,
+    ) -> anyhow::Result<ServerMessage<V>> {
+        let result = match self {
+            ServerMessageJson::Transition(TransitionJson {
                 start_version,
                 end_version,
                 modifications,
-            } => ServerMessage::Transition {
+            }) => ServerMessage::Transition {
                 start_version: start_version.try_into()?,
                 end_version: end_version.try_into()?,
                 modifications: modifications
                     .into_iter()
-                    .map(|sm: JsonValue| sm.try_into())
+                    .map(decode_modification)
                     .collect::<anyhow::Result<Vec<StateModification<V>>>>()?,
             },
-            ServerMessageJson::QueriesFailed { failures } => ServerMessage::QueriesFailed {
-                failures: failures
-                    .into_iter()
-                    .map(QueryFailure::try_from)
-                    .collect::<anyhow::Result<Vec<_>>>()?,
+            ServerMessageJson::QueriesFailed(QueriesFailedJson { failures }) => {
+                ServerMessage::QueriesFailed {
+                    failures: failures
+                        .into_iter()
+                        .map(QueryFailure::try_from)
+                        .collect::<anyhow::Result<Vec<_>>>()?,
+                }
             },
-            ServerMessageJson::MutationResponse {
+            ServerMessageJson::MutationResponse(MutationResponseJson {
                 request_id,
+                #[cfg(feature = "legacy-0.6")]
                 mutation_id,
                 success,
                 result,
                 ts,
                 log_lines,
-            } => {
+            }) => {
                 let result = if success {
                     Ok(result.try_into()?)
                 } else {
                     let msg: String = serde_json::from_value(result)?;
                     Err(msg)
                 };
-                let request_id = if let Some(request_id) = request_id {
-                    request_id
-                } else {
-                    mutation_id.ok_or_else(|| {
-                        anyhow::anyhow!("Either mutation_id or request_id must be set")
-                    })?
-                };
+                #[cfg(feature = "legacy-0.6")]
+                let request_id = request_id.or(mutation_id);
+                let request_id = require_request_id(request_id)?;
                 ServerMessage::MutationResponse {
                     request_id,
                     result,
@@ -718,48 +899,92 @@ impl<V: TryFrom<JsonValue, Error = anyhow::Error>> TryFrom<JsonValue> for Server
                     log_lines,
                 }
             },
-            ServerMessageJson::ActionResponse {
+            ServerMessageJson::ActionResponse(ActionResponseJson {
                 request_id,
+                #[cfg(feature = "legacy-0.6")]
                 action_id,
                 success,
                 result,
                 log_lines,
-            } => {
+            }) => {
                 let result = if success {
                     Ok(result.try_into()?)
                 } else {
                     let msg: String = serde_json::from_value(result)?;
                     Err(msg)
                 };
-                let request_id = if let Some(request_id) = request_id {
-                    request_id
-                } else {
-                    action_id.ok_or_else(|| {
-                        anyhow::anyhow!("Either mutation_id or request_id must be set")
-                    })?
-                };
+                #[cfg(feature = "legacy-0.6")]
+                let request_id = request_id.or(action_id);
+                let request_id = require_request_id(request_id)?;
                 ServerMessage::ActionResponse {
                     request_id,
                     result,
                     log_lines,
                 }
             },
-            ServerMessageJson::FatalError { error } => ServerMessage::FatalError {
+            ServerMessageJson::FatalError(FatalErrorJson { error }) => ServerMessage::FatalError {
                 error_message: error,
             },
-            ServerMessageJson::AuthError {
+            ServerMessageJson::AuthError(AuthErrorJson {
                 error,
                 base_version,
-            } => ServerMessage::AuthError {
+            }) => ServerMessage::AuthError {
                 error_message: error,
                 base_version,
             },
-            ServerMessageJson::Ping {} => ServerMessage::Ping {},
+            ServerMessageJson::Ping(PingJson {}) => ServerMessage::Ping {},
         };
         Ok(result)
     }
 }
 
+impl<V: TryFrom<JsonValue, Error = anyhow::Error>> TryFrom<JsonValue> for ServerMessage<V> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: JsonValue) -> Result<Self, Self::Error> {
+        let s: ServerMessageJson<JsonValue> = serde_json::from_value(value)?;
+        s.decode(StateModification::try_from)
+    }
+}
+
+/// Decode a message straight from its JSON text. This is faster than parsing
+/// a `JsonValue` first: only the values of query, mutation and action results
+/// are built as `JsonValue`s, instead of the whole message.
+impl<V: TryFrom<JsonValue, Error = anyhow::Error>> TryFrom<&RawValue> for ServerMessage<V> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &RawValue) -> Result<Self, Self::Error> {
+        let json = value.get();
+        ServerMessageJson::from_json_str(&type_tag(json)?, json)?
+            .decode(|modification: &RawValue| StateModification::try_from(modification))
+    }
+}
+
+impl<V: TryFrom<JsonValue, Error = anyhow::Error>> ServerMessage<V> {
+    /// Decode a message from the bytes of a received frame, borrowing them.
+    /// Like decoding from a `&RawValue`, this only builds the values of
+    /// results as `JsonValue`s, instead of the whole message.
+    pub fn from_json_slice(bytes: &[u8]) -> anyhow::Result<Self> {
+        let json: &RawValue = serde_json::from_slice(bytes)?;
+        Self::try_from(json)
+    }
+}
+
+/// Read the `type` tag of the JSON object `json`, skipping its other fields.
+///
+/// serde's internally tagged enums buffer the whole object before they look
+/// at the tag, so the text decoders read the tag first instead, and then
+/// decode the tagged variant's fields directly.
+fn type_tag(json: &str) -> anyhow::Result<Cow<'_, str>> {
+    #[derive(Deserialize)]
+    struct Tagged<'a> {
+        #[serde(rename = "type", borrow)]
+        tag: Cow<'a, str>,
+    }
+    let tagged: Tagged = serde_json::from_str(json)?;
+    Ok(tagged.tag)
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct UserIdentityAttributesJson {
@@ -883,6 +1108,7 @@ mod tests {
     use serde_json::{
         self,
         json,
+        value::RawValue,
         Value as JsonValue,
     };
 
@@ -893,7 +1119,13 @@ mod tests {
     use crate::{
         testing::assert_roundtrips,
         ClientMessage,
+        Query,
+        QueryId,
+        QuerySetModification,
         ServerMessage,
+        StateModification,
+        StateVersion,
+        UdfPath,
         UserIdentifier,
         UserIdentityAttributes,
     };
@@ -938,6 +1170,23 @@ mod tests {
             assert_roundtrips::<ServerMessage<TestValue>, JsonValue>(m);
         }
 
+        #[test]
+        fn proptest_server_message_decodes_from_raw_value(m in any::<ServerMessage<TestValue>>()) {
+            let json = JsonValue::from(m.clone()).to_string();
+            let raw: &RawValue = serde_json::from_str(&json).unwrap();
+            assert_eq!(ServerMessage::<TestValue>::try_from(raw).unwrap(), m);
+        }
+
+        #[test]
+        fn proptest_server_message_decodes_from_slice(m in any::<ServerMessage<TestValue>>()) {
+            let json = JsonValue::from(m.clone()).to_string();
+            let two_step: JsonValue = serde_json::from_str(&json).unwrap();
+            assert_eq!(
+                ServerMessage::<TestValue>::from_json_slice(json.as_bytes()).unwrap(),
+                ServerMessage::<TestValue>::try_from(two_step).unwrap(),
+            );
+        }
+
         #[test]
         fn proptest_user_identity_attributes_roundtrips(m in any::<UserIdentityAttributes>()) {
             assert_roundtrips::<UserIdentityAttributes, JsonValue>(m);
@@ -952,6 +1201,237 @@ mod tests {
         assert_roundtrips::<JsonValue, ClientMessage>(old_user_auth_message);
     }
 
+    #[cfg(feature = "legacy-0.6")]
+    #[test]
+    fn legacy_request_ids() {
+        let mutation = json!({"type": "Mutation", "mutationId": 3, "udfPath": "f", "args": []});
+        let ClientMessage::Mutation { request_id, .. } = mutation.try_into().unwrap() else {
+            panic!("Expected Mutation");
+        };
+        assert_eq!(request_id, 3);
+
+        let action_response = json!({
+            "type": "ActionResponse",
+            "actionId": 4,
+            "success": false,
+            "result": "oops",
+        });
+        let ServerMessage::<TestValue>::ActionResponse { request_id, .. } =
+            action_response.try_into().unwrap()
+        else {
+            panic!("Expected ActionResponse");
+        };
+        assert_eq!(request_id, 4);
+
+        let encoded = JsonValue::try_from(ClientMessage::Mutation {
+            request_id: 5,
+            udf_path: "f".parse().unwrap(),
+            args: vec![],
+        })
+        .unwrap();
+        assert_eq!(encoded["mutationId"], json!(5));
+        assert_eq!(encoded["requestId"], json!(5));
+    }
+
+    #[test]
+    fn malformed_args() {
+        let mutation = |args: JsonValue| {
+            ClientMessage::try_from(json!({
+                "type": "Mutation",
+                "requestId": 3,
+                "udfPath": "f",
+                "args": args,
+            }))
+        };
+        // A bare object is the single argument.
+        let ClientMessage::Mutation { args, .. } = mutation(json!({"a": 1})).unwrap() else {
+            panic!("Expected Mutation");
+        };
+        assert_eq!(args, vec![json!({"a": 1})]);
+
+        for args in [json!("a"), json!(1), json!(null)] {
+            let err = mutation(args).unwrap_err();
+            assert!(
+                err.to_string()
+                    .contains("args must be a JSON array of one element"),
+                "{err}"
+            );
+        }
+
+        let add = json!({
+            "type": "ModifyQuerySet",
+            "baseVersion": 0,
+            "newVersion": 1,
+            "modifications": [{"type": "Add", "queryId": 0, "udfPath": "f", "args": {}}],
+        });
+        let ClientMessage::ModifyQuerySet { modifications, .. } = add.try_into().unwrap() else {
+            panic!("Expected ModifyQuerySet");
+        };
+        let [QuerySetModification::Add(query)] = &modifications[..] else {
+            panic!("Expected a single Add");
+        };
+        assert_eq!(query.args, vec![json!({})]);
+    }
+
+    #[test]
+    fn component_paths() {
+        let udf_path: UdfPath = "waitlist/ratelimiter:limits:check".parse().unwrap();
+        let action = ClientMessage::Action {
+            request_id: 1,
+            udf_path: udf_path.clone(),
+            args: vec![json!({})],
+        };
+        let encoded = JsonValue::try_from(action.clone()).unwrap();
+        assert_eq!(encoded["udfPath"], json!("limits:check"));
+        assert_eq!(encoded["componentPath"], json!("waitlist/ratelimiter"));
+        assert_eq!(ClientMessage::try_from(encoded).unwrap(), action);
+
+        let add = ClientMessage::ModifyQuerySet {
+            base_version: 0,
+            new_version: 1,
+            modifications: vec![QuerySetModification::Add(Query {
+                query_id: QueryId::new(0),
+                udf_path,
+                args: vec![json!({})],
+                journal: None,
+            })],
+        };
+        let encoded = JsonValue::try_from(add.clone()).unwrap();
+        let query = &encoded["modifications"][0];
+        assert_eq!(query["udfPath"], json!("limits:check"));
+        assert_eq!(query["componentPath"], json!("waitlist/ratelimiter"));
+        assert_eq!(ClientMessage::try_from(encoded).unwrap(), add);
+
+        // Functions of the app itself don't send a component path.
+        let encoded = JsonValue::try_from(ClientMessage::Mutation {
+            request_id: 2,
+            udf_path: "waitlist/queue:add".parse().unwrap(),
+            args: vec![],
+        })
+        .unwrap();
+        assert_eq!(encoded["udfPath"], json!("waitlist/queue:add"));
+        assert_eq!(encoded.get("componentPath"), None);
+    }
+
+    #[cfg(not(feature = "legacy-0.6"))]
+    #[test]
+    fn missing_request_id_rejected() {
+        let mutation = json!({"type": "Mutation", "mutationId": 3, "udfPath": "f", "args": []});
+        let err = ClientMessage::try_from(mutation).unwrap_err();
+        assert!(err.to_string().contains("Missing requestId"), "{err}");
+
+        let mutation_response = json!({
+            "type": "MutationResponse",
+            "mutationId": 3,
+            "success": false,
+            "result": "oops",
+            "ts": null,
+        });
+        assert!(ServerMessage::<TestValue>::try_from(mutation_response).is_err());
+
+        let encoded = JsonValue::try_from(ClientMessage::Action {
+            request_id: 5,
+            udf_path: "f".parse().unwrap(),
+            args: vec![],
+        })
+        .unwrap();
+        assert_eq!(encoded.get("actionId"), None);
+        assert_eq!(encoded["requestId"], json!(5));
+    }
+
+    #[test]
+    fn server_message_from_raw_value() {
+        let decode = |json: &str| {
+            let raw: &RawValue = serde_json::from_str(json)?;
+            ServerMessage::<TestValue>::try_from(raw)
+        };
+        // The tag may come after the fields, and may be escaped.
+        let ping = decode(r#"{"extra": [1, {"type": "Transition"}], "type": "P\u0069ng"}"#);
+        assert_eq!(ping.unwrap(), ServerMessage::Ping);
+        let removed = decode(
+            r#"{"type": "Transition", "startVersion": {"querySet": 0, "identity": 0, "ts": "AAAAAAAAAAA="},
+                "endVersion": {"querySet": 1, "identity": 0, "ts": "AQAAAAAAAAA="},
+                "modifications": [{"queryId": 3, "type": "QueryRemoved"}]}"#,
+        );
+        let ServerMessage::Transition { modifications, .. } = removed.unwrap() else {
+            panic!("Expected Transition");
+        };
+        assert_eq!(
+            modifications,
+            vec![StateModification::QueryRemoved {
+                query_id: QueryId::new(3)
+            }]
+        );
+        for json in [
+            r#"{"type": "Unknown"}"#,
+            r#"{"type": 1}"#,
+            r#"{"requestId": 0}"#,
+            r#"[]"#,
+            r#"{"type": "FatalError"}"#,
+            r#"{"type": "Transition", "startVersion": {"querySet": 0, "identity": 0, "ts": "AAAAAAAAAAA="},
+                "endVersion": {"querySet": 0, "identity": 0, "ts": "AAAAAAAAAAA="},
+                "modifications": [{"type": "QueryMoved", "queryId": 0}]}"#,
+        ] {
+            assert!(decode(json).is_err(), "{json}");
+            let json: JsonValue = serde_json::from_str(json).unwrap();
+            assert!(ServerMessage::<TestValue>::try_from(json).is_err());
+        }
+    }
+
+    #[test]
+    fn server_message_missing_log_lines() {
+        let mutation_response = json!({
+            "type": "MutationResponse",
+            "requestId": 0,
+            "success": false,
+            "result": "oops",
+            "ts": null,
+        });
+        let ServerMessage::<TestValue>::MutationResponse { log_lines, .. } =
+            mutation_response.try_into().unwrap()
+        else {
+            panic!("Expected MutationResponse");
+        };
+        assert_eq!(log_lines, Vec::<String>::new());
+
+        let action_response = json!({
+            "type": "ActionResponse",
+            "requestId": 0,
+            "success": false,
+            "result": "oops",
+        });
+        let ServerMessage::<TestValue>::ActionResponse { log_lines, .. } =
+            action_response.try_into().unwrap()
+        else {
+            panic!("Expected ActionResponse");
+        };
+        assert_eq!(log_lines, Vec::<String>::new());
+
+        let transition = json!({
+            "type": "Transition",
+            "startVersion": JsonValue::from(StateVersion::initial()),
+            "endVersion": JsonValue::from(StateVersion::initial()),
+            "modifications": [
+                {"type": "QueryUpdated", "queryId": 0, "value": null, "journal": null},
+                {"type": "QueryFailed", "queryId": 1, "errorMessage": "oops", "journal": null},
+            ],
+        });
+        let ServerMessage::<TestValue>::Transition { modifications, .. } =
+            transition.try_into().unwrap()
+        else {
+            panic!("Expected Transition");
+        };
+        for modification in modifications {
+            match modification {
+                StateModification::QueryUpdated { log_lines, .. }
+                | StateModification::QueryFailed { log_lines, .. } => {
+                    assert_eq!(log_lines, Vec::<String>::new())
+                },
+                StateModification::QueryRemoved { .. } => panic!("Unexpected QueryRemoved"),
+            }
+        }
+    }
+
     #[test]
     fn user_identity_attributes_deserialize_token_identifier_given() {
         let serialized = "{\"tokenIdentifier\":\"fake_identifier\"}";
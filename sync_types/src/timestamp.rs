@@ -9,13 +9,29 @@ use std::{
 
 use anyhow::Context;
 use derive_more::FromStr;
-use serde::Serialize;
+use serde::{
+    de,
+    Deserialize,
+    Deserializer,
+    Serialize,
+    Serializer,
+};
 use serde_json::json;
 
+use crate::json::{
+    string_to_u64,
+    u64_to_string,
+};
+
 /// Database transaction timestamp.
 /// This is unique across all transactions.
 /// Units are nanoseconds since epoch.
-#[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Ord, Eq, FromStr, Hash, Serialize, Default)]
+///
+/// With serde, a timestamp is (de)serialized in the sync protocol's wire
+/// format: the base64 encoding of its little-endian bytes, as in the `ts` of a
+/// [`StateVersion`](crate::StateVersion). Converting to a
+/// [`serde_json::Value`] with `From` produces a number of nanoseconds instead.
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd, Ord, Eq, FromStr, Hash, Default)]
 pub struct Timestamp(u64);
 
 impl Timestamp {
@@ -148,6 +164,21 @@ impl From<Timestamp> for serde_json::Value {
     }
 }
 
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&u64_to_string(self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        string_to_u64(&s)
+            .and_then(Timestamp::try_from)
+            .map_err(de::Error::custom)
+    }
+}
+
 #[cfg(any(test, feature = "testing"))]
 impl proptest::arbitrary::Arbitrary for Timestamp {
     type Parameters = ();
@@ -168,3 +199,43 @@ impl Sub for Timestamp {
         Duration::from_nanos(self.0 - rhs.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use serde_json::{
+        json,
+        Value as JsonValue,
+    };
+
+    use super::Timestamp;
+    use crate::StateVersion;
+
+    #[test]
+    fn test_serde_wire_format() -> anyhow::Result<()> {
+        let ts = Timestamp::try_from(1_700_000_000_000_000_000u64)?;
+        let version = StateVersion {
+            query_set: 0,
+            identity: 0,
+            ts,
+        };
+        assert_eq!(serde_json::to_value(ts)?, JsonValue::from(version)["ts"]);
+
+        let max = json!("/////////38=");
+        assert_eq!(serde_json::to_value(Timestamp::MAX)?, max);
+        assert_eq!(serde_json::from_value::<Timestamp>(max)?, Timestamp::MAX);
+        assert!(serde_json::from_value::<Timestamp>(json!("//////////8=")).is_err());
+        assert!(serde_json::from_value::<Timestamp>(json!(12)).is_err());
+        Ok(())
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig { failure_persistence: None, .. ProptestConfig::default() })]
+
+        #[test]
+        fn test_serde_roundtrips(ts in any::<Timestamp>()) {
+            let json = serde_json::to_value(ts).unwrap();
+            prop_assert_eq!(serde_json::from_value::<Timestamp>(json).unwrap(), ts);
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use std::{
+    cmp,
     fmt::Display,
     ops::Deref,
 };
@@ -244,6 +245,18 @@ pub enum StateModification<V> {
     },
 }
 
+impl<V> StateModification<V> {
+    /// The lines the query logged while computing this result. Removed
+    /// queries have none.
+    pub fn log_lines(&self) -> &[String] {
+        match self {
+            StateModification::QueryUpdated { log_lines, .. }
+            | StateModification::QueryFailed { log_lines, .. } => log_lines,
+            StateModification::QueryRemoved { .. } => &[],
+        }
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 #[cfg_attr(any(test, feature = "testing"), derive(proptest_derive::Arbitrary))]
 pub struct QueryFailure {
@@ -268,6 +281,54 @@ impl StateVersion {
             ts: Timestamp::MIN,
         }
     }
+
+    /// Describe how each component changed from `self` to `to`, e.g. to debug
+    /// why a subscription looks stale.
+    ///
+    /// The description lists the query set version, the identity version and
+    /// the timestamp, in that order, separated by `, `. A component that
+    /// changed is shown as `name old -> new (delta)`, where the delta is a
+    /// signed count for the versions and a signed duration for the
+    /// timestamp. One that didn't is shown as `name unchanged at value`.
+    ///
+    /// ```
+    /// # use convex_sync_types::{StateVersion, Timestamp};
+    /// let from = StateVersion::initial();
+    /// let to = StateVersion {
+    ///     query_set: 2,
+    ///     ts: Timestamp::try_from(1_500_000u64)?,
+    ///     ..from
+    /// };
+    /// assert_eq!(
+    ///     from.describe_advance(&to),
+    ///     "query set 0 -> 2 (+2), identity unchanged at 0, ts 0 -> 1500000 (+1.5ms)"
+    /// );
+    /// # anyhow::Ok(())
+    /// ```
+    pub fn describe_advance(&self, to: &StateVersion) -> String {
+        let version = |name: &str, from: u32, to: u32| {
+            if from == to {
+                format!("{name} unchanged at {from}")
+            } else {
+                format!(
+                    "{name} {from} -> {to} ({:+})",
+                    i64::from(to) - i64::from(from)
+                )
+            }
+        };
+        let ts = match self.ts.cmp(&to.ts) {
+            cmp::Ordering::Equal => format!("ts unchanged at {}", self.ts),
+            cmp::Ordering::Less => format!("ts {} -> {} (+{:?})", self.ts, to.ts, to.ts - self.ts),
+            cmp::Ordering::Greater => {
+                format!("ts {} -> {} (-{:?})", self.ts, to.ts, self.ts - to.ts)
+            },
+        };
+        format!(
+            "{}, {}, {ts}",
+            version("query set", self.query_set, to.query_set),
+            version("identity", self.identity, to.identity),
+        )
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -310,6 +371,47 @@ pub enum ServerMessage<V: 'static> {
     Ping,
 }
 
+impl<V: 'static> ServerMessage<V> {
+    /// For a `Transition`, the log lines of all its modifications, e.g. to
+    /// debug everything that ran for a single transition in one place.
+    ///
+    /// Lines are in the order of the modifications in the transition, and in
+    /// the order each query logged them within a modification. Other messages
+    /// have no modifications, so this is empty for them.
+    ///
+    /// ```
+    /// # use convex_sync_types::{QueryId, ServerMessage, StateModification, StateVersion};
+    /// let updated = |query_id, log_lines: &[&str]| StateModification::QueryUpdated {
+    ///     query_id: QueryId::new(query_id),
+    ///     value: (),
+    ///     log_lines: log_lines.iter().map(|line| line.to_string()).collect(),
+    ///     journal: None,
+    /// };
+    /// let transition = ServerMessage::Transition {
+    ///     start_version: StateVersion::initial(),
+    ///     end_version: StateVersion::initial(),
+    ///     modifications: vec![
+    ///         updated(1, &["[LOG] 'loading users'", "[LOG] 'found 2'"]),
+    ///         StateModification::QueryRemoved { query_id: QueryId::new(2) },
+    ///         updated(0, &["[WARN] 'empty channel'"]),
+    ///     ],
+    /// };
+    /// assert_eq!(
+    ///     transition.all_log_lines(),
+    ///     ["[LOG] 'loading users'", "[LOG] 'found 2'", "[WARN] 'empty channel'"]
+    /// );
+    /// ```
+    pub fn all_log_lines(&self) -> Vec<String> {
+        match self {
+            ServerMessage::Transition { modifications, .. } => modifications
+                .iter()
+                .flat_map(|modification| modification.log_lines().iter().cloned())
+                .collect(),
+            _ => vec![],
+        }
+    }
+}
+
 /// List of log lines from a Convex function execution.
 pub type LogLines = Vec<String>;
 
@@ -343,3 +445,36 @@ impl proptest::arbitrary::Arbitrary for SessionId {
 // The seq number of a request with a session. Uniquely identifies a
 // modification request within a session.
 pub type SessionRequestSeqNumber = u32;
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        StateVersion,
+        Timestamp,
+    };
+
+    #[test]
+    fn test_describe_advance() -> anyhow::Result<()> {
+        let initial = StateVersion::initial();
+        let advanced = StateVersion {
+            query_set: 3,
+            identity: 1,
+            ts: Timestamp::MIN.add(Duration::from_secs(2))?,
+        };
+        assert_eq!(
+            initial.describe_advance(&advanced),
+            "query set 0 -> 3 (+3), identity 0 -> 1 (+1), ts 0 -> 2000000000 (+2s)"
+        );
+        assert_eq!(
+            advanced.describe_advance(&initial),
+            "query set 3 -> 0 (-3), identity 1 -> 0 (-1), ts 2000000000 -> 0 (-2s)"
+        );
+        assert_eq!(
+            initial.describe_advance(&initial),
+            "query set unchanged at 0, identity unchanged at 0, ts unchanged at 0"
+        );
+        Ok(())
+    }
+}
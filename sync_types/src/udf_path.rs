@@ -12,8 +12,24 @@ use crate::identifier::check_valid_identifier;
 /// User-specified path to a function, consisting of a module path and an
 /// optional function name, separated by a colon. If a function name isn't
 /// provided, the UDF loader uses the default export from the module.
+///
+/// A function in a [component](https://docs.convex.dev/components) is
+/// addressed by prefixing its path within the component with the component's
+/// path and another colon:
+///
+/// ```text
+/// udf_path: (component ':' module ':' function) | (module (':' function)?)
+/// component: identifier ('/' identifier)*
+/// ```
+///
+/// e.g. `waitlist:queue:add` or `waitlist/ratelimiter:limits:check`. Since
+/// module paths can contain `/` themselves, the function name can't be left
+/// out when there's a component: `waitlist:queue` is the function `queue` of
+/// the module `waitlist` in the app. The component path is sent to the
+/// deployment separately from the path within the component.
 #[derive(Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
 pub struct UdfPath {
+    component: Option<String>,
     module: ModulePath,
     function: Option<String>,
 }
@@ -24,6 +40,41 @@ impl UdfPath {
         self.module.is_system()
     }
 
+    /// What is the path of the component this UDF is in, e.g.
+    /// `waitlist/ratelimiter`? `None` for UDFs of the app itself.
+    pub fn component(&self) -> Option<&str> {
+        self.component.as_deref()
+    }
+
+    /// The path of this UDF within its component, i.e. without the component
+    /// prefix, paired with the component's path.
+    pub fn split_component(self) -> (Option<String>, UdfPath) {
+        let path = UdfPath {
+            component: None,
+            ..self
+        };
+        (self.component, path)
+    }
+
+    /// Move this UDF into `component`, replacing any component it was in.
+    /// Fails if `component` isn't a valid component path.
+    pub fn with_component(self, component: Option<String>) -> anyhow::Result<Self> {
+        let Some(component) = component else {
+            return Ok(Self {
+                component: None,
+                ..self
+            });
+        };
+        check_valid_component_path(&component)?;
+        // The function name is explicit in paths with a component.
+        let function = Some(self.function.unwrap_or_else(|| "default".to_string()));
+        Ok(Self {
+            component: Some(component),
+            module: self.module,
+            function,
+        })
+    }
+
     /// What is the module path for this UDF?
     pub fn module(&self) -> &ModulePath {
         &self.module
@@ -39,20 +90,46 @@ impl UdfPath {
         let function = self
             .function
             .ok_or_else(|| anyhow::anyhow!("Missing explicit ':default' function"))?;
-        Ok(CanonicalizedUdfPath { module, function })
+        Ok(CanonicalizedUdfPath {
+            component: self.component,
+            module,
+            function,
+        })
     }
 
     pub fn canonicalize(self) -> CanonicalizedUdfPath {
         let module = self.module.canonicalize();
         let function = self.function.unwrap_or_else(|| "default".to_string());
-        CanonicalizedUdfPath { module, function }
+        CanonicalizedUdfPath {
+            component: self.component,
+            module,
+            function,
+        }
     }
 }
 
+/// Check that `s` is a component path: identifiers separated by `/`.
+fn check_valid_component_path(s: &str) -> anyhow::Result<()> {
+    for name in s.split('/') {
+        check_valid_identifier(name)
+            .map_err(|e| anyhow::anyhow!("Invalid component path {s}: {e}"))?;
+    }
+    Ok(())
+}
+
 impl FromStr for UdfPath {
     type Err = anyhow::Error;
 
     fn from_str(p: &str) -> Result<Self, Self::Err> {
+        let (component, p) = match p.split(':').count() {
+            3 => {
+                let (component, p) = p.split_once(':').expect("Path has a colon");
+                check_valid_component_path(component)?;
+                (Some(component.to_owned()), p)
+            },
+            n if n > 3 => anyhow::bail!("Function path {p} has too many colons"),
+            _ => (None, p),
+        };
         let (module, function) = match p.rsplit_once(':') {
             Some((module, function)) => {
                 check_valid_identifier(function)?;
@@ -60,22 +137,25 @@ impl FromStr for UdfPath {
             },
             None => (p.parse()?, None),
         };
-        Ok(Self { module, function })
+        Ok(Self {
+            component,
+            module,
+            function,
+        })
     }
 }
 
 impl From<UdfPath> for String {
     fn from(p: UdfPath) -> Self {
-        if let Some(ref function) = p.function {
-            format!("{}:{}", p.module.as_str(), function)
-        } else {
-            format!("{}", p.module.as_str())
-        }
+        p.to_string()
     }
 }
 
 impl fmt::Display for UdfPath {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref component) = self.component {
+            write!(f, "{component}:")?;
+        }
         if let Some(ref function) = self.function {
             write!(f, "{}:{}", self.module.as_str(), function)
         } else {
@@ -87,6 +167,7 @@ impl fmt::Display for UdfPath {
 impl From<CanonicalizedUdfPath> for UdfPath {
     fn from(p: CanonicalizedUdfPath) -> Self {
         Self {
+            component: p.component,
             module: p.module.into(),
             function: Some(p.function),
         }
@@ -104,11 +185,11 @@ impl proptest::arbitrary::Arbitrary for UdfPath {
         use crate::identifier::arbitrary_regexes::IDENTIFIER_REGEX;
 
         prop_compose! {
-            fn inner()(path in any::<ModulePath>(), has_function in any::<bool>(), function_name in IDENTIFIER_REGEX) -> anyhow::Result<UdfPath> {
-                let s = if has_function {
-                    format!("{}:{function_name}", path.as_str())
-                } else {
-                    format!("{}", path.as_str())
+            fn inner()(component in prop::option::of(IDENTIFIER_REGEX), path in any::<ModulePath>(), has_function in any::<bool>(), function_name in IDENTIFIER_REGEX) -> anyhow::Result<UdfPath> {
+                let s = match component {
+                    Some(component) => format!("{component}:{}:{function_name}", path.as_str()),
+                    None if has_function => format!("{}:{function_name}", path.as_str()),
+                    None => format!("{}", path.as_str()),
                 };
                 UdfPath::from_str(&s)
             }
@@ -125,19 +206,28 @@ impl proptest::arbitrary::Arbitrary for UdfPath {
 /// more details.
 #[derive(Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
 pub struct CanonicalizedUdfPath {
+    component: Option<String>,
     module: CanonicalizedModulePath,
     function: String,
 }
 
 impl CanonicalizedUdfPath {
     pub fn new(module: CanonicalizedModulePath, function: String) -> Self {
-        Self { module, function }
+        Self {
+            component: None,
+            module,
+            function,
+        }
     }
 
     pub fn is_system(&self) -> bool {
         self.module.is_system()
     }
 
+    pub fn component(&self) -> Option<&str> {
+        self.component.as_deref()
+    }
+
     pub fn module(&self) -> &CanonicalizedModulePath {
         &self.module
     }
@@ -147,12 +237,14 @@ impl CanonicalizedUdfPath {
     }
 
     pub fn strip(self) -> UdfPath {
-        let function = if self.function == "default" {
+        // The function name is explicit in paths with a component.
+        let function = if self.function == "default" && self.component.is_none() {
             None
         } else {
             Some(self.function)
         };
         UdfPath {
+            component: self.component,
             module: self.module.strip(),
             function,
         }
@@ -161,12 +253,15 @@ impl CanonicalizedUdfPath {
 
 impl fmt::Debug for CanonicalizedUdfPath {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}:{}", self.module.as_str(), self.function)
+        fmt::Display::fmt(self, f)
     }
 }
 
 impl fmt::Display for CanonicalizedUdfPath {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref component) = self.component {
+            write!(f, "{component}:")?;
+        }
         write!(f, "{}:{}", self.module.as_str(), self.function)
     }
 }
@@ -182,7 +277,7 @@ impl FromStr for CanonicalizedUdfPath {
 
 impl From<CanonicalizedUdfPath> for String {
     fn from(p: CanonicalizedUdfPath) -> Self {
-        format!("{}:{}", p.module.as_str(), p.function)
+        p.to_string()
     }
 }
 
@@ -229,4 +324,53 @@ mod tests {
         let stripped = canonicalized.strip();
         assert_eq!(String::from(stripped), "test:function");
     }
+
+    #[test]
+    fn test_component_paths() -> anyhow::Result<()> {
+        // Plain paths are in the app itself, even with directories.
+        let p = UdfPath::from_str("waitlist/queue:add")?;
+        assert_eq!(p.component(), None);
+        assert_eq!(p.module().as_str(), "waitlist/queue");
+        assert_eq!(p.function_name(), "add");
+        let p = UdfPath::from_str("waitlist:queue")?;
+        assert_eq!(p.component(), None);
+        assert_eq!(p.function_name(), "queue");
+
+        let p = UdfPath::from_str("waitlist/ratelimiter:limits:check")?;
+        assert_eq!(p.component(), Some("waitlist/ratelimiter"));
+        assert_eq!(p.module().as_str(), "limits");
+        assert_eq!(p.function_name(), "check");
+        assert_eq!(p.to_string(), "waitlist/ratelimiter:limits:check");
+
+        let canonicalized = p.clone().canonicalize();
+        assert_eq!(
+            String::from(canonicalized.clone()),
+            "waitlist/ratelimiter:limits.js:check"
+        );
+        assert_eq!(canonicalized.component(), Some("waitlist/ratelimiter"));
+        assert_eq!(canonicalized.strip(), p);
+
+        let (component, path) = p.clone().split_component();
+        assert_eq!(component.as_deref(), Some("waitlist/ratelimiter"));
+        assert_eq!(path.to_string(), "limits:check");
+        assert_eq!(path.with_component(component)?, p);
+
+        // The function name stays explicit in paths with a component.
+        let p = UdfPath::from_str("limits")?.with_component(Some("waitlist".into()))?;
+        assert_eq!(p.to_string(), "waitlist:limits:default");
+        assert_eq!(p.clone().canonicalize().strip(), p);
+
+        for invalid in [
+            "wait-list:queue:add",
+            "waitlist/:queue:add",
+            ":queue:add",
+            "a:b:c:d",
+        ] {
+            assert!(UdfPath::from_str(invalid).is_err(), "{invalid}");
+        }
+        assert!(UdfPath::from_str("queue:add")?
+            .with_component(Some("wait list".into()))
+            .is_err());
+        Ok(())
+    }
 }